@@ -35,6 +35,9 @@ pub mod segwitv0;
 #[cfg(all(feature = "experimental", feature = "taproot"))]
 #[cfg_attr(docsrs, doc(cfg(all(feature = "experimental", feature = "taproot"))))]
 pub mod taproot;
+#[cfg(all(feature = "experimental", feature = "taproot"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "experimental", feature = "taproot"))))]
+pub mod taproot_musig2;
 pub mod timelock;
 pub mod transaction;
 
@@ -44,6 +47,13 @@ pub mod transaction;
 #[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
 pub type BitcoinSegwitV0 = Bitcoin<segwitv0::SegwitV0>;
 
+/// Bitcoin blockchain using SegWit version 0 transaction outputs and ECDSA cryptography with
+/// absolute timelocks, the cancel and punish paths are available after a fixed block height or
+/// time instead of a delay. This type is experimental for the same reasons as [`BitcoinSegwitV0`].
+#[cfg(feature = "experimental")]
+#[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
+pub type BitcoinSegwitV0Cltv = Bitcoin<segwitv0::SegwitV0<timelock::CLTVTimelock>>;
+
 #[cfg(feature = "experimental")]
 #[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
 impl Decodable for BitcoinSegwitV0 {
@@ -70,6 +80,14 @@ impl Encodable for BitcoinSegwitV0 {
 #[cfg_attr(docsrs, doc(cfg(all(feature = "experimental", feature = "taproot"))))]
 pub type BitcoinTaproot = Bitcoin<taproot::Taproot>;
 
+/// Bitcoin blockchain using SegWit version 1 transaction outputs, Schnorr cryptography, and
+/// MuSig2 off-chain multisigs for the cooperative spends. This type is experimental because its
+/// cryptography for Adaptor Signatures and multisigs is not ready for production and battle
+/// tested.
+#[cfg(all(feature = "experimental", feature = "taproot"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "experimental", feature = "taproot"))))]
+pub type BitcoinTaprootMusig2 = Bitcoin<taproot_musig2::TaprootMusig2>;
+
 /// Helper type enumerating over all Bitcoin inner variants available.
 #[non_exhaustive]
 pub enum Btc {
@@ -79,11 +97,14 @@ pub enum Btc {
     #[cfg(all(feature = "experimental", feature = "taproot"))]
     #[cfg_attr(docsrs, doc(cfg(all(feature = "experimental", feature = "taproot"))))]
     Taproot(BitcoinTaproot),
+    #[cfg(all(feature = "experimental", feature = "taproot"))]
+    #[cfg_attr(docsrs, doc(cfg(all(feature = "experimental", feature = "taproot"))))]
+    TaprootMusig2(BitcoinTaprootMusig2),
 }
 
 /// Variations of a Bitcoin implementation. Strategy allows different Bitcoin implementations based
-/// on, e.g., the SegWit version such as [`SegwitV0`][segwitv0::SegwitV0] or
-/// [`Taproot`][taproot::Taproot].
+/// on, e.g., the SegWit version such as [`SegwitV0`][segwitv0::SegwitV0],
+/// [`Taproot`][taproot::Taproot], or [`TaprootMusig2`][taproot_musig2::TaprootMusig2].
 pub trait Strategy: Clone + Copy + Debug {}
 
 /// The generic blockchain implementation of Bitcoin. [`Bitcoin`] takes a generic parameter
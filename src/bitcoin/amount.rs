@@ -15,6 +15,7 @@
 // Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
 
 use crate::consensus::{self, CanonicalBytes};
+use crate::trade::AtomicAmount;
 use bitcoin::Amount;
 
 impl CanonicalBytes for Amount {
@@ -31,3 +32,15 @@ impl CanonicalBytes for Amount {
         ))
     }
 }
+
+impl AtomicAmount for Amount {
+    const DECIMALS: u32 = 8;
+
+    fn as_atomic_units(&self) -> u64 {
+        self.as_sat()
+    }
+
+    fn from_atomic_units(units: u64) -> Self {
+        Amount::from_sat(units)
+    }
+}
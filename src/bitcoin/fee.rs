@@ -18,6 +18,9 @@
 //! that set the fee and check the fee on transactions given a [`FeeStrategy`] and a
 //! [`FeePriority`].
 //!
+//! Fees are computed on the virtual size of the transaction once finalized, the witness of each
+//! input is estimated with [`estimate_vsize`] before the transaction is signed.
+//!
 //! ```rust
 //! use farcaster_core::bitcoin::fee::SatPerVByte;
 //!
@@ -33,9 +36,13 @@
 //!# }
 //! ```
 
-use bitcoin::blockdata::transaction::TxOut;
+use bitcoin::blockdata::opcodes::{all, All, Class, ClassifyContext};
+use bitcoin::blockdata::script::{read_scriptint, Instruction, Script};
+use bitcoin::blockdata::transaction::{TxIn, TxOut};
+use bitcoin::consensus::encode::VarInt;
 use bitcoin::util::amount::Denomination;
-use bitcoin::util::psbt::PartiallySignedTransaction;
+use bitcoin::util::psbt::raw::ProprietaryKey;
+use bitcoin::util::psbt::{self, PartiallySignedTransaction};
 use bitcoin::Amount;
 
 use crate::bitcoin::transaction;
@@ -153,48 +160,218 @@ fn get_available_input_sat(tx: &PartiallySignedTransaction) -> Result<Amount, Fe
     ))
 }
 
+/// Maximum size of a DER encoded ECDSA signature followed by its sighash type.
+const ECDSA_SIG_MAX_SIZE: usize = 73;
+
+/// Size of a compressed public key.
+const PUBKEY_SIZE: usize = 33;
+
+/// Size of a BIP-340 Schnorr signature with the default sighash type.
+const SCHNORR_SIG_SIZE: usize = 64;
+
+/// Weight of the SegWit marker and flag.
+const SEGWIT_MARKER_WEIGHT: usize = 2;
+
+/// Estimates the virtual size of the transaction once all its inputs are finalized.
+///
+/// The witness of finalized inputs is used as is. For the other inputs the witness is estimated
+/// from the spent output and the partial transaction input metadata:
+///  * P2WPKH inputs are spent with a signature and a public key
+///  * P2WSH inputs are spent with a signature for each `OP_CHECKSIG(VERIFY)` in the executed
+///    branch of the witness script, the `OP_ELSE` branch is executed only if the input sequence
+///    matches the `OP_CSV` relative timelock of the branch or the transaction lock time matches
+///    its `OP_CLTV` absolute timelock, e.g. the punish path
+///  * P2TR inputs are spent through the key path, or through the largest leaf script listed in
+///    the input with a signature for each x-only public key in the script
+pub fn estimate_vsize(psbt: &PartiallySignedTransaction) -> Result<u64, FeeStrategyError> {
+    let witness_size = psbt
+        .inputs
+        .iter()
+        .zip(psbt.unsigned_tx.input.iter())
+        .map(|(input, txin)| estimate_witness_size(input, txin, psbt.unsigned_tx.lock_time))
+        .sum::<Result<usize, FeeStrategyError>>()?;
+    let weight = psbt.unsigned_tx.weight() + SEGWIT_MARKER_WEIGHT + witness_size;
+    Ok(((weight + 3) / 4) as u64)
+}
+
+fn estimate_witness_size(
+    input: &psbt::Input,
+    txin: &TxIn,
+    lock_time: u32,
+) -> Result<usize, FeeStrategyError> {
+    if let Some(witness) = &input.final_script_witness {
+        return Ok(witness.serialized_len());
+    }
+    let script_pubkey = &input
+        .witness_utxo
+        .as_ref()
+        .ok_or(FeeStrategyError::MissingInputsMetadata)?
+        .script_pubkey;
+    if script_pubkey.is_v0_p2wpkh() {
+        Ok(witness_size(&[ECDSA_SIG_MAX_SIZE, PUBKEY_SIZE]))
+    } else if script_pubkey.is_v0_p2wsh() {
+        let script = input
+            .witness_script
+            .as_ref()
+            .ok_or(FeeStrategyError::MissingInputsMetadata)?;
+        p2wsh_witness_size(script, txin.sequence, lock_time)
+    } else if script_pubkey.is_v1_p2tr() {
+        Ok(p2tr_witness_size(input))
+    } else {
+        Err(FeeStrategyError::new(
+            transaction::Error::UnsupportedWitness,
+        ))
+    }
+}
+
+fn p2wsh_witness_size(
+    script: &Script,
+    sequence: u32,
+    lock_time: u32,
+) -> Result<usize, FeeStrategyError> {
+    let instructions = script
+        .instructions()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| FeeStrategyError::new(transaction::Error::from(e)))?;
+    let position = |opcode| {
+        instructions
+            .iter()
+            .position(|ins| *ins == Instruction::Op(opcode))
+    };
+
+    // Select the executed branch and the witness item selecting it, if any
+    let (branch, selector) = match position(all::OP_IF) {
+        None => (&instructions[..], None),
+        Some(if_index) => {
+            let (else_index, endif_index) = position(all::OP_ELSE)
+                .zip(position(all::OP_ENDIF))
+                .ok_or_else(|| FeeStrategyError::new(transaction::Error::UnsupportedWitness))?;
+            let failure = &instructions[else_index + 1..endif_index];
+            let timelock_expired = timelock(failure, all::OP_CSV) == Some(sequence)
+                || timelock(failure, all::OP_CLTV) == Some(lock_time);
+            match timelock_expired {
+                true => (failure, Some(0)),
+                false => (&instructions[if_index + 1..else_index], Some(1)),
+            }
+        }
+    };
+
+    let sigs = branch
+        .iter()
+        .filter(|ins| {
+            matches!(
+                ins,
+                Instruction::Op(all::OP_CHECKSIG) | Instruction::Op(all::OP_CHECKSIGVERIFY)
+            )
+        })
+        .count();
+    let mut items = vec![ECDSA_SIG_MAX_SIZE; sigs];
+    items.extend(selector);
+    items.push(script.len());
+    Ok(witness_size(&items))
+}
+
+// Returns the timelock verified with the `OP_CSV` or `OP_CLTV` opcode in the list of
+// instructions.
+fn timelock(instructions: &[Instruction], verify: All) -> Option<u32> {
+    instructions.windows(2).find_map(|pair| match pair {
+        [Instruction::PushBytes(bytes), Instruction::Op(op)] if *op == verify => {
+            read_scriptint(bytes).ok().map(|value| value as u32)
+        }
+        [Instruction::Op(opcode), Instruction::Op(op)] if *op == verify => {
+            match opcode.classify(ClassifyContext::Legacy) {
+                Class::PushNum(value) => Some(value as u32),
+                _ => None,
+            }
+        }
+        _ => None,
+    })
+}
+
+fn p2tr_witness_size(input: &psbt::Input) -> usize {
+    input
+        .tap_scripts
+        .iter()
+        .map(|(control_block, (script, _))| {
+            let keys = script
+                .instructions()
+                .filter(|ins| matches!(ins, Ok(Instruction::PushBytes(bytes)) if bytes.len() == 32))
+                .count();
+            let mut items = vec![SCHNORR_SIG_SIZE; keys];
+            items.push(script.len());
+            items.push(control_block.size());
+            witness_size(&items)
+        })
+        .max()
+        .unwrap_or_else(|| witness_size(&[SCHNORR_SIG_SIZE]))
+}
+
+// Size of a serialized witness stack given the size of each item.
+fn witness_size(items: &[usize]) -> usize {
+    VarInt(items.len() as u64).len()
+        + items
+            .iter()
+            .map(|&len| VarInt(len as u64).len() + len)
+            .sum::<usize>()
+}
+
+/// Prefix of the proprietary key marking the fee-bearing output of a partial transaction.
+const PROPRIETARY_PREFIX: &[u8] = b"farcaster";
+
+/// Subtype of the proprietary key marking the fee-bearing output of a partial transaction.
+const FEE_OUTPUT_SUBTYPE: u8 = 0x00;
+
+fn fee_output_key() -> ProprietaryKey {
+    ProprietaryKey {
+        prefix: PROPRIETARY_PREFIX.to_vec(),
+        subtype: FEE_OUTPUT_SUBTYPE,
+        key: vec![],
+    }
+}
+
 impl Fee for PartiallySignedTransaction {
     type FeeUnit = SatPerVByte;
 
     type Amount = Amount;
 
-    /// Calculates and sets the fees on the given transaction and return the fees set
+    type Output = TxOut;
+
+    /// Calculates and sets the fees on the fee-bearing output of the given transaction and return
+    /// the fees set
     fn set_fee(
         &mut self,
         strategy: &FeeStrategy<SatPerVByte>,
         politic: FeePriority,
     ) -> Result<Self::Amount, FeeStrategyError> {
-        if self.unsigned_tx.output.len() != 1 {
-            return Err(FeeStrategyError::new(
-                transaction::Error::MultiUTXOUnsuported,
-            ));
-        }
-
+        let fee_output = self.fee_output()?;
         let input_sum = get_available_input_sat(self)?;
+        let other_outputs_sum = Amount::from_sat(
+            self.unsigned_tx
+                .output
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| *i != fee_output)
+                .map(|(_, txout)| txout.value)
+                .sum(),
+        );
 
-        // FIXME This does not account for witnesses
-        // currently the fees are wrong
-        // Get the transaction weight
-        //
-        // For transactions with an empty witness, this is simply the consensus-serialized size
-        // times four. For transactions with a witness, this is the non-witness
-        // consensus-serialized size multiplied by three plus the with-witness consensus-serialized
-        // size.
-        let weight = self.unsigned_tx.weight() as u64;
+        // Get the virtual size of the transaction with the estimated witnesses
+        let vsize = estimate_vsize(self)?;
 
         // Compute the fee amount to set in total
         let fee_amount = match strategy {
-            FeeStrategy::Fixed(sat_per_vbyte) => sat_per_vbyte.as_native_unit().checked_mul(weight),
+            FeeStrategy::Fixed(sat_per_vbyte) => sat_per_vbyte.as_native_unit().checked_mul(vsize),
             FeeStrategy::Range { min_inc, max_inc } => match politic {
-                FeePriority::Low => min_inc.as_native_unit().checked_mul(weight),
-                FeePriority::High => max_inc.as_native_unit().checked_mul(weight),
+                FeePriority::Low => min_inc.as_native_unit().checked_mul(vsize),
+                FeePriority::High => max_inc.as_native_unit().checked_mul(vsize),
             },
         }
         .ok_or(FeeStrategyError::AmountOfFeeTooHigh)?;
 
-        // Apply the fee on the first output
-        self.unsigned_tx.output[0].value = input_sum
-            .checked_sub(fee_amount)
+        // Apply the fee on the fee-bearing output, other outputs are left untouched
+        self.unsigned_tx.output[fee_output].value = input_sum
+            .checked_sub(other_outputs_sum)
+            .and_then(|available| available.checked_sub(fee_amount))
             .ok_or(FeeStrategyError::NotEnoughAssets)?
             .as_sat();
 
@@ -204,26 +381,96 @@ impl Fee for PartiallySignedTransaction {
 
     /// Validates that the fees for the given transaction are set accordingly to the strategy
     fn validate_fee(&self, strategy: &FeeStrategy<SatPerVByte>) -> Result<bool, FeeStrategyError> {
-        if self.unsigned_tx.output.len() != 1 {
-            return Err(FeeStrategyError::new(
-                transaction::Error::MultiUTXOUnsuported,
-            ));
+        // The fee must be attributable to a single output
+        self.fee_output()?;
+
+        Ok(strategy.check(&self.fee_rate()?))
+    }
+
+    /// Marks the output with a Farcaster proprietary key, the mark is serialized with the partial
+    /// transaction and removed from any previously designated output
+    fn set_fee_output(&mut self, index: usize) -> Result<(), FeeStrategyError> {
+        if index >= self.outputs.len() {
+            return Err(FeeStrategyError::InvalidFeeOutput);
+        }
+        let key = fee_output_key();
+        for output in self.outputs.iter_mut() {
+            output.proprietary.remove(&key);
         }
+        self.outputs[index].proprietary.insert(key, vec![]);
+        Ok(())
+    }
 
+    /// Returns the marked output, or the only output of the transaction if none is marked
+    fn fee_output(&self) -> Result<usize, FeeStrategyError> {
+        let key = fee_output_key();
+        let marked: Vec<usize> = self
+            .outputs
+            .iter()
+            .enumerate()
+            .filter(|(_, output)| output.proprietary.contains_key(&key))
+            .map(|(i, _)| i)
+            .collect();
+        match (marked.as_slice(), self.unsigned_tx.output.len()) {
+            ([index], len) if *index < len => Ok(*index),
+            ([], 1) => Ok(0),
+            _ => Err(FeeStrategyError::InvalidFeeOutput),
+        }
+    }
+
+    fn validate_outputs(&self, expected: &[TxOut]) -> Result<bool, FeeStrategyError> {
+        let fee_output = self.fee_output()?;
+        Ok(self
+            .unsigned_tx
+            .output
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != fee_output)
+            .map(|(_, txout)| txout)
+            .eq(expected.iter()))
+    }
+
+    /// Returns the fee paid per virtual byte, computed on the estimated virtual size of the
+    /// finalized transaction
+    fn fee_rate(&self) -> Result<SatPerVByte, FeeStrategyError> {
         let input_sum = get_available_input_sat(self)?.as_sat();
-        let output_sum = self.unsigned_tx.output[0].value;
+        let output_sum = self
+            .unsigned_tx
+            .output
+            .iter()
+            .try_fold(0u64, |sum, txout| sum.checked_add(txout.value))
+            .ok_or(FeeStrategyError::AmountOverflow)?;
+        // Outputs spending more than the inputs pay a negative fee
         let fee = input_sum
             .checked_sub(output_sum)
-            .ok_or(FeeStrategyError::AmountOfFeeTooHigh)?;
-        let weight = self.unsigned_tx.weight() as u64;
+            .ok_or(FeeStrategyError::AmountOfFeeTooLow)?;
+        let vsize = estimate_vsize(self)?;
 
-        let effective_sat_per_vbyte = SatPerVByte::from_sat(
-            weight
-                .checked_div(fee)
+        Ok(SatPerVByte::from_sat(
+            fee.checked_div(vsize)
                 .ok_or(FeeStrategyError::AmountOfFeeTooLow)?,
-        );
+        ))
+    }
 
-        Ok(strategy.check(&effective_sat_per_vbyte))
+    /// Splits the range above the minimum fee rate in `rungs` steps of at least one satoshi per
+    /// virtual byte, each rung pays the minimum relay fee increment required by replacements
+    fn fee_ladder(
+        strategy: &FeeStrategy<SatPerVByte>,
+        politic: FeePriority,
+        rungs: usize,
+    ) -> Vec<SatPerVByte> {
+        let (min, max) = match (strategy, politic) {
+            (FeeStrategy::Range { min_inc, max_inc }, FeePriority::Low) => {
+                (min_inc.as_sat(), max_inc.as_sat())
+            }
+            _ => return vec![],
+        };
+        let mut rates: Vec<u64> = (1..=rungs as u64)
+            .map(|i| min + (max.saturating_sub(min) * i / rungs as u64))
+            .filter(|rate| *rate > min)
+            .collect();
+        rates.dedup();
+        rates.into_iter().map(SatPerVByte::from_sat).collect()
     }
 }
 
@@ -231,6 +478,44 @@ impl Fee for PartiallySignedTransaction {
 mod tests {
     use super::*;
 
+    use bitcoin::blockdata::transaction::OutPoint;
+
+    fn psbt_with_amounts(input: u64, outputs: &[u64]) -> PartiallySignedTransaction {
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                ..Default::default()
+            }],
+            output: outputs
+                .iter()
+                .map(|value| TxOut {
+                    value: *value,
+                    script_pubkey: Script::default(),
+                })
+                .collect(),
+        })
+        .unwrap();
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: input,
+            script_pubkey: Script::default(),
+        });
+        psbt
+    }
+
+    #[test]
+    fn fee_rate_rejects_negative_fee_and_overflow() {
+        assert!(matches!(
+            psbt_with_amounts(1000, &[600, 600]).fee_rate(),
+            Err(FeeStrategyError::AmountOfFeeTooLow)
+        ));
+        assert!(matches!(
+            psbt_with_amounts(1000, &[u64::MAX, 1]).fee_rate(),
+            Err(FeeStrategyError::AmountOverflow)
+        ));
+    }
+
     #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
     struct SerdeTest {
         fee: SatPerVByte,
@@ -257,6 +542,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn fee_ladder_inside_range() {
+        let strategy = FeeStrategy::Range {
+            min_inc: SatPerVByte::from_sat(10),
+            max_inc: SatPerVByte::from_sat(40),
+        };
+        let ladder = PartiallySignedTransaction::fee_ladder(&strategy, FeePriority::Low, 3);
+        assert_eq!(
+            ladder,
+            vec![
+                SatPerVByte::from_sat(20),
+                SatPerVByte::from_sat(30),
+                SatPerVByte::from_sat(40)
+            ]
+        );
+        // Narrow ranges produce less rungs than requested
+        let strategy = FeeStrategy::Range {
+            min_inc: SatPerVByte::from_sat(10),
+            max_inc: SatPerVByte::from_sat(12),
+        };
+        let ladder = PartiallySignedTransaction::fee_ladder(&strategy, FeePriority::Low, 4);
+        assert_eq!(
+            ladder,
+            vec![SatPerVByte::from_sat(11), SatPerVByte::from_sat(12)]
+        );
+        // No room for bumping
+        assert!(PartiallySignedTransaction::fee_ladder(&strategy, FeePriority::High, 4).is_empty());
+        let strategy = FeeStrategy::Fixed(SatPerVByte::from_sat(10));
+        assert!(PartiallySignedTransaction::fee_ladder(&strategy, FeePriority::Low, 4).is_empty());
+    }
+
     #[test]
     fn display_sats_per_vbyte() {
         let fee_rate = SatPerVByte::from_sat(100);
@@ -18,6 +18,7 @@
 
 use std::convert::TryFrom;
 use std::fmt::{self, Debug};
+use std::marker::PhantomData;
 use std::str::FromStr;
 
 use crate::bitcoin::segwitv0::{
@@ -27,7 +28,7 @@ use crate::bitcoin::transaction::TxInRef;
 use crate::bitcoin::transaction::{MetadataOutput, Tx};
 use crate::bitcoin::{Bitcoin, BitcoinSegwitV0, Btc, Strategy};
 
-use crate::bitcoin::timelock::CSVTimelock;
+use crate::bitcoin::timelock::{CSVTimelock, Timelock};
 use crate::blockchain::Transactions;
 use crate::consensus::{self, CanonicalBytes};
 use crate::crypto::{DeriveKeys, SharedKeyId};
@@ -44,6 +45,7 @@ use bitcoin::util::sighash::SighashCache;
 
 use ecdsa_fun::adaptor::EncryptedSignature;
 
+pub mod anchor;
 mod buy;
 mod cancel;
 pub mod funding;
@@ -57,6 +59,9 @@ pub type BuyTx = Tx<Buy>;
 /// Cancel the buy transaction and allow refund or punish transaction.
 pub type CancelTx = Tx<Cancel>;
 
+/// Spend the anchor output of a pre-signed transaction to bump its fee.
+pub type CpfpTx = Tx<anchor::Child>;
+
 /// Funding the swap creating a SegWit v0 output.
 pub type FundingTx = Funding;
 
@@ -70,10 +75,14 @@ pub type PunishTx = Tx<Punish>;
 pub type RefundTx = Tx<Refund>;
 
 /// Inner type for the implementation of SegWit version 0 transactions and ECDSA cryptography.
+/// The timelock `T` used in the cancel and punish paths is relative ([`CSVTimelock`]) by default
+/// or absolute with [`CLTVTimelock`].
+///
+/// [`CLTVTimelock`]: crate::bitcoin::timelock::CLTVTimelock
 #[derive(Clone, Debug, Copy, Eq, PartialEq)]
-pub struct SegwitV0;
+pub struct SegwitV0<T = CSVTimelock>(PhantomData<T>);
 
-impl Strategy for SegwitV0 {}
+impl<T: Timelock> Strategy for SegwitV0<T> {}
 
 impl fmt::Display for Bitcoin<SegwitV0> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -104,7 +113,7 @@ pub struct CoopLock {
 }
 
 impl CoopLock {
-    pub fn script(data: DataLock<CSVTimelock, PublicKey>) -> Script {
+    pub fn script<T>(data: DataLock<T, PublicKey>) -> Script {
         let DataLock {
             success: DoubleKeys { alice, bob },
             ..
@@ -117,7 +126,7 @@ impl CoopLock {
             .into_script()
     }
 
-    pub fn v0_p2wsh(data: DataLock<CSVTimelock, PublicKey>) -> Script {
+    pub fn v0_p2wsh<T>(data: DataLock<T, PublicKey>) -> Script {
         Self::script(data).to_v0_p2wsh()
     }
 
@@ -195,7 +204,7 @@ pub struct PunishLock {
 }
 
 impl PunishLock {
-    pub fn script(data: DataPunishableLock<CSVTimelock, PublicKey>) -> Script {
+    pub fn script<T: Timelock>(data: DataPunishableLock<T, PublicKey>) -> Script {
         let DataPunishableLock {
             timelock,
             success: DoubleKeys { alice, bob },
@@ -208,8 +217,8 @@ impl PunishLock {
             .push_key(&bitcoin::util::key::PublicKey::new(bob))
             .push_opcode(opcodes::all::OP_CHECKSIG)
             .push_opcode(opcodes::all::OP_ELSE)
-            .push_int(timelock.as_u32().into())
-            .push_opcode(opcodes::all::OP_CSV)
+            .push_int(timelock.script_value().into())
+            .push_opcode(T::opcode())
             .push_opcode(opcodes::all::OP_DROP)
             .push_key(&bitcoin::util::key::PublicKey::new(failure))
             .push_opcode(opcodes::all::OP_CHECKSIG)
@@ -217,7 +226,7 @@ impl PunishLock {
             .into_script()
     }
 
-    pub fn v0_p2wsh(data: DataPunishableLock<CSVTimelock, PublicKey>) -> Script {
+    pub fn v0_p2wsh<T: Timelock>(data: DataPunishableLock<T, PublicKey>) -> Script {
         Self::script(data).to_v0_p2wsh()
     }
 
@@ -286,15 +295,15 @@ impl PunishLock {
             })?;
         // Timelock
         let _ = ints.next().ok_or(Error::WrongTemplate("Missing opcode"))?;
-        // CSV opcode
+        // CSV or CLTV opcode
         ints.next()
             .ok_or(Error::WrongTemplate("Missing opcode"))
             .map_or_else(Err, |v| match v {
-                Ok(Instruction::Op(all::OP_CSV)) => Ok(()),
+                Ok(Instruction::Op(all::OP_CSV)) | Ok(Instruction::Op(all::OP_CLTV)) => Ok(()),
                 Err(e) => Err(Error::new(e)),
-                _ => Err(Error::WrongTemplate("Missing CSV opcode")),
+                _ => Err(Error::WrongTemplate("Missing CSV or CLTV opcode")),
             })?;
-        // CSV opcode
+        // Drop opcode
         ints.next()
             .ok_or(Error::WrongTemplate("Missing opcode"))
             .map_or_else(Err, |v| match v {
@@ -367,26 +376,26 @@ impl TryFrom<Btc> for Bitcoin<SegwitV0> {
     }
 }
 
-impl Transactions for Bitcoin<SegwitV0> {
+impl<T: Timelock> Transactions for Bitcoin<SegwitV0<T>> {
     type Addr = bitcoin::Address;
     type Amt = bitcoin::Amount;
     type Tx = bitcoin::Transaction;
     type Px = PartiallySignedTransaction;
     type Out = MetadataOutput;
-    type Ti = CSVTimelock;
+    type Ti = T;
     type Ms = Sha256dHash;
     type Pk = PublicKey;
     type Si = Signature;
 
     type Funding = Funding;
-    type Lock = Tx<Lock>;
-    type Buy = Tx<Buy>;
-    type Cancel = Tx<Cancel>;
-    type Refund = Tx<Refund>;
-    type Punish = Tx<Punish>;
+    type Lock = Tx<Lock<T>>;
+    type Buy = Tx<Buy<T>>;
+    type Cancel = Tx<Cancel<T>>;
+    type Refund = Tx<Refund<T>>;
+    type Punish = Tx<Punish<T>>;
 }
 
-impl DeriveKeys for Bitcoin<SegwitV0> {
+impl<T: Timelock> DeriveKeys for Bitcoin<SegwitV0<T>> {
     type PublicKey = PublicKey;
     type PrivateKey = SecretKey;
 
@@ -498,6 +507,48 @@ pub fn sign_hash(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::bitcoin::fee::{estimate_vsize, SatPerVByte};
+    use crate::bitcoin::timelock::CLTVTimelock;
+    use crate::bitcoin::transaction::SubTransaction;
+    use crate::blockchain::{Fee, FeePriority, FeeStrategy, FeeStrategyError, Network};
+    use crate::transaction::{
+        Broadcastable, Buyable, Cancelable, Fundable, Linkable, Lockable, Punishable, Refundable,
+        Transaction, Witnessable,
+    };
+
+    use bitcoin::blockdata::transaction::{OutPoint, TxIn, TxOut};
+    use bitcoin::blockdata::witness::Witness;
+    use bitcoin::Amount;
+
+    fn keypair(byte: u8) -> (PublicKey, SecretKey) {
+        let secret = SecretKey::from_slice(&[byte; 32]).unwrap();
+        (
+            PublicKey::from_secret_key(&Secp256k1::new(), &secret),
+            secret,
+        )
+    }
+
+    fn sign<T: SubTransaction>(tx: &mut Tx<T>, path: ScriptPath, keypair: &(PublicKey, SecretKey)) {
+        let msg = tx.generate_witness_message(path).unwrap();
+        let sig = sign_hash(msg, &keypair.1).unwrap();
+        tx.add_witness(keypair.0, sig).unwrap();
+    }
+
+    // The estimation assumes the largest ECDSA signatures, the final virtual size is at most one
+    // virtual byte smaller and the fee rate paid is never below the requested rate.
+    fn assert_fee<T: SubTransaction>(tx: &mut Tx<T>, estimated: u64, rate: u64) {
+        let fee = tx
+            .as_partial()
+            .inputs
+            .iter()
+            .map(|input| input.witness_utxo.as_ref().unwrap().value)
+            .sum::<u64>()
+            - tx.as_partial().unsigned_tx.output[0].value;
+        let final_tx = tx.finalize_and_extract().unwrap();
+        let vsize = final_tx.vsize() as u64;
+        assert!(vsize <= estimated && estimated - vsize <= 1);
+        assert!(fee / vsize >= rate);
+    }
 
     #[test]
     fn from_string() {
@@ -510,4 +561,563 @@ mod tests {
         let parse = Bitcoin::<SegwitV0>::from_str("bitcoin");
         assert!(parse.is_ok());
     }
+
+    #[test]
+    fn estimate_fee_with_witnesses() {
+        let fund = keypair(1);
+        let alice = keypair(2);
+        let bob = keypair(3);
+        let destination =
+            bitcoin::Address::p2wpkh(&bitcoin::PublicKey::new(fund.0), bitcoin::Network::Regtest)
+                .unwrap();
+        let strategy = FeeStrategy::Fixed(SatPerVByte::from_sat(10));
+
+        let mut funding = Funding::initialize(fund.0, Network::Local).unwrap();
+        let address = funding.get_address().unwrap();
+        funding
+            .update(bitcoin::Transaction {
+                version: 2,
+                lock_time: 0,
+                input: vec![TxIn {
+                    previous_output: OutPoint::default(),
+                    script_sig: Script::default(),
+                    sequence: 0,
+                    witness: Witness::new(),
+                }],
+                output: vec![TxOut {
+                    value: 100_000,
+                    script_pubkey: address.script_pubkey(),
+                }],
+            })
+            .unwrap();
+
+        let datalock = DataLock {
+            timelock: CSVTimelock::new(10),
+            success: DoubleKeys::new(alice.0, bob.0),
+            failure: DoubleKeys::new(alice.0, bob.0),
+        };
+        let punish_lock = DataPunishableLock {
+            timelock: CSVTimelock::new(20),
+            success: DoubleKeys::new(alice.0, bob.0),
+            failure: alice.0,
+        };
+
+        // P2WPKH funding input, the lock fee is fixed by the target amount
+        let mut lock = LockTx::initialize(&funding, datalock, Amount::from_sat(99_000)).unwrap();
+        let estimated = estimate_vsize(lock.as_partial()).unwrap();
+        sign(&mut lock, ScriptPath::Success, &fund);
+        assert_fee(&mut lock, estimated, 1_000 / estimated);
+
+        // 2-of-2 multisig inputs
+        let mut cancel = CancelTx::initialize(&lock, datalock, punish_lock).unwrap();
+        let fee = cancel
+            .as_partial_mut()
+            .set_fee(&strategy, FeePriority::Low)
+            .unwrap();
+        let estimated = estimate_vsize(cancel.as_partial()).unwrap();
+        assert_eq!(fee, Amount::from_sat(estimated * 10));
+        assert!(cancel.as_partial().validate_fee(&strategy).unwrap());
+
+        let mut buy = BuyTx::initialize(&lock, datalock, destination.clone()).unwrap();
+        buy.as_partial_mut()
+            .set_fee(&strategy, FeePriority::Low)
+            .unwrap();
+        let estimated_buy = estimate_vsize(buy.as_partial()).unwrap();
+        sign(&mut buy, ScriptPath::Success, &alice);
+        sign(&mut buy, ScriptPath::Success, &bob);
+        assert_fee(&mut buy, estimated_buy, 10);
+
+        let mut refund = RefundTx::initialize(&cancel, destination.clone()).unwrap();
+        refund
+            .as_partial_mut()
+            .set_fee(&strategy, FeePriority::Low)
+            .unwrap();
+        assert!(refund.as_partial().validate_fee(&strategy).unwrap());
+        let estimated_refund = estimate_vsize(refund.as_partial()).unwrap();
+        sign(&mut refund, ScriptPath::Success, &alice);
+        sign(&mut refund, ScriptPath::Success, &bob);
+        assert_fee(&mut refund, estimated_refund, 10);
+
+        // Single signature punish path, selected with the punish timelock sequence
+        let mut punish_tx = PunishTx::initialize(&cancel, punish_lock, destination).unwrap();
+        punish_tx
+            .as_partial_mut()
+            .set_fee(&strategy, FeePriority::Low)
+            .unwrap();
+        let estimated_punish = estimate_vsize(punish_tx.as_partial()).unwrap();
+        assert!(estimated_punish < estimated_refund);
+        sign(&mut punish_tx, ScriptPath::Failure, &alice);
+        assert_fee(&mut punish_tx, estimated_punish, 10);
+
+        sign(&mut cancel, ScriptPath::Failure, &alice);
+        sign(&mut cancel, ScriptPath::Failure, &bob);
+        assert_fee(&mut cancel, estimated, 10);
+    }
+
+    #[test]
+    fn validate_fee_on_virtual_size() {
+        let (fund, _) = keypair(1);
+        let mut funding = Funding::initialize(fund, Network::Local).unwrap();
+        let address = funding.get_address().unwrap();
+        funding
+            .update(bitcoin::Transaction {
+                version: 2,
+                lock_time: 0,
+                input: vec![],
+                output: vec![TxOut {
+                    value: 100_000,
+                    script_pubkey: address.script_pubkey(),
+                }],
+            })
+            .unwrap();
+        let datalock = DataLock {
+            timelock: CSVTimelock::new(10),
+            success: DoubleKeys::new(keypair(2).0, keypair(3).0),
+            failure: DoubleKeys::new(keypair(2).0, keypair(3).0),
+        };
+        let punish_lock = DataPunishableLock {
+            timelock: CSVTimelock::new(10),
+            success: DoubleKeys::new(keypair(2).0, keypair(3).0),
+            failure: keypair(4).0,
+        };
+        let lock = LockTx::initialize(&funding, datalock, Amount::from_sat(99_000)).unwrap();
+        let mut cancel = CancelTx::initialize(&lock, datalock, punish_lock).unwrap();
+        let vsize = estimate_vsize(cancel.as_partial()).unwrap();
+
+        let range = FeeStrategy::Range {
+            min_inc: SatPerVByte::from_sat(5),
+            max_inc: SatPerVByte::from_sat(15),
+        };
+        for (rate, valid) in [(4, false), (5, true), (15, true), (40, false)] {
+            cancel.as_partial_mut().unsigned_tx.output[0].value = 99_000 - vsize * rate;
+            assert_eq!(cancel.as_partial().validate_fee(&range).unwrap(), valid);
+        }
+    }
+
+    #[test]
+    fn set_fee_with_multiple_outputs() {
+        let fund = keypair(1);
+        let alice = keypair(2);
+        let bob = keypair(3);
+        let change = TxOut {
+            value: 0,
+            script_pubkey: bitcoin::Address::p2wpkh(
+                &bitcoin::PublicKey::new(fund.0),
+                bitcoin::Network::Regtest,
+            )
+            .unwrap()
+            .script_pubkey(),
+        };
+        let strategy = FeeStrategy::Fixed(SatPerVByte::from_sat(10));
+
+        let mut funding = Funding::initialize(fund.0, Network::Local).unwrap();
+        let address = funding.get_address().unwrap();
+        funding
+            .update(bitcoin::Transaction {
+                version: 2,
+                lock_time: 0,
+                input: vec![],
+                output: vec![TxOut {
+                    value: 100_000,
+                    script_pubkey: address.script_pubkey(),
+                }],
+            })
+            .unwrap();
+        let datalock = DataLock {
+            timelock: CSVTimelock::new(10),
+            success: DoubleKeys::new(alice.0, bob.0),
+            failure: DoubleKeys::new(alice.0, bob.0),
+        };
+
+        // The change output absorbs the fee of the lock, the lock output is untouched
+        let mut lock = LockTx::initialize(&funding, datalock, Amount::from_sat(60_000)).unwrap();
+        let lock_output = lock.as_partial().unsigned_tx.output[0].clone();
+        lock.add_output(change.clone()).unwrap();
+        assert_eq!(lock.as_partial().fee_output().unwrap(), 0);
+        lock.as_partial_mut().set_fee_output(1).unwrap();
+        let fee = lock
+            .as_partial_mut()
+            .set_fee(&strategy, FeePriority::Low)
+            .unwrap();
+        assert_eq!(
+            lock.as_partial().unsigned_tx.output[1].value,
+            40_000 - fee.as_sat()
+        );
+        assert!(lock.as_partial().validate_fee(&strategy).unwrap());
+        assert!(lock
+            .as_partial()
+            .validate_outputs(std::slice::from_ref(&lock_output))
+            .unwrap());
+        lock.verify_template(datalock).unwrap();
+        lock.verify_target_amount(Amount::from_sat(60_000)).unwrap();
+        assert_eq!(lock.get_consumable_output().unwrap().tx_out, lock_output);
+
+        // The designation is serialized with the partial transaction
+        let lock = LockTx::from_partial(
+            PartiallySignedTransaction::from_canonical_bytes(
+                &lock.as_partial().as_canonical_bytes(),
+            )
+            .unwrap(),
+        );
+        assert_eq!(lock.as_partial().fee_output().unwrap(), 1);
+
+        // A service fee output is left untouched on the buy
+        let destination =
+            bitcoin::Address::p2wpkh(&bitcoin::PublicKey::new(alice.0), bitcoin::Network::Regtest)
+                .unwrap();
+        let service_fee = TxOut {
+            value: 1_000,
+            ..change
+        };
+        let mut buy = BuyTx::initialize(&lock, datalock, destination).unwrap();
+        buy.add_output(service_fee.clone()).unwrap();
+        let fee = buy
+            .as_partial_mut()
+            .set_fee(&strategy, FeePriority::Low)
+            .unwrap();
+        assert_eq!(
+            buy.as_partial().unsigned_tx.output[0].value,
+            60_000 - 1_000 - fee.as_sat()
+        );
+        assert!(buy.as_partial().validate_fee(&strategy).unwrap());
+        assert!(buy
+            .as_partial()
+            .validate_outputs(std::slice::from_ref(&service_fee))
+            .unwrap());
+        buy.as_partial_mut().unsigned_tx.output[1].value = 2_000;
+        assert!(!buy.as_partial().validate_outputs(&[service_fee]).unwrap());
+
+        // Without designation the fee cannot be attributed to an output
+        let mut psbt = buy.to_partial();
+        psbt.outputs[0].proprietary.clear();
+        assert!(matches!(
+            psbt.set_fee(&strategy, FeePriority::Low),
+            Err(FeeStrategyError::InvalidFeeOutput)
+        ));
+        assert!(matches!(
+            psbt.validate_fee(&strategy),
+            Err(FeeStrategyError::InvalidFeeOutput)
+        ));
+        assert!(matches!(
+            psbt.set_fee_output(2),
+            Err(FeeStrategyError::InvalidFeeOutput)
+        ));
+    }
+
+    #[test]
+    fn consolidate_funding_outputs() {
+        let fund = keypair(1);
+        let alice = keypair(2);
+        let bob = keypair(3);
+        let strategy = FeeStrategy::Fixed(SatPerVByte::from_sat(10));
+
+        let mut funding = Funding::initialize(fund.0, Network::Local).unwrap();
+        let address = funding.get_address().unwrap();
+        let payment = |vout: u32, values: &[u64]| bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint {
+                    vout,
+                    ..OutPoint::default()
+                },
+                script_sig: Script::default(),
+                sequence: 0,
+                witness: Witness::new(),
+            }],
+            output: values
+                .iter()
+                .map(|value| TxOut {
+                    value: *value,
+                    script_pubkey: address.script_pubkey(),
+                })
+                .collect(),
+        };
+
+        // A first payment with an unrelated change output, seen twice
+        let mut first = payment(0, &[40_000]);
+        first.output.push(TxOut {
+            value: 1_000_000,
+            script_pubkey: Script::default(),
+        });
+        funding.update(first.clone()).unwrap();
+        funding.update(first).unwrap();
+        assert_eq!(funding.get_consumable_outputs().unwrap().len(), 1);
+
+        // A second payment paying twice to the funding address
+        funding.update(payment(1, &[30_000, 35_000])).unwrap();
+        let outputs = funding.get_consumable_outputs().unwrap();
+        assert_eq!(outputs.len(), 3);
+        assert_eq!(funding.get_consumable_output().unwrap(), outputs[0]);
+
+        // The funding survives serialization with all the seen transactions
+        let bytes = consensus::serialize(&funding);
+        let decoded: Funding = consensus::deserialize(&bytes).unwrap();
+        assert_eq!(decoded.get_consumable_outputs().unwrap(), outputs);
+
+        let datalock = DataLock {
+            timelock: CSVTimelock::new(10),
+            success: DoubleKeys::new(alice.0, bob.0),
+            failure: DoubleKeys::new(alice.0, bob.0),
+        };
+        let punish_lock = DataPunishableLock {
+            timelock: CSVTimelock::new(20),
+            success: DoubleKeys::new(alice.0, bob.0),
+            failure: alice.0,
+        };
+
+        assert!(matches!(
+            LockTx::initialize(&funding, datalock, Amount::from_sat(105_001)),
+            Err(crate::transaction::Error::NotEnoughAssets)
+        ));
+
+        let mut lock = LockTx::initialize(&funding, datalock, Amount::from_sat(104_000)).unwrap();
+        assert_eq!(lock.inputs_count(), 3);
+        assert!(lock.verify_template(datalock).is_ok());
+        assert!(lock.verify_target_amount(Amount::from_sat(104_000)).is_ok());
+        assert!(matches!(
+            lock.verify_target_amount(Amount::from_sat(103_000)),
+            Err(crate::transaction::Error::InvalidTargetAmount)
+        ));
+
+        // Each input is signed separately
+        let estimated = estimate_vsize(lock.as_partial()).unwrap();
+        for index in 0..lock.inputs_count() {
+            let msg = lock
+                .generate_input_witness_message(index, ScriptPath::Success)
+                .unwrap();
+            let sig = sign_hash(msg, &fund.1).unwrap();
+            lock.add_input_witness(index, fund.0, sig).unwrap();
+        }
+        let cancel = CancelTx::initialize(&lock, datalock, punish_lock).unwrap();
+        // The estimation assumes the largest signature for each of the three inputs
+        let vsize = lock.finalize_and_extract().unwrap().vsize() as u64;
+        assert!(vsize <= estimated && estimated - vsize <= 3);
+
+        // The cancel spends the consolidated lock output
+        assert_eq!(
+            cancel.as_partial().unsigned_tx.input[0]
+                .previous_output
+                .txid,
+            lock.as_partial().unsigned_tx.txid()
+        );
+        let mut cancel = cancel;
+        cancel
+            .as_partial_mut()
+            .set_fee(&strategy, FeePriority::Low)
+            .unwrap();
+        assert!(cancel.as_partial().validate_fee(&strategy).unwrap());
+    }
+
+    #[test]
+    fn bump_fee_with_anchor_output() {
+        let fund = keypair(1);
+        let alice = keypair(2);
+        let bob = keypair(3);
+        let wallet = keypair(4);
+        let address = |pubkey: PublicKey| {
+            bitcoin::Address::p2wpkh(&bitcoin::PublicKey::new(pubkey), bitcoin::Network::Regtest)
+                .unwrap()
+        };
+        let strategy = FeeStrategy::Fixed(SatPerVByte::from_sat(1));
+
+        let mut funding = Funding::initialize(fund.0, Network::Local).unwrap();
+        funding
+            .update(bitcoin::Transaction {
+                version: 2,
+                lock_time: 0,
+                input: vec![TxIn {
+                    previous_output: OutPoint::default(),
+                    script_sig: Script::default(),
+                    sequence: 0,
+                    witness: Witness::new(),
+                }],
+                output: vec![TxOut {
+                    value: 100_000,
+                    script_pubkey: funding.get_address().unwrap().script_pubkey(),
+                }],
+            })
+            .unwrap();
+
+        let datalock = DataLock {
+            timelock: CSVTimelock::new(10),
+            success: DoubleKeys::new(alice.0, bob.0),
+            failure: DoubleKeys::new(alice.0, bob.0),
+        };
+        let punish_lock = DataPunishableLock {
+            timelock: CSVTimelock::new(20),
+            success: DoubleKeys::new(alice.0, bob.0),
+            failure: alice.0,
+        };
+        let lock = LockTx::initialize(&funding, datalock, Amount::from_sat(99_000)).unwrap();
+
+        // The cancel is pre-signed at a low fee rate with an anchor output
+        let mut cancel = CancelTx::initialize(&lock, datalock, punish_lock).unwrap();
+        assert!(cancel.get_anchor_output().is_none());
+        cancel.add_anchor().unwrap();
+        assert!(cancel.add_anchor().is_err());
+        cancel
+            .as_partial_mut()
+            .set_fee(&strategy, FeePriority::Low)
+            .unwrap();
+        assert!(cancel.as_partial().validate_fee(&strategy).unwrap());
+        assert!(cancel.verify_template(datalock, punish_lock).is_ok());
+        let anchor = cancel.get_anchor_output().unwrap();
+        assert_eq!(anchor.out_point.vout, 1);
+        assert_eq!(anchor.tx_out, anchor::anchor_output());
+
+        // Only an anchor is accepted after the main output
+        let mut other = CancelTx::initialize(&lock, datalock, punish_lock).unwrap();
+        other
+            .add_output(TxOut {
+                value: 330,
+                script_pubkey: address(bob.0).script_pubkey(),
+            })
+            .unwrap();
+        assert!(matches!(
+            other.verify_template(datalock, punish_lock),
+            Err(crate::transaction::Error::WrongTemplate(_))
+        ));
+
+        // Refund and punish spend the main output and accept an anchor too
+        let mut refund = RefundTx::initialize(&cancel, address(bob.0)).unwrap();
+        assert_eq!(
+            refund.as_partial().unsigned_tx.input[0]
+                .previous_output
+                .vout,
+            0
+        );
+        refund.add_anchor().unwrap();
+        assert!(refund.verify_template(address(bob.0)).is_ok());
+        let mut punish_tx = PunishTx::initialize(&cancel, punish_lock, address(alice.0)).unwrap();
+        punish_tx.add_anchor().unwrap();
+
+        sign(&mut cancel, ScriptPath::Success, &alice);
+        sign(&mut cancel, ScriptPath::Success, &bob);
+        let parent = cancel.finalize_and_extract().unwrap();
+        let parent_fee = 99_000 - parent.output.iter().map(|o| o.value).sum::<u64>();
+
+        // The anchor alone does not cover the fee of the child
+        let target = SatPerVByte::from_sat(20);
+        assert!(matches!(
+            cancel.cpfp_child(vec![], address(wallet.0), target),
+            Err(crate::transaction::Error::NotEnoughAssets)
+        ));
+
+        let utxo = MetadataOutput {
+            out_point: OutPoint::new(Default::default(), 3),
+            tx_out: TxOut {
+                value: 50_000,
+                script_pubkey: address(wallet.0).script_pubkey(),
+            },
+            script_pubkey: Some(
+                bitcoin::Address::p2pkh(
+                    &bitcoin::PublicKey::new(wallet.0),
+                    bitcoin::Network::Regtest,
+                )
+                .script_pubkey(),
+            ),
+        };
+        let mut child = cancel
+            .cpfp_child(vec![utxo], address(wallet.0), target)
+            .unwrap();
+        assert_eq!(child.inputs_count(), 2);
+        assert_eq!(
+            child.as_partial().unsigned_tx.input[0].previous_output,
+            anchor.out_point
+        );
+        let estimated = estimate_vsize(child.as_partial()).unwrap();
+        let msg = child
+            .generate_input_witness_message(1, ScriptPath::Success)
+            .unwrap();
+        child
+            .add_input_witness(1, wallet.0, sign_hash(msg, &wallet.1).unwrap())
+            .unwrap();
+        let child_tx = child.finalize_and_extract().unwrap();
+        let child_vsize = child_tx.vsize() as u64;
+        assert!(child_vsize <= estimated);
+
+        // The package pays at least the target fee rate
+        let child_fee = 50_330 - child_tx.output[0].value;
+        let package_vsize = parent.vsize() as u64 + child_vsize;
+        assert!((parent_fee + child_fee) / package_vsize >= target.as_sat());
+    }
+
+    #[test]
+    fn cancel_and_punish_with_cltv() {
+        let fund = keypair(1);
+        let alice = keypair(2);
+        let bob = keypair(3);
+        let destination =
+            bitcoin::Address::p2wpkh(&bitcoin::PublicKey::new(fund.0), bitcoin::Network::Regtest)
+                .unwrap();
+
+        let mut funding = Funding::initialize(fund.0, Network::Local).unwrap();
+        let address = funding.get_address().unwrap();
+        funding
+            .update(bitcoin::Transaction {
+                version: 2,
+                lock_time: 0,
+                input: vec![],
+                output: vec![TxOut {
+                    value: 100_000,
+                    script_pubkey: address.script_pubkey(),
+                }],
+            })
+            .unwrap();
+
+        let datalock = DataLock {
+            timelock: CLTVTimelock::from_height(800_000).unwrap(),
+            success: DoubleKeys::new(alice.0, bob.0),
+            failure: DoubleKeys::new(alice.0, bob.0),
+        };
+        let punish_lock = DataPunishableLock {
+            timelock: CLTVTimelock::from_time(1_700_000_000).unwrap(),
+            success: DoubleKeys::new(alice.0, bob.0),
+            failure: alice.0,
+        };
+
+        // The punish path is verified with OP_CLTV and parsed back
+        let script = PunishLock::script(punish_lock);
+        assert!(script
+            .instructions()
+            .any(|i| i == Ok(Instruction::Op(opcodes::all::OP_CLTV))));
+        let parsed = PunishLock::from_script(&script).unwrap();
+        assert_eq!(
+            parsed.get_pubkey(SwapRole::Alice, ScriptPath::Failure),
+            Some(&alice.0)
+        );
+
+        let lock =
+            Tx::<Lock<CLTVTimelock>>::initialize(&funding, datalock, Amount::from_sat(99_000))
+                .unwrap();
+        lock.verify_template(datalock).unwrap();
+
+        // The deadline is enforced with nLockTime, relative timelocks are disabled
+        let mut cancel =
+            Tx::<Cancel<CLTVTimelock>>::initialize(&lock, datalock, punish_lock).unwrap();
+        cancel.verify_template(datalock, punish_lock).unwrap();
+        let unsigned_cancel = &cancel.as_partial().unsigned_tx;
+        assert_eq!(unsigned_cancel.lock_time, 800_000);
+        assert_eq!(unsigned_cancel.input[0].sequence, CLTVTimelock::enable());
+        sign(&mut cancel, ScriptPath::Failure, &alice);
+        sign(&mut cancel, ScriptPath::Failure, &bob);
+        cancel.finalize_and_extract().unwrap();
+
+        let later_datalock = DataLock {
+            timelock: CLTVTimelock::from_height(800_001).unwrap(),
+            ..datalock
+        };
+        assert!(cancel.verify_template(later_datalock, punish_lock).is_err());
+
+        let mut punish_tx =
+            Tx::<Punish<CLTVTimelock>>::initialize(&cancel, punish_lock, destination).unwrap();
+        assert_eq!(punish_tx.as_partial().unsigned_tx.lock_time, 1_700_000_000);
+        // The punish branch verified with OP_CLTV is estimated with Alice's signature only
+        let estimated = estimate_vsize(punish_tx.as_partial()).unwrap();
+        sign(&mut punish_tx, ScriptPath::Failure, &alice);
+        let punish = punish_tx.finalize_and_extract().unwrap();
+        let vsize = punish.vsize() as u64;
+        assert!(vsize <= estimated && estimated - vsize <= 1);
+    }
 }
@@ -0,0 +1,229 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Anchor outputs for bumping the fee of pre-signed transactions with CPFP (child pays for
+//! parent).
+//!
+//! The fee of the cancel, refund and punish transactions is fixed when they are signed during the
+//! setup, long before they can be broadcasted. An optional anchor output, a small
+//! anyone-can-spend P2WSH output, can be appended to these transactions with
+//! [`Tx::add_anchor`]. When the fee rate of the network increases the anchor is spent by a child
+//! transaction, created with [`Tx::cpfp_child`], paying for the whole package.
+
+use std::marker::PhantomData;
+
+use bitcoin::blockdata::opcodes;
+use bitcoin::blockdata::script::{Builder, Script};
+use bitcoin::blockdata::transaction::{OutPoint, TxIn, TxOut};
+use bitcoin::blockdata::witness::Witness;
+use bitcoin::util::psbt::PartiallySignedTransaction;
+use bitcoin::Address;
+
+use crate::bitcoin::fee::{estimate_vsize, SatPerVByte};
+use crate::bitcoin::segwitv0::{cancel::Cancel, punish::Punish, refund::Refund};
+use crate::bitcoin::timelock::{CSVTimelock, Timelock};
+use crate::bitcoin::transaction::{Error, MetadataOutput, SubTransaction, Tx};
+use crate::transaction::Error as FError;
+
+/// Value in satoshis of an anchor output, the dust limit of a P2WSH output.
+pub const ANCHOR_AMOUNT: u64 = 330;
+
+/// Return the witness script of anchor outputs, a single `OP_TRUE` spendable by anyone with an
+/// empty signature.
+pub fn anchor_script() -> Script {
+    Builder::new()
+        .push_opcode(opcodes::all::OP_PUSHNUM_1)
+        .into_script()
+}
+
+/// Return the anchor output appended to pre-signed transactions.
+pub fn anchor_output() -> TxOut {
+    TxOut {
+        value: ANCHOR_AMOUNT,
+        script_pubkey: anchor_script().to_v0_p2wsh(),
+    }
+}
+
+/// Verify the outputs of a template with an optional anchor: only the main output, or the main
+/// output followed by an anchor output.
+pub(crate) fn verify_outputs(outputs: &[TxOut]) -> Result<(), FError> {
+    match outputs {
+        [_] => Ok(()),
+        [_, anchor] if *anchor == anchor_output() => Ok(()),
+        [_, _] => Err(FError::WrongTemplate(
+            "Second output is not an anchor output",
+        )),
+        _ => Err(FError::WrongTemplate(
+            "Number of outputs is not 1 or 2 with an anchor",
+        )),
+    }
+}
+
+/// Pre-signed transactions accepting an anchor output.
+pub trait Anchorable: SubTransaction {}
+
+impl<T: Timelock> Anchorable for Cancel<T> {}
+impl<T: Timelock> Anchorable for Refund<T> {}
+impl<T: Timelock> Anchorable for Punish<T> {}
+
+impl<T> Tx<T>
+where
+    T: Anchorable,
+{
+    /// Append an anchor output to the transaction. The anchor must be added before setting the
+    /// fee, both participants must agree on it as it changes the signed transaction.
+    pub fn add_anchor(&mut self) -> Result<(), FError> {
+        if self.get_anchor_output().is_some() {
+            return Err(FError::WrongTemplate("Anchor output already present"));
+        }
+        self.add_output(anchor_output()).map_err(FError::new)?;
+        // Safety: an output has just been added
+        self.psbt.outputs.last_mut().unwrap().witness_script = Some(anchor_script());
+        Ok(())
+    }
+
+    /// Return the anchor output of the transaction, if any.
+    pub fn get_anchor_output(&self) -> Option<MetadataOutput> {
+        let txid = self.psbt.unsigned_tx.txid();
+        self.psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .enumerate()
+            .skip(1)
+            .find(|(_, tx_out)| **tx_out == anchor_output())
+            .map(|(vout, tx_out)| MetadataOutput {
+                out_point: OutPoint::new(txid, vout as u32),
+                tx_out: tx_out.clone(),
+                script_pubkey: Some(anchor_script()),
+            })
+    }
+
+    /// Create the child transaction spending the anchor output such that the package, this
+    /// transaction and its child, pays at least the `target` fee rate.
+    ///
+    /// The anchor alone rarely covers the fee, additional P2WPKH `utxos` from the wallet, with
+    /// their script code set as in [`Funding`], are consumed and the remaining funds are sent to
+    /// the `change` address. The anchor input does not require a signature, the wallet inputs
+    /// are signed with [`Witnessable::generate_input_witness_message`] and
+    /// [`Witnessable::add_input_witness`] at their index, starting at 1.
+    ///
+    /// [`Funding`]: crate::bitcoin::segwitv0::FundingTx
+    /// [`Witnessable::generate_input_witness_message`]: crate::transaction::Witnessable::generate_input_witness_message
+    /// [`Witnessable::add_input_witness`]: crate::transaction::Witnessable::add_input_witness
+    pub fn cpfp_child(
+        &self,
+        utxos: Vec<MetadataOutput>,
+        change: Address,
+        target: SatPerVByte,
+    ) -> Result<Tx<Child>, FError> {
+        let anchor = self.get_anchor_output().ok_or(FError::MissingUTXO)?;
+
+        // Fee already paid by the parent, estimated on its finalized virtual size
+        let parent_input = self
+            .psbt
+            .inputs
+            .iter()
+            .map(|input| input.witness_utxo.as_ref().map(|tx_out| tx_out.value))
+            .sum::<Option<u64>>()
+            .ok_or(FError::MissingUTXO)?;
+        let parent_output = self
+            .psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .map(|tx_out| tx_out.value)
+            .sum::<u64>();
+        let parent_fee = parent_input
+            .checked_sub(parent_output)
+            .ok_or(FError::NotEnoughAssets)?;
+        let parent_vsize = estimate_vsize(&self.psbt).map_err(FError::new)?;
+
+        let outputs: Vec<MetadataOutput> = std::iter::once(anchor).chain(utxos).collect();
+        let available = outputs.iter().map(|o| o.tx_out.value).sum::<u64>();
+
+        let unsigned_tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: outputs
+                .iter()
+                .map(|o| TxIn {
+                    previous_output: o.out_point,
+                    script_sig: bitcoin::Script::default(),
+                    sequence: CSVTimelock::disable(),
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: vec![TxOut {
+                value: available,
+                script_pubkey: change.script_pubkey(),
+            }],
+        };
+
+        let mut psbt =
+            PartiallySignedTransaction::from_unsigned_tx(unsigned_tx).map_err(Error::from)?;
+
+        // Set the inputs witness data and sighash type
+        for (input, output_metadata) in psbt.inputs.iter_mut().zip(outputs) {
+            input.witness_utxo = Some(output_metadata.tx_out);
+            input.witness_script = output_metadata.script_pubkey;
+        }
+
+        // The child pays for the missing fee of the package and at least for itself
+        let child_vsize = estimate_vsize(&psbt).map_err(FError::new)?;
+        let rate = target.as_sat();
+        let fee = (rate * (parent_vsize + child_vsize))
+            .saturating_sub(parent_fee)
+            .max(rate * child_vsize);
+
+        let value = available
+            .checked_sub(fee)
+            .filter(|value| *value >= change.script_pubkey().dust_value().as_sat())
+            .ok_or(FError::NotEnoughAssets)?;
+        psbt.unsigned_tx.output[0].value = value;
+
+        Ok(Tx {
+            psbt,
+            _t: PhantomData,
+        })
+    }
+}
+
+/// Child transaction spending an anchor output and wallet P2WPKH outputs to bump the fee of its
+/// parent.
+#[derive(Debug)]
+pub struct Child;
+
+impl SubTransaction for Child {
+    fn finalize(psbt: &mut PartiallySignedTransaction) -> Result<(), FError> {
+        let anchor = anchor_script();
+        for input in psbt.inputs.iter_mut() {
+            let witness = match &input.witness_script {
+                Some(script) if *script == anchor => vec![script.to_bytes()],
+                _ => {
+                    let (pubkey, full_sig) = input
+                        .partial_sigs
+                        .iter()
+                        .next()
+                        .ok_or(FError::MissingSignature)?;
+                    vec![full_sig.to_vec(), pubkey.to_bytes()]
+                }
+            };
+            input.final_script_witness = Some(Witness::from_vec(witness));
+        }
+        Ok(())
+    }
+}
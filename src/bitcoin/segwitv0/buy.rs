@@ -32,13 +32,13 @@ use crate::transaction::{Buyable, Error as FError, Lockable};
 
 use crate::bitcoin::segwitv0::CoopLock;
 use crate::bitcoin::segwitv0::Sha256dHash;
-use crate::bitcoin::timelock::CSVTimelock;
+use crate::bitcoin::timelock::{CSVTimelock, Timelock};
 use crate::bitcoin::transaction::{Error, MetadataOutput, SubTransaction, Tx};
 
 #[derive(Debug)]
-pub struct Buy;
+pub struct Buy<T = CSVTimelock>(PhantomData<T>);
 
-impl SubTransaction for Buy {
+impl<T: Timelock> SubTransaction for Buy<T> {
     fn finalize(psbt: &mut PartiallySignedTransaction) -> Result<(), FError> {
         let script = psbt.inputs[0]
             .witness_script
@@ -71,18 +71,18 @@ impl SubTransaction for Buy {
     }
 }
 
-impl
+impl<T: Timelock>
     Buyable<
         Address,
         Transaction,
         PartiallySignedTransaction,
         MetadataOutput,
         Amount,
-        CSVTimelock,
+        T,
         Sha256dHash,
         PublicKey,
         Signature,
-    > for Tx<Buy>
+    > for Tx<Buy<T>>
 {
     fn initialize(
         prev: &impl Lockable<
@@ -91,12 +91,12 @@ impl
             PartiallySignedTransaction,
             MetadataOutput,
             Amount,
-            CSVTimelock,
+            T,
             Sha256dHash,
             PublicKey,
             Signature,
         >,
-        _lock: script::DataLock<CSVTimelock, PublicKey>,
+        _lock: script::DataLock<T, PublicKey>,
         destination_target: Address,
     ) -> Result<Self, FError> {
         let output_metadata = prev.get_consumable_output()?;
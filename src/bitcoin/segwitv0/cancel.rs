@@ -29,15 +29,16 @@ use crate::role::SwapRole;
 use crate::script;
 use crate::transaction::{Cancelable, Error as FError, Lockable};
 
+use crate::bitcoin::segwitv0::anchor;
 use crate::bitcoin::segwitv0::Sha256dHash;
 use crate::bitcoin::segwitv0::{CoopLock, PunishLock};
-use crate::bitcoin::timelock::CSVTimelock;
+use crate::bitcoin::timelock::{CSVTimelock, Timelock};
 use crate::bitcoin::transaction::{Error, MetadataOutput, SubTransaction, Tx};
 
 #[derive(Debug)]
-pub struct Cancel;
+pub struct Cancel<T = CSVTimelock>(PhantomData<T>);
 
-impl SubTransaction for Cancel {
+impl<T: Timelock> SubTransaction for Cancel<T> {
     fn finalize(psbt: &mut PartiallySignedTransaction) -> Result<(), FError> {
         let script = psbt.inputs[0]
             .witness_script
@@ -70,18 +71,18 @@ impl SubTransaction for Cancel {
     }
 }
 
-impl
+impl<T: Timelock>
     Cancelable<
         Address,
         Transaction,
         PartiallySignedTransaction,
         MetadataOutput,
         Amount,
-        CSVTimelock,
+        T,
         Sha256dHash,
         PublicKey,
         Signature,
-    > for Tx<Cancel>
+    > for Tx<Cancel<T>>
 {
     fn initialize(
         prev: &impl Lockable<
@@ -90,24 +91,24 @@ impl
             PartiallySignedTransaction,
             MetadataOutput,
             Amount,
-            CSVTimelock,
+            T,
             Sha256dHash,
             PublicKey,
             Signature,
         >,
-        lock: script::DataLock<CSVTimelock, PublicKey>,
-        punish_lock: script::DataPunishableLock<CSVTimelock, PublicKey>,
+        lock: script::DataLock<T, PublicKey>,
+        punish_lock: script::DataPunishableLock<T, PublicKey>,
     ) -> Result<Self, FError> {
         let script = PunishLock::script(punish_lock);
         let output_metadata = prev.get_consumable_output()?;
 
         let unsigned_tx = bitcoin::Transaction {
             version: 2,
-            lock_time: 0,
+            lock_time: lock.timelock.lock_time(),
             input: vec![TxIn {
                 previous_output: output_metadata.out_point,
                 script_sig: bitcoin::Script::default(),
-                sequence: lock.timelock.as_u32(),
+                sequence: lock.timelock.sequence(),
                 witness: Witness::new(),
             }],
             output: vec![TxOut {
@@ -134,24 +135,25 @@ impl
 
     fn verify_template(
         &self,
-        lock: script::DataLock<CSVTimelock, PublicKey>,
-        punish_lock: script::DataPunishableLock<CSVTimelock, PublicKey>,
+        lock: script::DataLock<T, PublicKey>,
+        punish_lock: script::DataPunishableLock<T, PublicKey>,
     ) -> Result<(), FError> {
         (self.psbt.unsigned_tx.version == 2)
             .then(|| 0)
             .ok_or(FError::WrongTemplate("Tx version is not 2"))?;
-        (self.psbt.unsigned_tx.lock_time == 0)
+        (self.psbt.unsigned_tx.lock_time == lock.timelock.lock_time())
             .then(|| 0)
-            .ok_or(FError::WrongTemplate("LockTime is not set to 0"))?;
+            .ok_or(FError::WrongTemplate(
+                "LockTime is not set correctly for timelock",
+            ))?;
         (self.psbt.unsigned_tx.input.len() == 1)
             .then(|| 0)
             .ok_or(FError::WrongTemplate("Number of inputs is not 1"))?;
-        (self.psbt.unsigned_tx.output.len() == 1)
-            .then(|| 0)
-            .ok_or(FError::WrongTemplate("Number of outputs is not 1"))?;
+        // An anchor output can follow the main output for fee bumping
+        anchor::verify_outputs(&self.psbt.unsigned_tx.output)?;
 
         let txin = &self.psbt.unsigned_tx.input[0];
-        (txin.sequence == lock.timelock.as_u32())
+        (txin.sequence == lock.timelock.sequence())
             .then(|| 0)
             .ok_or(FError::WrongTemplate(
                 "Sequence is not set correctly for timelock",
@@ -28,16 +28,19 @@ use crate::transaction::{Error as FError, Fundable, Linkable};
 use crate::bitcoin::transaction::{Error, MetadataOutput};
 
 /// Manages the steps to handle on-chain funding. Receives the public key derived from the key
-/// manager, receives the network of operations and the raw funding transaction when seen.
+/// manager, receives the network of operations and the raw funding transactions when seen. The
+/// funding address can receive several payments, all of them can be consumed by the lock.
 #[derive(Debug, Clone)]
 pub struct Funding {
     pubkey: Option<PublicKey>,
     network: Option<Network>,
-    seen_tx: Option<Transaction>,
+    seen_txs: Vec<Transaction>,
 }
 
-impl Linkable<MetadataOutput> for Funding {
-    fn get_consumable_output(&self) -> Result<MetadataOutput, FError> {
+impl Funding {
+    /// Return all the outputs paying to the funding address in the seen transactions, in the
+    /// order they were seen.
+    fn consumable_outputs(&self) -> Result<Vec<MetadataOutput>, FError> {
         // Create a **COMPRESSED** ECDSA public key.
         let pubkey = match self.pubkey {
             Some(pubkey) => bitcoin::util::key::PublicKey::new(pubkey),
@@ -64,22 +67,42 @@ impl Linkable<MetadataOutput> for Funding {
         // uncompressed public key is provided, but we construct the public key and we
         // ensure it is compressed.
         let script_pubkey = script_pubkey.unwrap().script_pubkey();
+        let script_code = Address::p2pkh(&pubkey, network).script_pubkey();
+        let (script_pubkey, script_code) = (&script_pubkey, &script_code);
 
-        match &self.seen_tx {
-            Some(t) => t
-                .output
-                .iter()
-                .enumerate()
-                .find(|(_, tx_out)| tx_out.script_pubkey == script_pubkey)
-                .map(|(ix, tx_out)| MetadataOutput {
-                    out_point: OutPoint::new(t.txid(), ix as u32),
-                    tx_out: tx_out.clone(),
-                    script_pubkey: Some(Address::p2pkh(&pubkey, network).script_pubkey()),
-                })
-                .ok_or(FError::MissingUTXO),
-            // The transaction has not been see yet, cannot infer the UTXO
-            None => Err(FError::MissingOnchainTransaction),
+        // The transaction has not been see yet, cannot infer the UTXO
+        if self.seen_txs.is_empty() {
+            return Err(FError::MissingOnchainTransaction);
         }
+
+        let outputs: Vec<MetadataOutput> = self
+            .seen_txs
+            .iter()
+            .flat_map(|t| {
+                let txid = t.txid();
+                t.output
+                    .iter()
+                    .enumerate()
+                    .filter(move |(_, tx_out)| &tx_out.script_pubkey == script_pubkey)
+                    .map(move |(ix, tx_out)| MetadataOutput {
+                        out_point: OutPoint::new(txid, ix as u32),
+                        tx_out: tx_out.clone(),
+                        script_pubkey: Some(script_code.clone()),
+                    })
+            })
+            .collect();
+
+        match outputs.is_empty() {
+            true => Err(FError::MissingUTXO),
+            false => Ok(outputs),
+        }
+    }
+}
+
+impl Linkable<MetadataOutput> for Funding {
+    fn get_consumable_output(&self) -> Result<MetadataOutput, FError> {
+        // Safety: `consumable_outputs` never returns an empty list
+        Ok(self.consumable_outputs()?.remove(0))
     }
 }
 
@@ -88,7 +111,7 @@ impl Fundable<Transaction, MetadataOutput, Address, PublicKey> for Funding {
         Ok(Funding {
             pubkey: Some(pubkey),
             network: Some(network),
-            seen_tx: None,
+            seen_txs: vec![],
         })
     }
 
@@ -113,7 +136,10 @@ impl Fundable<Transaction, MetadataOutput, Address, PublicKey> for Funding {
     }
 
     fn update(&mut self, tx: Transaction) -> Result<(), FError> {
-        self.seen_tx = Some(tx);
+        let txid = tx.txid();
+        if !self.seen_txs.iter().any(|t| t.txid() == txid) {
+            self.seen_txs.push(tx);
+        }
         Ok(())
     }
 
@@ -121,12 +147,16 @@ impl Fundable<Transaction, MetadataOutput, Address, PublicKey> for Funding {
         Ok(Self {
             pubkey: None,
             network: None,
-            seen_tx: Some(tx),
+            seen_txs: vec![tx],
         })
     }
 
     fn was_seen(&self) -> bool {
-        self.seen_tx.is_some()
+        !self.seen_txs.is_empty()
+    }
+
+    fn get_consumable_outputs(&self) -> Result<Vec<MetadataOutput>, FError> {
+        self.consumable_outputs()
     }
 }
 
@@ -134,7 +164,12 @@ impl Encodable for Funding {
     fn consensus_encode<W: std::io::Write>(&self, writer: &mut W) -> Result<usize, std::io::Error> {
         let mut len = self.pubkey.as_canonical_bytes().consensus_encode(writer)?;
         len += self.network.consensus_encode(writer)?;
-        len += self.seen_tx.as_canonical_bytes().consensus_encode(writer)?;
+        len += self
+            .seen_txs
+            .iter()
+            .map(CanonicalBytes::as_canonical_bytes)
+            .collect::<Vec<_>>()
+            .consensus_encode(writer)?;
         Ok(len)
     }
 }
@@ -144,7 +179,10 @@ impl Decodable for Funding {
         Ok(Funding {
             pubkey: Option::<PublicKey>::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
             network: Decodable::consensus_decode(d)?,
-            seen_tx: Option::<Transaction>::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            seen_txs: Vec::<Vec<u8>>::consensus_decode(d)?
+                .iter()
+                .map(|bytes| Transaction::from_canonical_bytes(bytes))
+                .collect::<Result<_, _>>()?,
         })
     }
 }
@@ -30,61 +30,87 @@ use crate::transaction::{Error as FError, Fundable, Lockable};
 
 use crate::bitcoin::segwitv0::CoopLock;
 use crate::bitcoin::segwitv0::Sha256dHash;
-use crate::bitcoin::timelock::CSVTimelock;
+use crate::bitcoin::timelock::{CSVTimelock, Timelock};
 use crate::bitcoin::transaction::{Error, MetadataOutput, SubTransaction, Tx};
 
 #[derive(Debug)]
-pub struct Lock;
+pub struct Lock<T = CSVTimelock>(PhantomData<T>);
 
-impl SubTransaction for Lock {
+impl<T: Timelock> SubTransaction for Lock<T> {
     fn finalize(psbt: &mut PartiallySignedTransaction) -> Result<(), FError> {
-        let (pubkey, full_sig) = psbt.inputs[0]
-            .partial_sigs
-            .iter()
-            .next()
-            .ok_or(FError::MissingSignature)?;
-        psbt.inputs[0].final_script_witness = Some(Witness::from_vec(vec![
-            full_sig.to_vec(),
-            pubkey.to_bytes(),
-        ]));
+        // Each input consumes a funding output and is signed separately
+        for input in psbt.inputs.iter_mut() {
+            let (pubkey, full_sig) = input
+                .partial_sigs
+                .iter()
+                .next()
+                .ok_or(FError::MissingSignature)?;
+            input.final_script_witness = Some(Witness::from_vec(vec![
+                full_sig.to_vec(),
+                pubkey.to_bytes(),
+            ]));
+        }
         Ok(())
     }
 }
 
-impl
+impl<T: Timelock> Tx<Lock<T>> {
+    /// Return the sum of the funding outputs consumed by the lock inputs, fails if an input is
+    /// missing its witness UTXO.
+    fn input_amount(&self) -> Result<u64, FError> {
+        self.psbt.inputs.iter().try_fold(0u64, |acc, input| {
+            let value = input
+                .witness_utxo
+                .as_ref()
+                .ok_or(FError::MissingUTXO)?
+                .value;
+            acc.checked_add(value).ok_or(FError::NotEnoughAssets)
+        })
+    }
+}
+
+impl<T: Timelock>
     Lockable<
         Address,
         Transaction,
         PartiallySignedTransaction,
         MetadataOutput,
         Amount,
-        CSVTimelock,
+        T,
         Sha256dHash,
         PublicKey,
         Signature,
-    > for Tx<Lock>
+    > for Tx<Lock<T>>
 {
     fn initialize(
         prev: &impl Fundable<Transaction, MetadataOutput, Address, PublicKey>,
-        lock: script::DataLock<CSVTimelock, PublicKey>,
+        lock: script::DataLock<T, PublicKey>,
         target_amount: Amount,
     ) -> Result<Self, FError> {
         let script = CoopLock::script(lock);
-        let output_metadata = prev.get_consumable_output()?;
+        // Consolidate all the outputs received on the funding address
+        let outputs = prev.get_consumable_outputs()?;
 
-        if output_metadata.tx_out.value < target_amount.as_sat() {
+        let input_amount = outputs
+            .iter()
+            .try_fold(0u64, |acc, o| acc.checked_add(o.tx_out.value))
+            .ok_or(FError::NotEnoughAssets)?;
+        if input_amount < target_amount.as_sat() {
             return Err(FError::NotEnoughAssets);
         }
 
         let unsigned_tx = bitcoin::Transaction {
             version: 2,
             lock_time: 0,
-            input: vec![TxIn {
-                previous_output: output_metadata.out_point,
-                script_sig: bitcoin::Script::default(),
-                sequence: CSVTimelock::disable(),
-                witness: Witness::new(),
-            }],
+            input: outputs
+                .iter()
+                .map(|o| TxIn {
+                    previous_output: o.out_point,
+                    script_sig: bitcoin::Script::default(),
+                    sequence: CSVTimelock::disable(),
+                    witness: Witness::new(),
+                })
+                .collect(),
             output: vec![TxOut {
                 value: target_amount.as_sat(),
                 script_pubkey: script.to_v0_p2wsh(),
@@ -94,9 +120,11 @@ impl
         let mut psbt =
             PartiallySignedTransaction::from_unsigned_tx(unsigned_tx).map_err(Error::from)?;
 
-        // Set the input witness data and sighash type
-        psbt.inputs[0].witness_utxo = Some(output_metadata.tx_out);
-        psbt.inputs[0].witness_script = output_metadata.script_pubkey;
+        // Set the inputs witness data and sighash type
+        for (input, output_metadata) in psbt.inputs.iter_mut().zip(outputs) {
+            input.witness_utxo = Some(output_metadata.tx_out);
+            input.witness_script = output_metadata.script_pubkey;
+        }
 
         // Set the script witness of the output
         psbt.outputs[0].witness_script = Some(script);
@@ -107,25 +135,26 @@ impl
         })
     }
 
-    fn verify_template(
-        &self,
-        lock: script::DataLock<CSVTimelock, PublicKey>,
-    ) -> Result<(), FError> {
+    fn verify_template(&self, lock: script::DataLock<T, PublicKey>) -> Result<(), FError> {
         (self.psbt.unsigned_tx.version == 2)
             .then(|| 0)
             .ok_or(FError::WrongTemplate("Tx version is not 2"))?;
         (self.psbt.unsigned_tx.lock_time == 0)
             .then(|| 0)
             .ok_or(FError::WrongTemplate("LockTime is not set to 0"))?;
-        (self.psbt.unsigned_tx.input.len() == 1)
+        (!self.psbt.unsigned_tx.input.is_empty())
             .then(|| 0)
-            .ok_or(FError::WrongTemplate("Number of inputs is not 1"))?;
-        (self.psbt.unsigned_tx.output.len() == 1)
+            .ok_or(FError::WrongTemplate("Missing funding input"))?;
+        // Additional outputs, e.g. change, are allowed after the lock output
+        (!self.psbt.unsigned_tx.output.is_empty())
             .then(|| 0)
-            .ok_or(FError::WrongTemplate("Number of outputs is not 1"))?;
+            .ok_or(FError::WrongTemplate("Missing lock output"))?;
 
-        let txin = &self.psbt.unsigned_tx.input[0];
-        (txin.sequence == CSVTimelock::disable())
+        self.psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .all(|txin| txin.sequence == CSVTimelock::disable())
             .then(|| 0)
             .ok_or(FError::WrongTemplate("Sequence timelock is not disabled"))?;
 
@@ -137,4 +166,15 @@ impl
 
         Ok(())
     }
+
+    fn verify_target_amount(&self, target_amount: Amount) -> Result<(), FError> {
+        // The consolidated funding outputs must cover the locked amount
+        if self.input_amount()? < target_amount.as_sat() {
+            return Err(FError::NotEnoughAssets);
+        }
+        match self.psbt.unsigned_tx.output[0].value == target_amount.as_sat() {
+            true => Ok(()),
+            false => Err(FError::InvalidTargetAmount),
+        }
+    }
 }
@@ -32,13 +32,13 @@ use crate::transaction::{Cancelable, Error, Punishable};
 
 use crate::bitcoin::segwitv0::PunishLock;
 use crate::bitcoin::segwitv0::Sha256dHash;
-use crate::bitcoin::timelock::CSVTimelock;
+use crate::bitcoin::timelock::{CSVTimelock, Timelock};
 use crate::bitcoin::transaction::{self, MetadataOutput, SubTransaction, Tx};
 
 #[derive(Debug)]
-pub struct Punish;
+pub struct Punish<T = CSVTimelock>(PhantomData<T>);
 
-impl SubTransaction for Punish {
+impl<T: Timelock> SubTransaction for Punish<T> {
     fn finalize(psbt: &mut PartiallySignedTransaction) -> Result<(), Error> {
         let script = psbt.inputs[0]
             .witness_script
@@ -65,18 +65,18 @@ impl SubTransaction for Punish {
     }
 }
 
-impl
+impl<T: Timelock>
     Punishable<
         Address,
         Transaction,
         PartiallySignedTransaction,
         MetadataOutput,
         Amount,
-        CSVTimelock,
+        T,
         Sha256dHash,
         PublicKey,
         Signature,
-    > for Tx<Punish>
+    > for Tx<Punish<T>>
 {
     fn initialize(
         prev: &impl Cancelable<
@@ -85,23 +85,23 @@ impl
             PartiallySignedTransaction,
             MetadataOutput,
             Amount,
-            CSVTimelock,
+            T,
             Sha256dHash,
             PublicKey,
             Signature,
         >,
-        punish_lock: script::DataPunishableLock<CSVTimelock, PublicKey>,
+        punish_lock: script::DataPunishableLock<T, PublicKey>,
         destination_target: Address,
     ) -> Result<Self, Error> {
         let output_metadata = prev.get_consumable_output()?;
 
         let unsigned_tx = bitcoin::Transaction {
             version: 2,
-            lock_time: 0,
+            lock_time: punish_lock.timelock.lock_time(),
             input: vec![TxIn {
                 previous_output: output_metadata.out_point,
                 script_sig: bitcoin::Script::default(),
-                sequence: punish_lock.timelock.as_u32(),
+                sequence: punish_lock.timelock.sequence(),
                 witness: Witness::new(),
             }],
             output: vec![TxOut {
@@ -30,15 +30,16 @@ use crate::role::SwapRole;
 use crate::script::ScriptPath;
 use crate::transaction::{Cancelable, Error as FError, Refundable};
 
+use crate::bitcoin::segwitv0::anchor;
 use crate::bitcoin::segwitv0::PunishLock;
 use crate::bitcoin::segwitv0::Sha256dHash;
-use crate::bitcoin::timelock::CSVTimelock;
+use crate::bitcoin::timelock::{CSVTimelock, Timelock};
 use crate::bitcoin::transaction::{Error, MetadataOutput, SubTransaction, Tx};
 
 #[derive(Debug)]
-pub struct Refund;
+pub struct Refund<T = CSVTimelock>(PhantomData<T>);
 
-impl SubTransaction for Refund {
+impl<T: Timelock> SubTransaction for Refund<T> {
     fn finalize(psbt: &mut PartiallySignedTransaction) -> Result<(), FError> {
         let script = psbt.inputs[0]
             .witness_script
@@ -76,18 +77,18 @@ impl SubTransaction for Refund {
     }
 }
 
-impl
+impl<T: Timelock>
     Refundable<
         Address,
         Transaction,
         PartiallySignedTransaction,
         MetadataOutput,
         Amount,
-        CSVTimelock,
+        T,
         Sha256dHash,
         PublicKey,
         Signature,
-    > for Tx<Refund>
+    > for Tx<Refund<T>>
 {
     fn initialize(
         prev: &impl Cancelable<
@@ -96,7 +97,7 @@ impl
             PartiallySignedTransaction,
             MetadataOutput,
             Amount,
-            CSVTimelock,
+            T,
             Sha256dHash,
             PublicKey,
             Signature,
@@ -143,9 +144,8 @@ impl
         (self.psbt.unsigned_tx.input.len() == 1)
             .then(|| 0)
             .ok_or(FError::WrongTemplate("Number of inputs is not 1"))?;
-        (self.psbt.unsigned_tx.output.len() == 1)
-            .then(|| 0)
-            .ok_or(FError::WrongTemplate("Number of outputs is not 1"))?;
+        // An anchor output can follow the main output for fee bumping
+        anchor::verify_outputs(&self.psbt.unsigned_tx.output)?;
 
         let txin = &self.psbt.unsigned_tx.input[0];
         (txin.sequence == 0)
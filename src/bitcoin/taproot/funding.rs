@@ -0,0 +1,126 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Implementation for handeling the funding transaction on-chain with a SegWit v1 output.
+
+use bitcoin::blockdata::transaction::{OutPoint, Transaction};
+use bitcoin::secp256k1::XOnlyPublicKey;
+use bitcoin::util::schnorr::TweakedPublicKey;
+use bitcoin::Address;
+
+use crate::blockchain::Network;
+use crate::consensus::{CanonicalBytes, Decodable, Encodable};
+use crate::transaction::{Error as FError, Fundable, Linkable};
+
+use crate::bitcoin::transaction::MetadataOutput;
+
+/// Manages the steps to handle on-chain funding. Receives the public key derived from the key
+/// manager, receives the network of operations and the raw funding transaction when seen.
+///
+/// The funding output is a key-path only SegWit v1 output where the funding public key is used
+/// directly as the output key. No script path is committed, so the key is not tweaked and can be
+/// used as is to sign the lock transaction.
+#[derive(Debug, Clone)]
+pub struct Funding {
+    pubkey: Option<XOnlyPublicKey>,
+    network: Option<Network>,
+    seen_tx: Option<Transaction>,
+}
+
+impl Funding {
+    fn address(pubkey: XOnlyPublicKey, network: Network) -> Address {
+        Address::p2tr_tweaked(
+            TweakedPublicKey::dangerous_assume_tweaked(pubkey),
+            network.into(),
+        )
+    }
+}
+
+impl Linkable<MetadataOutput> for Funding {
+    fn get_consumable_output(&self) -> Result<MetadataOutput, FError> {
+        let pubkey = self.pubkey.ok_or(FError::MissingPublicKey)?;
+        let network = self.network.ok_or(FError::MissingNetwork)?;
+        let script_pubkey = Self::address(pubkey, network).script_pubkey();
+
+        match &self.seen_tx {
+            Some(t) => t
+                .output
+                .iter()
+                .enumerate()
+                .find(|(_, tx_out)| tx_out.script_pubkey == script_pubkey)
+                .map(|(ix, tx_out)| MetadataOutput {
+                    out_point: OutPoint::new(t.txid(), ix as u32),
+                    tx_out: tx_out.clone(),
+                    script_pubkey: None,
+                })
+                .ok_or(FError::MissingUTXO),
+            // The transaction has not been see yet, cannot infer the UTXO
+            None => Err(FError::MissingOnchainTransaction),
+        }
+    }
+}
+
+impl Fundable<Transaction, MetadataOutput, Address, XOnlyPublicKey> for Funding {
+    fn initialize(pubkey: XOnlyPublicKey, network: Network) -> Result<Self, FError> {
+        Ok(Funding {
+            pubkey: Some(pubkey),
+            network: Some(network),
+            seen_tx: None,
+        })
+    }
+
+    fn get_address(&self) -> Result<Address, FError> {
+        let pubkey = self.pubkey.ok_or(FError::MissingPublicKey)?;
+        let network = self.network.ok_or(FError::MissingNetwork)?;
+        Ok(Self::address(pubkey, network))
+    }
+
+    fn update(&mut self, tx: Transaction) -> Result<(), FError> {
+        self.seen_tx = Some(tx);
+        Ok(())
+    }
+
+    fn raw(tx: Transaction) -> Result<Self, FError> {
+        Ok(Self {
+            pubkey: None,
+            network: None,
+            seen_tx: Some(tx),
+        })
+    }
+
+    fn was_seen(&self) -> bool {
+        self.seen_tx.is_some()
+    }
+}
+
+impl Encodable for Funding {
+    fn consensus_encode<W: std::io::Write>(&self, writer: &mut W) -> Result<usize, std::io::Error> {
+        let mut len = self.pubkey.as_canonical_bytes().consensus_encode(writer)?;
+        len += self.network.consensus_encode(writer)?;
+        len += self.seen_tx.as_canonical_bytes().consensus_encode(writer)?;
+        Ok(len)
+    }
+}
+
+impl Decodable for Funding {
+    fn consensus_decode<D: std::io::Read>(d: &mut D) -> Result<Self, crate::consensus::Error> {
+        Ok(Funding {
+            pubkey: Option::<XOnlyPublicKey>::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            network: Decodable::consensus_decode(d)?,
+            seen_tx: Option::<Transaction>::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+        })
+    }
+}
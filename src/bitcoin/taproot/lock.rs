@@ -0,0 +1,133 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+use std::marker::PhantomData;
+
+use bitcoin::blockdata::transaction::{TxIn, TxOut};
+use bitcoin::blockdata::witness::Witness;
+use bitcoin::secp256k1::schnorr::Signature;
+use bitcoin::secp256k1::XOnlyPublicKey;
+use bitcoin::util::psbt::PartiallySignedTransaction;
+use bitcoin::util::taproot::TapSighashHash;
+use bitcoin::Address;
+use bitcoin::Amount;
+use bitcoin::Transaction;
+
+use crate::script;
+use crate::transaction::{Error as FError, Fundable, Lockable};
+
+use crate::bitcoin::taproot::{set_output_tree, CoopLock, TaprootSubTransaction};
+use crate::bitcoin::timelock::CSVTimelock;
+use crate::bitcoin::transaction::{Error, MetadataOutput, SubTransaction, Tx};
+
+#[derive(Debug)]
+pub struct Lock;
+
+impl SubTransaction for Lock {
+    fn finalize(psbt: &mut PartiallySignedTransaction) -> Result<(), FError> {
+        let sig = psbt.inputs[0].tap_key_sig.ok_or(FError::MissingSignature)?;
+        psbt.inputs[0].final_script_witness = Some(Witness::from_vec(vec![sig.to_vec()]));
+        Ok(())
+    }
+}
+
+impl TaprootSubTransaction for Lock {}
+
+impl
+    Lockable<
+        Address,
+        Transaction,
+        PartiallySignedTransaction,
+        MetadataOutput,
+        Amount,
+        CSVTimelock,
+        TapSighashHash,
+        XOnlyPublicKey,
+        Signature,
+    > for Tx<Lock>
+{
+    fn initialize(
+        prev: &impl Fundable<Transaction, MetadataOutput, Address, XOnlyPublicKey>,
+        lock: script::DataLock<CSVTimelock, XOnlyPublicKey>,
+        target_amount: Amount,
+    ) -> Result<Self, FError> {
+        let output_metadata = prev.get_consumable_output()?;
+
+        if output_metadata.tx_out.value < target_amount.as_sat() {
+            return Err(FError::NotEnoughAssets);
+        }
+
+        let unsigned_tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: output_metadata.out_point,
+                script_sig: bitcoin::Script::default(),
+                sequence: CSVTimelock::disable(),
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: target_amount.as_sat(),
+                script_pubkey: CoopLock::v1_p2tr(lock),
+            }],
+        };
+
+        let mut psbt =
+            PartiallySignedTransaction::from_unsigned_tx(unsigned_tx).map_err(Error::from)?;
+
+        // Set the input witness data, the funding output is spent with the key path
+        psbt.inputs[0].witness_utxo = Some(output_metadata.tx_out);
+
+        // Set the tapscript tree of the output
+        set_output_tree(&mut psbt.outputs[0], CoopLock::tap_tree(lock));
+
+        Ok(Tx {
+            psbt,
+            _t: PhantomData,
+        })
+    }
+
+    fn verify_template(
+        &self,
+        lock: script::DataLock<CSVTimelock, XOnlyPublicKey>,
+    ) -> Result<(), FError> {
+        (self.psbt.unsigned_tx.version == 2)
+            .then(|| 0)
+            .ok_or(FError::WrongTemplate("Tx version is not 2"))?;
+        (self.psbt.unsigned_tx.lock_time == 0)
+            .then(|| 0)
+            .ok_or(FError::WrongTemplate("LockTime is not set to 0"))?;
+        (self.psbt.unsigned_tx.input.len() == 1)
+            .then(|| 0)
+            .ok_or(FError::WrongTemplate("Number of inputs is not 1"))?;
+        (self.psbt.unsigned_tx.output.len() == 1)
+            .then(|| 0)
+            .ok_or(FError::WrongTemplate("Number of outputs is not 1"))?;
+
+        let txin = &self.psbt.unsigned_tx.input[0];
+        (txin.sequence == CSVTimelock::disable())
+            .then(|| 0)
+            .ok_or(FError::WrongTemplate("Sequence timelock is not disabled"))?;
+
+        let txout = &self.psbt.unsigned_tx.output[0];
+        let script_pubkey = CoopLock::v1_p2tr(lock);
+        (txout.script_pubkey == script_pubkey)
+            .then(|| 0)
+            .ok_or(FError::WrongTemplate("Script pubkey does not match"))?;
+
+        Ok(())
+    }
+}
@@ -21,12 +21,69 @@ use std::convert::TryFrom;
 use std::fmt;
 use std::str::FromStr;
 
+use crate::bitcoin::taproot::{
+    buy::Buy, cancel::Cancel, funding::Funding, lock::Lock, punish::Punish, refund::Refund,
+};
+use crate::bitcoin::timelock::CSVTimelock;
+use crate::bitcoin::transaction::{MetadataOutput, SubTransaction, Tx};
 use crate::bitcoin::{Bitcoin, BitcoinTaproot, Btc, Strategy};
+use crate::blockchain::Transactions;
 use crate::consensus::{self, CanonicalBytes};
 use crate::crypto::{DeriveKeys, SharedKeyId};
-//use crate::role::Arbitrating;
+use crate::role::SwapRole;
+use crate::script::{DataLock, DataPunishableLock, DoubleKeys, ScriptPath};
+use crate::transaction::{Error as FError, Witnessable};
 
-use bitcoin::secp256k1::{schnorr::Signature, KeyPair, XOnlyPublicKey};
+use bitcoin::blockdata::opcodes;
+use bitcoin::blockdata::script::{Builder, Instruction, Script};
+use bitcoin::blockdata::witness::Witness;
+use bitcoin::secp256k1::{schnorr::Signature, KeyPair, Message, Secp256k1, XOnlyPublicKey};
+use bitcoin::util::psbt::{self, PartiallySignedTransaction, TapTree};
+use bitcoin::util::schnorr::SchnorrSig;
+use bitcoin::util::sighash::{Prevouts, SchnorrSighashType, SighashCache};
+use bitcoin::util::taproot::{
+    LeafVersion, TapLeafHash, TapSighashHash, TaprootBuilder, TaprootSpendInfo,
+};
+
+mod buy;
+mod cancel;
+pub mod funding;
+mod lock;
+mod punish;
+mod refund;
+
+/// Spend the lock output through the success script and reveal the first secret.
+pub type BuyTx = Tx<Buy>;
+
+/// Cancel the buy transaction through the timelocked script and allow refund or punish
+/// transaction.
+pub type CancelTx = Tx<Cancel>;
+
+/// Funding the swap creating a SegWit v1 key-path only output.
+pub type FundingTx = Funding;
+
+/// Locking the funding UTXO in a tapscript tree and allow buy or cancel transaction.
+pub type LockTx = Tx<Lock>;
+
+/// Spending the funds of the cancel transaction, terminating the swap in its non-optimal case.
+pub type PunishTx = Tx<Punish>;
+
+/// Spend the cancel output through the success script and reveal the second secret.
+pub type RefundTx = Tx<Refund>;
+
+/// The `x` coordinate of the `H` point defined in [`BIP-341`][bip-341], used as the internal key
+/// of the lock outputs so they can only be spent through their script paths.
+///
+/// [bip-341]: https://github.com/bitcoin/bips/blob/master/bip-0341.mediawiki
+const UNSPENDABLE_INTERNAL_KEY: [u8; 32] = [
+    0x50, 0x92, 0x9b, 0x74, 0xc1, 0xa0, 0x49, 0x54, 0xb7, 0x8b, 0x4b, 0x60, 0x35, 0xe9, 0x7a, 0x5e,
+    0x07, 0x8a, 0x5a, 0x0f, 0x28, 0xec, 0x96, 0xd5, 0x47, 0xbf, 0xee, 0x9a, 0xce, 0x80, 0x3a, 0xc0,
+];
+
+/// Returns the provably unspendable internal key used in all the swap tapscript trees.
+pub fn unspendable_internal_key() -> XOnlyPublicKey {
+    XOnlyPublicKey::from_slice(&UNSPENDABLE_INTERNAL_KEY).expect("valid x-only public key")
+}
 
 /// Inner type for the Taproot strategy with on-chain scripts.
 #[derive(Clone, Debug, Copy, Eq, PartialEq)]
@@ -57,7 +114,360 @@ impl From<BitcoinTaproot> for Btc {
     }
 }
 
-//impl Arbitrating for Bitcoin<Taproot> {}
+/// Tapscript tree locking the funds in the lock transaction. The tree contains two leaves: the
+/// success leaf used by the buy transaction with Alice and Bob buy keys, and the failure leaf
+/// used by the cancel transaction with Alice and Bob cancel keys after the cancel timelock.
+///
+/// The tree is committed under an unspendable internal key, see [`unspendable_internal_key`].
+pub struct CoopLock {
+    alice: XOnlyPublicKey,
+    bob: XOnlyPublicKey,
+}
+
+impl CoopLock {
+    /// Returns the success leaf script: `<alice> CHECKSIGVERIFY <bob> CHECKSIG`.
+    pub fn success_script(data: DataLock<CSVTimelock, XOnlyPublicKey>) -> Script {
+        let DataLock {
+            success: DoubleKeys { alice, bob },
+            ..
+        } = data;
+        Builder::new()
+            .push_slice(&alice.serialize())
+            .push_opcode(opcodes::all::OP_CHECKSIGVERIFY)
+            .push_slice(&bob.serialize())
+            .push_opcode(opcodes::all::OP_CHECKSIG)
+            .into_script()
+    }
+
+    /// Returns the failure leaf script: `<timelock> CSV DROP <alice> CHECKSIGVERIFY <bob>
+    /// CHECKSIG`.
+    pub fn failure_script(data: DataLock<CSVTimelock, XOnlyPublicKey>) -> Script {
+        let DataLock {
+            timelock,
+            failure: DoubleKeys { alice, bob },
+            ..
+        } = data;
+        Builder::new()
+            .push_int(timelock.as_u32().into())
+            .push_opcode(opcodes::all::OP_CSV)
+            .push_opcode(opcodes::all::OP_DROP)
+            .push_slice(&alice.serialize())
+            .push_opcode(opcodes::all::OP_CHECKSIGVERIFY)
+            .push_slice(&bob.serialize())
+            .push_opcode(opcodes::all::OP_CHECKSIG)
+            .into_script()
+    }
+
+    /// Returns the leaf script associated to the script path.
+    pub fn script(data: DataLock<CSVTimelock, XOnlyPublicKey>, path: ScriptPath) -> Script {
+        match path {
+            ScriptPath::Success => Self::success_script(data),
+            ScriptPath::Failure => Self::failure_script(data),
+        }
+    }
+
+    /// Returns the tapscript tree with both leaves.
+    pub fn tap_tree(data: DataLock<CSVTimelock, XOnlyPublicKey>) -> TapTree {
+        tap_tree(Self::success_script(data), Self::failure_script(data))
+    }
+
+    /// Returns the spending information of the tree, used to create control blocks.
+    pub fn spend_info(data: DataLock<CSVTimelock, XOnlyPublicKey>) -> TaprootSpendInfo {
+        spend_info(Self::tap_tree(data))
+    }
+
+    /// Returns the SegWit v1 script pubkey committing to the tree.
+    pub fn v1_p2tr(data: DataLock<CSVTimelock, XOnlyPublicKey>) -> Script {
+        Script::new_v1_p2tr_tweaked(Self::spend_info(data).output_key())
+    }
+
+    /// Parses one of the two leaf scripts and extracts Alice and Bob public keys.
+    pub fn from_script(s: &Script) -> Result<Self, FError> {
+        match script_pubkeys(s)?.as_slice() {
+            [alice, bob] => Ok(Self {
+                alice: *alice,
+                bob: *bob,
+            }),
+            _ => Err(FError::WrongTemplate("Leaf script is not a 2-of-2")),
+        }
+    }
+
+    /// Returns the public key of the given swap role in the 2-of-2 leaf script.
+    pub fn get_pubkey(&self, swap_role: SwapRole) -> &XOnlyPublicKey {
+        match swap_role {
+            SwapRole::Alice => &self.alice,
+            SwapRole::Bob => &self.bob,
+        }
+    }
+}
+
+/// Tapscript tree locking the funds in the cancel transaction. The tree contains two leaves: the
+/// success leaf used by the refund transaction with Alice and Bob refund keys, and the failure
+/// leaf used by the punish transaction with Alice punish key after the punish timelock.
+///
+/// The tree is committed under an unspendable internal key, see [`unspendable_internal_key`].
+pub struct PunishLock {
+    alice: XOnlyPublicKey,
+    bob: XOnlyPublicKey,
+    punish: XOnlyPublicKey,
+}
+
+impl PunishLock {
+    /// Returns the success leaf script: `<alice> CHECKSIGVERIFY <bob> CHECKSIG`.
+    pub fn success_script(data: DataPunishableLock<CSVTimelock, XOnlyPublicKey>) -> Script {
+        let DataPunishableLock {
+            success: DoubleKeys { alice, bob },
+            ..
+        } = data;
+        Builder::new()
+            .push_slice(&alice.serialize())
+            .push_opcode(opcodes::all::OP_CHECKSIGVERIFY)
+            .push_slice(&bob.serialize())
+            .push_opcode(opcodes::all::OP_CHECKSIG)
+            .into_script()
+    }
+
+    /// Returns the failure leaf script: `<timelock> CSV DROP <punish> CHECKSIG`.
+    pub fn failure_script(data: DataPunishableLock<CSVTimelock, XOnlyPublicKey>) -> Script {
+        let DataPunishableLock {
+            timelock, failure, ..
+        } = data;
+        Builder::new()
+            .push_int(timelock.as_u32().into())
+            .push_opcode(opcodes::all::OP_CSV)
+            .push_opcode(opcodes::all::OP_DROP)
+            .push_slice(&failure.serialize())
+            .push_opcode(opcodes::all::OP_CHECKSIG)
+            .into_script()
+    }
+
+    /// Returns the leaf script associated to the script path.
+    pub fn script(
+        data: DataPunishableLock<CSVTimelock, XOnlyPublicKey>,
+        path: ScriptPath,
+    ) -> Script {
+        match path {
+            ScriptPath::Success => Self::success_script(data),
+            ScriptPath::Failure => Self::failure_script(data),
+        }
+    }
+
+    /// Returns the tapscript tree with both leaves.
+    pub fn tap_tree(data: DataPunishableLock<CSVTimelock, XOnlyPublicKey>) -> TapTree {
+        tap_tree(Self::success_script(data), Self::failure_script(data))
+    }
+
+    /// Returns the spending information of the tree, used to create control blocks.
+    pub fn spend_info(data: DataPunishableLock<CSVTimelock, XOnlyPublicKey>) -> TaprootSpendInfo {
+        spend_info(Self::tap_tree(data))
+    }
+
+    /// Returns the SegWit v1 script pubkey committing to the tree.
+    pub fn v1_p2tr(data: DataPunishableLock<CSVTimelock, XOnlyPublicKey>) -> Script {
+        Script::new_v1_p2tr_tweaked(Self::spend_info(data).output_key())
+    }
+
+    /// Parses the success and the failure leaf scripts and extracts the public keys.
+    pub fn from_scripts(success: &Script, failure: &Script) -> Result<Self, FError> {
+        let (alice, bob) = match script_pubkeys(success)?.as_slice() {
+            [alice, bob] => (*alice, *bob),
+            _ => return Err(FError::WrongTemplate("Success leaf script is not a 2-of-2")),
+        };
+        let punish = match script_pubkeys(failure)?.as_slice() {
+            [punish] => *punish,
+            _ => {
+                return Err(FError::WrongTemplate(
+                    "Failure leaf script is not single key",
+                ))
+            }
+        };
+        Ok(Self { alice, bob, punish })
+    }
+
+    /// Returns the public key of the given swap role in the leaf script of the script path, if
+    /// the swap role has a key in that script.
+    pub fn get_pubkey(
+        &self,
+        swap_role: SwapRole,
+        script_path: ScriptPath,
+    ) -> Option<&XOnlyPublicKey> {
+        match script_path {
+            ScriptPath::Success => match swap_role {
+                SwapRole::Alice => Some(&self.alice),
+                SwapRole::Bob => Some(&self.bob),
+            },
+            ScriptPath::Failure => match swap_role {
+                SwapRole::Alice => Some(&self.punish),
+                SwapRole::Bob => None,
+            },
+        }
+    }
+}
+
+// Creates a balanced tree with the success and the failure leaves.
+fn tap_tree(success: Script, failure: Script) -> TapTree {
+    let builder = TaprootBuilder::new()
+        .add_leaf(1, success)
+        .and_then(|builder| builder.add_leaf(1, failure))
+        .expect("valid tree with two leaves at depth one");
+    TapTree::from_builder(builder).expect("complete tree without hidden nodes")
+}
+
+// Computes the spending information of a tree committed under the unspendable internal key.
+fn spend_info(tree: TapTree) -> TaprootSpendInfo {
+    tree.into_builder()
+        .finalize(&Secp256k1::verification_only(), unspendable_internal_key())
+        .expect("complete tree")
+}
+
+// Returns the x-only public keys pushed in a leaf script, in order of appearance.
+fn script_pubkeys(s: &Script) -> Result<Vec<XOnlyPublicKey>, FError> {
+    let mut keys = vec![];
+    for ins in s.instructions() {
+        if let Instruction::PushBytes(b) = ins.map_err(FError::new)? {
+            if b.len() == 32 {
+                keys.push(XOnlyPublicKey::from_slice(b).map_err(FError::new)?);
+            }
+        }
+    }
+    Ok(keys)
+}
+
+// Returns true if the leaf script is timelocked, i.e. is the failure path of a tree.
+fn is_timelocked(s: &Script) -> bool {
+    s.instructions()
+        .any(|ins| matches!(ins, Ok(Instruction::Op(opcodes::all::OP_CSV))))
+}
+
+/// Returns the leaf script associated to the script path from the tapscript tree of a partial
+/// transaction output. The failure path is the timelocked leaf.
+pub(crate) fn output_leaf(
+    output: &psbt::Output,
+    path: ScriptPath,
+) -> Result<(TaprootSpendInfo, Script), FError> {
+    let tree = output
+        .tap_tree
+        .clone()
+        .ok_or(FError::WrongTemplate("Missing tapscript tree"))?;
+    let script = tree
+        .script_leaves()
+        .map(|leaf| leaf.script().clone())
+        .find(|script| is_timelocked(script) == (path == ScriptPath::Failure))
+        .ok_or(FError::WrongTemplate("Missing leaf in tapscript tree"))?;
+    Ok((spend_info(tree), script))
+}
+
+/// Fills the partial transaction input with the data needed to spend the leaf script.
+pub(crate) fn set_script_spend(
+    input: &mut psbt::Input,
+    spend_info: &TaprootSpendInfo,
+    script: Script,
+) -> Result<(), FError> {
+    let control_block = spend_info
+        .control_block(&(script.clone(), LeafVersion::TapScript))
+        .ok_or(FError::WrongTemplate("Missing leaf in tapscript tree"))?;
+    input.tap_internal_key = Some(spend_info.internal_key());
+    input.tap_merkle_root = spend_info.merkle_root();
+    input
+        .tap_scripts
+        .insert(control_block, (script, LeafVersion::TapScript));
+    Ok(())
+}
+
+/// Fills the partial transaction output with the tapscript tree it commits to.
+pub(crate) fn set_output_tree(output: &mut psbt::Output, tree: TapTree) {
+    output.tap_internal_key = Some(unspendable_internal_key());
+    output.tap_tree = Some(tree);
+}
+
+/// Finalizes the first input of a script path spend. Signatures are pushed in the reverse order
+/// of the public keys in the leaf script, followed by the script and its control block.
+pub(crate) fn finalize_script_spend(psbt: &mut PartiallySignedTransaction) -> Result<(), FError> {
+    let (control_block, (script, leaf_version)) = psbt.inputs[0]
+        .tap_scripts
+        .iter()
+        .next()
+        .map(|(c, s)| (c.clone(), s.clone()))
+        .ok_or(FError::MissingWitness)?;
+    let leaf_hash = TapLeafHash::from_script(&script, leaf_version);
+
+    let mut witness = script_pubkeys(&script)?
+        .iter()
+        .rev()
+        .map(|pubkey| {
+            psbt.inputs[0]
+                .tap_script_sigs
+                .get(&(*pubkey, leaf_hash))
+                .map(SchnorrSig::to_vec)
+                .ok_or(FError::MissingSignature)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    witness.push(script.into_bytes());
+    witness.push(control_block.serialize());
+
+    psbt.inputs[0].final_script_witness = Some(Witness::from_vec(witness));
+    Ok(())
+}
+
+/// Marker for the inner types of [`Tx`] that spend a SegWit v1 output, implementing the
+/// Schnorr based [`Witnessable`] trait.
+pub trait TaprootSubTransaction: SubTransaction {}
+
+impl<T> Witnessable<TapSighashHash, XOnlyPublicKey, Signature> for Tx<T>
+where
+    T: TaprootSubTransaction,
+{
+    /// Computes the [`BIP-341`][bip-341] sighash of the first input, for the script path if a
+    /// leaf is set in the input, for the key path otherwise.
+    ///
+    /// [bip-341]: https://github.com/bitcoin/bips/blob/master/bip-0341.mediawiki
+    fn generate_witness_message(&self, _path: ScriptPath) -> Result<TapSighashHash, FError> {
+        let witness_utxo = self.psbt.inputs[0]
+            .witness_utxo
+            .clone()
+            .ok_or(FError::MissingWitness)?;
+        let prevouts = Prevouts::All(&[witness_utxo]);
+        let mut cache = SighashCache::new(&self.psbt.unsigned_tx);
+
+        match leaf_hash(&self.psbt.inputs[0]) {
+            Some(leaf_hash) => cache.taproot_script_spend_signature_hash(
+                0,
+                &prevouts,
+                leaf_hash,
+                SchnorrSighashType::Default,
+            ),
+            None => {
+                cache.taproot_key_spend_signature_hash(0, &prevouts, SchnorrSighashType::Default)
+            }
+        }
+        .map_err(FError::new)
+    }
+
+    fn add_witness(&mut self, pubkey: XOnlyPublicKey, sig: Signature) -> Result<(), FError> {
+        let sig = SchnorrSig {
+            sig,
+            hash_ty: SchnorrSighashType::Default,
+        };
+        match leaf_hash(&self.psbt.inputs[0]) {
+            Some(leaf_hash) => {
+                self.psbt.inputs[0]
+                    .tap_script_sigs
+                    .insert((pubkey, leaf_hash), sig);
+            }
+            None => self.psbt.inputs[0].tap_key_sig = Some(sig),
+        }
+        Ok(())
+    }
+}
+
+// Returns the leaf hash of the script spent by the input, if any.
+fn leaf_hash(input: &psbt::Input) -> Option<TapLeafHash> {
+    input
+        .tap_scripts
+        .values()
+        .next()
+        .map(|(script, ver)| TapLeafHash::from_script(script, *ver))
+}
 
 impl TryFrom<Btc> for Bitcoin<Taproot> {
     type Error = consensus::Error;
@@ -98,11 +508,24 @@ impl CanonicalBytes for XOnlyPublicKey {
     }
 }
 
-//impl Signatures for Bitcoin<Taproot> {
-//    type Message = Sha256dHash;
-//    type Signature = Signature;
-//    type EncryptedSignature = Signature;
-//}
+impl Transactions for Bitcoin<Taproot> {
+    type Addr = bitcoin::Address;
+    type Amt = bitcoin::Amount;
+    type Tx = bitcoin::Transaction;
+    type Px = PartiallySignedTransaction;
+    type Out = MetadataOutput;
+    type Ti = CSVTimelock;
+    type Ms = TapSighashHash;
+    type Pk = XOnlyPublicKey;
+    type Si = Signature;
+
+    type Funding = Funding;
+    type Lock = Tx<Lock>;
+    type Buy = Tx<Buy>;
+    type Cancel = Tx<Cancel>;
+    type Refund = Tx<Refund>;
+    type Punish = Tx<Punish>;
+}
 
 impl CanonicalBytes for Signature {
     fn as_canonical_bytes(&self) -> Vec<u8> {
@@ -116,3 +539,191 @@ impl CanonicalBytes for Signature {
         Signature::from_slice(bytes).map_err(consensus::Error::new)
     }
 }
+
+/// Computes the [`BIP-340`][bip-340] signature for the given [`BIP-341`][bip-341] sighash.
+/// Assumes that the hash is correctly computed.
+///
+/// [bip-340]: https://github.com/bitcoin/bips/blob/master/bip-0340.mediawiki
+/// [bip-341]: https://github.com/bitcoin/bips/blob/master/bip-0341.mediawiki
+pub fn sign_hash(
+    sighash: TapSighashHash,
+    keypair: &KeyPair,
+) -> Result<Signature, bitcoin::secp256k1::Error> {
+    let context = Secp256k1::new();
+    // Makes signature.
+    let msg = Message::from_slice(&sighash[..])?;
+    Ok(context.sign_schnorr_no_aux_rand(&msg, keypair))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Network;
+    use crate::transaction::{
+        Broadcastable, Buyable, Cancelable, Chainable, Fundable, Lockable, Punishable, Refundable,
+    };
+
+    use bitcoin::blockdata::transaction::{OutPoint, TxIn, TxOut};
+    use bitcoin::secp256k1::SecretKey;
+    use bitcoin::Amount;
+
+    fn keypair(byte: u8) -> (XOnlyPublicKey, KeyPair) {
+        let secp = Secp256k1::new();
+        let secret = SecretKey::from_slice(&[byte; 32]).unwrap();
+        let keypair = KeyPair::from_secret_key(&secp, secret);
+        (XOnlyPublicKey::from_keypair(&keypair), keypair)
+    }
+
+    fn sign<T: TaprootSubTransaction>(tx: &mut Tx<T>, path: ScriptPath, keypair: &KeyPair) {
+        let msg: TapSighashHash = tx.generate_witness_message(path).unwrap();
+        let sig = sign_hash(msg, keypair).unwrap();
+        Witnessable::<TapSighashHash, XOnlyPublicKey, Signature>::add_witness(
+            tx,
+            XOnlyPublicKey::from_keypair(keypair),
+            sig,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn from_string() {
+        let parse = Bitcoin::<Taproot>::from_str("Taproot");
+        assert!(parse.is_ok());
+        let parse = Bitcoin::<Taproot>::from_str("taproot");
+        assert!(parse.is_ok());
+    }
+
+    #[test]
+    fn unspendable_key_is_valid() {
+        assert_eq!(
+            "50929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0",
+            unspendable_internal_key().to_string()
+        );
+    }
+
+    #[test]
+    fn create_and_sign_transactions() {
+        let (fund, fund_kp) = keypair(1);
+        let (alice, alice_kp) = keypair(2);
+        let (bob, bob_kp) = keypair(3);
+        let (punish, punish_kp) = keypair(4);
+        let destination = bitcoin::Address::p2tr_tweaked(
+            bitcoin::util::schnorr::TweakedPublicKey::dangerous_assume_tweaked(fund),
+            bitcoin::Network::Regtest,
+        );
+
+        let mut funding = Funding::initialize(fund, Network::Local).unwrap();
+        let address = funding.get_address().unwrap();
+        let funding_tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::default(),
+                script_sig: Script::default(),
+                sequence: 0,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: 100_000,
+                script_pubkey: address.script_pubkey(),
+            }],
+        };
+        funding.update(funding_tx).unwrap();
+
+        let datalock = DataLock {
+            timelock: CSVTimelock::new(10),
+            success: DoubleKeys::new(alice, bob),
+            failure: DoubleKeys::new(alice, bob),
+        };
+        let punish_lock = DataPunishableLock {
+            timelock: CSVTimelock::new(10),
+            success: DoubleKeys::new(alice, bob),
+            failure: punish,
+        };
+
+        let mut lock = LockTx::initialize(&funding, datalock, Amount::from_sat(99_000)).unwrap();
+        lock.verify_template(datalock).unwrap();
+        lock.verify_target_amount(Amount::from_sat(99_000)).unwrap();
+        sign(&mut lock, ScriptPath::Success, &fund_kp);
+        let lock_tx = lock.finalize_and_extract().unwrap();
+        assert_eq!(lock_tx.input[0].witness.len(), 1);
+
+        let mut buy = BuyTx::initialize(&lock, datalock, destination.clone()).unwrap();
+        buy.is_build_on_top_of(&lock).unwrap();
+        buy.verify_template(destination.clone()).unwrap();
+        sign(&mut buy, ScriptPath::Success, &alice_kp);
+        sign(&mut buy, ScriptPath::Success, &bob_kp);
+        let buy_tx = buy.finalize_and_extract().unwrap();
+        assert_eq!(buy_tx.input[0].witness.len(), 4);
+
+        let mut cancel = CancelTx::initialize(&lock, datalock, punish_lock).unwrap();
+        cancel.is_build_on_top_of(&lock).unwrap();
+        cancel.verify_template(datalock, punish_lock).unwrap();
+        sign(&mut cancel, ScriptPath::Failure, &alice_kp);
+        sign(&mut cancel, ScriptPath::Failure, &bob_kp);
+        cancel.finalize_and_extract().unwrap();
+
+        let mut refund = RefundTx::initialize(&cancel, destination.clone()).unwrap();
+        refund.is_build_on_top_of(&cancel).unwrap();
+        refund.verify_template(destination.clone()).unwrap();
+        sign(&mut refund, ScriptPath::Success, &alice_kp);
+        sign(&mut refund, ScriptPath::Success, &bob_kp);
+        let refund_tx = refund.finalize_and_extract().unwrap();
+
+        let mut punish_tx = PunishTx::initialize(&cancel, punish_lock, destination).unwrap();
+        punish_tx.is_build_on_top_of(&cancel).unwrap();
+        sign(&mut punish_tx, ScriptPath::Failure, &punish_kp);
+        let punish_tx = punish_tx.finalize_and_extract().unwrap();
+        assert_eq!(punish_tx.input[0].witness.len(), 3);
+
+        // Verify the extracted signatures from the witness stacks
+        let secp = Secp256k1::verification_only();
+        let msg: TapSighashHash = buy.generate_witness_message(ScriptPath::Success).unwrap();
+        let sig = BuyTx::extract_witness(buy_tx);
+        secp.verify_schnorr(&sig, &Message::from_slice(&msg[..]).unwrap(), &bob)
+            .unwrap();
+        let msg: TapSighashHash = refund
+            .generate_witness_message(ScriptPath::Success)
+            .unwrap();
+        let sig = RefundTx::extract_witness(refund_tx);
+        secp.verify_schnorr(&sig, &Message::from_slice(&msg[..]).unwrap(), &alice)
+            .unwrap();
+    }
+
+    #[test]
+    fn parse_leaf_scripts() {
+        let (alice, _) = keypair(2);
+        let (bob, _) = keypair(3);
+        let datalock = DataLock {
+            timelock: CSVTimelock::new(10),
+            success: DoubleKeys::new(alice, bob),
+            failure: DoubleKeys::new(alice, bob),
+        };
+        let punish_lock = DataPunishableLock {
+            timelock: CSVTimelock::new(10),
+            success: DoubleKeys::new(alice, bob),
+            failure: alice,
+        };
+        let other_punish_lock = DataPunishableLock {
+            failure: bob,
+            ..punish_lock
+        };
+        assert_ne!(
+            PunishLock::v1_p2tr(punish_lock),
+            PunishLock::v1_p2tr(other_punish_lock)
+        );
+        let lock = CoopLock::from_script(&CoopLock::success_script(datalock)).unwrap();
+        assert_eq!(lock.get_pubkey(SwapRole::Alice), &alice);
+        assert_eq!(lock.get_pubkey(SwapRole::Bob), &bob);
+        let punish = PunishLock::from_scripts(
+            &PunishLock::success_script(punish_lock),
+            &PunishLock::failure_script(punish_lock),
+        )
+        .unwrap();
+        assert_eq!(
+            punish.get_pubkey(SwapRole::Alice, ScriptPath::Failure),
+            Some(&alice)
+        );
+        assert_eq!(punish.get_pubkey(SwapRole::Bob, ScriptPath::Failure), None);
+    }
+}
@@ -0,0 +1,142 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+use std::marker::PhantomData;
+
+use bitcoin::blockdata::transaction::{TxIn, TxOut};
+use bitcoin::blockdata::witness::Witness;
+use bitcoin::secp256k1::schnorr::Signature;
+use bitcoin::secp256k1::XOnlyPublicKey;
+use bitcoin::util::psbt::PartiallySignedTransaction;
+use bitcoin::util::taproot::TapSighashHash;
+use bitcoin::Address;
+use bitcoin::Amount;
+use bitcoin::Transaction;
+
+use crate::script::ScriptPath;
+use crate::transaction::{Cancelable, Error as FError, Refundable};
+
+use crate::bitcoin::taproot::{
+    finalize_script_spend, output_leaf, set_script_spend, TaprootSubTransaction,
+};
+use crate::bitcoin::timelock::CSVTimelock;
+use crate::bitcoin::transaction::{Error, MetadataOutput, SubTransaction, Tx};
+
+#[derive(Debug)]
+pub struct Refund;
+
+impl SubTransaction for Refund {
+    fn finalize(psbt: &mut PartiallySignedTransaction) -> Result<(), FError> {
+        finalize_script_spend(psbt)
+    }
+}
+
+impl TaprootSubTransaction for Refund {}
+
+impl
+    Refundable<
+        Address,
+        Transaction,
+        PartiallySignedTransaction,
+        MetadataOutput,
+        Amount,
+        CSVTimelock,
+        TapSighashHash,
+        XOnlyPublicKey,
+        Signature,
+    > for Tx<Refund>
+{
+    fn initialize(
+        prev: &impl Cancelable<
+            Address,
+            Transaction,
+            PartiallySignedTransaction,
+            MetadataOutput,
+            Amount,
+            CSVTimelock,
+            TapSighashHash,
+            XOnlyPublicKey,
+            Signature,
+        >,
+        refund_target: Address,
+    ) -> Result<Self, FError> {
+        let output_metadata = prev.get_consumable_output()?;
+        // Retrieve the success leaf from the tapscript tree committed in the cancel output
+        let (spend_info, script) = output_leaf(&prev.as_partial().outputs[0], ScriptPath::Success)?;
+
+        let unsigned_tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: output_metadata.out_point,
+                script_sig: bitcoin::Script::default(),
+                sequence: 0,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: output_metadata.tx_out.value,
+                script_pubkey: refund_target.script_pubkey(),
+            }],
+        };
+
+        let mut psbt =
+            PartiallySignedTransaction::from_unsigned_tx(unsigned_tx).map_err(Error::from)?;
+
+        // Set the input witness data and the success leaf to spend
+        psbt.inputs[0].witness_utxo = Some(output_metadata.tx_out);
+        set_script_spend(&mut psbt.inputs[0], &spend_info, script)?;
+
+        Ok(Tx {
+            psbt,
+            _t: PhantomData,
+        })
+    }
+
+    fn verify_template(&self, refund_target: Address) -> Result<(), FError> {
+        (self.psbt.unsigned_tx.version == 2)
+            .then(|| 0)
+            .ok_or(FError::WrongTemplate("Tx version is not 2"))?;
+        (self.psbt.unsigned_tx.lock_time == 0)
+            .then(|| 0)
+            .ok_or(FError::WrongTemplate("LockTime is not set to 0"))?;
+        (self.psbt.unsigned_tx.input.len() == 1)
+            .then(|| 0)
+            .ok_or(FError::WrongTemplate("Number of inputs is not 1"))?;
+        (self.psbt.unsigned_tx.output.len() == 1)
+            .then(|| 0)
+            .ok_or(FError::WrongTemplate("Number of outputs is not 1"))?;
+
+        let txin = &self.psbt.unsigned_tx.input[0];
+        (txin.sequence == 0)
+            .then(|| 0)
+            .ok_or(FError::WrongTemplate("Sequence is not set to 0"))?;
+
+        let txout = &self.psbt.unsigned_tx.output[0];
+        let script_pubkey = refund_target.script_pubkey();
+        (txout.script_pubkey == script_pubkey)
+            .then(|| 0)
+            .ok_or(FError::WrongTemplate("Script pubkey does not match"))?;
+
+        Ok(())
+    }
+
+    fn extract_witness(tx: bitcoin::Transaction) -> Signature {
+        let TxIn { witness, .. } = &tx.input[0];
+        let witness_bytes = witness.to_vec();
+        Signature::from_slice(witness_bytes[1].as_ref())
+            .expect("Validated transaction on-chain, signature and witness position is correct.")
+    }
+}
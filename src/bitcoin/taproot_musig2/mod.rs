@@ -0,0 +1,549 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Implementation of a Taproot strategy with MuSig2 off-chain multisigs for the arbitrating
+//! blockchain as Bitcoin. Inner implementation of [`BitcoinTaprootMusig2`].
+//!
+//! The lock output is a key-path only output whose internal key is the MuSig2 aggregation of
+//! Alice and Bob buy keys, the buy and the cancel transactions both spend it with an aggregated
+//! signature. The cancel transaction commits to its relative timelock through its input sequence,
+//! the failure keys of the lock are not used.
+//!
+//! The cancel output internal key is the MuSig2 aggregation of Alice and Bob refund keys, spent by
+//! the refund transaction, with a tapscript fallback leaf `<timelock> CSV DROP <punish> CHECKSIG`
+//! spent by the punish transaction.
+//!
+//! Buy, cancel, and refund transactions are thus indistinguishable on-chain from single-signature
+//! key-path spends. Signatures are produced with [`MultiSign`][crate::crypto::MultiSign], the
+//! participants exchanging their nonces with [`RevealNonces`][crate::protocol::message::RevealNonces].
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::bitcoin::taproot::funding::Funding;
+use crate::bitcoin::taproot_musig2::{
+    buy::Buy, cancel::Cancel, lock::Lock, punish::Punish, refund::Refund,
+};
+use crate::bitcoin::timelock::CSVTimelock;
+use crate::bitcoin::transaction::{MetadataOutput, Tx};
+use crate::bitcoin::{Bitcoin, BitcoinTaprootMusig2, Btc, Strategy};
+use crate::blockchain::Transactions;
+use crate::consensus;
+use crate::crypto::musig2::KeyAggContext;
+use crate::crypto::{DeriveKeys, SharedKeyId};
+use crate::script::{DataLock, DataPunishableLock, DoubleKeys};
+use crate::transaction::Error as FError;
+
+use bitcoin::blockdata::opcodes;
+use bitcoin::blockdata::script::{Builder, Script};
+use bitcoin::blockdata::witness::Witness;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::{schnorr::Signature, KeyPair, Secp256k1, XOnlyPublicKey};
+use bitcoin::util::psbt::{self, PartiallySignedTransaction, TapTree};
+use bitcoin::util::taproot::{TapBranchHash, TapSighashHash, TaprootBuilder, TaprootSpendInfo};
+use secp256kfun::XOnly;
+
+mod buy;
+mod cancel;
+mod lock;
+mod punish;
+mod refund;
+
+/// Spend the lock output with an aggregated signature and reveal the first secret.
+pub type BuyTx = Tx<Buy>;
+
+/// Cancel the buy transaction with an aggregated signature after the cancel timelock and allow
+/// refund or punish transaction.
+pub type CancelTx = Tx<Cancel>;
+
+/// Funding the swap creating a SegWit v1 key-path only output, see [`Funding`].
+pub type FundingTx = Funding;
+
+/// Locking the funding UTXO in a MuSig2 aggregated key and allow buy or cancel transaction.
+pub type LockTx = Tx<Lock>;
+
+/// Spending the funds of the cancel transaction through the tapscript fallback, terminating the
+/// swap in its non-optimal case.
+pub type PunishTx = Tx<Punish>;
+
+/// Spend the cancel output with an aggregated signature and reveal the second secret.
+pub type RefundTx = Tx<Refund>;
+
+/// Inner type for the Taproot strategy with MuSig2 off-chain multisigs.
+#[derive(Clone, Debug, Copy, Eq, PartialEq)]
+pub struct TaprootMusig2;
+
+impl Strategy for TaprootMusig2 {}
+
+impl fmt::Display for Bitcoin<TaprootMusig2> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Bitcoin<TaprootMusig2>")
+    }
+}
+
+impl FromStr for Bitcoin<TaprootMusig2> {
+    type Err = consensus::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "TaprootMusig2" | "taprootmusig2" => Ok(Self::new()),
+            _ => Err(consensus::Error::UnknownType),
+        }
+    }
+}
+
+impl From<BitcoinTaprootMusig2> for Btc {
+    fn from(v: BitcoinTaprootMusig2) -> Self {
+        Self::TaprootMusig2(v)
+    }
+}
+
+impl TryFrom<Btc> for Bitcoin<TaprootMusig2> {
+    type Error = consensus::Error;
+
+    fn try_from(v: Btc) -> Result<Self, consensus::Error> {
+        match v {
+            Btc::TaprootMusig2(v) => Ok(v),
+            _ => Err(consensus::Error::TypeMismatch),
+        }
+    }
+}
+
+/// Aggregates Alice and Bob x-only public keys with MuSig2 and tweaks the result with the optional
+/// tapscript tree merkle root.
+pub fn key_agg(
+    alice: XOnlyPublicKey,
+    bob: XOnlyPublicKey,
+    merkle_root: Option<TapBranchHash>,
+) -> KeyAggContext {
+    let keys = [alice, bob]
+        .iter()
+        .map(|key| XOnly::from_bytes(key.serialize()).expect("valid x-only public key"))
+        .collect();
+    KeyAggContext::new(keys, merkle_root.map(TapBranchHash::into_inner))
+        .expect("non empty list of keys")
+}
+
+/// Converts an aggregated key to a bitcoin x-only public key.
+pub fn to_xonly_public_key(key: XOnly) -> XOnlyPublicKey {
+    XOnlyPublicKey::from_slice(key.as_bytes()).expect("valid x-only public key")
+}
+
+/// Key-path only output locking the funds in the lock transaction. The internal key is the MuSig2
+/// aggregation of Alice and Bob buy keys.
+pub struct CoopLock;
+
+impl CoopLock {
+    /// Returns the MuSig2 key aggregation context used to sign the buy and cancel transactions.
+    pub fn key_agg(data: DataLock<CSVTimelock, XOnlyPublicKey>) -> KeyAggContext {
+        let DataLock {
+            success: DoubleKeys { alice, bob },
+            ..
+        } = data;
+        key_agg(alice, bob, None)
+    }
+
+    /// Returns the untweaked aggregated key.
+    pub fn internal_key(data: DataLock<CSVTimelock, XOnlyPublicKey>) -> XOnlyPublicKey {
+        to_xonly_public_key(Self::key_agg(data).internal_key())
+    }
+
+    /// Returns the SegWit v1 script pubkey of the tweaked aggregated key.
+    pub fn v1_p2tr(data: DataLock<CSVTimelock, XOnlyPublicKey>) -> Script {
+        Script::new_v1_p2tr(
+            &Secp256k1::verification_only(),
+            Self::internal_key(data),
+            None,
+        )
+    }
+}
+
+/// Output locking the funds in the cancel transaction. The internal key is the MuSig2 aggregation
+/// of Alice and Bob refund keys and the tapscript tree contains a single fallback leaf used by the
+/// punish transaction with Alice punish key after the punish timelock.
+pub struct PunishLock;
+
+impl PunishLock {
+    /// Returns the fallback leaf script: `<timelock> CSV DROP <punish> CHECKSIG`.
+    pub fn failure_script(data: DataPunishableLock<CSVTimelock, XOnlyPublicKey>) -> Script {
+        let DataPunishableLock {
+            timelock, failure, ..
+        } = data;
+        Builder::new()
+            .push_int(timelock.as_u32().into())
+            .push_opcode(opcodes::all::OP_CSV)
+            .push_opcode(opcodes::all::OP_DROP)
+            .push_slice(&failure.serialize())
+            .push_opcode(opcodes::all::OP_CHECKSIG)
+            .into_script()
+    }
+
+    /// Returns the tapscript tree with the fallback leaf.
+    pub fn tap_tree(data: DataPunishableLock<CSVTimelock, XOnlyPublicKey>) -> TapTree {
+        let builder = TaprootBuilder::new()
+            .add_leaf(0, Self::failure_script(data))
+            .expect("valid tree with a single leaf");
+        TapTree::from_builder(builder).expect("complete tree without hidden nodes")
+    }
+
+    /// Returns the MuSig2 key aggregation context used to sign the refund transaction.
+    pub fn key_agg(data: DataPunishableLock<CSVTimelock, XOnlyPublicKey>) -> KeyAggContext {
+        let DataPunishableLock {
+            success: DoubleKeys { alice, bob },
+            ..
+        } = data;
+        key_agg(alice, bob, Self::spend_info(data).merkle_root())
+    }
+
+    /// Returns the untweaked aggregated key.
+    pub fn internal_key(data: DataPunishableLock<CSVTimelock, XOnlyPublicKey>) -> XOnlyPublicKey {
+        let DataPunishableLock {
+            success: DoubleKeys { alice, bob },
+            ..
+        } = data;
+        to_xonly_public_key(key_agg(alice, bob, None).internal_key())
+    }
+
+    /// Returns the spending information of the output, used to create the control block.
+    pub fn spend_info(data: DataPunishableLock<CSVTimelock, XOnlyPublicKey>) -> TaprootSpendInfo {
+        Self::tap_tree(data)
+            .into_builder()
+            .finalize(&Secp256k1::verification_only(), Self::internal_key(data))
+            .expect("complete tree")
+    }
+
+    /// Returns the SegWit v1 script pubkey committing to the aggregated key and the tree.
+    pub fn v1_p2tr(data: DataPunishableLock<CSVTimelock, XOnlyPublicKey>) -> Script {
+        Script::new_v1_p2tr_tweaked(Self::spend_info(data).output_key())
+    }
+}
+
+/// Fills the partial transaction input with the data needed to spend the output with the key
+/// path.
+pub(crate) fn set_key_spend(
+    input: &mut psbt::Input,
+    internal_key: XOnlyPublicKey,
+    merkle_root: Option<TapBranchHash>,
+) {
+    input.tap_internal_key = Some(internal_key);
+    input.tap_merkle_root = merkle_root;
+}
+
+/// Finalizes the first input of a key path spend with the aggregated signature.
+pub(crate) fn finalize_key_spend(psbt: &mut PartiallySignedTransaction) -> Result<(), FError> {
+    let sig = psbt.inputs[0].tap_key_sig.ok_or(FError::MissingSignature)?;
+    psbt.inputs[0].final_script_witness = Some(Witness::from_vec(vec![sig.to_vec()]));
+    Ok(())
+}
+
+impl DeriveKeys for Bitcoin<TaprootMusig2> {
+    type PublicKey = XOnlyPublicKey;
+    type PrivateKey = KeyPair;
+
+    fn extra_public_keys() -> Vec<u16> {
+        // No extra key
+        vec![]
+    }
+
+    fn extra_shared_private_keys() -> Vec<SharedKeyId> {
+        // No shared key in Bitcoin, transparent ledger
+        vec![]
+    }
+}
+
+impl Transactions for Bitcoin<TaprootMusig2> {
+    type Addr = bitcoin::Address;
+    type Amt = bitcoin::Amount;
+    type Tx = bitcoin::Transaction;
+    type Px = PartiallySignedTransaction;
+    type Out = MetadataOutput;
+    type Ti = CSVTimelock;
+    type Ms = TapSighashHash;
+    type Pk = XOnlyPublicKey;
+    type Si = Signature;
+
+    type Funding = Funding;
+    type Lock = Tx<Lock>;
+    type Buy = Tx<Buy>;
+    type Cancel = Tx<Cancel>;
+    type Refund = Tx<Refund>;
+    type Punish = Tx<Punish>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitcoin::taproot::TaprootSubTransaction;
+    use crate::blockchain::Network;
+    use crate::crypto::musig2::Session;
+    use crate::crypto::{
+        AccordantKeyId, ArbitratingKeyId, EncSign, GenerateKey, MultiSign, ProveCrossGroupDleq,
+        RecoverSecret, Sign,
+    };
+    use crate::script::ScriptPath;
+    use crate::swap::btcxmr::KeyManager;
+    use crate::transaction::{
+        Broadcastable, Buyable, Cancelable, Chainable, Fundable, Lockable, Punishable, Refundable,
+        Transaction, Witnessable,
+    };
+
+    use bitcoin::blockdata::transaction::{OutPoint, TxIn, TxOut};
+    use bitcoin::secp256k1::{Message, PublicKey};
+    use bitcoin::Amount;
+
+    fn encryption_key(key_manager: &mut KeyManager) -> XOnlyPublicKey {
+        let key = key_manager.get_encryption_key().unwrap();
+        XOnlyPublicKey::from_slice(&key.serialize()[1..]).unwrap()
+    }
+
+    fn add_witness<T: TaprootSubTransaction>(
+        tx: &mut Tx<T>,
+        pubkey: XOnlyPublicKey,
+        sig: Signature,
+    ) {
+        Witnessable::<TapSighashHash, XOnlyPublicKey, Signature>::add_witness(tx, pubkey, sig)
+            .unwrap();
+    }
+
+    // Runs the two rounds of MuSig2 between Alice and Bob and returns the session with both
+    // partial signatures verified.
+    fn partial_sign(
+        alice: &mut KeyManager,
+        bob: &mut KeyManager,
+        key: ArbitratingKeyId,
+        key_agg: KeyAggContext,
+        encryption_key: Option<XOnlyPublicKey>,
+        msg: TapSighashHash,
+    ) -> (Session, Vec<crate::crypto::musig2::PartialSignature>) {
+        let alice_nonce = alice.generate_nonce(key).unwrap();
+        let bob_nonce = bob.generate_nonce(key).unwrap();
+        let encryption_key = encryption_key.map(|k| XOnly::from_bytes(k.serialize()).unwrap());
+        let session = Session::new(
+            key_agg,
+            &[alice_nonce, bob_nonce],
+            encryption_key,
+            &msg.into_inner(),
+        )
+        .unwrap();
+
+        let alice_sig = alice.partial_sign(key, &session).unwrap();
+        let bob_sig = bob.partial_sign(key, &session).unwrap();
+        // Secret nonces are consumed
+        assert!(alice.partial_sign(key, &session).is_err());
+
+        let alice_key: XOnlyPublicKey = alice.get_pubkey(key).unwrap();
+        let bob_key: XOnlyPublicKey = bob.get_pubkey(key).unwrap();
+        bob.verify_partial_signature(&alice_key, &alice_nonce, &session, &alice_sig)
+            .unwrap();
+        alice
+            .verify_partial_signature(&bob_key, &bob_nonce, &session, &bob_sig)
+            .unwrap();
+        assert!(alice
+            .verify_partial_signature(&alice_key, &bob_nonce, &session, &bob_sig)
+            .is_err());
+
+        (session, vec![alice_sig, bob_sig])
+    }
+
+    #[test]
+    fn from_string() {
+        let parse = Bitcoin::<TaprootMusig2>::from_str("TaprootMusig2");
+        assert!(parse.is_ok());
+        let parse = Bitcoin::<TaprootMusig2>::from_str("taprootmusig2");
+        assert!(parse.is_ok());
+    }
+
+    #[test]
+    fn create_and_sign_transactions() {
+        let mut alice_km = KeyManager::new([1; 32], 1).unwrap();
+        let mut bob_km = KeyManager::new([2; 32], 1).unwrap();
+        let key = |km: &mut KeyManager, id| -> XOnlyPublicKey { km.get_pubkey(id).unwrap() };
+        let fund = key(&mut bob_km, ArbitratingKeyId::Lock);
+        let destination = bitcoin::Address::p2tr(
+            &Secp256k1::verification_only(),
+            fund,
+            None,
+            bitcoin::Network::Regtest,
+        );
+
+        let mut funding = Funding::initialize(fund, Network::Local).unwrap();
+        let address = funding.get_address().unwrap();
+        let funding_tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::default(),
+                script_sig: Script::default(),
+                sequence: 0,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: 100_000,
+                script_pubkey: address.script_pubkey(),
+            }],
+        };
+        funding.update(funding_tx).unwrap();
+
+        let datalock = DataLock {
+            timelock: CSVTimelock::new(10),
+            success: DoubleKeys::new(
+                key(&mut alice_km, ArbitratingKeyId::Buy),
+                key(&mut bob_km, ArbitratingKeyId::Buy),
+            ),
+            failure: DoubleKeys::new(
+                key(&mut alice_km, ArbitratingKeyId::Cancel),
+                key(&mut bob_km, ArbitratingKeyId::Cancel),
+            ),
+        };
+        let punish_lock = DataPunishableLock {
+            timelock: CSVTimelock::new(10),
+            success: DoubleKeys::new(
+                key(&mut alice_km, ArbitratingKeyId::Refund),
+                key(&mut bob_km, ArbitratingKeyId::Refund),
+            ),
+            failure: key(&mut alice_km, ArbitratingKeyId::Punish),
+        };
+
+        let mut lock = LockTx::initialize(&funding, datalock, Amount::from_sat(99_000)).unwrap();
+        lock.verify_template(datalock).unwrap();
+        let msg: TapSighashHash = lock.generate_witness_message(ScriptPath::Success).unwrap();
+        let sig = bob_km.sign(ArbitratingKeyId::Lock, msg).unwrap();
+        add_witness(&mut lock, fund, sig);
+        lock.finalize_and_extract().unwrap();
+        // The lock output commits to the aggregated key without scripts
+        assert_eq!(
+            lock.as_partial().unsigned_tx.output[0].script_pubkey,
+            Script::new_v1_p2tr_tweaked(
+                bitcoin::util::schnorr::TweakedPublicKey::dangerous_assume_tweaked(
+                    to_xonly_public_key(CoopLock::key_agg(datalock).output_key())
+                )
+            )
+        );
+
+        // Buy: the aggregated signature is encrypted under Alice's encryption key, Alice decrypts
+        // it and Bob recovers Alice's secret from the on-chain witness
+        let alice_encryption_key = encryption_key(&mut alice_km);
+        let mut buy = BuyTx::initialize(&lock, datalock, destination.clone()).unwrap();
+        buy.is_build_on_top_of(&lock).unwrap();
+        buy.verify_template(destination.clone()).unwrap();
+        let msg: TapSighashHash = buy.generate_witness_message(ScriptPath::Success).unwrap();
+        let (session, sigs) = partial_sign(
+            &mut alice_km,
+            &mut bob_km,
+            ArbitratingKeyId::Buy,
+            CoopLock::key_agg(datalock),
+            Some(alice_encryption_key),
+            msg,
+        );
+        let output_key = to_xonly_public_key(session.key_agg().output_key());
+        assert!(bob_km.aggregate_signature(&session, &sigs).is_err());
+        let enc_sig = bob_km
+            .aggregate_encrypted_signature(&session, &sigs)
+            .unwrap();
+        bob_km
+            .verify_encrypted_signature(&output_key, &alice_encryption_key, msg, &enc_sig)
+            .unwrap();
+        let sig = alice_km
+            .decrypt_signature(AccordantKeyId::Spend, enc_sig.clone())
+            .unwrap();
+        add_witness(&mut buy, output_key, sig);
+        let buy_tx = buy.finalize_and_extract().unwrap();
+        assert_eq!(buy_tx.input[0].witness.len(), 1);
+        let sig = BuyTx::extract_witness(buy_tx);
+        let secp = Secp256k1::new();
+        secp.verify_schnorr(&sig, &Message::from_slice(&msg[..]).unwrap(), &output_key)
+            .unwrap();
+        let secret = bob_km
+            .recover_secret_key(enc_sig, &alice_encryption_key, sig)
+            .unwrap();
+        assert_eq!(
+            PublicKey::from_secret_key(&secp, &secret),
+            alice_km.get_encryption_key().unwrap()
+        );
+
+        // Cancel: a regular aggregated signature
+        let mut cancel = CancelTx::initialize(&lock, datalock, punish_lock).unwrap();
+        cancel.is_build_on_top_of(&lock).unwrap();
+        cancel.verify_template(datalock, punish_lock).unwrap();
+        let msg: TapSighashHash = cancel
+            .generate_witness_message(ScriptPath::Failure)
+            .unwrap();
+        let (session, sigs) = partial_sign(
+            &mut alice_km,
+            &mut bob_km,
+            ArbitratingKeyId::Buy,
+            CoopLock::key_agg(datalock),
+            None,
+            msg,
+        );
+        let sig = alice_km.aggregate_signature(&session, &sigs).unwrap();
+        add_witness(&mut cancel, output_key, sig);
+        let cancel_tx = cancel.finalize_and_extract().unwrap();
+        assert_eq!(cancel_tx.input[0].sequence, 10);
+
+        // Refund: the aggregated signature is encrypted under Bob's encryption key
+        let bob_encryption_key = encryption_key(&mut bob_km);
+        let mut refund = RefundTx::initialize(&cancel, destination.clone()).unwrap();
+        refund.is_build_on_top_of(&cancel).unwrap();
+        refund.verify_template(destination.clone()).unwrap();
+        let msg: TapSighashHash = refund
+            .generate_witness_message(ScriptPath::Success)
+            .unwrap();
+        let (session, sigs) = partial_sign(
+            &mut alice_km,
+            &mut bob_km,
+            ArbitratingKeyId::Refund,
+            PunishLock::key_agg(punish_lock),
+            Some(bob_encryption_key),
+            msg,
+        );
+        let output_key = to_xonly_public_key(session.key_agg().output_key());
+        assert_eq!(
+            PunishLock::spend_info(punish_lock).output_key().to_inner(),
+            output_key
+        );
+        let enc_sig = alice_km
+            .aggregate_encrypted_signature(&session, &sigs)
+            .unwrap();
+        let sig = bob_km
+            .decrypt_signature(AccordantKeyId::Spend, enc_sig.clone())
+            .unwrap();
+        add_witness(&mut refund, output_key, sig);
+        let refund_tx = refund.finalize_and_extract().unwrap();
+        let sig = RefundTx::extract_witness(refund_tx);
+        secp.verify_schnorr(&sig, &Message::from_slice(&msg[..]).unwrap(), &output_key)
+            .unwrap();
+        let secret = alice_km
+            .recover_secret_key(enc_sig, &bob_encryption_key, sig)
+            .unwrap();
+        assert_eq!(
+            PublicKey::from_secret_key(&secp, &secret),
+            bob_km.get_encryption_key().unwrap()
+        );
+
+        // Punish: the tapscript fallback signed by Alice alone
+        let mut punish = PunishTx::initialize(&cancel, punish_lock, destination).unwrap();
+        punish.is_build_on_top_of(&cancel).unwrap();
+        let msg: TapSighashHash = punish
+            .generate_witness_message(ScriptPath::Failure)
+            .unwrap();
+        let sig = alice_km.sign(ArbitratingKeyId::Punish, msg).unwrap();
+        add_witness(&mut punish, punish_lock.failure, sig);
+        let punish_tx = punish.finalize_and_extract().unwrap();
+        assert_eq!(punish_tx.input[0].witness.len(), 3);
+    }
+}
@@ -0,0 +1,109 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+use std::marker::PhantomData;
+
+use bitcoin::blockdata::transaction::{TxIn, TxOut};
+use bitcoin::blockdata::witness::Witness;
+use bitcoin::secp256k1::schnorr::Signature;
+use bitcoin::secp256k1::XOnlyPublicKey;
+use bitcoin::util::psbt::PartiallySignedTransaction;
+use bitcoin::util::taproot::TapSighashHash;
+use bitcoin::Address;
+use bitcoin::Amount;
+use bitcoin::Transaction;
+
+use crate::script;
+use crate::transaction::{Cancelable, Error, Punishable};
+
+use crate::bitcoin::taproot::{finalize_script_spend, set_script_spend, TaprootSubTransaction};
+use crate::bitcoin::taproot_musig2::PunishLock;
+use crate::bitcoin::timelock::CSVTimelock;
+use crate::bitcoin::transaction::{self, MetadataOutput, SubTransaction, Tx};
+
+#[derive(Debug)]
+pub struct Punish;
+
+impl SubTransaction for Punish {
+    fn finalize(psbt: &mut PartiallySignedTransaction) -> Result<(), Error> {
+        finalize_script_spend(psbt)
+    }
+}
+
+impl TaprootSubTransaction for Punish {}
+
+impl
+    Punishable<
+        Address,
+        Transaction,
+        PartiallySignedTransaction,
+        MetadataOutput,
+        Amount,
+        CSVTimelock,
+        TapSighashHash,
+        XOnlyPublicKey,
+        Signature,
+    > for Tx<Punish>
+{
+    fn initialize(
+        prev: &impl Cancelable<
+            Address,
+            Transaction,
+            PartiallySignedTransaction,
+            MetadataOutput,
+            Amount,
+            CSVTimelock,
+            TapSighashHash,
+            XOnlyPublicKey,
+            Signature,
+        >,
+        punish_lock: script::DataPunishableLock<CSVTimelock, XOnlyPublicKey>,
+        destination_target: Address,
+    ) -> Result<Self, Error> {
+        let output_metadata = prev.get_consumable_output()?;
+
+        let unsigned_tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: output_metadata.out_point,
+                script_sig: bitcoin::Script::default(),
+                sequence: punish_lock.timelock.as_u32(),
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: output_metadata.tx_out.value,
+                script_pubkey: destination_target.script_pubkey(),
+            }],
+        };
+
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(unsigned_tx)
+            .map_err(transaction::Error::from)?;
+
+        // Set the input witness data and the fallback leaf to spend
+        psbt.inputs[0].witness_utxo = Some(output_metadata.tx_out);
+        set_script_spend(
+            &mut psbt.inputs[0],
+            &PunishLock::spend_info(punish_lock),
+            PunishLock::failure_script(punish_lock),
+        )?;
+
+        Ok(Tx {
+            psbt,
+            _t: PhantomData,
+        })
+    }
+}
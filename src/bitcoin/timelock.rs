@@ -15,45 +15,224 @@
 // Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
 
 //! Timelock unit and `OP_CODE` to use in Bitcoin transactions and scripts.
+//!
+//! Timelocks are either relative with [`CSVTimelock`], counted from the confirmation of the
+//! transaction creating the output, or absolute with [`CLTVTimelock`], a fixed block height or
+//! median-time-past. Both implement [`Timelock`] to be used in scripts and transactions.
+
+use bitcoin::blockdata::opcodes;
 
 use crate::consensus::{self, CanonicalBytes};
 
-use std::fmt::Debug;
+use serde::{de, Deserialize, Deserializer};
+
+use std::convert::TryFrom;
+use std::fmt::{self, Debug};
 use std::str::FromStr;
 
-/// An `OP_CSV` value (32-bits integer) to use in transactions and scripts.
-#[derive(PartialEq, Eq, PartialOrd, Clone, Debug, Hash, Copy, Display, Serialize, Deserialize)]
-#[display("{0} blocks")]
+/// Threshold below which an `nLockTime` value is a block height, and above which it is a UNIX
+/// timestamp compared with the median-time-past.
+pub const LOCK_TIME_THRESHOLD: u32 = 500_000_000;
+
+/// A timelock enforced in Bitcoin scripts with its verification opcode and satisfied in the
+/// spending transaction with the input sequence or the transaction lock time.
+pub trait Timelock: Copy + Debug {
+    /// Return the opcode verifying the timelock in scripts, `OP_CSV` or `OP_CLTV`.
+    fn opcode() -> opcodes::All;
+
+    /// Return the value pushed in scripts before the verification opcode.
+    fn script_value(&self) -> u32;
+
+    /// Return the `nSequence` of an input spending an output locked with the timelock.
+    fn sequence(&self) -> u32;
+
+    /// Return the `nLockTime` of a transaction spending an output locked with the timelock.
+    fn lock_time(&self) -> u32;
+}
+
+/// Flag set in a relative timelock when it is expressed in units of 512 seconds instead of blocks
+/// (BIP-68).
+pub const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
+
+/// Mask of the 16 bits holding the value of a relative timelock (BIP-68).
+pub const SEQUENCE_LOCKTIME_MASK: u32 = 0x0000_ffff;
+
+/// Granularity in seconds of time-based relative timelocks (BIP-68).
+pub const SEQUENCE_LOCKTIME_GRANULARITY: u32 = 512;
+
+/// An `OP_CSV` value (32-bits integer) to use in transactions and scripts, encoded as defined in
+/// BIP-68: a number of blocks, or a number of 512 seconds intervals when the type flag is set.
+///
+/// Relative timelocks are parsed from and displayed as a number of blocks, e.g. `144 blocks`, or
+/// a duration in seconds, minutes, hours or days, e.g. `1d`, rounded up to the next 512 seconds
+/// interval. A bare integer is parsed as a number of blocks.
+#[derive(PartialEq, Eq, Clone, Debug, Hash, Copy, Serialize)]
 pub struct CSVTimelock(u32);
 
+/// Timelocks are ordered by value when they are in the same unit, timelocks in blocks and in
+/// time cannot be compared.
+impl PartialOrd for CSVTimelock {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        (self.is_time_based() == other.is_time_based()).then(|| self.0.cmp(&other.0))
+    }
+}
+
+impl fmt::Display for CSVTimelock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let seconds = match self.as_seconds() {
+            Some(seconds) => seconds,
+            None => return write!(f, "{} blocks", self.value()),
+        };
+        let units = [(86_400, "d"), (3_600, "h"), (60, "m")];
+        // Display the largest unit dividing the duration, otherwise the largest unit parsed back
+        // to the same number of intervals, e.g. `1d` for the 169 intervals of one day rounded up
+        let exact = units
+            .iter()
+            .find(|(unit, _)| seconds > 0 && seconds % unit == 0);
+        let rounded = || {
+            units.iter().find(|(unit, _)| {
+                let value = seconds / unit;
+                value > 0 && Self::from_seconds(value * unit).ok() == Some(*self)
+            })
+        };
+        match exact.or_else(rounded) {
+            Some((unit, suffix)) => write!(f, "{}{}", seconds / unit, suffix),
+            None => write!(f, "{}s", seconds),
+        }
+    }
+}
+
 impl FromStr for CSVTimelock {
     type Err = consensus::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let x = s
-            .parse::<u32>()
-            .map_err(|_| consensus::Error::ParseFailed("Failed parsing CSV timelock"))?;
-        Ok(CSVTimelock(x))
+        let s = s.trim();
+        let parse = |x: &str| {
+            x.trim()
+                .parse::<u32>()
+                .map_err(|_| consensus::Error::ParseFailed("Failed parsing CSV timelock"))
+        };
+        let blocks = s.strip_suffix("blocks").or_else(|| s.strip_suffix("block"));
+        if blocks.is_none() {
+            for (suffix, unit) in [("d", 86_400), ("h", 3_600), ("m", 60), ("s", 1)] {
+                if let Some(value) = s.strip_suffix(suffix) {
+                    let seconds = parse(value)?
+                        .checked_mul(unit)
+                        .ok_or(consensus::Error::ParseFailed("CSV timelock out of range"))?;
+                    return Self::from_seconds(seconds);
+                }
+            }
+        }
+        // A bare integer is a number of blocks
+        u16::try_from(parse(blocks.unwrap_or(s))?)
+            .map(Self::from_blocks)
+            .map_err(|_| consensus::Error::ParseFailed("CSV timelock out of range"))
+    }
+}
+
+impl<'de> Deserialize<'de> for CSVTimelock {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        CSVTimelock::from_sequence(u32::deserialize(deserializer)?).map_err(de::Error::custom)
     }
 }
 
 impl CSVTimelock {
-    /// Create a new raw check sequence verify timelock of given value.
+    /// Create a new raw check sequence verify timelock of given value, the value is interpreted
+    /// as defined in BIP-68 and is not validated, see [`CSVTimelock::from_sequence`].
     pub fn new(timelock: u32) -> Self {
         Self(timelock)
     }
 
+    /// Create a new check sequence verify timelock of given raw value as defined in BIP-68. Fails
+    /// if the value is not valid, see [`CSVTimelock::is_valid`].
+    pub fn from_sequence(sequence: u32) -> Result<Self, consensus::Error> {
+        Self(sequence).validate()
+    }
+
+    /// Create a new check sequence verify timelock of given number of blocks.
+    pub fn from_blocks(blocks: u16) -> Self {
+        Self(blocks as u32)
+    }
+
+    /// Create a new check sequence verify timelock of given number of 512 seconds intervals.
+    pub fn from_intervals(intervals: u16) -> Self {
+        Self(SEQUENCE_LOCKTIME_TYPE_FLAG | intervals as u32)
+    }
+
+    /// Create a new check sequence verify timelock of at least the given number of seconds,
+    /// rounded up to the next 512 seconds interval. Fails if the duration does not fit in the
+    /// 16 bits of the timelock value.
+    pub fn from_seconds(seconds: u32) -> Result<Self, consensus::Error> {
+        let intervals = (seconds as u64 + SEQUENCE_LOCKTIME_GRANULARITY as u64 - 1)
+            / SEQUENCE_LOCKTIME_GRANULARITY as u64;
+        u16::try_from(intervals)
+            .map(Self::from_intervals)
+            .map_err(|_| consensus::Error::ParseFailed("CSV timelock out of range"))
+    }
+
     /// Return the value of the check sequence verify.
     pub fn as_u32(&self) -> u32 {
         self.0
     }
 
+    /// Return `true` if the timelock is expressed in 512 seconds intervals, `false` if it is
+    /// expressed in blocks.
+    pub fn is_time_based(&self) -> bool {
+        self.0 & SEQUENCE_LOCKTIME_TYPE_FLAG != 0
+    }
+
+    /// Return the number of blocks or 512 seconds intervals of the timelock.
+    pub fn value(&self) -> u16 {
+        (self.0 & SEQUENCE_LOCKTIME_MASK) as u16
+    }
+
+    /// Return the duration in seconds of a time-based timelock, `None` for a timelock in blocks.
+    pub fn as_seconds(&self) -> Option<u32> {
+        self.is_time_based()
+            .then(|| self.value() as u32 * SEQUENCE_LOCKTIME_GRANULARITY)
+    }
+
+    /// Return `true` if the raw value only uses the type flag and the 16 bits value defined in
+    /// BIP-68, i.e. the timelock is enforced as intended by `OP_CSV`.
+    pub fn is_valid(&self) -> bool {
+        self.0 & !(SEQUENCE_LOCKTIME_TYPE_FLAG | SEQUENCE_LOCKTIME_MASK) == 0
+    }
+
+    /// Return the timelock if valid, an error otherwise.
+    pub fn validate(self) -> Result<Self, consensus::Error> {
+        match self.is_valid() {
+            true => Ok(self),
+            false => Err(consensus::Error::ParseFailed("CSV timelock out of range")),
+        }
+    }
+
     /// Return the value of nSequence that disable `CHECK_SEQUENCE_VERIFY`.
     pub fn disable() -> u32 {
         (1 << 31) as u32
     }
 }
 
+impl Timelock for CSVTimelock {
+    fn opcode() -> opcodes::All {
+        opcodes::all::OP_CSV
+    }
+
+    fn script_value(&self) -> u32 {
+        self.0
+    }
+
+    fn sequence(&self) -> u32 {
+        self.0
+    }
+
+    fn lock_time(&self) -> u32 {
+        0
+    }
+}
+
 impl From<u32> for CSVTimelock {
     fn from(u: u32) -> Self {
         Self::new(u)
@@ -68,13 +247,13 @@ impl From<CSVTimelock> for u32 {
 
 impl From<u16> for CSVTimelock {
     fn from(u: u16) -> Self {
-        Self::new(u as u32)
+        Self::from_blocks(u)
     }
 }
 
 impl From<u8> for CSVTimelock {
     fn from(u: u8) -> Self {
-        Self::new(u as u32)
+        Self::from_blocks(u as u16)
     }
 }
 
@@ -87,8 +266,276 @@ impl CanonicalBytes for CSVTimelock {
     where
         Self: Sized,
     {
-        Ok(CSVTimelock(
+        CSVTimelock::from_sequence(
+            bitcoin::consensus::encode::deserialize(bytes).map_err(consensus::Error::new)?,
+        )
+    }
+}
+
+/// An `OP_CLTV` value to use in transactions and scripts, an absolute block height or a UNIX
+/// timestamp compared with the median-time-past of the chain (BIP-113).
+///
+/// Absolute timelocks are parsed from and displayed as `height 800000` or `time 1700000000`, a
+/// bare integer is parsed as an `nLockTime` value.
+#[derive(PartialEq, Eq, Clone, Debug, Hash, Copy, Display, Serialize, Deserialize)]
+#[serde(try_from = "UncheckedCLTVTimelock")]
+pub enum CLTVTimelock {
+    /// Block height, below [`LOCK_TIME_THRESHOLD`].
+    #[display("height {0}")]
+    Height(u32),
+    /// UNIX timestamp compared with the median-time-past, above or equal to
+    /// [`LOCK_TIME_THRESHOLD`].
+    #[display("time {0}")]
+    MedianTimePast(u32),
+}
+
+// Deserialized timelock before checking the value against the variant
+#[derive(Deserialize)]
+#[serde(rename = "CLTVTimelock")]
+enum UncheckedCLTVTimelock {
+    Height(u32),
+    MedianTimePast(u32),
+}
+
+impl TryFrom<UncheckedCLTVTimelock> for CLTVTimelock {
+    type Error = consensus::Error;
+
+    fn try_from(timelock: UncheckedCLTVTimelock) -> Result<Self, Self::Error> {
+        match timelock {
+            UncheckedCLTVTimelock::Height(height) => Self::from_height(height),
+            UncheckedCLTVTimelock::MedianTimePast(timestamp) => Self::from_time(timestamp),
+        }
+    }
+}
+
+impl CLTVTimelock {
+    /// Create a new check lock time verify timelock at the given block height, fails if the height
+    /// is above or equal to [`LOCK_TIME_THRESHOLD`].
+    pub fn from_height(height: u32) -> Result<Self, consensus::Error> {
+        match height < LOCK_TIME_THRESHOLD {
+            true => Ok(Self::Height(height)),
+            false => Err(consensus::Error::ParseFailed("Invalid CLTV block height")),
+        }
+    }
+
+    /// Create a new check lock time verify timelock at the given UNIX timestamp, fails if the
+    /// timestamp is below [`LOCK_TIME_THRESHOLD`].
+    pub fn from_time(timestamp: u32) -> Result<Self, consensus::Error> {
+        match timestamp >= LOCK_TIME_THRESHOLD {
+            true => Ok(Self::MedianTimePast(timestamp)),
+            false => Err(consensus::Error::ParseFailed("Invalid CLTV timestamp")),
+        }
+    }
+
+    /// Create a new check lock time verify timelock from its `nLockTime` value, the variant is
+    /// given by [`LOCK_TIME_THRESHOLD`].
+    pub fn from_consensus(value: u32) -> Self {
+        match value < LOCK_TIME_THRESHOLD {
+            true => Self::Height(value),
+            false => Self::MedianTimePast(value),
+        }
+    }
+
+    /// Return the `nLockTime` value of the check lock time verify.
+    pub fn as_u32(&self) -> u32 {
+        match self {
+            Self::Height(value) | Self::MedianTimePast(value) => *value,
+        }
+    }
+
+    /// Return the value of nSequence that enables `nLockTime` while disabling relative timelocks
+    /// and signaling replaceability.
+    pub fn enable() -> u32 {
+        0xffff_fffd
+    }
+}
+
+/// Timelocks are ordered by value when they are of the same variant, a block height and a time
+/// cannot be compared.
+impl PartialOrd for CLTVTimelock {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        match (self, other) {
+            (Self::Height(a), Self::Height(b))
+            | (Self::MedianTimePast(a), Self::MedianTimePast(b)) => a.partial_cmp(b),
+            _ => None,
+        }
+    }
+}
+
+impl Timelock for CLTVTimelock {
+    fn opcode() -> opcodes::All {
+        opcodes::all::OP_CLTV
+    }
+
+    fn script_value(&self) -> u32 {
+        self.as_u32()
+    }
+
+    fn sequence(&self) -> u32 {
+        Self::enable()
+    }
+
+    fn lock_time(&self) -> u32 {
+        self.as_u32()
+    }
+}
+
+impl FromStr for CLTVTimelock {
+    type Err = consensus::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let parse = |x: &str| {
+            x.trim()
+                .parse::<u32>()
+                .map_err(|_| consensus::Error::ParseFailed("Failed parsing CLTV timelock"))
+        };
+        if let Some(height) = s.strip_prefix("height") {
+            return Self::from_height(parse(height)?);
+        }
+        if let Some(timestamp) = s.strip_prefix("time") {
+            return Self::from_time(parse(timestamp)?);
+        }
+        Ok(CLTVTimelock::from_consensus(parse(s)?))
+    }
+}
+
+impl From<CLTVTimelock> for u32 {
+    fn from(ti: CLTVTimelock) -> Self {
+        ti.as_u32()
+    }
+}
+
+impl CanonicalBytes for CLTVTimelock {
+    fn as_canonical_bytes(&self) -> Vec<u8> {
+        bitcoin::consensus::encode::serialize(&self.as_u32())
+    }
+
+    fn from_canonical_bytes(bytes: &[u8]) -> Result<Self, consensus::Error>
+    where
+        Self: Sized,
+    {
+        Ok(CLTVTimelock::from_consensus(
             bitcoin::consensus::encode::deserialize(bytes).map_err(consensus::Error::new)?,
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_timelock_units() {
+        let blocks = CSVTimelock::from_str("144 blocks").unwrap();
+        assert_eq!(blocks, CSVTimelock::from_blocks(144));
+        assert_eq!(format!("{}", blocks), "144 blocks");
+        assert_eq!(CSVTimelock::from_str("1 block").unwrap().as_u32(), 1);
+        // A bare integer is a number of blocks
+        assert_eq!(CSVTimelock::from_str("10").unwrap(), CSVTimelock::new(10));
+
+        // One day is rounded up to the next 512 seconds interval
+        let day = CSVTimelock::from_str("1d").unwrap();
+        assert!(day.is_time_based());
+        assert_eq!(day.value(), 169);
+        assert_eq!(day.as_u32(), SEQUENCE_LOCKTIME_TYPE_FLAG | 169);
+        assert_eq!(format!("{}", day), "1d");
+        assert_eq!(CSVTimelock::from_str(&format!("{}", day)).unwrap(), day);
+        // 90 minutes are rounded up to 11 intervals, i.e. 93 minutes and 52 seconds
+        assert_eq!(format!("{}", CSVTimelock::from_str("90m").unwrap()), "93m");
+        assert_eq!(format!("{}", CSVTimelock::from_str("600s").unwrap()), "17m");
+        assert_eq!(format!("{}", CSVTimelock::from_intervals(0)), "0s");
+        assert_eq!(
+            CSVTimelock::from_str("0s").unwrap(),
+            CSVTimelock::from_intervals(0)
+        );
+
+        let two_hours = CSVTimelock::from_str("128m").unwrap();
+        assert_eq!(two_hours, CSVTimelock::from_intervals(15));
+        assert_eq!(format!("{}", two_hours), "128m");
+        assert_eq!(two_hours.as_seconds(), Some(7_680));
+        assert_eq!(blocks.as_seconds(), None);
+
+        // Timelocks in different units cannot be compared
+        assert!(two_hours < day);
+        assert_eq!(blocks.partial_cmp(&day), None);
+    }
+
+    #[test]
+    fn csv_timelock_out_of_range() {
+        assert!(CSVTimelock::from_str("65536 blocks").is_err());
+        assert!(CSVTimelock::from_str("400d").is_err());
+        assert!(CSVTimelock::from_str("blocks").is_err());
+        assert!(CSVTimelock::from_str("65536").is_err());
+        assert!(CSVTimelock::from_str(&format!("{}", SEQUENCE_LOCKTIME_TYPE_FLAG | 1)).is_err());
+        assert!(!CSVTimelock::new(CSVTimelock::disable()).is_valid());
+        assert!(CSVTimelock::from_sequence(CSVTimelock::disable()).is_err());
+        assert!(CSVTimelock::from_sequence(1 << 23).is_err());
+        assert_eq!(
+            CSVTimelock::from_sequence(SEQUENCE_LOCKTIME_TYPE_FLAG | 169).unwrap(),
+            CSVTimelock::from_intervals(169)
+        );
+
+        // The type flag is preserved by the consensus encoding, invalid values are rejected
+        let day = CSVTimelock::from_str("1d").unwrap();
+        let bytes = day.as_canonical_bytes();
+        assert_eq!(CSVTimelock::from_canonical_bytes(&bytes).unwrap(), day);
+        let bytes = CSVTimelock::new(1 << 23).as_canonical_bytes();
+        assert!(CSVTimelock::from_canonical_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn cltv_timelock_variants() {
+        assert_eq!(
+            CLTVTimelock::from_height(800_000).unwrap(),
+            CLTVTimelock::Height(800_000)
+        );
+        assert!(CLTVTimelock::from_height(LOCK_TIME_THRESHOLD).is_err());
+        assert_eq!(
+            CLTVTimelock::from_time(1_700_000_000).unwrap(),
+            CLTVTimelock::MedianTimePast(1_700_000_000)
+        );
+        assert!(CLTVTimelock::from_time(800_000).is_err());
+        assert_eq!(
+            CLTVTimelock::from_consensus(LOCK_TIME_THRESHOLD),
+            CLTVTimelock::MedianTimePast(LOCK_TIME_THRESHOLD)
+        );
+    }
+
+    #[test]
+    fn cltv_timelock_in_transactions() {
+        let height = CLTVTimelock::from_str("800000").unwrap();
+        assert_eq!(format!("{}", height), "height 800000");
+        assert_eq!(height.lock_time(), 800_000);
+        // Relative timelocks are disabled, nLockTime is enforced
+        assert!(height.sequence() < u32::MAX && height.sequence() & (1 << 31) != 0);
+
+        let time = CLTVTimelock::from_str("1700000000").unwrap();
+        assert_eq!(format!("{}", time), "time 1700000000");
+        let bytes = time.as_canonical_bytes();
+        assert_eq!(CLTVTimelock::from_canonical_bytes(&bytes).unwrap(), time);
+
+        // The displayed form is parsed back, values must match their unit
+        assert_eq!(
+            CLTVTimelock::from_str(&format!("{}", height)).unwrap(),
+            height
+        );
+        assert_eq!(CLTVTimelock::from_str(&format!("{}", time)).unwrap(), time);
+        assert!(CLTVTimelock::from_str("height 1700000000").is_err());
+        assert!(CLTVTimelock::from_str("time 800000").is_err());
+        assert!(CLTVTimelock::from_str("height").is_err());
+
+        // Deserialization checks the value against the variant
+        let yaml = serde_yaml::to_string(&height).unwrap();
+        assert_eq!(serde_yaml::from_str::<CLTVTimelock>(&yaml).unwrap(), height);
+        assert_eq!(yaml, "---\nHeight: 800000\n");
+        assert!(serde_yaml::from_str::<CLTVTimelock>("Height: 1700000000").is_err());
+        assert!(serde_yaml::from_str::<CLTVTimelock>("MedianTimePast: 800000").is_err());
+
+        let csv = CSVTimelock::new(144);
+        assert_eq!((csv.sequence(), csv.lock_time()), (144, 0));
+
+        assert!(height < CLTVTimelock::Height(800_001));
+        assert_eq!(height.partial_cmp(&time), None);
+    }
+}
@@ -33,6 +33,7 @@ use bitcoin::{
 
 use thiserror::Error;
 
+use crate::blockchain::{Fee, FeeStrategyError};
 use crate::consensus::{self, CanonicalBytes};
 use crate::transaction::{Broadcastable, Error as FError, Finalizable, Linkable};
 use bitcoin::hashes::sha256d::Hash as Sha256dHash;
@@ -66,6 +67,9 @@ pub enum Error {
     /// Bitcoin script error
     #[error("Bitcoin script error: `{0}`")]
     BitcoinScript(#[from] bitcoin::blockdata::script::Error),
+    /// The witness of the input cannot be estimated from its script
+    #[error("The witness of the input cannot be estimated from its script")]
+    UnsupportedWitness,
 }
 
 impl From<Error> for FError {
@@ -99,6 +103,21 @@ pub struct Tx<T: SubTransaction> {
     pub(crate) _t: PhantomData<T>,
 }
 
+impl<T> Tx<T>
+where
+    T: SubTransaction,
+{
+    /// Appends an output to the transaction, e.g. a change or a service fee output. The
+    /// fee-bearing output is unchanged, the added output is left untouched when setting the fee
+    /// unless designated with [`Fee::set_fee_output`].
+    pub fn add_output(&mut self, tx_out: TxOut) -> Result<(), FeeStrategyError> {
+        let fee_output = self.psbt.fee_output()?;
+        self.psbt.unsigned_tx.output.push(tx_out);
+        self.psbt.outputs.push(psbt::Output::default());
+        self.psbt.set_fee_output(fee_output)
+    }
+}
+
 #[cfg(feature = "experimental")]
 #[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
 impl<T> Transaction<PartiallySignedTransaction, MetadataOutput, Amount> for Tx<T>
@@ -159,15 +178,11 @@ impl<T> Linkable<MetadataOutput> for Tx<T>
 where
     T: SubTransaction,
 {
+    /// The consumable output is always the first output of the template, additional outputs
+    /// such as change are not consumed by the swap transactions.
     fn get_consumable_output(&self) -> Result<MetadataOutput, FError> {
-        match self.psbt.unsigned_tx.output.len() {
-            1 => (),
-            2 => {
-                if !self.psbt.unsigned_tx.is_coin_base() {
-                    return Err(FError::new(Error::MultiUTXOUnsuported));
-                }
-            }
-            _ => return Err(FError::new(Error::MultiUTXOUnsuported)),
+        if self.psbt.unsigned_tx.output.is_empty() {
+            return Err(FError::MissingUTXO);
         }
 
         Ok(MetadataOutput {
@@ -184,30 +199,48 @@ impl<T> Witnessable<Sha256dHash, PublicKey, Signature> for Tx<T>
 where
     T: SubTransaction,
 {
-    /// ## Safety
-    /// This function is used for generating the witness message for all transactions but not
-    /// funding. So implying only 1 input is valid as all templates only have 1 input.
-    fn generate_witness_message(&self, _path: ScriptPath) -> Result<Sha256dHash, FError> {
+    /// Generate the witness message of the first input, all templates but the lock have a single
+    /// input.
+    fn generate_witness_message(&self, path: ScriptPath) -> Result<Sha256dHash, FError> {
+        self.generate_input_witness_message(0, path)
+    }
+
+    fn add_witness(&mut self, pubkey: PublicKey, sig: Signature) -> Result<(), FError> {
+        self.add_input_witness(0, pubkey, sig)
+    }
+
+    fn inputs_count(&self) -> usize {
+        self.psbt.inputs.len()
+    }
+
+    fn generate_input_witness_message(
+        &self,
+        index: usize,
+        _path: ScriptPath,
+    ) -> Result<Sha256dHash, FError> {
         let unsigned_tx = self.psbt.unsigned_tx.clone();
-        let txin = TxInRef::new(&unsigned_tx, 0);
+        let input = self.psbt.inputs.get(index).ok_or(FError::MissingUTXO)?;
+        let txin = TxInRef::new(&unsigned_tx, index);
 
-        let witness_utxo = self.psbt.inputs[0]
-            .witness_utxo
-            .clone()
-            .ok_or(FError::MissingWitness)?;
+        let witness_utxo = input.witness_utxo.clone().ok_or(FError::MissingWitness)?;
 
-        let script = self.psbt.inputs[0]
-            .witness_script
-            .clone()
-            .ok_or(FError::MissingWitness)?;
+        let script = input.witness_script.clone().ok_or(FError::MissingWitness)?;
         let value = witness_utxo.value;
 
         Ok(signature_hash(txin, &script, value, EcdsaSighashType::All))
     }
 
-    fn add_witness(&mut self, pubkey: PublicKey, sig: Signature) -> Result<(), FError> {
+    fn add_input_witness(
+        &mut self,
+        index: usize,
+        pubkey: PublicKey,
+        sig: Signature,
+    ) -> Result<(), FError> {
         let sig_all = EcdsaSig::sighash_all(sig);
-        self.psbt.inputs[0]
+        self.psbt
+            .inputs
+            .get_mut(index)
+            .ok_or(FError::MissingUTXO)?
             .partial_sigs
             .insert(bitcoin::PublicKey::new(pubkey), sig_all);
         Ok(())
@@ -309,6 +309,16 @@ pub enum FeeStrategyError {
     /// Not enough assets to cover the fees.
     #[error("Not enough assets to cover the fees")]
     NotEnoughAssets,
+    /// The sum of the amounts overflows.
+    #[error("Amount overflow")]
+    AmountOverflow,
+    /// The fee-bearing output is missing, ambiguous, or out of range.
+    #[error("Invalid fee-bearing output")]
+    InvalidFeeOutput,
+    /// The fee ladder has an unexpected number of rungs or its fee rates are not strictly
+    /// increasing.
+    #[error("Invalid fee ladder")]
+    InvalidFeeLadder,
     /// Any fee strategy error not part of this list.
     #[error("Other: {0}")]
     Other(Box<dyn error::Error + Sync + Send>),
@@ -387,8 +397,12 @@ impl FromStr for FeePriority {
 /// [`FeeStrategy`], in case the fee strategy allow multiple values a [`FeePriority`] is used to
 /// fix the amount.
 ///
+/// Transactions may have more than one output, e.g. a change output or a service fee output. The
+/// fee is always absorbed by the designated fee-bearing output, other outputs are never modified
+/// when setting the fee.
+///
 /// ```
-/// use bitcoin::Amount;
+/// use bitcoin::{Amount, TxOut};
 /// use bitcoin::util::psbt::PartiallySignedTransaction;
 /// use farcaster_core::crypto::SharedKeyId;
 /// use farcaster_core::blockchain::{Fee, FeeStrategy, FeePriority, FeeStrategyError};
@@ -399,6 +413,7 @@ impl FromStr for FeePriority {
 /// impl Fee for Psbt {
 ///     type FeeUnit = SatPerBytes;
 ///     type Amount = Amount;
+///     type Output = TxOut;
 ///
 ///     fn set_fee(
 ///         &mut self, strategy:
@@ -414,6 +429,30 @@ impl FromStr for FeePriority {
 ///     ) -> Result<bool, FeeStrategyError> {
 ///         todo!()
 ///     }
+///
+///     fn set_fee_output(&mut self, index: usize) -> Result<(), FeeStrategyError> {
+///         todo!()
+///     }
+///
+///     fn fee_output(&self) -> Result<usize, FeeStrategyError> {
+///         todo!()
+///     }
+///
+///     fn validate_outputs(&self, expected: &[TxOut]) -> Result<bool, FeeStrategyError> {
+///         todo!()
+///     }
+///
+///     fn fee_rate(&self) -> Result<SatPerBytes, FeeStrategyError> {
+///         todo!()
+///     }
+///
+///     fn fee_ladder(
+///         strategy: &FeeStrategy<SatPerBytes>,
+///         politic: FeePriority,
+///         rungs: usize,
+///     ) -> Vec<SatPerBytes> {
+///         todo!()
+///     }
 /// }
 /// ```
 pub trait Fee {
@@ -423,8 +462,11 @@ pub trait Fee {
     /// Type of asset quantity.
     type Amount;
 
-    /// Calculates and sets the fee on the given transaction and return the amount of fee set in
-    /// the blockchain native amount format.
+    /// Type of transaction output.
+    type Output;
+
+    /// Calculates and sets the fee on the fee-bearing output of the given transaction and return
+    /// the amount of fee set in the blockchain native amount format.
     fn set_fee(
         &mut self,
         strategy: &FeeStrategy<Self::FeeUnit>,
@@ -434,6 +476,32 @@ pub trait Fee {
     /// Validates that the fee for the given transaction are set accordingly to the strategy.
     fn validate_fee(&self, strategy: &FeeStrategy<Self::FeeUnit>)
         -> Result<bool, FeeStrategyError>;
+
+    /// Designates the output absorbing the fee, identified by its index in the transaction.
+    fn set_fee_output(&mut self, index: usize) -> Result<(), FeeStrategyError>;
+
+    /// Returns the index of the output absorbing the fee. Fails if no output is designated and
+    /// the transaction has more than one output.
+    fn fee_output(&self) -> Result<usize, FeeStrategyError>;
+
+    /// Validates that the outputs other than the fee-bearing output are, in order, the expected
+    /// outputs and are thus left untouched by the fee.
+    fn validate_outputs(&self, expected: &[Self::Output]) -> Result<bool, FeeStrategyError>;
+
+    /// Returns the effective fee rate paid by the transaction.
+    fn fee_rate(&self) -> Result<Self::FeeUnit, FeeStrategyError>;
+
+    /// Returns up to `rungs` increasing fee rates inside the strategy and strictly above the fee
+    /// rate selected by the politic, the last one being the maximum of the strategy. Used to
+    /// pre-sign a ladder of transactions replacing each other with increasing fees. A fixed
+    /// strategy, or a high politic, leaves no room for bumping and returns no fee rate.
+    fn fee_ladder(
+        strategy: &FeeStrategy<Self::FeeUnit>,
+        politic: FeePriority,
+        rungs: usize,
+    ) -> Vec<Self::FeeUnit>
+    where
+        Self: Sized;
 }
 
 impl FromStr for Network {
@@ -145,6 +145,17 @@ pub fn deserialize<T: Decodable>(data: &[u8]) -> Result<T, Error> {
     }
 }
 
+/// Decode an optional value appended at the end of an existing encoding. Data encoded before the
+/// value was added ends where the value starts and decodes as `None`, otherwise the value is
+/// decoded as an [`Option`].
+pub fn decode_trailing_option<T: Decodable, D: io::Read>(d: &mut D) -> Result<Option<T>, Error> {
+    let mut tag = [0u8; 1];
+    match d.read(&mut tag)? {
+        0 => Ok(None),
+        _ => Option::<T>::consensus_decode(&mut io::Read::chain(&tag[..], d)),
+    }
+}
+
 /// Deserialize an object from a vector of bytes, but will not report an error if said
 /// deserialization doesn't consume the entire vector.
 pub fn deserialize_partial<T: Decodable>(data: &[u8]) -> Result<(T, usize), Error> {
@@ -33,6 +33,12 @@ use crate::consensus::{self, CanonicalBytes, Decodable, Encodable};
 pub mod dleq;
 #[cfg(feature = "experimental")]
 #[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
+pub mod musig2;
+#[cfg(feature = "experimental")]
+#[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
+pub mod schnorr;
+#[cfg(feature = "experimental")]
+#[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
 pub mod slip10;
 
 /// List of cryptographic errors that can be encountered in cryptographic operations such as
@@ -54,6 +60,12 @@ pub enum Error {
     /// The adaptor signature does not pass the validation tests.
     #[error("The adaptor signature does not pass the validation")]
     InvalidEncryptedSignature,
+    /// The secret nonce is missing or has already been used.
+    #[error("The secret nonce is missing or has already been used")]
+    MissingNonce,
+    /// The partial signature does not pass the validation tests.
+    #[error("The partial signature does not pass the validation")]
+    InvalidPartialSignature,
     /// The proof does not pass the validation tests.
     #[error("The proof does not pass the validation")]
     InvalidProof,
@@ -315,8 +327,83 @@ impl Commit<KeccakCommitment> for CommitmentEngine {
         keccak.finalize(&mut out);
         KeccakCommitment::new(out)
     }
+
+    fn commit_to_salted<T: AsRef<[u8]>>(
+        &self,
+        value: T,
+        nonce: &CommitmentNonce,
+    ) -> KeccakCommitment {
+        let mut out = [0u8; 32];
+        let mut keccak = Keccak::v256();
+        keccak.update(nonce.as_bytes());
+        keccak.update(value.as_ref());
+        keccak.finalize(&mut out);
+        KeccakCommitment::new(out)
+    }
+}
+
+fixed_hash::construct_fixed_hash!(
+    /// Random blinding nonce of a salted commitment.
+    #[derive(Serialize, Deserialize)]
+    pub struct CommitmentNonce(32);
+);
+
+impl CommitmentNonce {
+    /// Draw a new nonce from the operating system random number generator.
+    pub fn new_random() -> Self {
+        let mut nonce = [0u8; 32];
+        rand_alt::RngCore::fill_bytes(&mut rand_alt::rngs::OsRng, &mut nonce);
+        Self(nonce)
+    }
+}
+
+impl CanonicalBytes for CommitmentNonce {
+    fn as_canonical_bytes(&self) -> Vec<u8> {
+        (*self).to_fixed_bytes().into()
+    }
+
+    fn from_canonical_bytes(bytes: &[u8]) -> Result<Self, consensus::Error>
+    where
+        Self: Sized,
+    {
+        Ok(Self(bytes.try_into().map_err(consensus::Error::new)?))
+    }
+}
+
+/// Scheme used to commit to the parameters during the commit/reveal phase, carried by the commit
+/// messages so commitments of both schemes can be decoded and verified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+#[display(Debug)]
+pub enum CommitmentScheme {
+    /// Each commitment is the hash of the canonical bytes of the value. Low-entropy values can be
+    /// recovered from the commitment by brute force.
+    Bare,
+    /// Each commitment is the hash of a random [`CommitmentNonce`] followed by the canonical bytes
+    /// of the value, the nonces are revealed with the values.
+    Salted,
+}
+
+impl Encodable for CommitmentScheme {
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        match self {
+            Self::Bare => 0x01u8.consensus_encode(writer),
+            Self::Salted => 0x02u8.consensus_encode(writer),
+        }
+    }
+}
+
+impl Decodable for CommitmentScheme {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        match Decodable::consensus_decode(d)? {
+            0x01u8 => Ok(Self::Bare),
+            0x02u8 => Ok(Self::Salted),
+            _ => Err(consensus::Error::UnknownType),
+        }
+    }
 }
 
+impl_strict_encoding!(CommitmentScheme);
+
 /// Required for arbitrating and accordant blockchains to dervice extra public keys (keys not
 /// automatically derives by the protocol by default) and extra shared private keys. Shared private
 /// keys are used in situation when e.g. blockchain is not transparent or when extra nonces should
@@ -455,13 +542,64 @@ pub trait EncSign<PublicKey, Message, Signature, EncryptedSignature> {
 /// encryption key.
 pub trait RecoverSecret<PublicKey, SecretKey, Signature, EncryptedSignature> {
     /// Recover the encryption key based on the encrypted signature, the encryption public key, and
-    /// the regular (decrypted) signature.
+    /// the regular (decrypted) signature. Fails if the signature is not the decryption of the
+    /// encrypted signature.
     fn recover_secret_key(
         &self,
         encrypted_sig: EncryptedSignature,
         encryption_key: &PublicKey,
         sig: Signature,
-    ) -> SecretKey;
+    ) -> Result<SecretKey, Error>;
+}
+
+/// Two-round multi-signature participant, e.g. MuSig2. Public nonces are generated and exchanged
+/// first, then each participant produces a partial signature with its arbitrating key in a signing
+/// session created with all the public nonces. Partial signatures are aggregated into a regular
+/// signature or, if the session is encrypted, into an encrypted signature.
+pub trait MultiSign<
+    PublicKey,
+    PublicNonce,
+    Session,
+    PartialSignature,
+    Signature,
+    EncryptedSignature,
+>
+{
+    /// Generate a fresh nonce for the private key identified by the arbitrating key identifier.
+    /// The secret nonce is kept internally until consumed by [`MultiSign::partial_sign`].
+    fn generate_nonce(&mut self, key: ArbitratingKeyId) -> Result<PublicNonce, Error>;
+
+    /// Produce the partial signature of the session with the corresponding private key identified
+    /// by the arbitrating key identifier. The secret nonce matching one of the session's public
+    /// nonces is consumed, if none is found an [`Error::MissingNonce`] is returned.
+    fn partial_sign(
+        &mut self,
+        key: ArbitratingKeyId,
+        session: &Session,
+    ) -> Result<PartialSignature, Error>;
+
+    /// Verify the partial signature of a participant with its public key and public nonce.
+    fn verify_partial_signature(
+        &self,
+        key: &PublicKey,
+        nonce: &PublicNonce,
+        session: &Session,
+        sig: &PartialSignature,
+    ) -> Result<(), Error>;
+
+    /// Aggregate all the participants partial signatures into a regular signature.
+    fn aggregate_signature(
+        &self,
+        session: &Session,
+        sigs: &[PartialSignature],
+    ) -> Result<Signature, Error>;
+
+    /// Aggregate all the participants partial signatures into an encrypted signature.
+    fn aggregate_encrypted_signature(
+        &self,
+        session: &Session,
+        sigs: &[PartialSignature],
+    ) -> Result<EncryptedSignature, Error>;
 }
 
 /// Commitment generator and verifier. Generated commitments can be validated against candidates,
@@ -470,6 +608,10 @@ pub trait Commit<Commitment> {
     /// Provides a generic method to commit to any value referencable as stream of bytes.
     fn commit_to<T: AsRef<[u8]>>(&self, value: T) -> Commitment;
 
+    /// Commit to a value blinded with a random nonce, see [`CommitmentScheme::Salted`]. The nonce
+    /// is revealed with the value to open the commitment.
+    fn commit_to_salted<T: AsRef<[u8]>>(&self, value: T, nonce: &CommitmentNonce) -> Commitment;
+
     /// Validate the equality between a candidate and a commitment, return `Ok(())` if the value
     /// commits to the same commitment's candidate, return [`Error::InvalidCommitment`]
     /// otherwise.
@@ -483,6 +625,24 @@ pub trait Commit<Commitment> {
             Err(Error::InvalidCommitment)
         }
     }
+
+    /// Validate the equality between a candidate blinded with the revealed nonce and a salted
+    /// commitment, return [`Error::InvalidCommitment`] if they differ.
+    fn validate_salted<T: AsRef<[u8]>>(
+        &self,
+        candidate: T,
+        nonce: &CommitmentNonce,
+        commitment: Commitment,
+    ) -> Result<(), Error>
+    where
+        Commitment: Eq,
+    {
+        if self.commit_to_salted(candidate, nonce) == commitment {
+            Ok(())
+        } else {
+            Err(Error::InvalidCommitment)
+        }
+    }
 }
 
 /// Proof generator and verifier for the cross-group projection of the accordant public spend key
@@ -0,0 +1,615 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Two-round multi-signatures (MuSig2) producing [`BIP-340`][bip-340] signatures valid for a
+//! [`BIP-341`][bip-341] tweaked aggregated key, with support for adaptor signatures.
+//!
+//! The scheme is inspired by [`BIP-327`][bip-327] but is **not** compatible with it: participant
+//! keys are x-only keys lifted to their even `y` point, every key gets an aggregation coefficient
+//! (there is no second key optimization), and the nonce coefficient commits to the Taproot output
+//! key instead of the aggregated nonce and key of BIP-327. Keys are sorted before aggregation so
+//! the aggregated key does not depend on the order in which participants are provided. The
+//! aggregated key is always tweaked as a Taproot output key, with or without a script tree merkle
+//! root.
+//!
+//! When a signing [`Session`] is created with an encryption key the aggregated partial signatures
+//! form an [`EncryptedSignature`] that can be decrypted and used to recover the decryption key
+//! with the [`schnorr`][crate::crypto::schnorr] adaptor engine.
+//!
+//! [bip-327]: https://github.com/bitcoin/bips/blob/master/bip-0327.mediawiki
+//! [bip-340]: https://github.com/bitcoin/bips/blob/master/bip-0340.mediawiki
+//! [bip-341]: https://github.com/bitcoin/bips/blob/master/bip-0341.mediawiki
+
+use std::io;
+
+use crate::consensus::{self, CanonicalBytes, Decodable, Encodable};
+use crate::crypto::schnorr::EncryptedSignature;
+
+use bitcoin::secp256k1::schnorr::Signature;
+use secp256kfun::{
+    derive_nonce, g,
+    hash::{HashAdd, Tagged},
+    marker::*,
+    nonce::NonceGen,
+    s, Point, Scalar, XOnly, G,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Aggregation of the participants public keys into a single Taproot output key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyAggContext {
+    /// The sorted participants keys lifted to their even `y` point.
+    keys: Vec<Point<EvenY>>,
+    /// The aggregation coefficient of each key.
+    coefficients: Vec<Scalar<Public>>,
+    /// The untweaked aggregated key, i.e. the Taproot internal key.
+    internal_key: Point<EvenY>,
+    /// Set if the sum of the keys had to be negated to get the internal key.
+    internal_needs_negation: bool,
+    /// The Taproot tweak applied on the internal key.
+    tweak: Scalar<Public, Zero>,
+    /// The tweaked aggregated key, i.e. the Taproot output key.
+    output_key: Point<EvenY>,
+    /// Set if the tweaked key had to be negated to get the output key.
+    output_needs_negation: bool,
+}
+
+impl KeyAggContext {
+    /// Aggregate the x-only public keys and tweak the result with the optional tapscript tree
+    /// merkle root. Returns `None` if the list of keys is empty.
+    #[allow(non_snake_case)]
+    pub fn new(mut keys: Vec<XOnly>, merkle_root: Option<[u8; 32]>) -> Option<Self> {
+        if keys.is_empty() {
+            return None;
+        }
+        keys.sort_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+
+        let list_hash = keys
+            .iter()
+            .fold(Sha256::default().tagged(b"KeyAgg list"), |hash, key| {
+                hash.add(*key)
+            })
+            .finalize();
+        let coefficients: Vec<Scalar<Public>> = keys
+            .iter()
+            .map(|key| {
+                Scalar::from_hash(
+                    Sha256::default()
+                        .tagged(b"KeyAgg coefficient")
+                        .add(&list_hash[..])
+                        .add(*key),
+                )
+                .mark::<Public>()
+            })
+            .collect();
+        let keys: Vec<Point<EvenY>> = keys.iter().map(XOnly::to_point).collect();
+
+        let Q = keys
+            .iter()
+            .zip(coefficients.iter())
+            .fold(Point::zero().mark::<Jacobian>(), |acc, (X, a)| {
+                g!(acc + a * X)
+            })
+            .mark::<NonZero>()
+            .expect("computationally unreachable")
+            .mark::<Normal>();
+        let (internal_key, internal_needs_negation) = Q.into_point_with_even_y();
+
+        let mut tweak_hash = Sha256::default()
+            .tagged(b"TapTweak")
+            .add(internal_key.to_xonly());
+        if let Some(merkle_root) = merkle_root {
+            tweak_hash = tweak_hash.add(&merkle_root[..]);
+        }
+        let mut tweak_bytes = [0u8; 32];
+        tweak_bytes.copy_from_slice(&tweak_hash.finalize()[..]);
+        let tweak = Scalar::from_bytes_mod_order(tweak_bytes).mark::<Public>();
+
+        let P = g!(internal_key + tweak * G)
+            .mark::<NonZero>()
+            .expect("computationally unreachable")
+            .mark::<Normal>();
+        let (output_key, output_needs_negation) = P.into_point_with_even_y();
+
+        Some(Self {
+            keys,
+            coefficients,
+            internal_key,
+            internal_needs_negation,
+            tweak,
+            output_key,
+            output_needs_negation,
+        })
+    }
+
+    /// Returns the untweaked aggregated key, to use as the Taproot internal key.
+    pub fn internal_key(&self) -> XOnly {
+        self.internal_key.to_xonly()
+    }
+
+    /// Returns the tweaked aggregated key, the Taproot output key signatures are valid for.
+    pub fn output_key(&self) -> XOnly {
+        self.output_key.to_xonly()
+    }
+
+    // Returns the coefficient of the participant key, if part of the aggregation.
+    fn coefficient(&self, key: &Point<EvenY>) -> Option<&Scalar<Public>> {
+        self.keys
+            .iter()
+            .position(|k| k == key)
+            .map(|i| &self.coefficients[i])
+    }
+
+    // True if the participants secret keys must be negated, the output key being computed from
+    // a possibly negated internal key and negated again if the tweaked key had an odd `y`.
+    fn needs_negation(&self) -> bool {
+        self.internal_needs_negation ^ self.output_needs_negation
+    }
+}
+
+/// A pair of secret nonces, must be used for only one partial signature. The nonces are not
+/// cloneable and are consumed when signing to prevent their reuse.
+#[derive(Debug)]
+pub struct SecretNonce {
+    r1: Scalar,
+    r2: Scalar,
+}
+
+impl SecretNonce {
+    /// Generate a fresh pair of secret nonces for the secret key. Nonces are derived with the
+    /// nonce generator from the secret key and the public key, the generator must add randomness
+    /// for the nonces to be different at each call.
+    ///
+    /// # Safety
+    ///
+    /// Only use a nonce generator with synthetic randomness, e.g.
+    /// [`Synthetic`](secp256kfun::nonce::Synthetic). A
+    /// [`Deterministic`](secp256kfun::nonce::Deterministic) generator returns the same nonces for
+    /// every session signed with the same key, and two partial signatures with the same nonces on
+    /// different messages leak the secret key.
+    pub fn generate(nonce_gen: &impl NonceGen, secret_key: &Scalar) -> Self {
+        let public_key = g!(secret_key * G).mark::<Normal>().to_xonly();
+        let r1 = derive_nonce!(
+            nonce_gen => nonce_gen,
+            secret => secret_key,
+            public => [public_key, b"MuSig/nonce/1".as_ref()]
+        );
+        let r2 = derive_nonce!(
+            nonce_gen => nonce_gen,
+            secret => secret_key,
+            public => [public_key, b"MuSig/nonce/2".as_ref()]
+        );
+        Self { r1, r2 }
+    }
+
+    /// Returns the public nonces to share with the other participants.
+    pub fn public_nonce(&self) -> PublicNonce {
+        PublicNonce {
+            r1: g!(self.r1 * G).mark::<Normal>(),
+            r2: g!(self.r2 * G).mark::<Normal>(),
+        }
+    }
+}
+
+/// A pair of public nonces, shared with the other participants before signing.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublicNonce {
+    r1: Point,
+    r2: Point,
+}
+
+impl CanonicalBytes for PublicNonce {
+    fn as_canonical_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(66);
+        bytes.extend_from_slice(&self.r1.to_bytes());
+        bytes.extend_from_slice(&self.r2.to_bytes());
+        bytes
+    }
+
+    fn from_canonical_bytes(bytes: &[u8]) -> Result<Self, consensus::Error>
+    where
+        Self: Sized,
+    {
+        if bytes.len() != 66 {
+            return Err(consensus::Error::ParseFailed("Invalid public nonce length"));
+        }
+        Ok(Self {
+            r1: Point::from_slice(&bytes[..33])
+                .ok_or(consensus::Error::ParseFailed("Invalid nonce point"))?,
+            r2: Point::from_slice(&bytes[33..])
+                .ok_or(consensus::Error::ParseFailed("Invalid nonce point"))?,
+        })
+    }
+}
+
+impl Encodable for PublicNonce {
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        self.as_canonical_bytes().consensus_encode(writer)
+    }
+}
+
+impl Decodable for PublicNonce {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Self::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())
+    }
+}
+
+/// A participant partial signature, aggregated with the other participants ones into the final
+/// signature.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Serialize, Deserialize)]
+pub struct PartialSignature(Scalar<Public, Zero>);
+
+impl CanonicalBytes for PartialSignature {
+    fn as_canonical_bytes(&self) -> Vec<u8> {
+        self.0.to_bytes().into()
+    }
+
+    fn from_canonical_bytes(bytes: &[u8]) -> Result<Self, consensus::Error>
+    where
+        Self: Sized,
+    {
+        Scalar::from_slice(bytes)
+            .map(|s| Self(s.mark::<Public>()))
+            .ok_or(consensus::Error::ParseFailed("Invalid partial signature"))
+    }
+}
+
+impl Encodable for PartialSignature {
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        self.as_canonical_bytes().consensus_encode(writer)
+    }
+}
+
+impl Decodable for PartialSignature {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Self::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())
+    }
+}
+
+/// A signing session for a message, created by every participant once all the public nonces are
+/// exchanged. Partial signatures are produced, verified and aggregated in a session.
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Session {
+    key_agg: KeyAggContext,
+    nonces: Vec<PublicNonce>,
+    encryption_key: Option<XOnly>,
+    /// The nonce coefficient `b`.
+    b: Scalar<Public, Zero>,
+    /// The final nonce of the signature, including the encryption key if any.
+    R: Point<EvenY>,
+    /// Set if the participants nonces must be negated.
+    nonce_needs_negation: bool,
+    /// The BIP-340 challenge `e`.
+    challenge: Scalar<Public, Zero>,
+}
+
+impl Session {
+    /// Create a signing session for the 32 bytes message with all the participants public
+    /// nonces. If an encryption key is provided the aggregated signature is encrypted under that
+    /// key. Returns `None` if the aggregated nonce is invalid.
+    #[allow(non_snake_case)]
+    pub fn new(
+        key_agg: KeyAggContext,
+        nonces: &[PublicNonce],
+        encryption_key: Option<XOnly>,
+        message: &[u8; 32],
+    ) -> Option<Self> {
+        let (R1, R2) = nonces.iter().fold(
+            (
+                Point::zero().mark::<Jacobian>(),
+                Point::zero().mark::<Jacobian>(),
+            ),
+            |(R1, R2), nonce| (g!(R1 + nonce.r1), g!(R2 + nonce.r2)),
+        );
+        let R1 = R1.mark::<NonZero>()?.mark::<Normal>();
+        let R2 = R2.mark::<NonZero>()?.mark::<Normal>();
+
+        let b = Scalar::from_hash(
+            Sha256::default()
+                .tagged(b"MuSig/noncecoef")
+                .add(R1)
+                .add(R2)
+                .add(key_agg.output_key.to_xonly())
+                .add(&message[..]),
+        )
+        .mark::<Public>()
+        .mark::<Zero>();
+
+        let R = match encryption_key {
+            Some(Y) => {
+                let Y = Y.to_point();
+                g!(R1 + b * R2 + Y)
+            }
+            None => g!(R1 + b * R2),
+        };
+        let (R, nonce_needs_negation) = R
+            .mark::<NonZero>()?
+            .mark::<Normal>()
+            .into_point_with_even_y();
+
+        let challenge = challenge(&R, &key_agg.output_key, message);
+
+        Some(Self {
+            key_agg,
+            nonces: nonces.to_vec(),
+            encryption_key,
+            b,
+            R,
+            nonce_needs_negation,
+            challenge,
+        })
+    }
+
+    /// Returns the key aggregation context of the session.
+    pub fn key_agg(&self) -> &KeyAggContext {
+        &self.key_agg
+    }
+
+    /// Returns the participants public nonces of the session.
+    pub fn nonces(&self) -> &[PublicNonce] {
+        &self.nonces
+    }
+
+    /// Produce the partial signature of the participant, consuming its secret nonce. Returns
+    /// `None` if the secret key is not part of the key aggregation.
+    pub fn partial_sign(
+        &self,
+        secret_key: &Scalar,
+        secret_nonce: SecretNonce,
+    ) -> Option<PartialSignature> {
+        let SecretNonce { mut r1, mut r2 } = secret_nonce;
+        let (public_key, key_needs_negation) =
+            g!(secret_key * G).mark::<Normal>().into_point_with_even_y();
+        let a = self.key_agg.coefficient(&public_key)?;
+
+        let mut x = secret_key.clone();
+        x.conditional_negate(key_needs_negation ^ self.key_agg.needs_negation());
+        r1.conditional_negate(self.nonce_needs_negation);
+        r2.conditional_negate(self.nonce_needs_negation);
+
+        let (b, e) = (&self.b, &self.challenge);
+        Some(PartialSignature(
+            s!(r1 + b * r2 + e * a * x).mark::<Public>(),
+        ))
+    }
+
+    /// Verify the partial signature of a participant against its x-only public key and public
+    /// nonce.
+    #[allow(non_snake_case)]
+    pub fn verify_partial_signature(
+        &self,
+        public_key: &XOnly,
+        public_nonce: &PublicNonce,
+        partial_signature: &PartialSignature,
+    ) -> bool {
+        let X = public_key.to_point();
+        let a = match self.key_agg.coefficient(&X) {
+            Some(a) => a,
+            None => return false,
+        };
+        let X = X.conditional_negate(self.key_agg.needs_negation());
+        let R = g!(public_nonce.r1 + self.b * public_nonce.r2)
+            .mark::<Normal>()
+            .conditional_negate(self.nonce_needs_negation);
+        let (s, e) = (&partial_signature.0, &self.challenge);
+
+        g!(s * G) == g!(R + (e * a) * X)
+    }
+
+    // Sums the partial signatures and the tweak contribution.
+    fn aggregate_scalar(&self, partial_signatures: &[PartialSignature]) -> Scalar<Public, Zero> {
+        let mut tweak = self.key_agg.tweak;
+        tweak.conditional_negate(self.key_agg.output_needs_negation);
+        let e = &self.challenge;
+        partial_signatures.iter().fold(
+            s!(e * tweak).mark::<Public>(),
+            |acc, PartialSignature(s)| s!(acc + s).mark::<Public>(),
+        )
+    }
+
+    /// Aggregate the partial signatures into a valid BIP-340 signature for the output key.
+    /// Returns `None` if the session has an encryption key.
+    pub fn aggregate(&self, partial_signatures: &[PartialSignature]) -> Option<Signature> {
+        if self.encryption_key.is_some() {
+            return None;
+        }
+        let s = self.aggregate_scalar(partial_signatures);
+
+        let mut bytes = [0u8; 64];
+        bytes[..32].copy_from_slice(self.R.to_xonly().as_bytes());
+        bytes[32..].copy_from_slice(&s.to_bytes());
+        Some(Signature::from_slice(&bytes).expect("64 bytes signature"))
+    }
+
+    /// Aggregate the partial signatures into an encrypted signature for the output key. Returns
+    /// `None` if the session has no encryption key.
+    pub fn aggregate_encrypted(
+        &self,
+        partial_signatures: &[PartialSignature],
+    ) -> Option<EncryptedSignature> {
+        self.encryption_key.as_ref()?;
+        let s_hat = self.aggregate_scalar(partial_signatures);
+
+        Some(EncryptedSignature::new(
+            self.R,
+            s_hat,
+            self.nonce_needs_negation,
+        ))
+    }
+}
+
+/// Compute the BIP-340 challenge `H_tag(R.x || X.x || m)`.
+#[allow(non_snake_case)]
+fn challenge(R: &Point<EvenY>, X: &Point<EvenY>, message: &[u8; 32]) -> Scalar<Public, Zero> {
+    let hash = Sha256::default()
+        .tagged(b"BIP0340/challenge")
+        .add(R.to_xonly())
+        .add(X.to_xonly())
+        .add(&message[..]);
+    let mut bytes = [0u8; 32];
+    bytes.copy_from_slice(&hash.finalize()[..]);
+    Scalar::from_bytes_mod_order(bytes).mark::<Public>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::schnorr::Adaptor;
+
+    use bitcoin::secp256k1::{Message, Secp256k1, XOnlyPublicKey};
+    use secp256kfun::nonce::{Deterministic, GlobalRng, Synthetic};
+
+    type NG = Synthetic<Sha256, GlobalRng<rand::rngs::ThreadRng>>;
+
+    fn keypair() -> (Scalar, XOnly) {
+        let secret = Scalar::random(&mut rand::thread_rng());
+        let public = g!(secret * G).mark::<Normal>().to_xonly();
+        (secret, public)
+    }
+
+    fn verify(pubkey: &XOnly, message: &[u8; 32], sig: &Signature) -> bool {
+        let secp = Secp256k1::verification_only();
+        let pubkey = XOnlyPublicKey::from_slice(pubkey.as_bytes()).unwrap();
+        let message = Message::from_slice(message).unwrap();
+        secp.verify_schnorr(sig, &message, &pubkey).is_ok()
+    }
+
+    #[test]
+    fn aggregated_key_matches_taproot_output_key() {
+        let secp = Secp256k1::verification_only();
+        for merkle_root in [None, Some([3u8; 32])] {
+            let (_, alice) = keypair();
+            let (_, bob) = keypair();
+            let key_agg = KeyAggContext::new(vec![alice, bob], merkle_root).unwrap();
+            let key_agg_rev = KeyAggContext::new(vec![bob, alice], merkle_root).unwrap();
+            assert_eq!(key_agg, key_agg_rev);
+
+            let internal_key =
+                XOnlyPublicKey::from_slice(key_agg.internal_key().as_bytes()).unwrap();
+            let merkle_root = merkle_root.map(|root| {
+                use bitcoin::hashes::Hash;
+                bitcoin::util::taproot::TapBranchHash::from_inner(root)
+            });
+            let script = bitcoin::Script::new_v1_p2tr(&secp, internal_key, merkle_root);
+            assert_eq!(&script[2..], key_agg.output_key().as_bytes());
+        }
+        assert!(KeyAggContext::new(vec![], None).is_none());
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn sign_and_aggregate() {
+        let nonce_gen = NG::default();
+        for i in 0..16u8 {
+            let (x1, X1) = keypair();
+            let (x2, X2) = keypair();
+            let message = [i; 32];
+            let merkle_root = if i % 2 == 0 { None } else { Some([i; 32]) };
+            let key_agg = KeyAggContext::new(vec![X1, X2], merkle_root).unwrap();
+
+            let nonce1 = SecretNonce::generate(&nonce_gen, &x1);
+            let nonce2 = SecretNonce::generate(&nonce_gen, &x2);
+            let nonces = [nonce1.public_nonce(), nonce2.public_nonce()];
+            let session = Session::new(key_agg.clone(), &nonces, None, &message).unwrap();
+
+            let sig1 = session.partial_sign(&x1, nonce1).unwrap();
+            let sig2 = session.partial_sign(&x2, nonce2).unwrap();
+            assert!(session.verify_partial_signature(&X1, &nonces[0], &sig1));
+            assert!(session.verify_partial_signature(&X2, &nonces[1], &sig2));
+            assert!(!session.verify_partial_signature(&X1, &nonces[0], &sig2));
+            assert!(!session.verify_partial_signature(&X2, &nonces[0], &sig2));
+
+            let sig = session.aggregate(&[sig1, sig2]).unwrap();
+            assert!(verify(&key_agg.output_key(), &message, &sig));
+            assert!(session.aggregate_encrypted(&[sig1, sig2]).is_none());
+        }
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn sign_aggregate_and_decrypt() {
+        let nonce_gen = NG::default();
+        let adaptor = Adaptor::new(Deterministic::<Sha256>::default());
+        for i in 0..16u8 {
+            let (x1, X1) = keypair();
+            let (x2, X2) = keypair();
+            let (y, Y) = keypair();
+            let message = [i; 32];
+            let key_agg = KeyAggContext::new(vec![X1, X2], None).unwrap();
+
+            let nonce1 = SecretNonce::generate(&nonce_gen, &x1);
+            let nonce2 = SecretNonce::generate(&nonce_gen, &x2);
+            let nonces = [nonce1.public_nonce(), nonce2.public_nonce()];
+            let session = Session::new(key_agg.clone(), &nonces, Some(Y), &message).unwrap();
+
+            let sig1 = session.partial_sign(&x1, nonce1).unwrap();
+            let sig2 = session.partial_sign(&x2, nonce2).unwrap();
+            assert!(session.verify_partial_signature(&X1, &nonces[0], &sig1));
+            assert!(session.verify_partial_signature(&X2, &nonces[1], &sig2));
+            assert!(session.aggregate(&[sig1, sig2]).is_none());
+
+            let enc_sig = session.aggregate_encrypted(&[sig1, sig2]).unwrap();
+            assert!(adaptor.verify_encrypted_signature(
+                &key_agg.output_key(),
+                &Y,
+                &message,
+                &enc_sig
+            ));
+            let sig = adaptor.decrypt_signature(&y, enc_sig.clone());
+            assert!(verify(&key_agg.output_key(), &message, &sig));
+            let recovered = adaptor.recover_decryption_key(&Y, &sig, &enc_sig).unwrap();
+            assert!(recovered == y || recovered == -y);
+        }
+    }
+
+    #[test]
+    fn reject_foreign_key() {
+        let nonce_gen = NG::default();
+        let (x1, x_1) = keypair();
+        let (_, x_2) = keypair();
+        let (x3, _) = keypair();
+        let key_agg = KeyAggContext::new(vec![x_1, x_2], None).unwrap();
+        let nonce = SecretNonce::generate(&nonce_gen, &x1);
+        let session = Session::new(key_agg, &[nonce.public_nonce()], None, &[0u8; 32]).unwrap();
+        assert!(session.partial_sign(&x3, nonce).is_none());
+    }
+
+    #[test]
+    fn nonce_and_partial_signature_encoding() {
+        let nonce_gen = NG::default();
+        let (x, pubkey) = keypair();
+        let nonce = SecretNonce::generate(&nonce_gen, &x);
+        let public_nonce = nonce.public_nonce();
+        let bytes = public_nonce.as_canonical_bytes();
+        assert_eq!(bytes.len(), 66);
+        assert_eq!(
+            PublicNonce::from_canonical_bytes(&bytes).unwrap(),
+            public_nonce
+        );
+        assert!(PublicNonce::from_canonical_bytes(&bytes[1..]).is_err());
+
+        let key_agg = KeyAggContext::new(vec![pubkey], None).unwrap();
+        let session = Session::new(key_agg, &[public_nonce], None, &[1u8; 32]).unwrap();
+        let sig = session.partial_sign(&x, nonce).unwrap();
+        let encoded = consensus::serialize(&sig);
+        assert_eq!(
+            consensus::deserialize::<PartialSignature>(&encoded).unwrap(),
+            sig
+        );
+    }
+}
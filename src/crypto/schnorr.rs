@@ -0,0 +1,366 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Adaptor signatures, or encrypted signatures, over [`BIP-340`][bip-340] Schnorr signatures on
+//! secp256k1.
+//!
+//! Public keys, both signing and encryption ones, are x-only and lifted to their even `y`
+//! coordinate point as specified in BIP-340. The decryption key corresponding to an encryption
+//! key is thus the secret whose point has an even `y`, the one returned by
+//! [`Adaptor::recover_decryption_key`].
+//!
+//! [bip-340]: https://github.com/bitcoin/bips/blob/master/bip-0340.mediawiki
+
+use std::io;
+
+use crate::consensus::{self, CanonicalBytes, Decodable, Encodable};
+
+use bitcoin::secp256k1::schnorr::Signature;
+use secp256kfun::{
+    derive_nonce, g,
+    hash::{HashAdd, Tagged},
+    marker::*,
+    nonce::NonceGen,
+    s, Point, Scalar, XOnly, G,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// An encrypted BIP-340 signature, a.k.a. adaptor signature. Can only be created with
+/// [`Adaptor::encrypted_sign`] and decrypted into a valid [`Signature`] with the secret
+/// corresponding to the encryption key.
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EncryptedSignature {
+    /// The nonce point of the decrypted signature, it already includes the encryption key.
+    R: Point<EvenY>,
+    /// The encrypted `s` value of the signature.
+    s_hat: Scalar<Public, Zero>,
+    /// Whether the decryption key must be negated when decrypting, set when `r·G + Y` did not
+    /// have an even `y` coordinate.
+    needs_negation: bool,
+}
+
+impl EncryptedSignature {
+    #[allow(non_snake_case)]
+    pub(crate) fn new(R: Point<EvenY>, s_hat: Scalar<Public, Zero>, needs_negation: bool) -> Self {
+        Self {
+            R,
+            s_hat,
+            needs_negation,
+        }
+    }
+}
+
+/// Encrypted signatures engine, produces, verifies, decrypts encrypted signatures and recovers
+/// decryption keys. Nonces are derived with the provided nonce generator.
+#[derive(Clone, Debug, Default)]
+pub struct Adaptor<NG> {
+    nonce_gen: NG,
+}
+
+impl<NG> Adaptor<NG> {
+    /// Create a new adaptor signature engine using the given nonce generator.
+    pub fn new(nonce_gen: NG) -> Self {
+        Self { nonce_gen }
+    }
+}
+
+impl<NG: NonceGen> Adaptor<NG> {
+    /// Create an encrypted signature for the 32 bytes message with the signing secret key. The
+    /// signature is encrypted under the x-only encryption key.
+    #[allow(non_snake_case)]
+    pub fn encrypted_sign(
+        &self,
+        signing_key: &Scalar,
+        encryption_key: &XOnly,
+        message: &[u8; 32],
+    ) -> EncryptedSignature {
+        let (X, x_needs_negation) = g!(signing_key * G)
+            .mark::<Normal>()
+            .into_point_with_even_y();
+        let mut x = signing_key.clone();
+        x.conditional_negate(x_needs_negation);
+        let Y = encryption_key.to_point();
+
+        let mut r = derive_nonce!(
+            nonce_gen => self.nonce_gen,
+            secret => &x,
+            public => [X.to_xonly(), encryption_key, &message[..]]
+        );
+        let R = g!(r * G + Y)
+            .mark::<NonZero>()
+            .expect("computationally unreachable")
+            .mark::<Normal>();
+        let (R, needs_negation) = R.into_point_with_even_y();
+        r.conditional_negate(needs_negation);
+
+        let c = challenge(&R, &X, message);
+        let s_hat = s!(r + c * x).mark::<Public>();
+
+        EncryptedSignature {
+            R,
+            s_hat,
+            needs_negation,
+        }
+    }
+
+    /// Verify that the encrypted signature is valid for the message under the x-only verification
+    /// key and that it decrypts with the secret of the x-only encryption key.
+    #[allow(non_snake_case)]
+    pub fn verify_encrypted_signature(
+        &self,
+        verification_key: &XOnly,
+        encryption_key: &XOnly,
+        message: &[u8; 32],
+        encrypted_signature: &EncryptedSignature,
+    ) -> bool {
+        let EncryptedSignature {
+            R,
+            s_hat,
+            needs_negation,
+        } = encrypted_signature;
+        let X = verification_key.to_point();
+        let Y = encryption_key
+            .to_point()
+            .conditional_negate(!needs_negation);
+        let c = challenge(R, &X, message);
+
+        g!(s_hat * G) == g!(R + Y + c * X)
+    }
+
+    /// Decrypt the encrypted signature with the decryption secret key and return a valid BIP-340
+    /// signature. The decryption key is negated if its point has an odd `y` coordinate.
+    pub fn decrypt_signature(
+        &self,
+        decryption_key: &Scalar,
+        encrypted_signature: EncryptedSignature,
+    ) -> Signature {
+        let EncryptedSignature {
+            R,
+            s_hat,
+            needs_negation,
+        } = encrypted_signature;
+        let mut y = decryption_key.clone();
+        let y_is_odd = !g!(y * G).mark::<Normal>().is_y_even();
+        y.conditional_negate(y_is_odd ^ needs_negation);
+        let s = s!(s_hat + y).mark::<Public>();
+
+        to_signature(&R, &s)
+    }
+
+    /// Recover the decryption secret key from the encrypted signature and its decrypted version.
+    /// The returned secret always corresponds to the even `y` point of the x-only encryption key.
+    /// Returns `None` if the signature is not the decryption of the encrypted signature.
+    #[allow(non_snake_case)]
+    pub fn recover_decryption_key(
+        &self,
+        encryption_key: &XOnly,
+        signature: &Signature,
+        encrypted_signature: &EncryptedSignature,
+    ) -> Option<Scalar> {
+        let EncryptedSignature {
+            R,
+            s_hat,
+            needs_negation,
+        } = encrypted_signature;
+        let (R_x, s) = from_signature(signature)?;
+        if R_x != R.to_xonly() {
+            return None;
+        }
+
+        let mut y = s!(s - s_hat).mark::<NonZero>()?;
+        y.conditional_negate(*needs_negation);
+        let Y = encryption_key.to_point();
+        if g!(y * G) != Y {
+            return None;
+        }
+
+        Some(y)
+    }
+}
+
+/// Compute the BIP-340 challenge `H_tag(R.x || X.x || m)`.
+#[allow(non_snake_case)]
+fn challenge(R: &Point<EvenY>, X: &Point<EvenY>, message: &[u8; 32]) -> Scalar<Public, Zero> {
+    let hash = Sha256::default()
+        .tagged(b"BIP0340/challenge")
+        .add(R.to_xonly())
+        .add(X.to_xonly())
+        .add(&message[..]);
+    let mut bytes = [0u8; 32];
+    bytes.copy_from_slice(&hash.finalize()[..]);
+    Scalar::from_bytes_mod_order(bytes).mark::<Public>()
+}
+
+#[allow(non_snake_case)]
+fn to_signature(R: &Point<EvenY>, s: &Scalar<Public, Zero>) -> Signature {
+    let mut bytes = [0u8; 64];
+    bytes[..32].copy_from_slice(R.to_xonly().as_bytes());
+    bytes[32..].copy_from_slice(&s.to_bytes());
+    Signature::from_slice(&bytes).expect("64 bytes signature")
+}
+
+fn from_signature(signature: &Signature) -> Option<(XOnly, Scalar<Public, Zero>)> {
+    let bytes: &[u8] = signature.as_ref();
+    let r_x = XOnly::from_slice(&bytes[..32])?;
+    let mut s = [0u8; 32];
+    s.copy_from_slice(&bytes[32..]);
+    Some((r_x, Scalar::from_bytes(s)?.mark::<Public>()))
+}
+
+impl CanonicalBytes for EncryptedSignature {
+    fn as_canonical_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(65);
+        bytes.extend_from_slice(self.R.to_xonly().as_bytes());
+        bytes.extend_from_slice(&self.s_hat.to_bytes());
+        bytes.push(self.needs_negation as u8);
+        bytes
+    }
+
+    fn from_canonical_bytes(bytes: &[u8]) -> Result<Self, consensus::Error>
+    where
+        Self: Sized,
+    {
+        if bytes.len() != 65 {
+            return Err(consensus::Error::ParseFailed(
+                "Invalid encrypted signature length",
+            ));
+        }
+        let r_x = XOnly::from_slice(&bytes[..32])
+            .ok_or(consensus::Error::ParseFailed("Invalid nonce point"))?;
+        let mut s_hat = [0u8; 32];
+        s_hat.copy_from_slice(&bytes[32..64]);
+        let s_hat = Scalar::from_bytes(s_hat)
+            .ok_or(consensus::Error::ParseFailed("Invalid scalar"))?
+            .mark::<Public>();
+        let needs_negation = match bytes[64] {
+            0x00 => false,
+            0x01 => true,
+            _ => return Err(consensus::Error::ParseFailed("Invalid negation flag")),
+        };
+        Ok(Self {
+            R: r_x.to_point(),
+            s_hat,
+            needs_negation,
+        })
+    }
+}
+
+impl Encodable for EncryptedSignature {
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        self.as_canonical_bytes().consensus_encode(writer)
+    }
+}
+
+impl Decodable for EncryptedSignature {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Self::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::secp256k1::{Message, Secp256k1, XOnlyPublicKey};
+    use secp256kfun::nonce::{Deterministic, GlobalRng, Synthetic};
+
+    fn keypair() -> (Scalar, XOnly) {
+        let secret = Scalar::random(&mut rand::thread_rng());
+        let public = g!(secret * G).mark::<Normal>().to_xonly();
+        (secret, public)
+    }
+
+    fn verify(pubkey: &XOnly, message: &[u8; 32], sig: &Signature) -> bool {
+        let secp = Secp256k1::verification_only();
+        let pubkey = XOnlyPublicKey::from_slice(pubkey.as_bytes()).unwrap();
+        let message = Message::from_slice(message).unwrap();
+        secp.verify_schnorr(sig, &message, &pubkey).is_ok()
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn encrypt_verify_decrypt_recover() {
+        let adaptor =
+            Adaptor::new(Synthetic::<Sha256, GlobalRng<rand::rngs::ThreadRng>>::default());
+        // run multiple times to cover both parities of the keys and nonces
+        for i in 0..32u8 {
+            let (x, X) = keypair();
+            let (y, Y) = keypair();
+            let message = [i; 32];
+
+            let enc_sig = adaptor.encrypted_sign(&x, &Y, &message);
+            assert!(adaptor.verify_encrypted_signature(&X, &Y, &message, &enc_sig));
+
+            let sig = adaptor.decrypt_signature(&y, enc_sig.clone());
+            assert!(verify(&X, &message, &sig));
+
+            let recovered = adaptor
+                .recover_decryption_key(&Y, &sig, &enc_sig)
+                .expect("Valid decrypted signature");
+            assert_eq!(g!(recovered * G), Y.to_point());
+            assert!(recovered == y || recovered == -y);
+        }
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn reject_wrong_keys() {
+        let adaptor = Adaptor::new(Deterministic::<Sha256>::default());
+        let (x, X) = keypair();
+        let (_, X2) = keypair();
+        let (y, Y) = keypair();
+        let (y2, Y2) = keypair();
+        let message = [42u8; 32];
+
+        let enc_sig = adaptor.encrypted_sign(&x, &Y, &message);
+        assert!(!adaptor.verify_encrypted_signature(&X2, &Y, &message, &enc_sig));
+        assert!(!adaptor.verify_encrypted_signature(&X, &Y2, &message, &enc_sig));
+        assert!(!adaptor.verify_encrypted_signature(&X, &Y, &[0u8; 32], &enc_sig));
+
+        let sig = adaptor.decrypt_signature(&y2, enc_sig.clone());
+        assert!(!verify(&X, &message, &sig));
+        assert!(adaptor.recover_decryption_key(&Y, &sig, &enc_sig).is_none());
+
+        let sig = adaptor.decrypt_signature(&y, enc_sig.clone());
+        assert!(adaptor
+            .recover_decryption_key(&Y2, &sig, &enc_sig)
+            .is_none());
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn encrypted_signature_encoding() {
+        let adaptor = Adaptor::new(Deterministic::<Sha256>::default());
+        let (x, _) = keypair();
+        let (_, Y) = keypair();
+        let enc_sig = adaptor.encrypted_sign(&x, &Y, &[7u8; 32]);
+
+        let bytes = enc_sig.as_canonical_bytes();
+        assert_eq!(bytes.len(), 65);
+        assert_eq!(
+            EncryptedSignature::from_canonical_bytes(&bytes).unwrap(),
+            enc_sig
+        );
+        let encoded = consensus::serialize(&enc_sig);
+        assert_eq!(
+            consensus::deserialize::<EncryptedSignature>(&encoded).unwrap(),
+            enc_sig
+        );
+        assert!(EncryptedSignature::from_canonical_bytes(&bytes[..64]).is_err());
+    }
+}
@@ -110,6 +110,12 @@ pub enum Error {
     /// A trade error.
     #[error("Trade error: {0}")]
     Trade(#[from] trade::Error),
+    /// A swap state machine error.
+    #[error("State machine error: {0}")]
+    StateMachine(#[from] protocol::machine::Error),
+    /// A protocol message inconsistent with the deal or the counter-party parameters.
+    #[error("Validation error: {0}")]
+    Validation(#[from] protocol::validation::Error),
 }
 
 /// Result of an high level computation such as in Alice and Bob roles executing the protocol,
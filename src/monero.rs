@@ -21,6 +21,7 @@ use crate::blockchain::Network;
 use crate::consensus::{self, CanonicalBytes, Decodable, Encodable};
 use crate::crypto::{self, AccordantKeySet, AccordantKeys, DeriveKeys, SharedKeyId};
 use crate::role::Accordant;
+use crate::trade::AtomicAmount;
 
 use monero::util::key::{PrivateKey, PublicKey};
 use monero::Address;
@@ -141,6 +142,18 @@ impl CanonicalBytes for Amount {
     }
 }
 
+impl AtomicAmount for Amount {
+    const DECIMALS: u32 = 12;
+
+    fn as_atomic_units(&self) -> u64 {
+        self.as_pico()
+    }
+
+    fn from_atomic_units(units: u64) -> Self {
+        Amount::from_pico(units)
+    }
+}
+
 impl CanonicalBytes for Address {
     fn as_canonical_bytes(&self) -> Vec<u8> {
         self.as_bytes()
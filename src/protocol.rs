@@ -16,18 +16,20 @@
 
 //! Protocol execution and messages exchanged between peers. Execution steps of a swap are carried
 //! by [`Alice`] and [`Bob`] structures. Each contain the list of methods needed to proceed a swap.
+//! The state machines in [`machine`] call these methods in order to execute a swap end-to-end, and
+//! can be saved and restored at any step with [`checkpoint`].
 
 // For this file we allow having complex types
 #![allow(clippy::type_complexity)]
 
 use std::io;
 
-use crate::blockchain::{Fee, FeePriority, FeeStrategy, Transactions};
+use crate::blockchain::{Fee, FeePriority, FeeStrategy, FeeStrategyError, Transactions};
 use crate::consensus::{self, CanonicalBytes, Decodable, Encodable};
 use crate::crypto::{
-    self, AccordantKeyId, ArbitratingKeyId, Commit, DeriveKeys, EncSign, KeyGenerator,
-    RecoverSecret, SharedKeyId, Sign, TaggedElement, TaggedElements, TaggedExtraKeys,
-    TaggedSharedKeys,
+    self, AccordantKeyId, ArbitratingKeyId, Commit, CommitmentNonce, CommitmentScheme, DeriveKeys,
+    EncSign, KeyGenerator, RecoverSecret, SharedKeyId, Sign, TaggedElement, TaggedElements,
+    TaggedExtraKeys, TaggedSharedKeys,
 };
 use crate::protocol::message::{
     BuyProcedureSignature, CommitAliceParameters, CommitBobParameters, CoreArbitratingSetup,
@@ -42,7 +44,11 @@ use crate::transaction::{
 };
 use crate::{Error, Res};
 
+pub mod checkpoint;
+pub mod machine;
 pub mod message;
+pub mod transcript;
+pub mod validation;
 
 struct ValidatedCoreTransactions<Px, Ti, Pk> {
     lock: Px,
@@ -53,7 +59,10 @@ struct ValidatedCoreTransactions<Px, Ti, Pk> {
 
 /// Container for the three main transactions used as the arbitrating engine on-chain. The `lock`,
 /// the lock `cancel`, and the cancel `refund`.
-#[derive(Debug, Clone, Copy, Hash, Serialize, Deserialize)]
+///
+/// An optional ladder of `cancel` and `refund` transactions, replacing the main ones at increasing
+/// fee rates, can be pre-signed along with them, see [`FeeBump`].
+#[derive(Debug, Clone, Hash, Serialize, Deserialize)]
 pub struct CoreArbitratingTransactions<Px> {
     /// Partial transaction raw type representing the lock.
     pub lock: Px,
@@ -61,6 +70,8 @@ pub struct CoreArbitratingTransactions<Px> {
     pub cancel: Px,
     /// Partial transaction raw type representing the refund.
     pub refund: Px,
+    /// Partial cancel and refund transactions at increasing fee rates.
+    pub bumps: Vec<FeeBump<Px>>,
 }
 
 impl<Px> CoreArbitratingTransactions<Px> {
@@ -68,6 +79,7 @@ impl<Px> CoreArbitratingTransactions<Px> {
         self,
         swap_id: SwapId,
         cancel_sig: Sig,
+        bump_cancel_sigs: Vec<Sig>,
     ) -> CoreArbitratingSetup<Px, Sig> {
         CoreArbitratingSetup {
             swap_id,
@@ -75,10 +87,91 @@ impl<Px> CoreArbitratingTransactions<Px> {
             cancel: self.cancel,
             refund: self.refund,
             cancel_sig,
+            bumps: self.bumps,
+            bump_cancel_sigs,
         }
     }
+
+    /// Number of rungs in the fee ladder, the main cancel and refund transactions being the
+    /// first rung.
+    pub fn rungs_count(&self) -> usize {
+        self.bumps.len() + 1
+    }
 }
 
+impl<Px> CoreArbitratingTransactions<Px>
+where
+    Px: Clone,
+{
+    /// Returns the core arbitrating transactions of the rung at `index` in the fee ladder, with
+    /// no fee bumps, or `None` if the rung does not exist. The rung `0` is made of the main cancel
+    /// and refund transactions.
+    pub fn rung(&self, index: usize) -> Option<Self> {
+        let (cancel, refund) = match index {
+            0 => (self.cancel.clone(), self.refund.clone()),
+            i => {
+                let bump = self.bumps.get(i - 1)?;
+                (bump.cancel.clone(), bump.refund.clone())
+            }
+        };
+        Some(CoreArbitratingTransactions {
+            lock: self.lock.clone(),
+            cancel,
+            refund,
+            bumps: vec![],
+        })
+    }
+}
+
+impl<Px> CoreArbitratingTransactions<Px>
+where
+    Px: Fee,
+    Px::FeeUnit: PartialOrd,
+{
+    /// Select the index of the rung to broadcast for the current `fee_rate` of the network: the
+    /// first rung paying at least the fee rate on its cancel transaction, or the last rung if
+    /// none of them does.
+    pub fn select_rung(&self, fee_rate: &Px::FeeUnit) -> Res<usize> {
+        let cancels = std::iter::once(&self.cancel).chain(self.bumps.iter().map(|b| &b.cancel));
+        let mut selected = 0;
+        for (index, cancel) in cancels.enumerate() {
+            selected = index;
+            if cancel.fee_rate()? >= *fee_rate {
+                break;
+            }
+        }
+        Ok(selected)
+    }
+}
+
+impl<Px> Encodable for CoreArbitratingTransactions<Px>
+where
+    Px: CanonicalBytes,
+{
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        let mut len = self.lock.as_canonical_bytes().consensus_encode(writer)?;
+        len += self.cancel.as_canonical_bytes().consensus_encode(writer)?;
+        len += self.refund.as_canonical_bytes().consensus_encode(writer)?;
+        Ok(len + self.bumps.consensus_encode(writer)?)
+    }
+}
+
+impl<Px> Decodable for CoreArbitratingTransactions<Px>
+where
+    Px: CanonicalBytes,
+{
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(CoreArbitratingTransactions {
+            lock: Px::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            cancel: Px::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            refund: Px::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            bumps: Decodable::consensus_decode(d)?,
+        })
+    }
+}
+
+impl_strict_encoding!(CoreArbitratingTransactions<Px>, Px: CanonicalBytes);
+
 /// Container for the set of parameters needed to build or verify some parameters on the
 /// [`CoreArbitratingTransactions`].
 #[derive(Debug, Clone, Copy, Hash, Serialize, Deserialize)]
@@ -96,6 +189,83 @@ pub struct TxSignatures<Sig> {
     pub adapted_sig: Sig,
 }
 
+/// A `cancel` transaction and its `refund` replacing the main ones with a higher fee rate. The
+/// `cancel` spends the same lock output and the `refund` spends this `cancel`.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeBump<Px> {
+    /// Partial transaction raw type representing the cancel.
+    pub cancel: Px,
+    /// Partial transaction raw type representing the refund.
+    pub refund: Px,
+}
+
+impl<Px> Encodable for FeeBump<Px>
+where
+    Px: CanonicalBytes,
+{
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        let len = self.cancel.as_canonical_bytes().consensus_encode(writer)?;
+        Ok(len + self.refund.as_canonical_bytes().consensus_encode(writer)?)
+    }
+}
+
+impl<Px> Decodable for FeeBump<Px>
+where
+    Px: CanonicalBytes,
+{
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(FeeBump {
+            cancel: Px::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            refund: Px::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+        })
+    }
+}
+
+impl_strict_encoding!(FeeBump<Px>, Px: CanonicalBytes);
+
+/// Alice's signatures for a rung of the fee ladder: the regular signature on the `cancel` and the
+/// encrypted signature on the `refund`.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeBumpSignatures<Sig, EncSig> {
+    /// The `Ac` `cancel (d)` signature.
+    pub cancel_sig: Sig,
+    /// The `Ar(Tb)` `refund (e)` adaptor signature.
+    pub refund_adaptor_sig: EncSig,
+}
+
+impl<Sig, EncSig> Encodable for FeeBumpSignatures<Sig, EncSig>
+where
+    Sig: CanonicalBytes,
+    EncSig: CanonicalBytes,
+{
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        let len = self
+            .cancel_sig
+            .as_canonical_bytes()
+            .consensus_encode(writer)?;
+        Ok(len
+            + self
+                .refund_adaptor_sig
+                .as_canonical_bytes()
+                .consensus_encode(writer)?)
+    }
+}
+
+impl<Sig, EncSig> Decodable for FeeBumpSignatures<Sig, EncSig>
+where
+    Sig: CanonicalBytes,
+    EncSig: CanonicalBytes,
+{
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(FeeBumpSignatures {
+            cancel_sig: Sig::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            refund_adaptor_sig: EncSig::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+        })
+    }
+}
+
+impl_strict_encoding!(FeeBumpSignatures<Sig, EncSig>, Sig: CanonicalBytes, EncSig: CanonicalBytes);
+
 /// The partial `punish` transaction with its signature.
 #[derive(Debug, Clone, Copy, Hash, Serialize, Deserialize)]
 pub struct FullySignedPunish<Px, Sig> {
@@ -103,6 +273,87 @@ pub struct FullySignedPunish<Px, Sig> {
     pub punish_sig: Sig,
 }
 
+/// Random nonces blinding the salted commitments to a set of [`Parameters`], one nonce per
+/// committed value. The nonces of the tagged keys are tagged with the tag of their key.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommitmentNonces {
+    pub buy: CommitmentNonce,
+    pub cancel: CommitmentNonce,
+    pub refund: CommitmentNonce,
+    pub punish: Option<CommitmentNonce>,
+    pub adaptor: CommitmentNonce,
+    pub extra_arbitrating_keys: Vec<TaggedElement<u16, CommitmentNonce>>,
+    pub arbitrating_shared_keys: Vec<TaggedElement<SharedKeyId, CommitmentNonce>>,
+    pub spend: CommitmentNonce,
+    pub extra_accordant_keys: Vec<TaggedElement<u16, CommitmentNonce>>,
+    pub accordant_shared_keys: Vec<TaggedElement<SharedKeyId, CommitmentNonce>>,
+}
+
+impl CommitmentNonces {
+    /// Draw a random nonce for each value of the parameters.
+    pub fn new_random<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>(
+        parameters: &Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>,
+    ) -> Self {
+        fn tagged<T: Clone, E>(
+            elems: &[TaggedElement<T, E>],
+        ) -> TaggedElements<T, CommitmentNonce> {
+            elems
+                .iter()
+                .map(|elem| TaggedElement::new(elem.tag().clone(), CommitmentNonce::new_random()))
+                .collect()
+        }
+        Self {
+            buy: CommitmentNonce::new_random(),
+            cancel: CommitmentNonce::new_random(),
+            refund: CommitmentNonce::new_random(),
+            punish: parameters
+                .punish
+                .as_ref()
+                .map(|_| CommitmentNonce::new_random()),
+            adaptor: CommitmentNonce::new_random(),
+            extra_arbitrating_keys: tagged(&parameters.extra_arbitrating_keys),
+            arbitrating_shared_keys: tagged(&parameters.arbitrating_shared_keys),
+            spend: CommitmentNonce::new_random(),
+            extra_accordant_keys: tagged(&parameters.extra_accordant_keys),
+            accordant_shared_keys: tagged(&parameters.accordant_shared_keys),
+        }
+    }
+}
+
+impl Encodable for CommitmentNonces {
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        let mut len = self.buy.as_canonical_bytes().consensus_encode(writer)?;
+        len += self.cancel.as_canonical_bytes().consensus_encode(writer)?;
+        len += self.refund.as_canonical_bytes().consensus_encode(writer)?;
+        len += self.punish.as_canonical_bytes().consensus_encode(writer)?;
+        len += self.adaptor.as_canonical_bytes().consensus_encode(writer)?;
+        len += self.extra_arbitrating_keys.consensus_encode(writer)?;
+        len += self.arbitrating_shared_keys.consensus_encode(writer)?;
+        len += self.spend.as_canonical_bytes().consensus_encode(writer)?;
+        len += self.extra_accordant_keys.consensus_encode(writer)?;
+        Ok(len + self.accordant_shared_keys.consensus_encode(writer)?)
+    }
+}
+
+impl Decodable for CommitmentNonces {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(CommitmentNonces {
+            buy: CommitmentNonce::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            cancel: CommitmentNonce::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            refund: CommitmentNonce::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            punish: Option::<CommitmentNonce>::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            adaptor: CommitmentNonce::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            extra_arbitrating_keys: Decodable::consensus_decode(d)?,
+            arbitrating_shared_keys: Decodable::consensus_decode(d)?,
+            spend: CommitmentNonce::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            extra_accordant_keys: Decodable::consensus_decode(d)?,
+            accordant_shared_keys: Decodable::consensus_decode(d)?,
+        })
+    }
+}
+
+impl_strict_encoding!(CommitmentNonces);
+
 /// One has to produce a set of parameters at the start of a swap and receive a mostly equivalent
 /// set from counter-party during the reveal procedure. Container for all the parameters a
 /// participant needs to execture a swap.
@@ -113,6 +364,9 @@ pub struct FullySignedPunish<Px, Sig> {
 ///
 /// Timelocks and fee strategy are only present in the `local` set of parameters and not part of
 /// the reveal process, thus they are optional too.
+///
+/// The commitment nonces are present when the parameters are committed with the
+/// [`CommitmentScheme::Salted`] scheme, they are revealed with the parameters.
 #[derive(Debug, Clone, Hash, Serialize, Deserialize)]
 pub struct Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr> {
     pub buy: Pk,
@@ -130,6 +384,7 @@ pub struct Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr> {
     pub cancel_timelock: Option<Ti>,
     pub punish_timelock: Option<Ti>,
     pub fee_strategy: Option<FeeStrategy<F>>,
+    pub commitment_nonces: Option<CommitmentNonces>,
 }
 
 impl<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr> Encodable for Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>
@@ -167,11 +422,11 @@ where
             .punish_timelock
             .as_canonical_bytes()
             .consensus_encode(writer)?;
-        Ok(len
-            + self
-                .fee_strategy
-                .as_canonical_bytes()
-                .consensus_encode(writer)?)
+        len += self
+            .fee_strategy
+            .as_canonical_bytes()
+            .consensus_encode(writer)?;
+        Ok(len + self.commitment_nonces.consensus_encode(writer)?)
     }
 }
 
@@ -205,50 +460,103 @@ where
             fee_strategy: Option::<FeeStrategy<F>>::from_canonical_bytes(
                 unwrap_vec_ref!(d).as_ref(),
             )?,
+            commitment_nonces: consensus::decode_trailing_option(d)?,
         })
     }
 }
 
 impl_strict_encoding!(Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>, Pk: CanonicalBytes, Qk: CanonicalBytes, Rk: CanonicalBytes, Sk: CanonicalBytes, Addr: CanonicalBytes, Ti: CanonicalBytes, F: CanonicalBytes, Pr: CanonicalBytes);
 
-/// Transform a vector of tagged elements `K` into a vector of tagged commitments.
-/// [`CanonicalBytes`] are used for computing the commitment of each elements.
+// Return the nonce tagged with the tag of the key
+fn tagged_nonce<'a, T: Eq>(
+    nonces: &'a [TaggedElement<T, CommitmentNonce>],
+    tag: &T,
+) -> Option<&'a CommitmentNonce> {
+    nonces
+        .iter()
+        .find(|tagged_nonce| tagged_nonce.tag() == tag)
+        .map(TaggedElement::elem)
+}
+
+/// Commit to an element, blinded with its nonce if the commitment is salted. [`CanonicalBytes`]
+/// are used for computing the commitment.
+pub fn commit_to_elem<K: CanonicalBytes, C>(
+    wallet: &impl Commit<C>,
+    elem: &K,
+    nonce: Option<&CommitmentNonce>,
+) -> C {
+    match nonce {
+        Some(nonce) => wallet.commit_to_salted(elem.as_canonical_bytes(), nonce),
+        None => wallet.commit_to(elem.as_canonical_bytes()),
+    }
+}
+
+/// Verifies a commitment against a revealed element, blinded with the revealed nonce if the
+/// commitment is salted.
+pub fn verify_commitment<K: CanonicalBytes, C: Eq>(
+    wallet: &impl Commit<C>,
+    elem: &K,
+    nonce: Option<&CommitmentNonce>,
+    commitment: C,
+) -> Result<(), crypto::Error> {
+    match nonce {
+        Some(nonce) => wallet.validate_salted(elem.as_canonical_bytes(), nonce, commitment),
+        None => wallet.validate(elem.as_canonical_bytes(), commitment),
+    }
+}
+
+/// Transform a vector of tagged elements `K` into a vector of tagged commitments, salted with the
+/// nonces of the same tags if any. [`CanonicalBytes`] are used for computing the commitment of
+/// each elements.
 pub fn commit_to_vec<T: Clone + Eq, K: CanonicalBytes, C: Clone + Eq>(
     wallet: &impl Commit<C>,
     keys: &[TaggedElement<T, K>],
+    nonces: Option<&[TaggedElement<T, CommitmentNonce>]>,
 ) -> TaggedElements<T, C> {
     keys.iter()
         .map(|tagged_key| {
+            let nonce = nonces.map(|nonces| {
+                tagged_nonce(nonces, tagged_key.tag()).expect("A nonce per tagged element")
+            });
             TaggedElement::new(
                 tagged_key.tag().clone(),
-                wallet.commit_to(tagged_key.elem().as_canonical_bytes()),
+                commit_to_elem(wallet, tagged_key.elem(), nonce),
             )
         })
         .collect()
 }
 
-/// Verifies a vector of tagged commitments against a vector of revealed tagged elements. Fails if
-/// a tag is not found in the commitments or doesn't match the commitment.  [`CanonicalBytes`] are
-/// used for computing the commitment of each elements.
+/// Verifies a vector of tagged commitments against a vector of revealed tagged elements, salted
+/// with the revealed nonces of the same tags if any. Fails if a tag is not found in the
+/// commitments or the nonces or doesn't match the commitment.  [`CanonicalBytes`] are used for
+/// computing the commitment of each elements.
 pub fn verify_vec_of_commitments<T: Eq, K: CanonicalBytes, C: Clone + Eq>(
     wallet: &impl Commit<C>,
     keys: Vec<TaggedElement<T, K>>,
+    nonces: Option<&[TaggedElement<T, CommitmentNonce>]>,
     commitments: &[TaggedElement<T, C>],
 ) -> Result<(), Error> {
     keys.into_iter()
         .map(|tagged_key| {
+            let nonce = nonces
+                .map(|nonces| {
+                    tagged_nonce(nonces, tagged_key.tag())
+                        .ok_or(Error::Crypto(crypto::Error::InvalidCommitment))
+                })
+                .transpose()?;
             commitments
                 .iter()
                 .find(|tagged_commitment| tagged_commitment.tag() == tagged_key.tag())
                 .map(|tagged_commitment| {
-                    wallet
-                        .validate(
-                            tagged_key.elem().as_canonical_bytes(),
-                            tagged_commitment.elem().clone(),
-                        )
-                        .map_err(Error::Crypto)
+                    verify_commitment(
+                        wallet,
+                        tagged_key.elem(),
+                        nonce,
+                        tagged_commitment.elem().clone(),
+                    )
+                    .map_err(Error::Crypto)
                 })
-                .ok_or(Error::Crypto(crypto::Error::InvalidCommitment))
+                .ok_or(Error::Crypto(crypto::Error::InvalidCommitment))?
         })
         .collect::<Result<Vec<_>, _>>()
         .map(|_| ())
@@ -261,29 +569,55 @@ where
     Rk: CanonicalBytes,
     Sk: CanonicalBytes,
 {
-    /// Generates protocol message that commits to Alice's parameters.
+    // Return the commitment scheme of the parameters, salted when they carry nonces
+    fn commitment_scheme(&self) -> CommitmentScheme {
+        match self.commitment_nonces {
+            Some(_) => CommitmentScheme::Salted,
+            None => CommitmentScheme::Bare,
+        }
+    }
+
+    /// Generates protocol message that commits to Alice's parameters, salted with the commitment
+    /// nonces if any.
     pub fn commit_alice<C: Clone + Eq>(
         &self,
         swap_id: SwapId,
         wallet: &impl Commit<C>,
     ) -> CommitAliceParameters<C> {
+        let nonces = self.commitment_nonces.as_ref();
         CommitAliceParameters {
             swap_id,
-            buy: wallet.commit_to(self.buy.as_canonical_bytes()),
-            cancel: wallet.commit_to(self.cancel.as_canonical_bytes()),
-            refund: wallet.commit_to(self.refund.as_canonical_bytes()),
-            punish: wallet.commit_to(
-                self.punish
-                    .clone()
-                    .expect("Alice has punish")
-                    .as_canonical_bytes(),
+            scheme: self.commitment_scheme(),
+            buy: commit_to_elem(wallet, &self.buy, nonces.map(|n| &n.buy)),
+            cancel: commit_to_elem(wallet, &self.cancel, nonces.map(|n| &n.cancel)),
+            refund: commit_to_elem(wallet, &self.refund, nonces.map(|n| &n.refund)),
+            punish: commit_to_elem(
+                wallet,
+                self.punish.as_ref().expect("Alice has punish"),
+                nonces.map(|n| n.punish.as_ref().expect("Alice has punish nonce")),
+            ),
+            adaptor: commit_to_elem(wallet, &self.adaptor, nonces.map(|n| &n.adaptor)),
+            extra_arbitrating_keys: commit_to_vec(
+                wallet,
+                &self.extra_arbitrating_keys,
+                nonces.map(|n| &n.extra_arbitrating_keys[..]),
+            ),
+            arbitrating_shared_keys: commit_to_vec(
+                wallet,
+                &self.arbitrating_shared_keys,
+                nonces.map(|n| &n.arbitrating_shared_keys[..]),
+            ),
+            spend: commit_to_elem(wallet, &self.spend, nonces.map(|n| &n.spend)),
+            extra_accordant_keys: commit_to_vec(
+                wallet,
+                &self.extra_accordant_keys,
+                nonces.map(|n| &n.extra_accordant_keys[..]),
+            ),
+            accordant_shared_keys: commit_to_vec(
+                wallet,
+                &self.accordant_shared_keys,
+                nonces.map(|n| &n.accordant_shared_keys[..]),
             ),
-            adaptor: wallet.commit_to(self.adaptor.as_canonical_bytes()),
-            extra_arbitrating_keys: commit_to_vec(wallet, &self.extra_arbitrating_keys),
-            arbitrating_shared_keys: commit_to_vec(wallet, &self.arbitrating_shared_keys),
-            spend: wallet.commit_to(self.spend.as_canonical_bytes()),
-            extra_accordant_keys: commit_to_vec(wallet, &self.extra_accordant_keys),
-            accordant_shared_keys: commit_to_vec(wallet, &self.accordant_shared_keys),
         }
     }
 
@@ -302,26 +636,46 @@ where
             extra_accordant_keys: self.extra_accordant_keys,
             accordant_shared_keys: self.accordant_shared_keys,
             address: self.destination_address,
+            nonces: self.commitment_nonces,
         }
     }
 
-    /// Generates protocol message that commits to Bob's parameters.
+    /// Generates protocol message that commits to Bob's parameters, salted with the commitment
+    /// nonces if any.
     pub fn commit_bob<C: Clone + Eq>(
         &self,
         swap_id: SwapId,
         wallet: &impl Commit<C>,
     ) -> CommitBobParameters<C> {
+        let nonces = self.commitment_nonces.as_ref();
         CommitBobParameters {
             swap_id,
-            buy: wallet.commit_to(self.buy.as_canonical_bytes()),
-            cancel: wallet.commit_to(self.cancel.as_canonical_bytes()),
-            refund: wallet.commit_to(self.refund.as_canonical_bytes()),
-            adaptor: wallet.commit_to(self.adaptor.as_canonical_bytes()),
-            extra_arbitrating_keys: commit_to_vec(wallet, &self.extra_arbitrating_keys),
-            arbitrating_shared_keys: commit_to_vec(wallet, &self.arbitrating_shared_keys),
-            spend: wallet.commit_to(self.spend.as_canonical_bytes()),
-            extra_accordant_keys: commit_to_vec(wallet, &self.extra_accordant_keys),
-            accordant_shared_keys: commit_to_vec(wallet, &self.accordant_shared_keys),
+            scheme: self.commitment_scheme(),
+            buy: commit_to_elem(wallet, &self.buy, nonces.map(|n| &n.buy)),
+            cancel: commit_to_elem(wallet, &self.cancel, nonces.map(|n| &n.cancel)),
+            refund: commit_to_elem(wallet, &self.refund, nonces.map(|n| &n.refund)),
+            adaptor: commit_to_elem(wallet, &self.adaptor, nonces.map(|n| &n.adaptor)),
+            extra_arbitrating_keys: commit_to_vec(
+                wallet,
+                &self.extra_arbitrating_keys,
+                nonces.map(|n| &n.extra_arbitrating_keys[..]),
+            ),
+            arbitrating_shared_keys: commit_to_vec(
+                wallet,
+                &self.arbitrating_shared_keys,
+                nonces.map(|n| &n.arbitrating_shared_keys[..]),
+            ),
+            spend: commit_to_elem(wallet, &self.spend, nonces.map(|n| &n.spend)),
+            extra_accordant_keys: commit_to_vec(
+                wallet,
+                &self.extra_accordant_keys,
+                nonces.map(|n| &n.extra_accordant_keys[..]),
+            ),
+            accordant_shared_keys: commit_to_vec(
+                wallet,
+                &self.accordant_shared_keys,
+                nonces.map(|n| &n.accordant_shared_keys[..]),
+            ),
         }
     }
 
@@ -339,6 +693,7 @@ where
             extra_accordant_keys: self.extra_accordant_keys,
             accordant_shared_keys: self.accordant_shared_keys,
             address: self.destination_address,
+            nonces: self.commitment_nonces,
         }
     }
 }
@@ -423,7 +778,10 @@ where
     /// # Safety
     ///
     /// All the data passed to the function are considered trusted and does not require extra
-    /// validation. Thus we assume the deal has been validated upfront.
+    /// validation. Thus we assume the deal has been validated upfront, e.g. with a
+    /// [`DealValidator`](crate::trade::DealValidator).
+    ///
+    /// The parameters carry random nonces salting the commitments to the keys.
     ///
     pub fn generate_parameters<Kg, Amt, Bmt, Ti, F, Pk, Qk, Rk, Sk, Pr>(
         &self,
@@ -471,7 +829,7 @@ where
 
         let (spend, adaptor, proof) = key_gen.generate_proof()?;
 
-        Ok(Parameters {
+        let mut parameters = Parameters {
             buy: key_gen.get_pubkey(ArbitratingKeyId::Buy)?,
             cancel: key_gen.get_pubkey(ArbitratingKeyId::Cancel)?,
             refund: key_gen.get_pubkey(ArbitratingKeyId::Refund)?,
@@ -487,7 +845,10 @@ where
             cancel_timelock: Some(deal.parameters.cancel_timelock),
             punish_timelock: Some(deal.parameters.punish_timelock),
             fee_strategy: Some(deal.parameters.fee_strategy),
-        })
+            commitment_nonces: None,
+        };
+        parameters.commitment_nonces = Some(CommitmentNonces::new_random(&parameters));
+        Ok(parameters)
     }
 
     /// Generates the witness on the refund transaction and encrypt it.
@@ -611,6 +972,76 @@ where
             .map_err(Into::into)
     }
 
+    /// Validates the fee ladder of [`CoreArbitratingTransactions`] and signs each rung starting
+    /// at rung `1`, the main transactions being signed with [`cosign_arbitrating_cancel`] and
+    /// [`sign_adaptor_refund`].
+    ///
+    /// # Safety
+    ///
+    /// Same as [`sign_adaptor_refund`], every rung is validated as the main transactions are.
+    ///
+    /// # Execution
+    ///
+    ///  * Validate that the fee rates of the cancel and refund transactions strictly increase
+    ///    from one rung to the next
+    ///  * For each rung validate the [`Lockable`], [`Cancelable`], [`Refundable`] partial
+    ///    transactions, then sign the cancel and adaptor sign the refund
+    ///
+    /// Returns the signatures of each rung.
+    ///
+    /// [`cosign_arbitrating_cancel`]: Alice::cosign_arbitrating_cancel
+    /// [`sign_adaptor_refund`]: Alice::sign_adaptor_refund
+    ///
+    pub fn sign_fee_bumps<Amt, Px, Pk, Qk, Rk, Sk, Ti, F, Pr, S, Ms, Si, EncSig>(
+        &self,
+        wallet: &mut S,
+        alice_parameters: &Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>,
+        bob_parameters: &Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>,
+        core: &CoreArbitratingTransactions<Px>,
+        arb_params: ArbitratingParameters<Amt, Ti, F>,
+    ) -> Res<Vec<FeeBumpSignatures<Si, EncSig>>>
+    where
+        S: Sign<Pk, Ms, Si> + EncSign<Pk, Ms, Si, EncSig>,
+        Ar: Transactions<Addr = Addr, Amt = Amt, Ti = Ti, Ms = Ms, Pk = Pk, Si = Si, Px = Px>,
+        Px: Clone + Fee<FeeUnit = F>,
+        F: Copy + PartialOrd,
+        Pk: Copy,
+        Ti: Copy,
+        Amt: Copy + PartialEq,
+    {
+        // Verifies that each rung replaces the previous one with a higher fee rate.
+        let mut previous = (core.cancel.fee_rate()?, core.refund.fee_rate()?);
+        for bump in core.bumps.iter() {
+            let rates = (bump.cancel.fee_rate()?, bump.refund.fee_rate()?);
+            if rates.0 <= previous.0 || rates.1 <= previous.1 {
+                return Err(FeeStrategyError::InvalidFeeLadder.into());
+            }
+            previous = rates;
+        }
+
+        (1..core.rungs_count())
+            .filter_map(|index| core.rung(index))
+            .map(|rung| {
+                Ok(FeeBumpSignatures {
+                    cancel_sig: self.cosign_arbitrating_cancel(
+                        wallet,
+                        alice_parameters,
+                        bob_parameters,
+                        &rung,
+                        arb_params,
+                    )?,
+                    refund_adaptor_sig: self.sign_adaptor_refund(
+                        wallet,
+                        alice_parameters,
+                        bob_parameters,
+                        &rung,
+                        arb_params,
+                    )?,
+                })
+            })
+            .collect()
+    }
+
     /// Validates the adaptor buy witness with based on the parameters and the buy arbitrating
     /// transactions.
     ///
@@ -847,21 +1278,22 @@ where
     /// Given the Bob's parameters, the refund transaction and the encrypted signature for the
     /// refund transaction, return the secret key used to encrypt the signature.
     ///
-    /// This method is used if the refund occurs to allow Alice to unlock her funds.
+    /// This method is used if the refund occurs to allow Alice to unlock her funds. Fails if the
+    /// refund signature is not the decryption of the encrypted signature.
     pub fn recover_accordant_key<Amt, Tx, Px, Pk, Qk, Rk, Sk, Ti, F, Pr, S, Si, EncSig>(
         &self,
         wallet: &mut S,
         bob_parameters: &Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>,
         refund_adaptor_sig: EncSig,
         refund_tx: Tx,
-    ) -> Rk
+    ) -> Res<Rk>
     where
         S: RecoverSecret<Pk, Rk, Si, EncSig>,
         Ar: Transactions<Addr = Addr, Amt = Amt, Ti = Ti, Pk = Pk, Si = Si, Px = Px, Tx = Tx>,
     {
         let encryption_key = &bob_parameters.adaptor;
         let signature = <Ar::Refund>::extract_witness(refund_tx);
-        wallet.recover_secret_key(refund_adaptor_sig, encryption_key, signature)
+        Ok(wallet.recover_secret_key(refund_adaptor_sig, encryption_key, signature)?)
     }
 
     // Internal method to parse and validate the core arbitratring transactions received by Alice
@@ -988,6 +1420,9 @@ pub struct Bob<Addr, Ar, Ac> {
     pub refund_address: Addr,
     /// The fee politic to apply during the swap fee calculation
     pub fee_politic: FeePriority,
+    /// The number of fee bumps, on top of the main cancel and refund transactions, to pre-sign
+    /// inside the fee strategy range
+    pub fee_bumps: u16,
 }
 
 impl<Addr, Ar, Ac> Encodable for Bob<Addr, Ar, Ac>
@@ -1000,11 +1435,11 @@ where
         let mut len = self.fee_politic.consensus_encode(writer)?;
         len += self.arbitrating.consensus_encode(writer)?;
         len += self.accordant.consensus_encode(writer)?;
-        Ok(len
-            + self
-                .refund_address
-                .as_canonical_bytes()
-                .consensus_encode(writer)?)
+        len += self
+            .refund_address
+            .as_canonical_bytes()
+            .consensus_encode(writer)?;
+        Ok(len + self.fee_bumps.consensus_encode(writer)?)
     }
 }
 
@@ -1019,17 +1454,19 @@ where
         let arbitrating = Decodable::consensus_decode(d)?;
         let accordant = Decodable::consensus_decode(d)?;
         let refund_address = Addr::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?;
+        let fee_bumps = Decodable::consensus_decode(d)?;
         Ok(Bob {
             arbitrating,
             accordant,
             refund_address,
             fee_politic,
+            fee_bumps,
         })
     }
 }
 
 impl<Addr, Ar, Ac> Bob<Addr, Ar, Ac> {
-    /// Create a new [`Bob`] role with the local parameters.
+    /// Create a new [`Bob`] role with the local parameters, without fee bumps.
     pub fn new(
         arbitrating: Ar,
         accordant: Ac,
@@ -1041,8 +1478,16 @@ impl<Addr, Ar, Ac> Bob<Addr, Ar, Ac> {
             accordant,
             refund_address,
             fee_politic,
+            fee_bumps: 0,
         }
     }
+
+    /// Pre-sign up to `fee_bumps` cancel and refund transactions at increasing fee rates inside
+    /// the fee strategy range, see [`FeeBump`].
+    pub fn with_fee_bumps(mut self, fee_bumps: u16) -> Self {
+        self.fee_bumps = fee_bumps;
+        self
+    }
 }
 
 impl<Addr, Ar, Ac> Bob<Addr, Ar, Ac>
@@ -1055,7 +1500,8 @@ where
     /// # Safety
     ///
     /// All the data passed to the function are considered trusted and does not require extra
-    /// validation. The deal is assumend to be validated by user upfront.
+    /// validation. The deal is assumend to be validated by user upfront, e.g. with a
+    /// [`DealValidator`](crate::trade::DealValidator).
     ///
     /// The parameters contain:
     ///
@@ -1063,6 +1509,7 @@ where
     ///  * The shared private keys (for reading opaque blockchains)
     ///  * The timelock parameters from the deal
     ///  * The target arbitrating address used by Bob
+    ///  * Random nonces salting the commitments to the keys
     ///
     pub fn generate_parameters<Amt, Bmt, Pk, Qk, Rk, Sk, Ti, F, Pr, Kg>(
         &self,
@@ -1110,7 +1557,7 @@ where
 
         let (spend, adaptor, proof) = key_gen.generate_proof()?;
 
-        Ok(Parameters {
+        let mut parameters = Parameters {
             buy: key_gen.get_pubkey(ArbitratingKeyId::Buy)?,
             cancel: key_gen.get_pubkey(ArbitratingKeyId::Cancel)?,
             refund: key_gen.get_pubkey(ArbitratingKeyId::Refund)?,
@@ -1126,7 +1573,10 @@ where
             cancel_timelock: Some(deal.parameters.cancel_timelock),
             punish_timelock: Some(deal.parameters.punish_timelock),
             fee_strategy: Some(deal.parameters.fee_strategy.clone()),
-        })
+            commitment_nonces: None,
+        };
+        parameters.commitment_nonces = Some(CommitmentNonces::new_random(&parameters));
+        Ok(parameters)
     }
 
     /// Initialize the core arbitrating transactions composed of: [`Lockable`], [`Cancelable`], and
@@ -1165,6 +1615,9 @@ where
     /// The fee on each transactions are set according to the [`FeeStrategy`] specified in the
     /// deal and the [`FeePriority`] in `self`.
     ///
+    /// When `fee_bumps` is set in `self` a ladder of cancel and refund transactions is created
+    /// with the fee rates returned by [`Fee::fee_ladder`], each rung replacing the previous one.
+    ///
     /// [`FeeStrategy`]: crate::blockchain::FeeStrategy
     ///
     pub fn core_arbitrating_transactions<Amt, Tx, Px, Pk, Qk, Rk, Sk, Ti, F, Pr, Out>(
@@ -1249,10 +1702,28 @@ where
             .as_partial_mut()
             .set_fee(fee_strategy, self.fee_politic)?;
 
+        // Create the cancel and refund transactions of each rung in the fee ladder, each rung
+        // pays a fixed fee rate taken in the strategy range.
+        let bumps = Px::fee_ladder(fee_strategy, self.fee_politic, self.fee_bumps as usize)
+            .into_iter()
+            .map(|fee_rate| {
+                let fixed = FeeStrategy::Fixed(fee_rate);
+                let mut cancel = <Ar::Cancel>::initialize(&lock, cancel_lock, punish_lock)?;
+                cancel.as_partial_mut().set_fee(&fixed, self.fee_politic)?;
+                let mut refund = <Ar::Refund>::initialize(&cancel, self.refund_address.clone())?;
+                refund.as_partial_mut().set_fee(&fixed, self.fee_politic)?;
+                Ok(FeeBump {
+                    cancel: cancel.to_partial(),
+                    refund: refund.to_partial(),
+                })
+            })
+            .collect::<Res<Vec<_>>>()?;
+
         Ok(CoreArbitratingTransactions {
             lock: lock.to_partial(),
             cancel: cancel.to_partial(),
             refund: refund.to_partial(),
+            bumps,
         })
     }
 
@@ -1359,6 +1830,62 @@ where
         Ok(())
     }
 
+    /// Co-sign the [`Cancelable`] transactions of the fee ladder, one signature per rung
+    /// starting at rung `1`. See [`cosign_arbitrating_cancel`].
+    ///
+    /// [`cosign_arbitrating_cancel`]: Bob::cosign_arbitrating_cancel
+    ///
+    pub fn cosign_arbitrating_cancel_bumps<S, Px, Si, Pk, Ms>(
+        &self,
+        wallet: &mut S,
+        core: &CoreArbitratingTransactions<Px>,
+    ) -> Res<Vec<Si>>
+    where
+        S: Sign<Pk, Ms, Si>,
+        Ar: Transactions<Addr = Addr, Ms = Ms, Pk = Pk, Si = Si, Px = Px>,
+        Px: Clone,
+    {
+        (1..core.rungs_count())
+            .filter_map(|index| core.rung(index))
+            .map(|rung| self.cosign_arbitrating_cancel(wallet, &rung))
+            .collect()
+    }
+
+    /// Validates Alice's adaptor refund witnesses of the fee ladder, one per rung starting at rung
+    /// `1`. See [`validate_adaptor_refund`].
+    ///
+    /// [`validate_adaptor_refund`]: Bob::validate_adaptor_refund
+    ///
+    pub fn validate_fee_bumps<Amt, Px, Pk, Qk, Rk, Sk, Ti, F, Pr, S, Ms, Si, EncSig>(
+        &self,
+        wallet: &mut S,
+        alice_parameters: &Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>,
+        bob_parameters: &Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>,
+        core: &CoreArbitratingTransactions<Px>,
+        fee_bumps: &[FeeBumpSignatures<Si, EncSig>],
+    ) -> Res<()>
+    where
+        S: EncSign<Pk, Ms, Si, EncSig>,
+        Ar: Transactions<Addr = Addr, Amt = Amt, Ti = Ti, Ms = Ms, Pk = Pk, Si = Si, Px = Px>,
+        Px: Clone,
+    {
+        if fee_bumps.len() != core.bumps.len() {
+            return Err(FeeStrategyError::InvalidFeeLadder.into());
+        }
+        for (index, signatures) in fee_bumps.iter().enumerate() {
+            // Safety: the number of signatures matches the number of bumps
+            let rung = core.rung(index + 1).expect("rung exists");
+            self.validate_adaptor_refund(
+                wallet,
+                alice_parameters,
+                bob_parameters,
+                &rung,
+                &signatures.refund_adaptor_sig,
+            )?;
+        }
+        Ok(())
+    }
+
     /// Creates the [`Buyable`] transaction and generate the adaptor witness
     ///
     /// # Safety
@@ -1462,10 +1989,11 @@ where
             swap_id,
             buy: buy.to_partial(),
             buy_adaptor_sig: sig,
+            transcript: None,
         })
     }
 
-    /// Sign the arbitrating [`Lockable`] transaction and return the signature.
+    /// Sign the arbitrating [`Lockable`] transaction and return the signatures, one per input.
     ///
     /// # Safety
     ///
@@ -1480,9 +2008,9 @@ where
     ///
     ///  * Parse the [`Lockable`] partial transaction in [`CoreArbitratingTransactions`]
     ///  * Retreive the funding public key from the paramters
-    ///  * Generate the witness data and sign it
+    ///  * Generate the witness data of each input and sign it
     ///
-    /// Returns the signature.
+    /// Returns the signatures ordered by input index, the lock has one input per funding output.
     ///
     /// [`sign_arbitrating_lock`]: Bob::sign_arbitrating_lock
     /// [`validate_adaptor_refund`]: Bob::validate_adaptor_refund
@@ -1491,7 +2019,7 @@ where
         &self,
         wallet: &mut S,
         core: &CoreArbitratingTransactions<Px>,
-    ) -> Res<Si>
+    ) -> Res<Vec<Si>>
     where
         S: Sign<Pk, Ms, Si>,
         Ar: Transactions<Addr = Addr, Ms = Ms, Pk = Pk, Si = Si, Px = Px>,
@@ -1504,9 +2032,13 @@ where
         // Initialize the lock transaction based on the partial transaction format.
         let lock = <Ar::Lock>::from_partial(partial_lock);
 
-        // Generate the witness message to sign and sign with the fund key.
-        let msg = lock.generate_witness_message(ScriptPath::Success)?;
-        wallet.sign(ArbitratingKeyId::Lock, msg).map_err(Into::into)
+        // Generate the witness message of each input and sign with the fund key.
+        (0..lock.inputs_count())
+            .map(|index| {
+                let msg = lock.generate_input_witness_message(index, ScriptPath::Success)?;
+                wallet.sign(ArbitratingKeyId::Lock, msg).map_err(Into::into)
+            })
+            .collect()
     }
 
     /// Sign the arbitrating [`Refundable`] transaction and adapt the counter-party adaptor witness
@@ -1566,20 +2098,21 @@ where
     }
 
     /// This function allows to recover the secret key used to encrypt the buy signature, allowing
-    /// Bob to recover Alice's secret and transfer ownership of funds.
+    /// Bob to recover Alice's secret and transfer ownership of funds. Fails if the buy signature
+    /// is not the decryption of the encrypted signature.
     pub fn recover_accordant_key<S, Tx, Px, Si, Pk, Qk, Rk, Sk, Ti, F, Pr, EncSig>(
         &self,
         wallet: &mut S,
         alice_parameters: &Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>,
         buy_adaptor_sig: EncSig,
         buy_tx: Tx,
-    ) -> Rk
+    ) -> Res<Rk>
     where
         S: RecoverSecret<Pk, Rk, Si, EncSig>,
         Ar: Transactions<Addr = Addr, Tx = Tx, Px = Px, Pk = Pk, Si = Si>,
     {
         let encryption_key = &alice_parameters.adaptor;
         let signature = <Ar::Buy>::extract_witness(buy_tx);
-        wallet.recover_secret_key(buy_adaptor_sig, encryption_key, signature)
+        Ok(wallet.recover_secret_key(buy_adaptor_sig, encryption_key, signature)?)
     }
 }
@@ -0,0 +1,149 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Versioned checkpoints of a running swap. A [`Checkpoint`] bundles a swap state machine with the
+//! wallet holding the swap keys, it can be serialized at any [`Step`] and restored later to
+//! continue the swap where it stopped.
+//!
+//! Checkpoints are serialized with Farcaster consensus (or strict encoding) and start with
+//! [`CHECKPOINT_MAGIC_BYTES`] followed by the checkpoint version. Only checkpoints with version
+//! [`CHECKPOINT_VERSION`] are accepted when decoding. Checkpoints of version 1 were taken before
+//! the fee ladders, the transcript hashes, the salted commitments and the deal fills were added to
+//! the swaps, they cannot be restored and the swaps must be recovered manually.
+//!
+//! [`Step`]: crate::protocol::machine::Step
+
+use std::io;
+
+use crate::consensus::{self, Decodable, Encodable};
+
+/// First six magic bytes of a serialized checkpoint.
+pub const CHECKPOINT_MAGIC_BYTES: &[u8; 6] = b"FCCKPT";
+
+/// Version of the checkpoint serialization format produced by this library.
+pub const CHECKPOINT_VERSION: u16 = 2;
+
+/// A snapshot of a swap containing the state machine, [`AliceSwap`] or [`BobSwap`], and the
+/// wallet used to derive the swap keys.
+///
+/// [`AliceSwap`]: crate::protocol::machine::AliceSwap
+/// [`BobSwap`]: crate::protocol::machine::BobSwap
+#[derive(Debug, Clone)]
+pub struct Checkpoint<T, W> {
+    /// The swap state machine.
+    pub state: T,
+    /// The wallet holding the swap keys.
+    pub wallet: W,
+}
+
+impl<T, W> Checkpoint<T, W> {
+    /// Create a new checkpoint for the swap state machine and its wallet.
+    pub fn new(state: T, wallet: W) -> Self {
+        Self { state, wallet }
+    }
+
+    /// Return the version of the checkpoint serialization format.
+    pub fn version(&self) -> u16 {
+        CHECKPOINT_VERSION
+    }
+
+    /// Consume the checkpoint and return the state machine and the wallet to resume the swap.
+    pub fn into_inner(self) -> (T, W) {
+        (self.state, self.wallet)
+    }
+}
+
+impl<T, W> Encodable for Checkpoint<T, W>
+where
+    T: Encodable,
+    W: Encodable,
+{
+    fn consensus_encode<S: io::Write>(&self, s: &mut S) -> Result<usize, io::Error> {
+        let mut len = CHECKPOINT_MAGIC_BYTES.consensus_encode(s)?;
+        len += CHECKPOINT_VERSION.consensus_encode(s)?;
+        len += self.state.consensus_encode(s)?;
+        Ok(len + self.wallet.consensus_encode(s)?)
+    }
+}
+
+impl<T, W> Decodable for Checkpoint<T, W>
+where
+    T: Decodable,
+    W: Decodable,
+{
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        let magic_bytes: [u8; 6] = Decodable::consensus_decode(d)?;
+        if magic_bytes != *CHECKPOINT_MAGIC_BYTES {
+            return Err(consensus::Error::IncorrectMagicBytes);
+        }
+        match u16::consensus_decode(d)? {
+            CHECKPOINT_VERSION => (),
+            1 => {
+                return Err(consensus::Error::ParseFailed(
+                    "Checkpoint version 1 cannot be restored",
+                ))
+            }
+            _ => {
+                return Err(consensus::Error::ParseFailed(
+                    "Unsupported checkpoint version",
+                ))
+            }
+        }
+        Ok(Self {
+            state: Decodable::consensus_decode(d)?,
+            wallet: Decodable::consensus_decode(d)?,
+        })
+    }
+}
+
+impl_strict_encoding!(Checkpoint<T, W>, T: Encodable + Decodable, W: Encodable + Decodable);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::{deserialize, serialize_hex};
+
+    #[test]
+    fn serialize_checkpoint() {
+        let checkpoint = Checkpoint::new(0xcafeu16, 0x42u8);
+        assert_eq!(checkpoint.version(), CHECKPOINT_VERSION);
+        assert_eq!(serialize_hex(&checkpoint), "4643434b50540200feca42");
+    }
+
+    #[test]
+    fn reject_invalid_checkpoint() {
+        let bad_magic = hex::decode("4643535741500200feca42").unwrap();
+        assert!(matches!(
+            deserialize::<Checkpoint<u16, u8>>(&bad_magic),
+            Err(consensus::Error::IncorrectMagicBytes)
+        ));
+        let v1 = hex::decode("4643434b50540100feca42").unwrap();
+        assert!(matches!(
+            deserialize::<Checkpoint<u16, u8>>(&v1),
+            Err(consensus::Error::ParseFailed(msg)) if msg.starts_with("Checkpoint version 1")
+        ));
+        let bad_version = hex::decode("4643434b50540300feca42").unwrap();
+        assert!(matches!(
+            deserialize::<Checkpoint<u16, u8>>(&bad_version),
+            Err(consensus::Error::ParseFailed(
+                "Unsupported checkpoint version"
+            ))
+        ));
+        let good = hex::decode("4643434b50540200feca42").unwrap();
+        let checkpoint: Checkpoint<u16, u8> = deserialize(&good).unwrap();
+        assert_eq!(checkpoint.into_inner(), (0xcafe, 0x42));
+    }
+}
@@ -0,0 +1,1782 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Swap state machines for [`Alice`] and [`Bob`]. A state machine drives the protocol execution
+//! end-to-end by calling [`Alice`] and [`Bob`] methods in the correct order, it consumes
+//! [`ProtocolMessage`] received from the counter-party and blockchain [`Event`] reported by
+//! syncers, and emits the list of [`Output`] to execute for moving forward: messages to send,
+//! transactions to broadcast, or assets to lock.
+//!
+//! Each input is only accepted at a specific [`Step`] of the swap, any input received out of order
+//! is rejected with [`Error::OutOfOrder`] and leaves the state machine untouched. If an input
+//! fails validation the error is returned and the state machine stays at the same step. Messages
+//! are checked against the deal with [`validation`] before being processed, and messages carrying
+//! transactions before their signatures.
+//!
+//! [`validation`]: crate::protocol::validation
+//!
+//! The message flow for both participants is:
+//!
+//!  1. `start` generates the parameters and emits the commitment, the taker of a deal accepting
+//!     partial fills first emits its [`DealFill`] set with `with_fill` and the maker only accepts
+//!     the taker's commitment after validating the fill
+//!  2. upon reception of the counter-party commitment the parameters and the proof are revealed
+//!  3. the counter-party parameters and then its proof are received and validated
+//!  4. Bob funds the swap and sends the [`CoreArbitratingSetup`] when funding is seen on-chain
+//!  5. Alice validates the setup and returns the [`RefundProcedureSignatures`]
+//!  6. Bob validates the signatures and broadcasts the lock
+//!  7. Alice locks the accordant assets when the arbitrating lock is final
+//!  8. Bob sends the [`BuyProcedureSignature`] when the accordant lock is final
+//!  9. Alice broadcasts the buy and Bob recovers the accordant secret key from it
+//!
+//! When the cancel timelock expires before the buy is seen on-chain either participant broadcasts
+//! the cancel transaction, then Bob refunds or Alice punishes after the punish timelock.
+//!
+//! If Bob pre-signs a fee ladder, see [`FeeBump`], the cancel transaction can be replaced with a
+//! higher fee rate rung with `bump_cancel`. The refund and punish transactions are then built on the
+//! rung of the cancel seen on-chain.
+//!
+//! Swaps created with `new_validated` only start if the deal passes a [`DealValidator`]. Swaps of a
+//! deal accepting partial fills lock the amounts of the taker's fill, see `arbitrating_amount` and
+//! `accordant_amount`.
+//!
+//! [`FeeBump`]: crate::protocol::FeeBump
+
+// For this file we allow having complex types
+#![allow(clippy::type_complexity)]
+
+use std::io;
+
+use thiserror::Error;
+
+use crate::blockchain::{Fee, FeeStrategyError, Transactions};
+use crate::consensus::{self, CanonicalBytes, Decodable, Encodable};
+use crate::crypto::{
+    ArbitratingKeyId, Commit, DeriveKeys, EncSign, KeyGenerator, RecoverSecret, Sign,
+};
+use crate::protocol::message::{
+    Abort, AbortReason, BuyProcedureSignature, CommitAliceParameters, CommitBobParameters,
+    CoreArbitratingSetup, DealFill, MessageType, ProtocolMessage, RefundProcedureSignatures,
+    RevealProof,
+};
+use crate::protocol::transcript::TranscriptHash;
+use crate::protocol::{
+    Alice, ArbitratingParameters, Bob, CoreArbitratingTransactions, FeeBumpSignatures, Parameters,
+};
+use crate::role::{SwapRole, TradeRole};
+use crate::script::ScriptPath;
+use crate::swap::SwapId;
+use crate::trade::{self, AtomicAmount, Deal, DealValidator};
+use crate::transaction::{Broadcastable, Fundable, Refundable, Transaction, TxLabel, Witnessable};
+use crate::Res;
+
+/// List of possible errors when driving a swap with a state machine.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// The input is not expected at the current step of the swap.
+    #[error("Unexpected {input} at step {step}")]
+    OutOfOrder {
+        /// The current step of the swap.
+        step: Step,
+        /// The name of the rejected input.
+        input: &'static str,
+    },
+    /// The message is related to another swap.
+    #[error("Message for swap {found} received by swap {expected}")]
+    SwapIdMismatch {
+        /// The swap identifier of the state machine.
+        expected: SwapId,
+        /// The swap identifier found in the message.
+        found: SwapId,
+    },
+    /// A value required at the current step is missing from the state.
+    #[error("Missing {0} in swap state")]
+    MissingState(&'static str),
+    /// The transaction does not match any of the pre-signed transactions of the swap.
+    #[error("Unknown {0} transaction")]
+    UnknownTransaction(&'static str),
+    /// The transcript hash of the message differs from the transcript of the swap, the
+    /// counter-party did not see the same protocol run or stripped the transcript.
+    #[error("Transcript {found:?} of the message differs from the swap transcript {expected}")]
+    TranscriptMismatch {
+        /// The transcript hash of the state machine.
+        expected: TranscriptHash,
+        /// The transcript hash found in the message, if any.
+        found: Option<TranscriptHash>,
+    },
+}
+
+/// Steps of a swap. A state machine waits at a step until the expected input is received, the
+/// documentation of each step describes the input waited on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+#[display(Debug)]
+pub enum Step {
+    /// The swap is created but not started.
+    Init,
+    /// Waiting for the counter-party commitment.
+    Commit,
+    /// Waiting for the counter-party revealed parameters.
+    RevealParameters,
+    /// Waiting for the counter-party cross-group discrete logarithm proof.
+    RevealProof,
+    /// Bob is waiting for the funding transaction to be seen on-chain.
+    Funding,
+    /// Alice is waiting for the [`CoreArbitratingSetup`].
+    CoreArbitratingSetup,
+    /// Bob is waiting for the [`RefundProcedureSignatures`].
+    RefundProcedureSignatures,
+    /// Alice is waiting for the arbitrating lock to be final.
+    ArbitratingLock,
+    /// Bob is waiting for the accordant lock to be final.
+    AccordantLock,
+    /// Alice is waiting for the [`BuyProcedureSignature`].
+    BuyProcedureSignature,
+    /// Waiting for the buy transaction to be seen on-chain.
+    Buy,
+    /// The cancel transaction is broadcasted, Alice waits for the refund or the punish timelock,
+    /// Bob waits for the cancel to be seen on-chain.
+    Cancel,
+    /// Bob is waiting for the refund transaction to be seen on-chain.
+    Refund,
+    /// Alice is waiting for the punish transaction to be seen on-chain.
+    Punish,
+    /// The assets are swapped, final step.
+    SwapSuccess,
+    /// The swap is cancelled and both participants are refunded, final step.
+    Refunded,
+    /// The swap is cancelled and Alice punished Bob, final step.
+    Punished,
+    /// The swap is aborted before any asset is locked, final step.
+    Aborted,
+}
+
+impl Step {
+    /// Return `true` if no input is accepted anymore at this step.
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            Self::SwapSuccess | Self::Refunded | Self::Punished | Self::Aborted
+        )
+    }
+
+    // Swaps can be aborted as long as the arbitrating lock is not broadcasted.
+    fn is_abortable(&self) -> bool {
+        matches!(
+            self,
+            Self::Commit
+                | Self::RevealParameters
+                | Self::RevealProof
+                | Self::Funding
+                | Self::CoreArbitratingSetup
+                | Self::RefundProcedureSignatures
+        )
+    }
+}
+
+impl Encodable for Step {
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        let code: u8 = match self {
+            Self::Init => 0x01,
+            Self::Commit => 0x02,
+            Self::RevealParameters => 0x03,
+            Self::RevealProof => 0x04,
+            Self::Funding => 0x05,
+            Self::CoreArbitratingSetup => 0x06,
+            Self::RefundProcedureSignatures => 0x07,
+            Self::ArbitratingLock => 0x08,
+            Self::AccordantLock => 0x09,
+            Self::BuyProcedureSignature => 0x0a,
+            Self::Buy => 0x0b,
+            Self::Cancel => 0x0c,
+            Self::Refund => 0x0d,
+            Self::Punish => 0x0e,
+            Self::SwapSuccess => 0x0f,
+            Self::Refunded => 0x10,
+            Self::Punished => 0x11,
+            Self::Aborted => 0x12,
+        };
+        code.consensus_encode(writer)
+    }
+}
+
+impl Decodable for Step {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        match Decodable::consensus_decode(d)? {
+            0x01u8 => Ok(Self::Init),
+            0x02u8 => Ok(Self::Commit),
+            0x03u8 => Ok(Self::RevealParameters),
+            0x04u8 => Ok(Self::RevealProof),
+            0x05u8 => Ok(Self::Funding),
+            0x06u8 => Ok(Self::CoreArbitratingSetup),
+            0x07u8 => Ok(Self::RefundProcedureSignatures),
+            0x08u8 => Ok(Self::ArbitratingLock),
+            0x09u8 => Ok(Self::AccordantLock),
+            0x0au8 => Ok(Self::BuyProcedureSignature),
+            0x0bu8 => Ok(Self::Buy),
+            0x0cu8 => Ok(Self::Cancel),
+            0x0du8 => Ok(Self::Refund),
+            0x0eu8 => Ok(Self::Punish),
+            0x0fu8 => Ok(Self::SwapSuccess),
+            0x10u8 => Ok(Self::Refunded),
+            0x11u8 => Ok(Self::Punished),
+            0x12u8 => Ok(Self::Aborted),
+            _ => Err(consensus::Error::UnknownType),
+        }
+    }
+}
+
+impl_strict_encoding!(Step);
+
+/// Blockchain events reported to the state machines. Transactions are reported when the state
+/// machine needs their content, e.g. to extract a witness.
+#[derive(Debug, Clone)]
+pub enum Event<Tx> {
+    /// The funding transactions are seen on-chain, i.e. all the transactions paying to the
+    /// funding address. If they do not cover the amount to lock the event is rejected and can be
+    /// reported again when more funds are received.
+    FundingSeen(Vec<Tx>),
+    /// The arbitrating lock transaction is final.
+    ArbitratingLockFinal,
+    /// The accordant lock is final with the correct amount.
+    AccordantLockFinal,
+    /// The cancel timelock expired, the cancel transaction can be broadcasted.
+    CancelTimelockExpired,
+    /// The cancel transaction is seen on-chain, the transaction identifies the rung of the fee
+    /// ladder used.
+    CancelSeen(Tx),
+    /// The punish timelock expired, the punish transaction can be broadcasted.
+    PunishTimelockExpired,
+    /// The buy transaction is seen on-chain.
+    BuySeen(Tx),
+    /// The refund transaction is seen on-chain.
+    RefundSeen(Tx),
+    /// The punish transaction is seen on-chain.
+    PunishSeen,
+}
+
+impl<Tx> Event<Tx> {
+    /// Return the name of the event.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::FundingSeen(_) => "FundingSeen",
+            Self::ArbitratingLockFinal => "ArbitratingLockFinal",
+            Self::AccordantLockFinal => "AccordantLockFinal",
+            Self::CancelTimelockExpired => "CancelTimelockExpired",
+            Self::CancelSeen(_) => "CancelSeen",
+            Self::PunishTimelockExpired => "PunishTimelockExpired",
+            Self::BuySeen(_) => "BuySeen",
+            Self::RefundSeen(_) => "RefundSeen",
+            Self::PunishSeen => "PunishSeen",
+        }
+    }
+}
+
+/// Actions emitted by the state machines, must be executed in order.
+// Outputs are consumed right away, the messages are not boxed
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone)]
+pub enum Output<C, Pk, Qk, Rk, Sk, Addr, Pr, Px, Si, EncSig, N, Amt, Bmt, Tx> {
+    /// Send the message to the counter-party.
+    Message(ProtocolMessage<C, Pk, Qk, Rk, Sk, Addr, Pr, Px, Si, EncSig, N, Amt, Bmt>),
+    /// Bob must fund the address with the arbitrating assets.
+    FundArbitrating(Addr),
+    /// Alice must lock the accordant assets, the accordant keys are available in both
+    /// participants' parameters.
+    LockAccordant,
+    /// Broadcast the fully signed transaction.
+    Broadcast(TxLabel, Tx),
+    /// The counter-party accordant secret key is recovered, the accordant assets can be swept.
+    RecoveredAccordantKey(Rk),
+}
+
+type Outputs<C, Pk, Qk, Rk, Sk, Addr, Pr, Px, Si, EncSig, N, Amt, Bmt, Tx> =
+    Res<Vec<Output<C, Pk, Qk, Rk, Sk, Addr, Pr, Px, Si, EncSig, N, Amt, Bmt, Tx>>>;
+
+fn required<'a, T>(value: &'a Option<T>, name: &'static str) -> Result<&'a T, Error> {
+    value.as_ref().ok_or(Error::MissingState(name))
+}
+
+fn check_swap_id(expected: SwapId, found: SwapId) -> Result<(), Error> {
+    match expected == found {
+        true => Ok(()),
+        false => Err(Error::SwapIdMismatch { expected, found }),
+    }
+}
+
+// The transcript of the message must match the one of the swap, a message without transcript
+// is rejected as the binding to the protocol run would be lost
+fn check_transcript(
+    transcript: Option<TranscriptHash>,
+    found: Option<TranscriptHash>,
+) -> Result<(), Error> {
+    let expected = *required(&transcript, "transcript")?;
+    match found == Some(expected) {
+        true => Ok(()),
+        false => Err(Error::TranscriptMismatch { expected, found }),
+    }
+}
+
+// Absorb both reveals and proofs in the protocol order, Alice's first, the revealed messages are
+// rebuilt from the parameters
+fn absorb_reveals<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>(
+    transcript: TranscriptHash,
+    swap_id: SwapId,
+    alice_parameters: &Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>,
+    bob_parameters: &Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>,
+) -> Result<TranscriptHash, Error>
+where
+    Pk: Copy + CanonicalBytes,
+    Qk: Clone + CanonicalBytes,
+    Rk: Clone + CanonicalBytes,
+    Sk: Clone + CanonicalBytes,
+    Addr: Clone + CanonicalBytes,
+    Ti: Clone,
+    F: Clone,
+    Pr: Clone + CanonicalBytes,
+{
+    let alice_proof = RevealProof {
+        swap_id,
+        proof: required(&alice_parameters.proof, "Alice proof")?.clone(),
+    };
+    let bob_proof = RevealProof {
+        swap_id,
+        proof: required(&bob_parameters.proof, "Bob proof")?.clone(),
+    };
+    Ok(transcript
+        .absorb(
+            MessageType::RevealAliceParameters,
+            &alice_parameters.clone().reveal_alice(swap_id),
+        )
+        .absorb(MessageType::RevealProof, &alice_proof)
+        .absorb(
+            MessageType::RevealBobParameters,
+            &bob_parameters.clone().reveal_bob(swap_id),
+        )
+        .absorb(MessageType::RevealProof, &bob_proof))
+}
+
+// State shared by both swap state machines, the participant specific state is kept in the state
+// machines
+#[derive(Debug, Clone)]
+struct SwapState<Addr, Amt, Bmt, Ti, F, Pk, Qk, Rk, Sk, Pr, Px, Si, EncSig> {
+    swap_id: SwapId,
+    step: Step,
+    deal: Deal<Amt, Bmt, Ti, F>,
+    fill: Option<trade::DealFill<Amt, Bmt>>,
+    alice_parameters: Option<Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>>,
+    bob_parameters: Option<Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>>,
+    core: Option<CoreArbitratingTransactions<Px>>,
+    alice_cancel_sig: Option<Si>,
+    bob_cancel_sig: Option<Si>,
+    refund_adaptor_sig: Option<EncSig>,
+    fee_bumps: Vec<FeeBumpSignatures<Si, EncSig>>,
+    bob_bump_cancel_sigs: Vec<Si>,
+    rung: u16,
+    transcript: Option<TranscriptHash>,
+}
+
+impl<Addr, Amt, Bmt, Ti, F, Pk, Qk, Rk, Sk, Pr, Px, Si, EncSig>
+    SwapState<Addr, Amt, Bmt, Ti, F, Pk, Qk, Rk, Sk, Pr, Px, Si, EncSig>
+{
+    fn new(swap_id: SwapId, deal: Deal<Amt, Bmt, Ti, F>) -> Self {
+        Self {
+            swap_id,
+            step: Step::Init,
+            deal,
+            fill: None,
+            alice_parameters: None,
+            bob_parameters: None,
+            core: None,
+            alice_cancel_sig: None,
+            bob_cancel_sig: None,
+            refund_adaptor_sig: None,
+            fee_bumps: vec![],
+            bob_bump_cancel_sigs: vec![],
+            rung: 0,
+            transcript: None,
+        }
+    }
+
+    // The maker of a deal accepting partial fills waits for the taker's fill before accepting its
+    // commitment
+    fn awaits_fill(&self, role: SwapRole) -> bool {
+        role == self.deal.parameters.swap_role(&TradeRole::Maker)
+            && self.deal.fill_range.is_some()
+            && self.fill.is_none()
+    }
+
+    fn abort<C, N, Tx>(
+        &mut self,
+        reason: AbortReason,
+        error_body: Option<String>,
+    ) -> Outputs<C, Pk, Qk, Rk, Sk, Addr, Pr, Px, Si, EncSig, N, Amt, Bmt, Tx> {
+        if !self.step.is_abortable() {
+            return Err(Error::OutOfOrder {
+                step: self.step,
+                input: "abort",
+            }
+            .into());
+        }
+        self.step = Step::Aborted;
+        Ok(vec![Output::Message(ProtocolMessage::Abort(Abort {
+            swap_id: self.swap_id,
+            reason,
+            error_body,
+            transcript: self.transcript,
+        }))])
+    }
+
+    // Alice's refund adaptor signature of the rung at `index` in the fee ladder.
+    fn refund_adaptor_sig(&self, index: usize) -> Result<&EncSig, Error> {
+        match index {
+            0 => required(&self.refund_adaptor_sig, "refund adaptor signature"),
+            i => Ok(&self
+                .fee_bumps
+                .get(i - 1)
+                .ok_or(Error::MissingState("fee bump signatures"))?
+                .refund_adaptor_sig),
+        }
+    }
+
+    // Fully sign the cancel transaction of the rung at `index` in the fee ladder.
+    fn signed_cancel<Ar>(&self, index: usize) -> Res<Ar::Tx>
+    where
+        Ar: Transactions<Px = Px, Pk = Pk, Si = Si>,
+        Pk: Copy,
+        Px: Clone,
+        Si: Clone,
+    {
+        let alice_parameters = required(&self.alice_parameters, "Alice parameters")?;
+        let bob_parameters = required(&self.bob_parameters, "Bob parameters")?;
+        let core = required(&self.core, "core arbitrating transactions")?
+            .rung(index)
+            .ok_or(Error::MissingState("fee ladder rung"))?;
+        let (alice_sig, bob_sig) = match index {
+            0 => (
+                required(&self.alice_cancel_sig, "Alice cancel signature")?.clone(),
+                required(&self.bob_cancel_sig, "Bob cancel signature")?.clone(),
+            ),
+            i => (
+                self.fee_bumps
+                    .get(i - 1)
+                    .ok_or(Error::MissingState("fee bump signatures"))?
+                    .cancel_sig
+                    .clone(),
+                self.bob_bump_cancel_sigs
+                    .get(i - 1)
+                    .ok_or(Error::MissingState("Bob fee bump signature"))?
+                    .clone(),
+            ),
+        };
+
+        let mut cancel = <Ar::Cancel>::from_partial(core.cancel);
+        cancel.add_witness(alice_parameters.cancel, alice_sig)?;
+        cancel.add_witness(bob_parameters.cancel, bob_sig)?;
+        Ok(cancel.finalize_and_extract()?)
+    }
+
+    // Find the rung of the fee ladder of a cancel transaction seen on-chain.
+    fn cancel_rung<Ar>(&self, cancel: &Ar::Tx) -> Res<u16>
+    where
+        Ar: Transactions<Px = Px, Pk = Pk, Si = Si>,
+        Ar::Tx: PartialEq,
+        Pk: Copy,
+        Px: Clone,
+        Si: Clone,
+    {
+        let core = required(&self.core, "core arbitrating transactions")?;
+        for index in 0..core.rungs_count() {
+            if self.signed_cancel::<Ar>(index)? == *cancel {
+                return Ok(index as u16);
+            }
+        }
+        Err(Error::UnknownTransaction("cancel").into())
+    }
+
+    fn bump_cancel<Ar, C, N>(
+        &mut self,
+        fee_rate: &F,
+    ) -> Outputs<C, Pk, Qk, Rk, Sk, Addr, Pr, Px, Si, EncSig, N, Amt, Bmt, Ar::Tx>
+    where
+        Ar: Transactions<Px = Px, Pk = Pk, Si = Si>,
+        Pk: Copy,
+        Px: Clone + Fee<FeeUnit = F>,
+        Si: Clone,
+        F: PartialOrd,
+    {
+        if self.step != Step::Cancel {
+            return Err(Error::OutOfOrder {
+                step: self.step,
+                input: "bump_cancel",
+            }
+            .into());
+        }
+        let rung = required(&self.core, "core arbitrating transactions")?.select_rung(fee_rate)?;
+        if rung <= self.rung as usize {
+            return Ok(vec![]);
+        }
+        let cancel = self.signed_cancel::<Ar>(rung)?;
+        self.rung = rung as u16;
+        Ok(vec![Output::Broadcast(TxLabel::Cancel, cancel)])
+    }
+}
+
+impl<Addr, Amt, Bmt, Ti, F, Pk, Qk, Rk, Sk, Pr, Px, Si, EncSig>
+    SwapState<Addr, Amt, Bmt, Ti, F, Pk, Qk, Rk, Sk, Pr, Px, Si, EncSig>
+where
+    Deal<Amt, Bmt, Ti, F>: Encodable,
+    Amt: AtomicAmount + Copy + PartialOrd + CanonicalBytes,
+    Bmt: AtomicAmount + Copy + PartialEq + CanonicalBytes,
+    Ti: Copy,
+    F: Copy,
+{
+    // Start the transcript, the taker of a deal accepting partial fills sends its fill before its
+    // commitment
+    fn start<C, N, Tx>(
+        &mut self,
+        role: SwapRole,
+    ) -> Outputs<C, Pk, Qk, Rk, Sk, Addr, Pr, Px, Si, EncSig, N, Amt, Bmt, Tx> {
+        let transcript = TranscriptHash::start(self.swap_id, self.deal.fingerprint());
+        if role == self.deal.parameters.swap_role(&TradeRole::Maker)
+            || self.deal.fill_range.is_none()
+        {
+            self.transcript = Some(transcript);
+            return Ok(vec![]);
+        }
+        let fill = DealFill {
+            swap_id: self.swap_id,
+            fill: *required(&self.fill, "deal fill")?,
+        };
+        self.transcript = Some(transcript.absorb(MessageType::DealFill, &fill));
+        Ok(vec![Output::Message(ProtocolMessage::DealFill(fill))])
+    }
+
+    // The maker validates the taker's fill against the deal
+    fn accept_fill(&mut self, fill: DealFill<Amt, Bmt>) -> Res<()> {
+        self.deal.validate_fill(&fill.fill)?;
+        self.transcript =
+            Some(required(&self.transcript, "transcript")?.absorb(MessageType::DealFill, &fill));
+        self.fill = Some(fill.fill);
+        Ok(())
+    }
+
+    // The arbitrating parameters of the deal at the arbitrating amount filled by the taker
+    fn arbitrating_params(&self) -> Res<ArbitratingParameters<Amt, Ti, F>> {
+        match &self.fill {
+            Some(fill) => Ok(self.deal.to_filled_arbitrating_params(fill)?),
+            None => Ok(self.deal.to_arbitrating_params()),
+        }
+    }
+}
+
+// Accessors and abort of the state machines, delegating to the shared swap state
+macro_rules! impl_swap_state_accessors {
+    () => {
+        /// Return the swap identifier.
+        pub fn swap_id(&self) -> SwapId {
+            self.state.swap_id
+        }
+
+        /// Return the current step of the swap.
+        pub fn step(&self) -> Step {
+            self.state.step
+        }
+
+        /// Return the deal executed by the swap.
+        pub fn deal(&self) -> &Deal<Amt, Bmt, Ti, F> {
+            &self.state.deal
+        }
+
+        /// Return the taker's fill if the deal accepts partial fills, set with `with_fill` by the
+        /// taker or received by the maker.
+        pub fn fill(&self) -> Option<&trade::DealFill<Amt, Bmt>> {
+            self.state.fill.as_ref()
+        }
+
+        /// Return the arbitrating amount locked by the swap, the amount of the taker's fill if
+        /// any or the amount of the deal.
+        pub fn arbitrating_amount(&self) -> Amt
+        where
+            Amt: Copy,
+        {
+            self.state
+                .fill
+                .as_ref()
+                .map_or(self.state.deal.parameters.arbitrating_amount, |fill| {
+                    fill.arbitrating_amount
+                })
+        }
+
+        /// Return the accordant amount locked by the swap, the amount of the taker's fill if any
+        /// or the amount of the deal.
+        pub fn accordant_amount(&self) -> Bmt
+        where
+            Bmt: Copy,
+        {
+            self.state
+                .fill
+                .as_ref()
+                .map_or(self.state.deal.parameters.accordant_amount, |fill| {
+                    fill.accordant_amount
+                })
+        }
+
+        /// Return Alice's parameters once generated or revealed.
+        pub fn alice_parameters(&self) -> Option<&Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>> {
+            self.state.alice_parameters.as_ref()
+        }
+
+        /// Return Bob's parameters once generated or revealed.
+        pub fn bob_parameters(&self) -> Option<&Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>> {
+            self.state.bob_parameters.as_ref()
+        }
+
+        /// Return the core arbitrating transactions once created or received.
+        pub fn core_arbitrating_transactions(&self) -> Option<&CoreArbitratingTransactions<Px>> {
+            self.state.core.as_ref()
+        }
+
+        /// Return the rung of the fee ladder of the last cancel transaction broadcasted or seen.
+        pub fn rung(&self) -> usize {
+            self.state.rung as usize
+        }
+
+        /// Return the transcript hash of the protocol messages exchanged so far, see
+        /// [`transcript`](crate::protocol::transcript). The transcript starts when the swap
+        /// starts.
+        pub fn transcript(&self) -> Option<TranscriptHash> {
+            self.state.transcript
+        }
+
+        /// Abort the swap and emit the [`Abort`] message for the counter-party. Swaps can only be
+        /// aborted before the arbitrating lock is broadcasted.
+        pub fn abort<Tx, N>(
+            &mut self,
+            reason: AbortReason,
+            error_body: Option<String>,
+        ) -> Outputs<C, Pk, Qk, Rk, Sk, Addr, Pr, Px, Si, EncSig, N, Amt, Bmt, Tx> {
+            self.state.abort(reason, error_body)
+        }
+    };
+}
+
+/// Alice's swap state machine, see the [module documentation](self) for the message flow.
+#[derive(Debug, Clone)]
+pub struct AliceSwap<Addr, Ar, Ac, Amt, Bmt, Ti, F, Pk, Qk, Rk, Sk, Pr, C, Px, Si, EncSig> {
+    alice: Alice<Addr, Ar, Ac>,
+    bob_commit: Option<CommitBobParameters<C>>,
+    state: SwapState<Addr, Amt, Bmt, Ti, F, Pk, Qk, Rk, Sk, Pr, Px, Si, EncSig>,
+}
+
+impl<Addr, Ar, Ac, Amt, Bmt, Ti, F, Pk, Qk, Rk, Sk, Pr, C, Px, Si, EncSig>
+    AliceSwap<Addr, Ar, Ac, Amt, Bmt, Ti, F, Pk, Qk, Rk, Sk, Pr, C, Px, Si, EncSig>
+{
+    /// Create a new state machine for Alice at [`Step::Init`].
+    pub fn new(swap_id: SwapId, alice: Alice<Addr, Ar, Ac>, deal: Deal<Amt, Bmt, Ti, F>) -> Self {
+        Self {
+            alice,
+            bob_commit: None,
+            state: SwapState::new(swap_id, deal),
+        }
+    }
+
+    /// Create a new state machine for Alice at [`Step::Init`] if the deal passes the validator,
+    /// fails with [`InvalidDeal`] listing the policy violations otherwise.
+    ///
+    /// [`InvalidDeal`]: crate::trade::Error::InvalidDeal
+    pub fn new_validated(
+        swap_id: SwapId,
+        alice: Alice<Addr, Ar, Ac>,
+        deal: Deal<Amt, Bmt, Ti, F>,
+        validator: &DealValidator<Amt, Bmt, Ti, F>,
+    ) -> Res<Self>
+    where
+        Amt: PartialOrd,
+        Bmt: PartialOrd,
+        Ti: PartialOrd + CanonicalBytes,
+        F: PartialOrd,
+    {
+        validator.validate(&deal)?;
+        Ok(Self::new(swap_id, alice, deal))
+    }
+
+    /// Set the taker's fill of a deal accepting partial fills, created with [`Deal::fill`]. The
+    /// fill is sent to the maker when the swap starts and the swap locks the filled amounts, fails
+    /// if the fill does not match the deal. Only the taker sets its fill.
+    pub fn with_fill(mut self, fill: trade::DealFill<Amt, Bmt>) -> Res<Self>
+    where
+        Deal<Amt, Bmt, Ti, F>: Encodable,
+        Amt: AtomicAmount + Copy + PartialOrd,
+        Bmt: AtomicAmount + Copy + PartialEq,
+        Ti: Copy,
+        F: Copy,
+    {
+        self.state.deal.validate_fill(&fill)?;
+        self.state.fill = Some(fill);
+        Ok(self)
+    }
+
+    impl_swap_state_accessors!();
+}
+
+impl<Addr, Ar, Ac, Amt, Bmt, Ti, F, Pk, Qk, Rk, Sk, Pr, C, Px, Si, EncSig, Tx, Ms>
+    AliceSwap<Addr, Ar, Ac, Amt, Bmt, Ti, F, Pk, Qk, Rk, Sk, Pr, C, Px, Si, EncSig>
+where
+    Addr: Clone + CanonicalBytes,
+    Ar: Transactions<Addr = Addr, Amt = Amt, Tx = Tx, Px = Px, Ti = Ti, Ms = Ms, Pk = Pk, Si = Si>
+        + DeriveKeys<PublicKey = Pk, PrivateKey = Rk>,
+    Ac: DeriveKeys<PublicKey = Qk, PrivateKey = Sk>,
+    Amt: AtomicAmount + Copy + PartialOrd + CanonicalBytes,
+    Bmt: AtomicAmount + Copy + PartialEq + CanonicalBytes,
+    Ti: Copy,
+    F: Copy,
+    Deal<Amt, Bmt, Ti, F>: Encodable,
+    Pk: Copy + PartialEq + CanonicalBytes,
+    Qk: Clone + PartialEq + CanonicalBytes,
+    Rk: Clone + CanonicalBytes,
+    Sk: Clone + CanonicalBytes,
+    Pr: Clone + CanonicalBytes,
+    C: Clone + Eq + CanonicalBytes,
+    Px: Clone + Fee<FeeUnit = F> + CanonicalBytes,
+    Si: Clone + CanonicalBytes,
+    EncSig: Clone,
+{
+    /// Start the swap: generate Alice's parameters and emit the commitment.
+    pub fn start<S, N>(
+        &mut self,
+        wallet: &mut S,
+        engine: &impl Commit<C>,
+    ) -> Outputs<C, Pk, Qk, Rk, Sk, Addr, Pr, Px, Si, EncSig, N, Amt, Bmt, Tx>
+    where
+        S: KeyGenerator<Pk, Qk, Rk, Sk, Pr>,
+    {
+        if self.state.step != Step::Init {
+            return Err(Error::OutOfOrder {
+                step: self.state.step,
+                input: "start",
+            }
+            .into());
+        }
+        let parameters = self.alice.generate_parameters(wallet, &self.state.deal)?;
+        let mut outputs = self.state.start(SwapRole::Alice)?;
+        let commit: CommitAliceParameters<C> = parameters.commit_alice(self.state.swap_id, engine);
+        self.state.alice_parameters = Some(parameters);
+        self.state.step = Step::Commit;
+        outputs.push(Output::Message(ProtocolMessage::CommitAliceParameters(
+            commit,
+        )));
+        Ok(outputs)
+    }
+
+    /// Handle a message received from Bob and return the outputs to execute.
+    pub fn handle_message<S, N>(
+        &mut self,
+        wallet: &mut S,
+        engine: &impl Commit<C>,
+        msg: ProtocolMessage<C, Pk, Qk, Rk, Sk, Addr, Pr, Px, Si, EncSig, N, Amt, Bmt>,
+    ) -> Outputs<C, Pk, Qk, Rk, Sk, Addr, Pr, Px, Si, EncSig, N, Amt, Bmt, Tx>
+    where
+        S: KeyGenerator<Pk, Qk, Rk, Sk, Pr> + Sign<Pk, Ms, Si> + EncSign<Pk, Ms, Si, EncSig>,
+        F: PartialOrd,
+    {
+        check_swap_id(self.state.swap_id, msg.swap_id())?;
+        match (self.state.step, msg) {
+            (Step::Commit, ProtocolMessage::DealFill(fill))
+                if self.state.awaits_fill(SwapRole::Alice) =>
+            {
+                self.state.accept_fill(fill)?;
+                Ok(vec![])
+            }
+            (Step::Commit, ProtocolMessage::CommitBobParameters(commit))
+                if !self.state.awaits_fill(SwapRole::Alice) =>
+            {
+                self.alice
+                    .validate_commit_bob_parameters(self.state.swap_id, &commit)?;
+                let parameters = required(&self.state.alice_parameters, "Alice parameters")?;
+                let proof = RevealProof {
+                    swap_id: self.state.swap_id,
+                    proof: required(&parameters.proof, "Alice proof")?.clone(),
+                };
+                let reveal = parameters.clone().reveal_alice(self.state.swap_id);
+                self.state.transcript = Some(
+                    required(&self.state.transcript, "transcript")?
+                        .absorb(
+                            MessageType::CommitAliceParameters,
+                            &parameters.commit_alice(self.state.swap_id, engine),
+                        )
+                        .absorb(MessageType::CommitBobParameters, &commit),
+                );
+                self.bob_commit = Some(commit);
+                self.state.step = Step::RevealParameters;
+                Ok(vec![
+                    Output::Message(ProtocolMessage::RevealAliceParameters(reveal)),
+                    Output::Message(ProtocolMessage::RevealProof(proof)),
+                ])
+            }
+            (Step::RevealParameters, ProtocolMessage::RevealBobParameters(reveal)) => {
+                self.alice.validate_reveal_bob_parameters(
+                    self.state.swap_id,
+                    required(&self.state.alice_parameters, "Alice parameters")?,
+                    &reveal,
+                )?;
+                required(&self.bob_commit, "Bob commitment")?
+                    .verify_with_reveal(engine, reveal.clone())?;
+                self.state.bob_parameters = Some(reveal.into_parameters());
+                self.state.step = Step::RevealProof;
+                Ok(vec![])
+            }
+            (Step::RevealProof, ProtocolMessage::RevealProof(reveal)) => {
+                self.alice
+                    .validate_reveal_proof(self.state.swap_id, &reveal)?;
+                let parameters = self
+                    .state
+                    .bob_parameters
+                    .as_mut()
+                    .ok_or(Error::MissingState("Bob parameters"))?;
+                wallet.verify_proof(
+                    &parameters.spend,
+                    &parameters.adaptor,
+                    reveal.proof.clone(),
+                )?;
+                parameters.proof = Some(reveal.proof);
+                let alice_parameters = required(&self.state.alice_parameters, "Alice parameters")?;
+                let bob_parameters = required(&self.state.bob_parameters, "Bob parameters")?;
+                self.state.transcript = Some(absorb_reveals(
+                    *required(&self.state.transcript, "transcript")?,
+                    self.state.swap_id,
+                    alice_parameters,
+                    bob_parameters,
+                )?);
+                self.state.step = Step::CoreArbitratingSetup;
+                Ok(vec![])
+            }
+            (Step::CoreArbitratingSetup, ProtocolMessage::CoreArbitratingSetup(setup)) => {
+                let alice_parameters = required(&self.state.alice_parameters, "Alice parameters")?;
+                let bob_parameters = required(&self.state.bob_parameters, "Bob parameters")?;
+                self.alice.validate_core_arbitrating_setup(
+                    self.state.swap_id,
+                    &self.state.deal,
+                    self.state.fill.as_ref(),
+                    alice_parameters,
+                    bob_parameters,
+                    &setup,
+                )?;
+                let transcript = required(&self.state.transcript, "transcript")?
+                    .absorb(MessageType::CoreArbitratingSetup, &setup);
+                let arb_params = self.state.arbitrating_params()?;
+                let bob_cancel_sig = setup.cancel_sig.clone();
+                let bob_bump_cancel_sigs = setup.bump_cancel_sigs.clone();
+                let core = setup.into_arbitrating_tx();
+
+                // Validates the core transactions and sign the refund procedure
+                let refund_adaptor_sig = self.alice.sign_adaptor_refund(
+                    wallet,
+                    alice_parameters,
+                    bob_parameters,
+                    &core,
+                    arb_params,
+                )?;
+                let cancel_sig = self.alice.cosign_arbitrating_cancel(
+                    wallet,
+                    alice_parameters,
+                    bob_parameters,
+                    &core,
+                    arb_params,
+                )?;
+
+                // Bob's cancel signature is needed to cancel the swap later
+                let cancel = <Ar::Cancel>::from_partial(core.cancel.clone());
+                let msg = cancel.generate_witness_message(ScriptPath::Failure)?;
+                wallet.verify_signature(&bob_parameters.cancel, msg, &bob_cancel_sig)?;
+
+                // Validates and signs the fee ladder, Bob's cancel signatures of each rung are
+                // needed to bump the cancel later
+                let fee_bumps = self.alice.sign_fee_bumps(
+                    wallet,
+                    alice_parameters,
+                    bob_parameters,
+                    &core,
+                    arb_params,
+                )?;
+                if bob_bump_cancel_sigs.len() != core.bumps.len() {
+                    return Err(FeeStrategyError::InvalidFeeLadder.into());
+                }
+                for (bump, sig) in core.bumps.iter().zip(bob_bump_cancel_sigs.iter()) {
+                    let cancel = <Ar::Cancel>::from_partial(bump.cancel.clone());
+                    let msg = cancel.generate_witness_message(ScriptPath::Failure)?;
+                    wallet.verify_signature(&bob_parameters.cancel, msg, sig)?;
+                }
+
+                let refund_procedure = RefundProcedureSignatures {
+                    swap_id: self.state.swap_id,
+                    cancel_sig: cancel_sig.clone(),
+                    refund_adaptor_sig: refund_adaptor_sig.clone(),
+                    fee_bumps: fee_bumps.clone(),
+                    transcript: Some(transcript),
+                };
+                self.state.transcript = Some(transcript);
+                self.state.core = Some(core);
+                self.state.alice_cancel_sig = Some(cancel_sig);
+                self.state.bob_cancel_sig = Some(bob_cancel_sig);
+                self.state.refund_adaptor_sig = Some(refund_adaptor_sig);
+                self.state.fee_bumps = fee_bumps;
+                self.state.bob_bump_cancel_sigs = bob_bump_cancel_sigs;
+                self.state.step = Step::ArbitratingLock;
+                Ok(vec![Output::Message(
+                    ProtocolMessage::RefundProcedureSignatures(refund_procedure),
+                )])
+            }
+            (Step::BuyProcedureSignature, ProtocolMessage::BuyProcedureSignature(adaptor_buy)) => {
+                let alice_parameters = required(&self.state.alice_parameters, "Alice parameters")?;
+                let bob_parameters = required(&self.state.bob_parameters, "Bob parameters")?;
+                let core = required(&self.state.core, "core arbitrating transactions")?;
+                self.alice.validate_buy_procedure_signature(
+                    self.state.swap_id,
+                    &self.state.deal,
+                    core,
+                    &adaptor_buy,
+                )?;
+                check_transcript(self.state.transcript, adaptor_buy.transcript)?;
+                let arb_params = self.state.arbitrating_params()?;
+                self.alice.validate_adaptor_buy(
+                    wallet,
+                    alice_parameters,
+                    bob_parameters,
+                    core,
+                    arb_params,
+                    &adaptor_buy,
+                )?;
+                let sigs = self.alice.fully_sign_buy(
+                    wallet,
+                    alice_parameters,
+                    bob_parameters,
+                    core,
+                    arb_params,
+                    &adaptor_buy,
+                )?;
+
+                let mut buy = <Ar::Buy>::from_partial(adaptor_buy.buy);
+                buy.add_witness(bob_parameters.buy, sigs.adapted_sig)?;
+                buy.add_witness(alice_parameters.buy, sigs.sig)?;
+                let buy = buy.finalize_and_extract()?;
+
+                self.state.step = Step::Buy;
+                Ok(vec![Output::Broadcast(TxLabel::Buy, buy)])
+            }
+            (step, ProtocolMessage::Abort(_)) if step.is_abortable() => {
+                self.state.step = Step::Aborted;
+                Ok(vec![])
+            }
+            (step, msg) => Err(Error::OutOfOrder {
+                step,
+                input: msg.name(),
+            }
+            .into()),
+        }
+    }
+
+    /// Replace the cancel transaction broadcasted with the rung of the fee ladder selected for the
+    /// current network `fee_rate`, see [`CoreArbitratingTransactions::select_rung`]. Nothing is
+    /// broadcasted if the selected rung does not pay more than the last cancel broadcasted.
+    pub fn bump_cancel<N>(
+        &mut self,
+        fee_rate: &F,
+    ) -> Outputs<C, Pk, Qk, Rk, Sk, Addr, Pr, Px, Si, EncSig, N, Amt, Bmt, Tx>
+    where
+        F: PartialOrd,
+    {
+        self.state.bump_cancel::<Ar, C, N>(fee_rate)
+    }
+
+    /// Handle a blockchain event and return the outputs to execute.
+    pub fn handle_event<S, N>(
+        &mut self,
+        wallet: &mut S,
+        event: Event<Tx>,
+    ) -> Outputs<C, Pk, Qk, Rk, Sk, Addr, Pr, Px, Si, EncSig, N, Amt, Bmt, Tx>
+    where
+        S: Sign<Pk, Ms, Si> + RecoverSecret<Pk, Rk, Si, EncSig>,
+        Tx: Clone + PartialEq,
+    {
+        match (self.state.step, event) {
+            (Step::ArbitratingLock, Event::ArbitratingLockFinal) => {
+                self.state.step = Step::BuyProcedureSignature;
+                Ok(vec![Output::LockAccordant])
+            }
+            (Step::BuyProcedureSignature | Step::Buy, Event::CancelTimelockExpired) => {
+                let cancel = self.state.signed_cancel::<Ar>(0)?;
+                self.state.rung = 0;
+                self.state.step = Step::Cancel;
+                Ok(vec![Output::Broadcast(TxLabel::Cancel, cancel)])
+            }
+            (Step::BuyProcedureSignature | Step::Buy | Step::Cancel, Event::CancelSeen(cancel)) => {
+                self.state.rung = self.state.cancel_rung::<Ar>(&cancel)?;
+                self.state.step = Step::Cancel;
+                Ok(vec![])
+            }
+            (Step::Buy | Step::Cancel, Event::BuySeen(_)) => {
+                self.state.step = Step::SwapSuccess;
+                Ok(vec![])
+            }
+            (Step::Cancel | Step::Punish, Event::RefundSeen(refund)) => {
+                let alice_parameters = required(&self.state.alice_parameters, "Alice parameters")?;
+                let bob_parameters = required(&self.state.bob_parameters, "Bob parameters")?;
+                let core = required(&self.state.core, "core arbitrating transactions")?;
+
+                // Find the rung of the refund by verifying Alice's adapted signature against the
+                // refund of each rung
+                let adapted_sig = <Ar::Refund>::extract_witness(refund.clone());
+                let rung = (0..core.rungs_count())
+                    .filter_map(|index| core.rung(index))
+                    .position(|rung| {
+                        <Ar::Refund>::from_partial(rung.refund)
+                            .generate_witness_message(ScriptPath::Success)
+                            .map(|msg| {
+                                wallet
+                                    .verify_signature(&alice_parameters.refund, msg, &adapted_sig)
+                                    .is_ok()
+                            })
+                            .unwrap_or(false)
+                    })
+                    .ok_or(Error::UnknownTransaction("refund"))?;
+                let refund_adaptor_sig = self.state.refund_adaptor_sig(rung)?.clone();
+
+                let key = self.alice.recover_accordant_key(
+                    wallet,
+                    bob_parameters,
+                    refund_adaptor_sig,
+                    refund,
+                )?;
+                self.state.step = Step::Refunded;
+                Ok(vec![Output::RecoveredAccordantKey(key)])
+            }
+            (Step::Cancel, Event::PunishTimelockExpired) => {
+                let alice_parameters = required(&self.state.alice_parameters, "Alice parameters")?;
+                let bob_parameters = required(&self.state.bob_parameters, "Bob parameters")?;
+                let core = required(&self.state.core, "core arbitrating transactions")?
+                    .rung(self.rung())
+                    .ok_or(Error::MissingState("fee ladder rung"))?;
+                let signed_punish = self.alice.fully_sign_punish(
+                    wallet,
+                    alice_parameters,
+                    bob_parameters,
+                    &core,
+                    self.state.arbitrating_params()?,
+                )?;
+
+                let mut punish = <Ar::Punish>::from_partial(signed_punish.punish);
+                punish.add_witness(
+                    *required(&alice_parameters.punish, "Alice punish key")?,
+                    signed_punish.punish_sig,
+                )?;
+                let punish = punish.finalize_and_extract()?;
+
+                self.state.step = Step::Punish;
+                Ok(vec![Output::Broadcast(TxLabel::Punish, punish)])
+            }
+            (Step::Punish, Event::PunishSeen) => {
+                self.state.step = Step::Punished;
+                Ok(vec![])
+            }
+            (step, event) => Err(Error::OutOfOrder {
+                step,
+                input: event.name(),
+            }
+            .into()),
+        }
+    }
+}
+
+impl<Addr, Ar, Ac, Amt, Bmt, Ti, F, Pk, Qk, Rk, Sk, Pr, C, Px, Si, EncSig> Encodable
+    for AliceSwap<Addr, Ar, Ac, Amt, Bmt, Ti, F, Pk, Qk, Rk, Sk, Pr, C, Px, Si, EncSig>
+where
+    Addr: CanonicalBytes,
+    Ar: Encodable,
+    Ac: Encodable,
+    Amt: CanonicalBytes,
+    Bmt: CanonicalBytes,
+    Ti: CanonicalBytes,
+    F: CanonicalBytes,
+    Pk: CanonicalBytes,
+    Qk: CanonicalBytes,
+    Rk: CanonicalBytes,
+    Sk: CanonicalBytes,
+    Pr: CanonicalBytes,
+    C: CanonicalBytes,
+    Px: CanonicalBytes,
+    Si: CanonicalBytes,
+    EncSig: CanonicalBytes,
+{
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        let mut len = SwapRole::Alice.consensus_encode(writer)?;
+        len += self.state.swap_id.consensus_encode(writer)?;
+        len += self.state.step.consensus_encode(writer)?;
+        len += self.alice.consensus_encode(writer)?;
+        len += self.state.deal.consensus_encode(writer)?;
+        len += self.state.fill.consensus_encode(writer)?;
+        len += self.state.alice_parameters.consensus_encode(writer)?;
+        len += self.bob_commit.consensus_encode(writer)?;
+        len += self.state.bob_parameters.consensus_encode(writer)?;
+        len += self.state.core.consensus_encode(writer)?;
+        len += self
+            .state
+            .alice_cancel_sig
+            .as_canonical_bytes()
+            .consensus_encode(writer)?;
+        len += self
+            .state
+            .bob_cancel_sig
+            .as_canonical_bytes()
+            .consensus_encode(writer)?;
+        len += self
+            .state
+            .refund_adaptor_sig
+            .as_canonical_bytes()
+            .consensus_encode(writer)?;
+        len += self.state.fee_bumps.consensus_encode(writer)?;
+        len += self
+            .state
+            .bob_bump_cancel_sigs
+            .iter()
+            .map(CanonicalBytes::as_canonical_bytes)
+            .collect::<Vec<_>>()
+            .consensus_encode(writer)?;
+        len += self.state.rung.consensus_encode(writer)?;
+        Ok(len + self.state.transcript.consensus_encode(writer)?)
+    }
+}
+
+impl<Addr, Ar, Ac, Amt, Bmt, Ti, F, Pk, Qk, Rk, Sk, Pr, C, Px, Si, EncSig> Decodable
+    for AliceSwap<Addr, Ar, Ac, Amt, Bmt, Ti, F, Pk, Qk, Rk, Sk, Pr, C, Px, Si, EncSig>
+where
+    Addr: CanonicalBytes,
+    Ar: Decodable,
+    Ac: Decodable,
+    Amt: CanonicalBytes,
+    Bmt: CanonicalBytes,
+    Ti: CanonicalBytes,
+    F: CanonicalBytes,
+    Pk: CanonicalBytes,
+    Qk: CanonicalBytes,
+    Rk: CanonicalBytes,
+    Sk: CanonicalBytes,
+    Pr: CanonicalBytes,
+    C: CanonicalBytes,
+    Px: CanonicalBytes,
+    Si: CanonicalBytes,
+    EncSig: CanonicalBytes,
+{
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        if SwapRole::consensus_decode(d)? != SwapRole::Alice {
+            return Err(consensus::Error::TypeMismatch);
+        }
+        let swap_id = Decodable::consensus_decode(d)?;
+        let step = Decodable::consensus_decode(d)?;
+        let alice = Decodable::consensus_decode(d)?;
+        let deal = Decodable::consensus_decode(d)?;
+        let fill = Decodable::consensus_decode(d)?;
+        let alice_parameters = Decodable::consensus_decode(d)?;
+        let bob_commit = Decodable::consensus_decode(d)?;
+        let bob_parameters = Decodable::consensus_decode(d)?;
+        let core = Decodable::consensus_decode(d)?;
+        let alice_cancel_sig = Option::<Si>::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?;
+        let bob_cancel_sig = Option::<Si>::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?;
+        let refund_adaptor_sig =
+            Option::<EncSig>::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?;
+        let fee_bumps = Decodable::consensus_decode(d)?;
+        let bob_bump_cancel_sigs = Vec::<Vec<u8>>::consensus_decode(d)?
+            .iter()
+            .map(|bytes| Si::from_canonical_bytes(bytes))
+            .collect::<Result<_, _>>()?;
+        let rung = Decodable::consensus_decode(d)?;
+        Ok(Self {
+            alice,
+            bob_commit,
+            state: SwapState {
+                swap_id,
+                step,
+                deal,
+                fill,
+                alice_parameters,
+                bob_parameters,
+                core,
+                alice_cancel_sig,
+                bob_cancel_sig,
+                refund_adaptor_sig,
+                fee_bumps,
+                bob_bump_cancel_sigs,
+                rung,
+                transcript: Decodable::consensus_decode(d)?,
+            },
+        })
+    }
+}
+
+impl_strict_encoding!(AliceSwap<Addr, Ar, Ac, Amt, Bmt, Ti, F, Pk, Qk, Rk, Sk, Pr, C, Px, Si, EncSig>, Addr: CanonicalBytes, Ar: Encodable + Decodable, Ac: Encodable + Decodable, Amt: CanonicalBytes, Bmt: CanonicalBytes, Ti: CanonicalBytes, F: CanonicalBytes, Pk: CanonicalBytes, Qk: CanonicalBytes, Rk: CanonicalBytes, Sk: CanonicalBytes, Pr: CanonicalBytes, C: CanonicalBytes, Px: CanonicalBytes, Si: CanonicalBytes, EncSig: CanonicalBytes);
+
+/// Bob's swap state machine, see the [module documentation](self) for the message flow.
+#[derive(Debug, Clone)]
+pub struct BobSwap<Addr, Ar, Ac, Amt, Bmt, Ti, F, Pk, Qk, Rk, Sk, Pr, C, Px, Si, EncSig> {
+    bob: Bob<Addr, Ar, Ac>,
+    alice_commit: Option<CommitAliceParameters<C>>,
+    buy_procedure_signature: Option<BuyProcedureSignature<Px, EncSig>>,
+    state: SwapState<Addr, Amt, Bmt, Ti, F, Pk, Qk, Rk, Sk, Pr, Px, Si, EncSig>,
+}
+
+impl<Addr, Ar, Ac, Amt, Bmt, Ti, F, Pk, Qk, Rk, Sk, Pr, C, Px, Si, EncSig>
+    BobSwap<Addr, Ar, Ac, Amt, Bmt, Ti, F, Pk, Qk, Rk, Sk, Pr, C, Px, Si, EncSig>
+{
+    /// Create a new state machine for Bob at [`Step::Init`].
+    pub fn new(swap_id: SwapId, bob: Bob<Addr, Ar, Ac>, deal: Deal<Amt, Bmt, Ti, F>) -> Self {
+        Self {
+            bob,
+            alice_commit: None,
+            buy_procedure_signature: None,
+            state: SwapState::new(swap_id, deal),
+        }
+    }
+
+    /// Create a new state machine for Bob at [`Step::Init`] if the deal passes the validator,
+    /// fails with [`InvalidDeal`] listing the policy violations otherwise.
+    ///
+    /// [`InvalidDeal`]: crate::trade::Error::InvalidDeal
+    pub fn new_validated(
+        swap_id: SwapId,
+        bob: Bob<Addr, Ar, Ac>,
+        deal: Deal<Amt, Bmt, Ti, F>,
+        validator: &DealValidator<Amt, Bmt, Ti, F>,
+    ) -> Res<Self>
+    where
+        Amt: PartialOrd,
+        Bmt: PartialOrd,
+        Ti: PartialOrd + CanonicalBytes,
+        F: PartialOrd,
+    {
+        validator.validate(&deal)?;
+        Ok(Self::new(swap_id, bob, deal))
+    }
+
+    /// Set the taker's fill of a deal accepting partial fills, created with [`Deal::fill`]. The
+    /// fill is sent to the maker when the swap starts and the swap locks the filled amounts, fails
+    /// if the fill does not match the deal. Only the taker sets its fill.
+    pub fn with_fill(mut self, fill: trade::DealFill<Amt, Bmt>) -> Res<Self>
+    where
+        Deal<Amt, Bmt, Ti, F>: Encodable,
+        Amt: AtomicAmount + Copy + PartialOrd,
+        Bmt: AtomicAmount + Copy + PartialEq,
+        Ti: Copy,
+        F: Copy,
+    {
+        self.state.deal.validate_fill(&fill)?;
+        self.state.fill = Some(fill);
+        Ok(self)
+    }
+
+    impl_swap_state_accessors!();
+}
+
+impl<Addr, Ar, Ac, Amt, Bmt, Ti, F, Pk, Qk, Rk, Sk, Pr, C, Px, Si, EncSig, Tx, Ms, Out>
+    BobSwap<Addr, Ar, Ac, Amt, Bmt, Ti, F, Pk, Qk, Rk, Sk, Pr, C, Px, Si, EncSig>
+where
+    Addr: Clone + CanonicalBytes,
+    Ar: Transactions<
+            Addr = Addr,
+            Amt = Amt,
+            Tx = Tx,
+            Px = Px,
+            Out = Out,
+            Ti = Ti,
+            Ms = Ms,
+            Pk = Pk,
+            Si = Si,
+        > + DeriveKeys<PublicKey = Pk, PrivateKey = Rk>,
+    Ac: DeriveKeys<PublicKey = Qk, PrivateKey = Sk>,
+    Amt: AtomicAmount + Copy + PartialOrd + CanonicalBytes,
+    Bmt: AtomicAmount + Copy + PartialEq + CanonicalBytes,
+    Ti: Copy,
+    F: Copy,
+    Deal<Amt, Bmt, Ti, F>: Encodable,
+    Pk: Copy + PartialEq + CanonicalBytes,
+    Qk: Clone + PartialEq + CanonicalBytes,
+    Rk: Clone + CanonicalBytes,
+    Sk: Clone + CanonicalBytes,
+    Pr: Clone + CanonicalBytes,
+    C: Clone + Eq + CanonicalBytes,
+    Px: Clone + Fee<FeeUnit = F> + CanonicalBytes,
+    Out: Eq,
+    Si: Clone + CanonicalBytes,
+    EncSig: Clone,
+{
+    /// Start the swap: generate Bob's parameters and emit the commitment.
+    pub fn start<S, N>(
+        &mut self,
+        wallet: &mut S,
+        engine: &impl Commit<C>,
+    ) -> Outputs<C, Pk, Qk, Rk, Sk, Addr, Pr, Px, Si, EncSig, N, Amt, Bmt, Tx>
+    where
+        S: KeyGenerator<Pk, Qk, Rk, Sk, Pr>,
+    {
+        if self.state.step != Step::Init {
+            return Err(Error::OutOfOrder {
+                step: self.state.step,
+                input: "start",
+            }
+            .into());
+        }
+        let parameters = self.bob.generate_parameters(wallet, &self.state.deal)?;
+        let mut outputs = self.state.start(SwapRole::Bob)?;
+        let commit: CommitBobParameters<C> = parameters.commit_bob(self.state.swap_id, engine);
+        self.state.bob_parameters = Some(parameters);
+        self.state.step = Step::Commit;
+        outputs.push(Output::Message(ProtocolMessage::CommitBobParameters(
+            commit,
+        )));
+        Ok(outputs)
+    }
+
+    /// Handle a message received from Alice and return the outputs to execute.
+    pub fn handle_message<S, N>(
+        &mut self,
+        wallet: &mut S,
+        engine: &impl Commit<C>,
+        msg: ProtocolMessage<C, Pk, Qk, Rk, Sk, Addr, Pr, Px, Si, EncSig, N, Amt, Bmt>,
+    ) -> Outputs<C, Pk, Qk, Rk, Sk, Addr, Pr, Px, Si, EncSig, N, Amt, Bmt, Tx>
+    where
+        S: KeyGenerator<Pk, Qk, Rk, Sk, Pr> + Sign<Pk, Ms, Si> + EncSign<Pk, Ms, Si, EncSig>,
+    {
+        check_swap_id(self.state.swap_id, msg.swap_id())?;
+        match (self.state.step, msg) {
+            (Step::Commit, ProtocolMessage::DealFill(fill))
+                if self.state.awaits_fill(SwapRole::Bob) =>
+            {
+                self.state.accept_fill(fill)?;
+                Ok(vec![])
+            }
+            (Step::Commit, ProtocolMessage::CommitAliceParameters(commit))
+                if !self.state.awaits_fill(SwapRole::Bob) =>
+            {
+                self.bob
+                    .validate_commit_alice_parameters(self.state.swap_id, &commit)?;
+                let parameters = required(&self.state.bob_parameters, "Bob parameters")?;
+                let proof = RevealProof {
+                    swap_id: self.state.swap_id,
+                    proof: required(&parameters.proof, "Bob proof")?.clone(),
+                };
+                let reveal = parameters.clone().reveal_bob(self.state.swap_id);
+                self.state.transcript = Some(
+                    required(&self.state.transcript, "transcript")?
+                        .absorb(MessageType::CommitAliceParameters, &commit)
+                        .absorb(
+                            MessageType::CommitBobParameters,
+                            &parameters.commit_bob(self.state.swap_id, engine),
+                        ),
+                );
+                self.alice_commit = Some(commit);
+                self.state.step = Step::RevealParameters;
+                Ok(vec![
+                    Output::Message(ProtocolMessage::RevealBobParameters(reveal)),
+                    Output::Message(ProtocolMessage::RevealProof(proof)),
+                ])
+            }
+            (Step::RevealParameters, ProtocolMessage::RevealAliceParameters(reveal)) => {
+                self.bob.validate_reveal_alice_parameters(
+                    self.state.swap_id,
+                    required(&self.state.bob_parameters, "Bob parameters")?,
+                    &reveal,
+                )?;
+                required(&self.alice_commit, "Alice commitment")?
+                    .verify_with_reveal(engine, reveal.clone())?;
+                self.state.alice_parameters = Some(reveal.into_parameters());
+                self.state.step = Step::RevealProof;
+                Ok(vec![])
+            }
+            (Step::RevealProof, ProtocolMessage::RevealProof(reveal)) => {
+                self.bob
+                    .validate_reveal_proof(self.state.swap_id, &reveal)?;
+                let parameters = self
+                    .state
+                    .alice_parameters
+                    .as_mut()
+                    .ok_or(Error::MissingState("Alice parameters"))?;
+                wallet.verify_proof(
+                    &parameters.spend,
+                    &parameters.adaptor,
+                    reveal.proof.clone(),
+                )?;
+                parameters.proof = Some(reveal.proof);
+                let alice_parameters = required(&self.state.alice_parameters, "Alice parameters")?;
+                let bob_parameters = required(&self.state.bob_parameters, "Bob parameters")?;
+                self.state.transcript = Some(absorb_reveals(
+                    *required(&self.state.transcript, "transcript")?,
+                    self.state.swap_id,
+                    alice_parameters,
+                    bob_parameters,
+                )?);
+
+                let funding_key = wallet.get_pubkey(ArbitratingKeyId::Lock)?;
+                let funding =
+                    <Ar::Funding>::initialize(funding_key, self.state.deal.parameters.network)?;
+                let address = funding.get_address()?;
+
+                self.state.step = Step::Funding;
+                Ok(vec![Output::FundArbitrating(address)])
+            }
+            (
+                Step::RefundProcedureSignatures,
+                ProtocolMessage::RefundProcedureSignatures(refund_procedure),
+            ) => {
+                let alice_parameters = required(&self.state.alice_parameters, "Alice parameters")?;
+                let bob_parameters = required(&self.state.bob_parameters, "Bob parameters")?;
+                let core = required(&self.state.core, "core arbitrating transactions")?;
+                let arb_params = self.state.arbitrating_params()?;
+
+                self.bob.validate_refund_procedure_signatures(
+                    self.state.swap_id,
+                    &self.state.deal,
+                    self.state.fill.as_ref(),
+                    alice_parameters,
+                    bob_parameters,
+                    core,
+                    &refund_procedure,
+                )?;
+                check_transcript(self.state.transcript, refund_procedure.transcript)?;
+                self.bob.validate_adaptor_refund(
+                    wallet,
+                    alice_parameters,
+                    bob_parameters,
+                    core,
+                    &refund_procedure.refund_adaptor_sig,
+                )?;
+
+                // Alice's cancel signature is needed to cancel the swap later
+                let cancel = <Ar::Cancel>::from_partial(core.cancel.clone());
+                let msg = cancel.generate_witness_message(ScriptPath::Failure)?;
+                wallet.verify_signature(
+                    &alice_parameters.cancel,
+                    msg,
+                    &refund_procedure.cancel_sig,
+                )?;
+
+                // Validates the signatures of the fee ladder
+                self.bob.validate_fee_bumps(
+                    wallet,
+                    alice_parameters,
+                    bob_parameters,
+                    core,
+                    &refund_procedure.fee_bumps,
+                )?;
+                for (bump, sigs) in core.bumps.iter().zip(refund_procedure.fee_bumps.iter()) {
+                    let cancel = <Ar::Cancel>::from_partial(bump.cancel.clone());
+                    let msg = cancel.generate_witness_message(ScriptPath::Failure)?;
+                    wallet.verify_signature(&alice_parameters.cancel, msg, &sigs.cancel_sig)?;
+                }
+
+                let adaptor_buy = BuyProcedureSignature {
+                    transcript: self.state.transcript,
+                    ..self.bob.sign_adaptor_buy(
+                        self.state.swap_id,
+                        wallet,
+                        alice_parameters,
+                        bob_parameters,
+                        core,
+                        arb_params,
+                    )?
+                };
+
+                let lock_sigs = self.bob.sign_arbitrating_lock(wallet, core)?;
+                let lock_key = wallet.get_pubkey(ArbitratingKeyId::Lock)?;
+                let mut lock = <Ar::Lock>::from_partial(core.lock.clone());
+                for (index, sig) in lock_sigs.into_iter().enumerate() {
+                    lock.add_input_witness(index, lock_key, sig)?;
+                }
+                let lock = lock.finalize_and_extract()?;
+
+                self.state.alice_cancel_sig = Some(refund_procedure.cancel_sig);
+                self.state.refund_adaptor_sig = Some(refund_procedure.refund_adaptor_sig);
+                self.state.fee_bumps = refund_procedure.fee_bumps;
+                self.buy_procedure_signature = Some(adaptor_buy);
+                self.state.step = Step::AccordantLock;
+                Ok(vec![Output::Broadcast(TxLabel::Lock, lock)])
+            }
+            (step, ProtocolMessage::Abort(_)) if step.is_abortable() => {
+                self.state.step = Step::Aborted;
+                Ok(vec![])
+            }
+            (step, msg) => Err(Error::OutOfOrder {
+                step,
+                input: msg.name(),
+            }
+            .into()),
+        }
+    }
+
+    /// Replace the cancel transaction broadcasted with the rung of the fee ladder selected for the
+    /// current network `fee_rate`, see [`CoreArbitratingTransactions::select_rung`]. Nothing is
+    /// broadcasted if the selected rung does not pay more than the last cancel broadcasted.
+    pub fn bump_cancel<N>(
+        &mut self,
+        fee_rate: &F,
+    ) -> Outputs<C, Pk, Qk, Rk, Sk, Addr, Pr, Px, Si, EncSig, N, Amt, Bmt, Tx>
+    where
+        F: PartialOrd,
+    {
+        self.state.bump_cancel::<Ar, C, N>(fee_rate)
+    }
+
+    /// Handle a blockchain event and return the outputs to execute.
+    pub fn handle_event<S, N>(
+        &mut self,
+        wallet: &mut S,
+        event: Event<Tx>,
+    ) -> Outputs<C, Pk, Qk, Rk, Sk, Addr, Pr, Px, Si, EncSig, N, Amt, Bmt, Tx>
+    where
+        S: KeyGenerator<Pk, Qk, Rk, Sk, Pr>
+            + Sign<Pk, Ms, Si>
+            + EncSign<Pk, Ms, Si, EncSig>
+            + RecoverSecret<Pk, Rk, Si, EncSig>,
+        Tx: PartialEq,
+    {
+        match (self.state.step, event) {
+            (Step::Funding, Event::FundingSeen(txs)) => {
+                let alice_parameters = required(&self.state.alice_parameters, "Alice parameters")?;
+                let bob_parameters = required(&self.state.bob_parameters, "Bob parameters")?;
+
+                let funding_key = wallet.get_pubkey(ArbitratingKeyId::Lock)?;
+                let mut funding =
+                    <Ar::Funding>::initialize(funding_key, self.state.deal.parameters.network)?;
+                for tx in txs {
+                    funding.update(tx)?;
+                }
+
+                let core = self.bob.core_arbitrating_transactions(
+                    alice_parameters,
+                    bob_parameters,
+                    funding,
+                    self.state.arbitrating_params()?,
+                )?;
+                let cancel_sig = self.bob.cosign_arbitrating_cancel(wallet, &core)?;
+                let bump_cancel_sigs = self.bob.cosign_arbitrating_cancel_bumps(wallet, &core)?;
+                let setup: CoreArbitratingSetup<Px, Si> = core.clone().into_arbitrating_setup(
+                    self.state.swap_id,
+                    cancel_sig.clone(),
+                    bump_cancel_sigs.clone(),
+                );
+                let transcript = required(&self.state.transcript, "transcript")?
+                    .absorb(MessageType::CoreArbitratingSetup, &setup);
+
+                self.state.transcript = Some(transcript);
+                self.state.core = Some(core);
+                self.state.bob_cancel_sig = Some(cancel_sig);
+                self.state.bob_bump_cancel_sigs = bump_cancel_sigs;
+                self.state.step = Step::RefundProcedureSignatures;
+                Ok(vec![Output::Message(
+                    ProtocolMessage::CoreArbitratingSetup(setup),
+                )])
+            }
+            (Step::AccordantLock, Event::AccordantLockFinal) => {
+                let adaptor_buy =
+                    required(&self.buy_procedure_signature, "buy procedure signature")?.clone();
+                self.state.step = Step::Buy;
+                Ok(vec![Output::Message(
+                    ProtocolMessage::BuyProcedureSignature(adaptor_buy),
+                )])
+            }
+            (Step::AccordantLock | Step::Buy, Event::CancelTimelockExpired) => {
+                let cancel = self.state.signed_cancel::<Ar>(0)?;
+                self.state.rung = 0;
+                self.state.step = Step::Cancel;
+                Ok(vec![Output::Broadcast(TxLabel::Cancel, cancel)])
+            }
+            (Step::AccordantLock | Step::Buy | Step::Cancel, Event::CancelSeen(cancel)) => {
+                let rung = self.state.cancel_rung::<Ar>(&cancel)?;
+                let alice_parameters = required(&self.state.alice_parameters, "Alice parameters")?;
+                let bob_parameters = required(&self.state.bob_parameters, "Bob parameters")?;
+                let core = required(&self.state.core, "core arbitrating transactions")?
+                    .rung(rung as usize)
+                    .ok_or(Error::MissingState("fee ladder rung"))?;
+                let refund_adaptor_sig = self.state.refund_adaptor_sig(rung as usize)?;
+                let sigs = self
+                    .bob
+                    .fully_sign_refund(wallet, &core, refund_adaptor_sig)?;
+
+                let mut refund = <Ar::Refund>::from_partial(core.refund.clone());
+                refund.add_witness(alice_parameters.refund, sigs.adapted_sig)?;
+                refund.add_witness(bob_parameters.refund, sigs.sig)?;
+                let refund = refund.finalize_and_extract()?;
+
+                self.state.rung = rung;
+                self.state.step = Step::Refund;
+                Ok(vec![Output::Broadcast(TxLabel::Refund, refund)])
+            }
+            (Step::Buy | Step::Cancel, Event::BuySeen(buy)) => {
+                let alice_parameters = required(&self.state.alice_parameters, "Alice parameters")?;
+                let adaptor_buy =
+                    required(&self.buy_procedure_signature, "buy procedure signature")?;
+                let key = self.bob.recover_accordant_key(
+                    wallet,
+                    alice_parameters,
+                    adaptor_buy.buy_adaptor_sig.clone(),
+                    buy,
+                )?;
+                self.state.step = Step::SwapSuccess;
+                Ok(vec![Output::RecoveredAccordantKey(key)])
+            }
+            (Step::Refund, Event::RefundSeen(_)) => {
+                self.state.step = Step::Refunded;
+                Ok(vec![])
+            }
+            (Step::Cancel | Step::Refund, Event::PunishSeen) => {
+                self.state.step = Step::Punished;
+                Ok(vec![])
+            }
+            (step, event) => Err(Error::OutOfOrder {
+                step,
+                input: event.name(),
+            }
+            .into()),
+        }
+    }
+}
+
+impl<Addr, Ar, Ac, Amt, Bmt, Ti, F, Pk, Qk, Rk, Sk, Pr, C, Px, Si, EncSig> Encodable
+    for BobSwap<Addr, Ar, Ac, Amt, Bmt, Ti, F, Pk, Qk, Rk, Sk, Pr, C, Px, Si, EncSig>
+where
+    Addr: CanonicalBytes,
+    Ar: Encodable,
+    Ac: Encodable,
+    Amt: CanonicalBytes,
+    Bmt: CanonicalBytes,
+    Ti: CanonicalBytes,
+    F: CanonicalBytes,
+    Pk: CanonicalBytes,
+    Qk: CanonicalBytes,
+    Rk: CanonicalBytes,
+    Sk: CanonicalBytes,
+    Pr: CanonicalBytes,
+    C: CanonicalBytes,
+    Px: CanonicalBytes,
+    Si: CanonicalBytes,
+    EncSig: CanonicalBytes,
+{
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        let mut len = SwapRole::Bob.consensus_encode(writer)?;
+        len += self.state.swap_id.consensus_encode(writer)?;
+        len += self.state.step.consensus_encode(writer)?;
+        len += self.bob.consensus_encode(writer)?;
+        len += self.state.deal.consensus_encode(writer)?;
+        len += self.state.fill.consensus_encode(writer)?;
+        len += self.state.bob_parameters.consensus_encode(writer)?;
+        len += self.alice_commit.consensus_encode(writer)?;
+        len += self.state.alice_parameters.consensus_encode(writer)?;
+        len += self.state.core.consensus_encode(writer)?;
+        len += self
+            .state
+            .alice_cancel_sig
+            .as_canonical_bytes()
+            .consensus_encode(writer)?;
+        len += self
+            .state
+            .bob_cancel_sig
+            .as_canonical_bytes()
+            .consensus_encode(writer)?;
+        len += self
+            .state
+            .refund_adaptor_sig
+            .as_canonical_bytes()
+            .consensus_encode(writer)?;
+        len += self.state.fee_bumps.consensus_encode(writer)?;
+        len += self
+            .state
+            .bob_bump_cancel_sigs
+            .iter()
+            .map(CanonicalBytes::as_canonical_bytes)
+            .collect::<Vec<_>>()
+            .consensus_encode(writer)?;
+        len += self.state.rung.consensus_encode(writer)?;
+        len += self.buy_procedure_signature.consensus_encode(writer)?;
+        Ok(len + self.state.transcript.consensus_encode(writer)?)
+    }
+}
+
+impl<Addr, Ar, Ac, Amt, Bmt, Ti, F, Pk, Qk, Rk, Sk, Pr, C, Px, Si, EncSig> Decodable
+    for BobSwap<Addr, Ar, Ac, Amt, Bmt, Ti, F, Pk, Qk, Rk, Sk, Pr, C, Px, Si, EncSig>
+where
+    Addr: CanonicalBytes,
+    Ar: Decodable,
+    Ac: Decodable,
+    Amt: CanonicalBytes,
+    Bmt: CanonicalBytes,
+    Ti: CanonicalBytes,
+    F: CanonicalBytes,
+    Pk: CanonicalBytes,
+    Qk: CanonicalBytes,
+    Rk: CanonicalBytes,
+    Sk: CanonicalBytes,
+    Pr: CanonicalBytes,
+    C: CanonicalBytes,
+    Px: CanonicalBytes,
+    Si: CanonicalBytes,
+    EncSig: CanonicalBytes,
+{
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        if SwapRole::consensus_decode(d)? != SwapRole::Bob {
+            return Err(consensus::Error::TypeMismatch);
+        }
+        let swap_id = Decodable::consensus_decode(d)?;
+        let step = Decodable::consensus_decode(d)?;
+        let bob = Decodable::consensus_decode(d)?;
+        let deal = Decodable::consensus_decode(d)?;
+        let fill = Decodable::consensus_decode(d)?;
+        let bob_parameters = Decodable::consensus_decode(d)?;
+        let alice_commit = Decodable::consensus_decode(d)?;
+        let alice_parameters = Decodable::consensus_decode(d)?;
+        let core = Decodable::consensus_decode(d)?;
+        let alice_cancel_sig = Option::<Si>::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?;
+        let bob_cancel_sig = Option::<Si>::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?;
+        let refund_adaptor_sig =
+            Option::<EncSig>::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?;
+        let fee_bumps = Decodable::consensus_decode(d)?;
+        let bob_bump_cancel_sigs = Vec::<Vec<u8>>::consensus_decode(d)?
+            .iter()
+            .map(|bytes| Si::from_canonical_bytes(bytes))
+            .collect::<Result<_, _>>()?;
+        let rung = Decodable::consensus_decode(d)?;
+        let buy_procedure_signature = Decodable::consensus_decode(d)?;
+        Ok(Self {
+            bob,
+            alice_commit,
+            buy_procedure_signature,
+            state: SwapState {
+                swap_id,
+                step,
+                deal,
+                fill,
+                alice_parameters,
+                bob_parameters,
+                core,
+                alice_cancel_sig,
+                bob_cancel_sig,
+                refund_adaptor_sig,
+                fee_bumps,
+                bob_bump_cancel_sigs,
+                rung,
+                transcript: Decodable::consensus_decode(d)?,
+            },
+        })
+    }
+}
+
+impl_strict_encoding!(BobSwap<Addr, Ar, Ac, Amt, Bmt, Ti, F, Pk, Qk, Rk, Sk, Pr, C, Px, Si, EncSig>, Addr: CanonicalBytes, Ar: Encodable + Decodable, Ac: Encodable + Decodable, Amt: CanonicalBytes, Bmt: CanonicalBytes, Ti: CanonicalBytes, F: CanonicalBytes, Pk: CanonicalBytes, Qk: CanonicalBytes, Rk: CanonicalBytes, Sk: CanonicalBytes, Pr: CanonicalBytes, C: CanonicalBytes, Px: CanonicalBytes, Si: CanonicalBytes, EncSig: CanonicalBytes);
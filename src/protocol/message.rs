@@ -16,15 +16,27 @@
 
 //! Protocol messages exchanged between swap daemons at each step of the swap protocol. These
 //! messages are untrusted and must be validated uppon reception by each swap participant.
+//!
+//! On the wire every message is wrapped in a [`ProtocolMessage`] frame: the
+//! [`PROTOCOL_VERSION`] byte, the [`MessageType`] of the message as a little-endian `u16`, the
+//! length of the payload as a little-endian `u32`, then the consensus encoded message. Decoding a
+//! frame dispatches on the message type, a transport can read frames without knowing which message
+//! comes next.
 
 use std::fmt;
-use std::io;
+use std::io::{self, Read};
+use std::str::FromStr;
 
 use crate::consensus::{self, CanonicalBytes, Decodable, Encodable};
-use crate::crypto::{Commit, SharedKeyId, TaggedElement};
-use crate::protocol::Parameters;
-use crate::protocol::{verify_vec_of_commitments, CoreArbitratingTransactions};
+use crate::crypto::{self, Commit, CommitmentScheme, SharedKeyId, TaggedElement};
+use crate::protocol::transcript::TranscriptHash;
+use crate::protocol::{machine, validation, Parameters};
+use crate::protocol::{
+    verify_commitment, verify_vec_of_commitments, CommitmentNonces, CoreArbitratingTransactions,
+    FeeBump, FeeBumpSignatures,
+};
 use crate::swap::SwapId;
+use crate::trade;
 use crate::Error;
 
 /// Forces Alice to commit to the result of her cryptographic setup before receiving Bob's setup.
@@ -33,6 +45,9 @@ use crate::Error;
 pub struct CommitAliceParameters<C> {
     /// The swap identifier related to this message.
     pub swap_id: SwapId,
+    /// The scheme of the commitments, salted commitments are opened with the nonces revealed in
+    /// [`RevealAliceParameters`].
+    pub scheme: CommitmentScheme,
     /// Commitment to the buy public key.
     pub buy: C,
     /// Commitment to the cancel public key.
@@ -71,30 +86,75 @@ where
         Sk: CanonicalBytes,
         Addr: CanonicalBytes,
     {
-        wallet.validate(reveal.buy.as_canonical_bytes(), self.buy.clone())?;
-        wallet.validate(reveal.cancel.as_canonical_bytes(), self.cancel.clone())?;
-        wallet.validate(reveal.refund.as_canonical_bytes(), self.refund.clone())?;
-        wallet.validate(reveal.punish.as_canonical_bytes(), self.punish.clone())?;
-        wallet.validate(reveal.adaptor.as_canonical_bytes(), self.adaptor.clone())?;
+        let nonces = match self.scheme {
+            CommitmentScheme::Bare => None,
+            CommitmentScheme::Salted => Some(
+                reveal
+                    .nonces
+                    .as_ref()
+                    .ok_or(crypto::Error::InvalidCommitment)?,
+            ),
+        };
+        verify_commitment(
+            wallet,
+            &reveal.buy,
+            nonces.map(|n| &n.buy),
+            self.buy.clone(),
+        )?;
+        verify_commitment(
+            wallet,
+            &reveal.cancel,
+            nonces.map(|n| &n.cancel),
+            self.cancel.clone(),
+        )?;
+        verify_commitment(
+            wallet,
+            &reveal.refund,
+            nonces.map(|n| &n.refund),
+            self.refund.clone(),
+        )?;
+        verify_commitment(
+            wallet,
+            &reveal.punish,
+            nonces
+                .map(|n| n.punish.as_ref().ok_or(crypto::Error::InvalidCommitment))
+                .transpose()?,
+            self.punish.clone(),
+        )?;
+        verify_commitment(
+            wallet,
+            &reveal.adaptor,
+            nonces.map(|n| &n.adaptor),
+            self.adaptor.clone(),
+        )?;
         verify_vec_of_commitments(
             wallet,
             reveal.extra_arbitrating_keys,
+            nonces.map(|n| &n.extra_arbitrating_keys[..]),
             &self.extra_arbitrating_keys,
         )?;
         verify_vec_of_commitments(
             wallet,
             reveal.arbitrating_shared_keys,
+            nonces.map(|n| &n.arbitrating_shared_keys[..]),
             &self.arbitrating_shared_keys,
         )?;
-        wallet.validate(reveal.spend.as_canonical_bytes(), self.spend.clone())?;
+        verify_commitment(
+            wallet,
+            &reveal.spend,
+            nonces.map(|n| &n.spend),
+            self.spend.clone(),
+        )?;
         verify_vec_of_commitments(
             wallet,
             reveal.extra_accordant_keys,
+            nonces.map(|n| &n.extra_accordant_keys[..]),
             &self.extra_accordant_keys,
         )?;
         verify_vec_of_commitments(
             wallet,
             reveal.accordant_shared_keys,
+            nonces.map(|n| &n.accordant_shared_keys[..]),
             &self.accordant_shared_keys,
         )
     }
@@ -115,6 +175,7 @@ where
 {
     fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
         let mut len = self.swap_id.consensus_encode(s)?;
+        len += self.scheme.consensus_encode(s)?;
         len += self.buy.as_canonical_bytes().consensus_encode(s)?;
         len += self.cancel.as_canonical_bytes().consensus_encode(s)?;
         len += self.refund.as_canonical_bytes().consensus_encode(s)?;
@@ -133,8 +194,12 @@ where
     C: CanonicalBytes,
 {
     fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        let swap_id = Decodable::consensus_decode(d)?;
+        let (scheme, prefix) = decode_scheme(d)?;
+        let d = &mut prefix.as_slice().chain(d);
         Ok(Self {
-            swap_id: Decodable::consensus_decode(d)?,
+            swap_id,
+            scheme,
             buy: C::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
             cancel: C::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
             refund: C::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
@@ -151,12 +216,27 @@ where
 
 impl_strict_encoding!(CommitAliceParameters<C>, C: CanonicalBytes);
 
+// Decode the scheme of a commit message. Commit messages encoded before the scheme was added are
+// bare and start with the length of the buy commitment instead, a little-endian `u16` never equal
+// to a scheme tag for commitments longer than two bytes. The byte read is then returned to be
+// decoded again with the commitments.
+fn decode_scheme<D: io::Read>(d: &mut D) -> Result<(CommitmentScheme, Vec<u8>), consensus::Error> {
+    let tag = u8::consensus_decode(d)?;
+    match CommitmentScheme::consensus_decode(&mut &[tag][..]) {
+        Ok(scheme) => Ok((scheme, vec![])),
+        Err(_) => Ok((CommitmentScheme::Bare, vec![tag])),
+    }
+}
+
 /// Forces Bob to commit to the result of his cryptographic setup before receiving Alice's setup.
 /// This is done to remove adaptive behavior in the cryptographic parameters.
 #[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct CommitBobParameters<C> {
     /// The swap identifier related to this message.
     pub swap_id: SwapId,
+    /// The scheme of the commitments, salted commitments are opened with the nonces revealed in
+    /// [`RevealBobParameters`].
+    pub scheme: CommitmentScheme,
     /// Commitment to the buy public key.
     pub buy: C,
     /// Commitment to the cancel public key.
@@ -193,29 +273,67 @@ where
         Sk: CanonicalBytes,
         Addr: CanonicalBytes,
     {
-        wallet.validate(reveal.buy.as_canonical_bytes(), self.buy.clone())?;
-        wallet.validate(reveal.cancel.as_canonical_bytes(), self.cancel.clone())?;
-        wallet.validate(reveal.refund.as_canonical_bytes(), self.refund.clone())?;
-        wallet.validate(reveal.adaptor.as_canonical_bytes(), self.adaptor.clone())?;
+        let nonces = match self.scheme {
+            CommitmentScheme::Bare => None,
+            CommitmentScheme::Salted => Some(
+                reveal
+                    .nonces
+                    .as_ref()
+                    .ok_or(crypto::Error::InvalidCommitment)?,
+            ),
+        };
+        verify_commitment(
+            wallet,
+            &reveal.buy,
+            nonces.map(|n| &n.buy),
+            self.buy.clone(),
+        )?;
+        verify_commitment(
+            wallet,
+            &reveal.cancel,
+            nonces.map(|n| &n.cancel),
+            self.cancel.clone(),
+        )?;
+        verify_commitment(
+            wallet,
+            &reveal.refund,
+            nonces.map(|n| &n.refund),
+            self.refund.clone(),
+        )?;
+        verify_commitment(
+            wallet,
+            &reveal.adaptor,
+            nonces.map(|n| &n.adaptor),
+            self.adaptor.clone(),
+        )?;
         verify_vec_of_commitments(
             wallet,
             reveal.extra_arbitrating_keys,
+            nonces.map(|n| &n.extra_arbitrating_keys[..]),
             &self.extra_arbitrating_keys,
         )?;
         verify_vec_of_commitments(
             wallet,
             reveal.arbitrating_shared_keys,
+            nonces.map(|n| &n.arbitrating_shared_keys[..]),
             &self.arbitrating_shared_keys,
         )?;
-        wallet.validate(reveal.spend.as_canonical_bytes(), self.spend.clone())?;
+        verify_commitment(
+            wallet,
+            &reveal.spend,
+            nonces.map(|n| &n.spend),
+            self.spend.clone(),
+        )?;
         verify_vec_of_commitments(
             wallet,
             reveal.extra_accordant_keys,
+            nonces.map(|n| &n.extra_accordant_keys[..]),
             &self.extra_accordant_keys,
         )?;
         verify_vec_of_commitments(
             wallet,
             reveal.accordant_shared_keys,
+            nonces.map(|n| &n.accordant_shared_keys[..]),
             &self.accordant_shared_keys,
         )
     }
@@ -236,6 +354,7 @@ where
 {
     fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
         let mut len = self.swap_id.consensus_encode(s)?;
+        len += self.scheme.consensus_encode(s)?;
         len += self.buy.as_canonical_bytes().consensus_encode(s)?;
         len += self.cancel.as_canonical_bytes().consensus_encode(s)?;
         len += self.refund.as_canonical_bytes().consensus_encode(s)?;
@@ -253,8 +372,12 @@ where
     C: CanonicalBytes,
 {
     fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        let swap_id = Decodable::consensus_decode(d)?;
+        let (scheme, prefix) = decode_scheme(d)?;
+        let d = &mut prefix.as_slice().chain(d);
         Ok(Self {
-            swap_id: Decodable::consensus_decode(d)?,
+            swap_id,
+            scheme,
             buy: C::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
             cancel: C::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
             refund: C::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
@@ -354,6 +477,8 @@ pub struct RevealAliceParameters<Pk, Qk, Rk, Sk, Addr> {
     pub accordant_shared_keys: Vec<TaggedElement<SharedKeyId, Sk>>,
     /// Reveal the destination address.
     pub address: Addr,
+    /// Reveal the nonces of the salted commitments.
+    pub nonces: Option<CommitmentNonces>,
 }
 
 impl<Pk, Qk, Rk, Sk, Addr> RevealAliceParameters<Pk, Qk, Rk, Sk, Addr> {
@@ -374,6 +499,7 @@ impl<Pk, Qk, Rk, Sk, Addr> RevealAliceParameters<Pk, Qk, Rk, Sk, Addr> {
             cancel_timelock: None,
             punish_timelock: None,
             fee_strategy: None,
+            commitment_nonces: self.nonces,
         }
     }
 }
@@ -412,7 +538,8 @@ where
         len += self.spend.as_canonical_bytes().consensus_encode(s)?;
         len += self.extra_accordant_keys.consensus_encode(s)?;
         len += self.accordant_shared_keys.consensus_encode(s)?;
-        Ok(len + self.address.as_canonical_bytes().consensus_encode(s)?)
+        len += self.address.as_canonical_bytes().consensus_encode(s)?;
+        Ok(len + self.nonces.consensus_encode(s)?)
     }
 }
 
@@ -438,6 +565,7 @@ where
             extra_accordant_keys: Decodable::consensus_decode(d)?,
             accordant_shared_keys: Decodable::consensus_decode(d)?,
             address: Addr::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            nonces: consensus::decode_trailing_option(d)?,
         })
     }
 }
@@ -475,6 +603,8 @@ pub struct RevealBobParameters<Pk, Qk, Rk, Sk, Addr> {
     pub accordant_shared_keys: Vec<TaggedElement<SharedKeyId, Sk>>,
     /// The refund Bitcoin address.
     pub address: Addr,
+    /// Reveal the nonces of the salted commitments.
+    pub nonces: Option<CommitmentNonces>,
 }
 
 impl<Pk, Qk, Rk, Sk, Addr> RevealBobParameters<Pk, Qk, Rk, Sk, Addr> {
@@ -495,6 +625,7 @@ impl<Pk, Qk, Rk, Sk, Addr> RevealBobParameters<Pk, Qk, Rk, Sk, Addr> {
             cancel_timelock: None,
             punish_timelock: None,
             fee_strategy: None,
+            commitment_nonces: self.nonces,
         }
     }
 }
@@ -531,7 +662,8 @@ where
         len += self.spend.as_canonical_bytes().consensus_encode(s)?;
         len += self.extra_accordant_keys.consensus_encode(s)?;
         len += self.accordant_shared_keys.consensus_encode(s)?;
-        Ok(len + self.address.as_canonical_bytes().consensus_encode(s)?)
+        len += self.address.as_canonical_bytes().consensus_encode(s)?;
+        Ok(len + self.nonces.consensus_encode(s)?)
     }
 }
 
@@ -556,6 +688,7 @@ where
             extra_accordant_keys: Decodable::consensus_decode(d)?,
             accordant_shared_keys: Decodable::consensus_decode(d)?,
             address: Addr::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            nonces: consensus::decode_trailing_option(d)?,
         })
     }
 }
@@ -564,14 +697,15 @@ impl_strict_encoding!(RevealBobParameters<Pk, Qk, Rk, Sk, Addr>, Pk: CanonicalBy
 
 /// Sends the [`Lockable`], [`Cancelable`] and [`Refundable`] arbritrating transactions from
 /// [`SwapRole::Bob`] to [`SwapRole::Alice`], as well as Bob's signature for the [`Cancelable`]
-/// transaction.
+/// transaction. The optional fee ladder is sent along with Bob's signature of each rung's
+/// [`Cancelable`] transaction.
 ///
 /// [`SwapRole::Alice`]: crate::role::SwapRole::Alice
 /// [`SwapRole::Bob`]: crate::role::SwapRole::Bob
 /// [`Lockable`]: crate::transaction::Lockable
 /// [`Cancelable`]: crate::transaction::Cancelable
 /// [`Refundable`]: crate::transaction::Refundable
-#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct CoreArbitratingSetup<Px, Sig> {
     /// The swap identifier related to this message.
     pub swap_id: SwapId,
@@ -583,6 +717,10 @@ pub struct CoreArbitratingSetup<Px, Sig> {
     pub refund: Px,
     /// The `Bc` `cancel (d)` signature.
     pub cancel_sig: Sig,
+    /// The arbitrating `cancel (d)` and `refund (e)` transactions of the fee ladder.
+    pub bumps: Vec<FeeBump<Px>>,
+    /// The `Bc` `cancel (d)` signatures of the fee ladder, one per bump.
+    pub bump_cancel_sigs: Vec<Sig>,
 }
 
 impl<Px, Sig> CoreArbitratingSetup<Px, Sig> {
@@ -593,6 +731,7 @@ impl<Px, Sig> CoreArbitratingSetup<Px, Sig> {
             lock: self.lock,
             cancel: self.cancel,
             refund: self.refund,
+            bumps: self.bumps,
         }
     }
 }
@@ -617,7 +756,15 @@ where
         len += self.lock.as_canonical_bytes().consensus_encode(s)?;
         len += self.cancel.as_canonical_bytes().consensus_encode(s)?;
         len += self.refund.as_canonical_bytes().consensus_encode(s)?;
-        Ok(len + self.cancel_sig.as_canonical_bytes().consensus_encode(s)?)
+        len += self.cancel_sig.as_canonical_bytes().consensus_encode(s)?;
+        len += self.bumps.consensus_encode(s)?;
+        Ok(len
+            + self
+                .bump_cancel_sigs
+                .iter()
+                .map(CanonicalBytes::as_canonical_bytes)
+                .collect::<Vec<_>>()
+                .consensus_encode(s)?)
     }
 }
 
@@ -633,6 +780,11 @@ where
             cancel: Px::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
             refund: Px::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
             cancel_sig: Sig::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            bumps: Decodable::consensus_decode(d)?,
+            bump_cancel_sigs: Vec::<Vec<u8>>::consensus_decode(d)?
+                .iter()
+                .map(|bytes| Sig::from_canonical_bytes(bytes))
+                .collect::<Result<_, _>>()?,
         })
     }
 }
@@ -640,14 +792,15 @@ where
 impl_strict_encoding!(CoreArbitratingSetup<Px, Sig>, Px: CanonicalBytes, Sig: CanonicalBytes);
 
 /// Protocol message is intended to transmit [`SwapRole::Alice`]'s signature for the [`Cancelable`]
-/// transaction and Alice's adaptor signature for the [`Refundable`] transaction. Uppon reception
-/// [`SwapRole::Bob`] must validate the signatures.
+/// transaction and Alice's adaptor signature for the [`Refundable`] transaction, as well as her
+/// signatures for each rung of the fee ladder. Uppon reception [`SwapRole::Bob`] must validate the
+/// signatures.
 ///
 /// [`SwapRole::Alice`]: crate::role::SwapRole::Alice
 /// [`SwapRole::Bob`]: crate::role::SwapRole::Bob
 /// [`Cancelable`]: crate::transaction::Cancelable
 /// [`Refundable`]: crate::transaction::Refundable
-#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RefundProcedureSignatures<Sig, EncSig> {
     /// The swap identifier related to this message.
     pub swap_id: SwapId,
@@ -655,6 +808,10 @@ pub struct RefundProcedureSignatures<Sig, EncSig> {
     pub cancel_sig: Sig,
     /// The `Ar(Tb)` `refund (e)` adaptor signature.
     pub refund_adaptor_sig: EncSig,
+    /// The `Ac` `cancel (d)` and `Ar(Tb)` `refund (e)` signatures of the fee ladder, one per bump.
+    pub fee_bumps: Vec<FeeBumpSignatures<Sig, EncSig>>,
+    /// OPTIONAL transcript of the swap seen by Alice, see [`transcript`](crate::protocol::transcript).
+    pub transcript: Option<TranscriptHash>,
 }
 
 impl<Sig, EncSig> fmt::Display for RefundProcedureSignatures<Sig, EncSig>
@@ -675,11 +832,12 @@ where
     fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
         let mut len = self.swap_id.consensus_encode(s)?;
         len += self.cancel_sig.as_canonical_bytes().consensus_encode(s)?;
-        Ok(len
-            + self
-                .refund_adaptor_sig
-                .as_canonical_bytes()
-                .consensus_encode(s)?)
+        len += self
+            .refund_adaptor_sig
+            .as_canonical_bytes()
+            .consensus_encode(s)?;
+        len += self.fee_bumps.consensus_encode(s)?;
+        Ok(len + self.transcript.consensus_encode(s)?)
     }
 }
 
@@ -693,6 +851,8 @@ where
             swap_id: Decodable::consensus_decode(d)?,
             cancel_sig: Sig::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
             refund_adaptor_sig: EncSig::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            fee_bumps: Decodable::consensus_decode(d)?,
+            transcript: Decodable::consensus_decode(d)?,
         })
     }
 }
@@ -713,6 +873,8 @@ pub struct BuyProcedureSignature<Px, EncSig> {
     pub buy: Px,
     /// The `Bb(Ta)` `buy (c)` adaptor signature.
     pub buy_adaptor_sig: EncSig,
+    /// OPTIONAL transcript of the swap seen by Bob, see [`transcript`](crate::protocol::transcript).
+    pub transcript: Option<TranscriptHash>,
 }
 
 impl<Px, EncSig> fmt::Display for BuyProcedureSignature<Px, EncSig>
@@ -733,11 +895,11 @@ where
     fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
         let mut len = self.swap_id.consensus_encode(s)?;
         len += self.buy.as_canonical_bytes().consensus_encode(s)?;
-        Ok(len
-            + self
-                .buy_adaptor_sig
-                .as_canonical_bytes()
-                .consensus_encode(s)?)
+        len += self
+            .buy_adaptor_sig
+            .as_canonical_bytes()
+            .consensus_encode(s)?;
+        Ok(len + self.transcript.consensus_encode(s)?)
     }
 }
 
@@ -751,12 +913,256 @@ where
             swap_id: Decodable::consensus_decode(d)?,
             buy: Px::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
             buy_adaptor_sig: EncSig::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            transcript: Decodable::consensus_decode(d)?,
         })
     }
 }
 
 impl_strict_encoding!(BuyProcedureSignature<Px, EncSig>, Px: consensus::CanonicalBytes, EncSig: consensus::CanonicalBytes);
 
+/// Reveals the public nonces of a participant for the cooperative transactions signed with
+/// two-round multi-signatures, e.g. MuSig2. Each participant sends this message to its
+/// counterparty before producing any partial signature. Nonces must be fresh and used only once.
+///
+/// [`SwapRole::Alice`] and [`SwapRole::Bob`] then exchange their partial signatures instead of
+/// their signatures and adaptor signatures in [`CoreArbitratingSetup`],
+/// [`RefundProcedureSignatures`], and [`BuyProcedureSignature`].
+///
+/// [`SwapRole::Alice`]: crate::role::SwapRole::Alice
+/// [`SwapRole::Bob`]: crate::role::SwapRole::Bob
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RevealNonces<N> {
+    /// The swap identifier related to this message.
+    pub swap_id: SwapId,
+    /// The public nonce for the `buy (c)` transaction.
+    pub buy: N,
+    /// The public nonce for the `cancel (d)` transaction.
+    pub cancel: N,
+    /// The public nonce for the `refund (e)` transaction.
+    pub refund: N,
+}
+
+impl<N> fmt::Display for RevealNonces<N>
+where
+    N: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl<N> Encodable for RevealNonces<N>
+where
+    N: CanonicalBytes,
+{
+    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        let mut len = self.swap_id.consensus_encode(s)?;
+        len += self.buy.as_canonical_bytes().consensus_encode(s)?;
+        len += self.cancel.as_canonical_bytes().consensus_encode(s)?;
+        Ok(len + self.refund.as_canonical_bytes().consensus_encode(s)?)
+    }
+}
+
+impl<N> Decodable for RevealNonces<N>
+where
+    N: CanonicalBytes,
+{
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(Self {
+            swap_id: Decodable::consensus_decode(d)?,
+            buy: N::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            cancel: N::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            refund: N::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+        })
+    }
+}
+
+impl_strict_encoding!(RevealNonces<N>, N: CanonicalBytes);
+
+/// Sends the taker's [`trade::DealFill`] to the maker when the deal accepts partial fills, see
+/// [`trade::FillRange`]. The taker sends this message before its commitment and the maker only
+/// accepts the taker's commitment once the fill is validated against the deal, the swap then locks
+/// and exchanges the filled amounts.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(serialize = "Amt: fmt::Display, Bmt: fmt::Display"))]
+#[serde(bound(
+    deserialize = "Amt: FromStr, Amt::Err: fmt::Display, Bmt: FromStr, Bmt::Err: fmt::Display"
+))]
+pub struct DealFill<Amt, Bmt> {
+    /// The swap identifier related to this message.
+    pub swap_id: SwapId,
+    /// The amounts chosen by the taker.
+    pub fill: trade::DealFill<Amt, Bmt>,
+}
+
+impl<Amt, Bmt> fmt::Display for DealFill<Amt, Bmt>
+where
+    Amt: fmt::Debug,
+    Bmt: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl<Amt, Bmt> Encodable for DealFill<Amt, Bmt>
+where
+    Amt: CanonicalBytes,
+    Bmt: CanonicalBytes,
+{
+    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        let len = self.swap_id.consensus_encode(s)?;
+        Ok(len + self.fill.consensus_encode(s)?)
+    }
+}
+
+impl<Amt, Bmt> Decodable for DealFill<Amt, Bmt>
+where
+    Amt: CanonicalBytes,
+    Bmt: CanonicalBytes,
+{
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(Self {
+            swap_id: Decodable::consensus_decode(d)?,
+            fill: Decodable::consensus_decode(d)?,
+        })
+    }
+}
+
+impl_strict_encoding!(DealFill<Amt, Bmt>, Amt: CanonicalBytes, Bmt: CanonicalBytes);
+
+/// Machine-readable reason of an [`Abort`], encoded with a stable numeric code so the
+/// counter-party can react without parsing the error body. Codes unknown to this version decode as
+/// [`AbortReason::Unknown`].
+///
+/// Reasons are serialized as their code and built from it with [`From<u16>`], so a code defined in
+/// this version always deserializes as the reason of that code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+#[display(Debug)]
+#[serde(from = "u16", into = "u16")]
+pub enum AbortReason {
+    /// Code `0x0001`, the user cancelled the swap.
+    UserCancelled,
+    /// Code `0x0002`, the counter-party did not answer in time.
+    Timeout,
+    /// Code `0x0003`, the deal expired before the swap started.
+    DealExpired,
+    /// Code `0x0004`, a message is inconsistent with the deal or the deal is not accepted.
+    DealMismatch,
+    /// Code `0x0005`, a signature or an adaptor signature does not pass the validation.
+    InvalidSignature,
+    /// Code `0x0006`, a proof or a commitment does not pass the validation.
+    InvalidProof,
+    /// Code `0x0007`, a fee rate is out of the fee strategy range.
+    FeeOutOfRange,
+    /// Code `0x0008`, a transaction does not follow the expected template.
+    InvalidTransaction,
+    /// Code `0x0009`, a message cannot be decoded.
+    InvalidMessage,
+    /// Code `0x000a`, a message is received out of order or for another swap.
+    ProtocolViolation,
+    /// Code `0x000b`, a local error unrelated to the counter-party.
+    Internal,
+    /// A code not defined in this version.
+    Unknown(u16),
+}
+
+impl AbortReason {
+    /// Return the numeric code of the reason.
+    pub fn to_u16(&self) -> u16 {
+        match self {
+            Self::UserCancelled => 0x0001,
+            Self::Timeout => 0x0002,
+            Self::DealExpired => 0x0003,
+            Self::DealMismatch => 0x0004,
+            Self::InvalidSignature => 0x0005,
+            Self::InvalidProof => 0x0006,
+            Self::FeeOutOfRange => 0x0007,
+            Self::InvalidTransaction => 0x0008,
+            Self::InvalidMessage => 0x0009,
+            Self::ProtocolViolation => 0x000a,
+            Self::Internal => 0x000b,
+            Self::Unknown(code) => *code,
+        }
+    }
+}
+
+impl From<u16> for AbortReason {
+    fn from(code: u16) -> Self {
+        match code {
+            0x0001 => Self::UserCancelled,
+            0x0002 => Self::Timeout,
+            0x0003 => Self::DealExpired,
+            0x0004 => Self::DealMismatch,
+            0x0005 => Self::InvalidSignature,
+            0x0006 => Self::InvalidProof,
+            0x0007 => Self::FeeOutOfRange,
+            0x0008 => Self::InvalidTransaction,
+            0x0009 => Self::InvalidMessage,
+            0x000a => Self::ProtocolViolation,
+            0x000b => Self::Internal,
+            code => Self::Unknown(code),
+        }
+    }
+}
+
+impl From<AbortReason> for u16 {
+    fn from(reason: AbortReason) -> Self {
+        reason.to_u16()
+    }
+}
+
+impl From<&Error> for AbortReason {
+    fn from(error: &Error) -> Self {
+        match error {
+            Error::Consensus(_) => Self::InvalidMessage,
+            Error::Crypto(
+                crypto::Error::InvalidSignature
+                | crypto::Error::InvalidEncryptedSignature
+                | crypto::Error::InvalidPartialSignature
+                | crypto::Error::InvalidRingSignature
+                | crypto::Error::InvalidProofOfKnowledge,
+            ) => Self::InvalidSignature,
+            Error::Crypto(
+                crypto::Error::InvalidProof
+                | crypto::Error::InvalidAdaptorKey
+                | crypto::Error::InvalidCommitment
+                | crypto::Error::InvalidPedersenCommitment,
+            ) => Self::InvalidProof,
+            Error::Crypto(_) => Self::Internal,
+            Error::FeeStrategy(_) => Self::FeeOutOfRange,
+            Error::Transaction(_) => Self::InvalidTransaction,
+            Error::Trade(trade::Error::ExpiredDeal(_)) => Self::DealExpired,
+            Error::Trade(_) => Self::DealMismatch,
+            Error::StateMachine(
+                machine::Error::OutOfOrder { .. }
+                | machine::Error::SwapIdMismatch { .. }
+                | machine::Error::TranscriptMismatch { .. },
+            ) => Self::ProtocolViolation,
+            Error::StateMachine(_) => Self::Internal,
+            Error::Validation(validation::Error::Diverged {
+                field: validation::Field::FeeStrategy(_),
+                ..
+            }) => Self::FeeOutOfRange,
+            Error::Validation(_) => Self::DealMismatch,
+        }
+    }
+}
+
+impl Encodable for AbortReason {
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        self.to_u16().consensus_encode(writer)
+    }
+}
+
+impl Decodable for AbortReason {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(u16::consensus_decode(d)?.into())
+    }
+}
+
+impl_strict_encoding!(AbortReason);
+
 /// Optional courtesy message from either [`SwapRole`] to inform the counterparty
 /// that they have aborted the swap with an `OPTIONAL` message body to provide the reason.
 ///
@@ -766,14 +1172,34 @@ impl_strict_encoding!(BuyProcedureSignature<Px, EncSig>, Px: consensus::Canonica
 pub struct Abort {
     /// The swap identifier related to this message.
     pub swap_id: SwapId,
+    /// The machine-readable reason of the abort.
+    pub reason: AbortReason,
     /// OPTIONAL `body`: error string.
     pub error_body: Option<String>,
+    /// OPTIONAL transcript of the swap seen by the sender, see
+    /// [`transcript`](crate::protocol::transcript).
+    pub transcript: Option<TranscriptHash>,
+}
+
+impl Abort {
+    /// Create the abort message for a swap failing with the error, the reason is mapped from the
+    /// error and the body is the error message.
+    pub fn from_error(swap_id: SwapId, error: &Error) -> Self {
+        Self {
+            swap_id,
+            reason: error.into(),
+            error_body: Some(error.to_string()),
+            transcript: None,
+        }
+    }
 }
 
 impl Encodable for Abort {
     fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
-        let len = self.swap_id.consensus_encode(s)?;
-        Ok(len + self.error_body.consensus_encode(s)?)
+        let mut len = self.swap_id.consensus_encode(s)?;
+        len += self.reason.consensus_encode(s)?;
+        len += self.error_body.consensus_encode(s)?;
+        Ok(len + self.transcript.consensus_encode(s)?)
     }
 }
 
@@ -781,9 +1207,455 @@ impl Decodable for Abort {
     fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
         Ok(Self {
             swap_id: Decodable::consensus_decode(d)?,
+            reason: Decodable::consensus_decode(d)?,
             error_body: Option::<String>::consensus_decode(d)?,
+            transcript: Decodable::consensus_decode(d)?,
         })
     }
 }
 
 impl_strict_encoding!(Abort);
+
+/// Version of the protocol message framing, the first byte of every encoded
+/// [`ProtocolMessage`]. Only frames with this version are accepted when decoding.
+pub const PROTOCOL_VERSION: u8 = 2;
+
+/// Stable numeric identifier of each protocol message, encoded as a `u16` in the frame of a
+/// [`ProtocolMessage`]. Identifiers are never reused, new messages get new identifiers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+#[display(Debug)]
+pub enum MessageType {
+    /// Type `0x0001`, see [`CommitAliceParameters`].
+    CommitAliceParameters,
+    /// Type `0x0002`, see [`CommitBobParameters`].
+    CommitBobParameters,
+    /// Type `0x0003`, see [`RevealProof`].
+    RevealProof,
+    /// Type `0x0004`, see [`RevealAliceParameters`].
+    RevealAliceParameters,
+    /// Type `0x0005`, see [`RevealBobParameters`].
+    RevealBobParameters,
+    /// Type `0x0006`, see [`CoreArbitratingSetup`].
+    CoreArbitratingSetup,
+    /// Type `0x0007`, see [`RefundProcedureSignatures`].
+    RefundProcedureSignatures,
+    /// Type `0x0008`, see [`BuyProcedureSignature`].
+    BuyProcedureSignature,
+    /// Type `0x0009`, see [`Abort`].
+    Abort,
+    /// Type `0x000a`, see [`RevealNonces`].
+    RevealNonces,
+    /// Type `0x000b`, see [`DealFill`].
+    DealFill,
+}
+
+impl MessageType {
+    /// Return the numeric identifier of the message type.
+    pub fn to_u16(&self) -> u16 {
+        match self {
+            Self::CommitAliceParameters => 0x0001,
+            Self::CommitBobParameters => 0x0002,
+            Self::RevealProof => 0x0003,
+            Self::RevealAliceParameters => 0x0004,
+            Self::RevealBobParameters => 0x0005,
+            Self::CoreArbitratingSetup => 0x0006,
+            Self::RefundProcedureSignatures => 0x0007,
+            Self::BuyProcedureSignature => 0x0008,
+            Self::Abort => 0x0009,
+            Self::RevealNonces => 0x000a,
+            Self::DealFill => 0x000b,
+        }
+    }
+}
+
+impl TryFrom<u16> for MessageType {
+    type Error = consensus::Error;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            0x0001 => Ok(Self::CommitAliceParameters),
+            0x0002 => Ok(Self::CommitBobParameters),
+            0x0003 => Ok(Self::RevealProof),
+            0x0004 => Ok(Self::RevealAliceParameters),
+            0x0005 => Ok(Self::RevealBobParameters),
+            0x0006 => Ok(Self::CoreArbitratingSetup),
+            0x0007 => Ok(Self::RefundProcedureSignatures),
+            0x0008 => Ok(Self::BuyProcedureSignature),
+            0x0009 => Ok(Self::Abort),
+            0x000a => Ok(Self::RevealNonces),
+            0x000b => Ok(Self::DealFill),
+            _ => Err(consensus::Error::UnknownType),
+        }
+    }
+}
+
+impl Encodable for MessageType {
+    fn consensus_encode<W: io::Write>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        self.to_u16().consensus_encode(writer)
+    }
+}
+
+impl Decodable for MessageType {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Self::try_from(u16::consensus_decode(d)?)
+    }
+}
+
+impl_strict_encoding!(MessageType);
+
+/// A protocol message exchanged between swap participants, wraps every message consumed or
+/// emitted by the swap state machines defined in [`machine`](crate::protocol::machine).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ProtocolMessage<C, Pk, Qk, Rk, Sk, Addr, Pr, Px, Si, EncSig, N, Amt, Bmt> {
+    /// See [`CommitAliceParameters`].
+    CommitAliceParameters(CommitAliceParameters<C>),
+    /// See [`CommitBobParameters`].
+    CommitBobParameters(CommitBobParameters<C>),
+    /// See [`RevealProof`].
+    RevealProof(RevealProof<Pr>),
+    /// See [`RevealAliceParameters`].
+    RevealAliceParameters(RevealAliceParameters<Pk, Qk, Rk, Sk, Addr>),
+    /// See [`RevealBobParameters`].
+    RevealBobParameters(RevealBobParameters<Pk, Qk, Rk, Sk, Addr>),
+    /// See [`CoreArbitratingSetup`].
+    CoreArbitratingSetup(CoreArbitratingSetup<Px, Si>),
+    /// See [`RefundProcedureSignatures`].
+    RefundProcedureSignatures(RefundProcedureSignatures<Si, EncSig>),
+    /// See [`BuyProcedureSignature`].
+    BuyProcedureSignature(BuyProcedureSignature<Px, EncSig>),
+    /// See [`Abort`].
+    Abort(Abort),
+    /// See [`RevealNonces`].
+    RevealNonces(RevealNonces<N>),
+    /// See [`DealFill`].
+    #[serde(bound(serialize = "Amt: fmt::Display, Bmt: fmt::Display"))]
+    #[serde(bound(
+        deserialize = "Amt: FromStr, Amt::Err: fmt::Display, Bmt: FromStr, Bmt::Err: fmt::Display"
+    ))]
+    DealFill(DealFill<Amt, Bmt>),
+}
+
+impl<C, Pk, Qk, Rk, Sk, Addr, Pr, Px, Si, EncSig, N, Amt, Bmt>
+    ProtocolMessage<C, Pk, Qk, Rk, Sk, Addr, Pr, Px, Si, EncSig, N, Amt, Bmt>
+{
+    /// Return the swap identifier related to the message.
+    pub fn swap_id(&self) -> SwapId {
+        match self {
+            Self::CommitAliceParameters(m) => m.swap_id,
+            Self::CommitBobParameters(m) => m.swap_id,
+            Self::RevealProof(m) => m.swap_id,
+            Self::RevealAliceParameters(m) => m.swap_id,
+            Self::RevealBobParameters(m) => m.swap_id,
+            Self::CoreArbitratingSetup(m) => m.swap_id,
+            Self::RefundProcedureSignatures(m) => m.swap_id,
+            Self::BuyProcedureSignature(m) => m.swap_id,
+            Self::Abort(m) => m.swap_id,
+            Self::RevealNonces(m) => m.swap_id,
+            Self::DealFill(m) => m.swap_id,
+        }
+    }
+
+    /// Return the type of the message.
+    pub fn message_type(&self) -> MessageType {
+        match self {
+            Self::CommitAliceParameters(_) => MessageType::CommitAliceParameters,
+            Self::CommitBobParameters(_) => MessageType::CommitBobParameters,
+            Self::RevealProof(_) => MessageType::RevealProof,
+            Self::RevealAliceParameters(_) => MessageType::RevealAliceParameters,
+            Self::RevealBobParameters(_) => MessageType::RevealBobParameters,
+            Self::CoreArbitratingSetup(_) => MessageType::CoreArbitratingSetup,
+            Self::RefundProcedureSignatures(_) => MessageType::RefundProcedureSignatures,
+            Self::BuyProcedureSignature(_) => MessageType::BuyProcedureSignature,
+            Self::Abort(_) => MessageType::Abort,
+            Self::RevealNonces(_) => MessageType::RevealNonces,
+            Self::DealFill(_) => MessageType::DealFill,
+        }
+    }
+
+    /// Return the name of the message.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::CommitAliceParameters(_) => "CommitAliceParameters",
+            Self::CommitBobParameters(_) => "CommitBobParameters",
+            Self::RevealProof(_) => "RevealProof",
+            Self::RevealAliceParameters(_) => "RevealAliceParameters",
+            Self::RevealBobParameters(_) => "RevealBobParameters",
+            Self::CoreArbitratingSetup(_) => "CoreArbitratingSetup",
+            Self::RefundProcedureSignatures(_) => "RefundProcedureSignatures",
+            Self::BuyProcedureSignature(_) => "BuyProcedureSignature",
+            Self::Abort(_) => "Abort",
+            Self::RevealNonces(_) => "RevealNonces",
+            Self::DealFill(_) => "DealFill",
+        }
+    }
+}
+
+impl<C, Pk, Qk, Rk, Sk, Addr, Pr, Px, Si, EncSig, N, Amt, Bmt> fmt::Display
+    for ProtocolMessage<C, Pk, Qk, Rk, Sk, Addr, Pr, Px, Si, EncSig, N, Amt, Bmt>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {{ swap_id: {} }}", self.name(), self.swap_id())
+    }
+}
+
+impl<C, Pk, Qk, Rk, Sk, Addr, Pr, Px, Si, EncSig, N, Amt, Bmt> Encodable
+    for ProtocolMessage<C, Pk, Qk, Rk, Sk, Addr, Pr, Px, Si, EncSig, N, Amt, Bmt>
+where
+    C: CanonicalBytes,
+    Pk: CanonicalBytes,
+    Qk: CanonicalBytes,
+    Rk: CanonicalBytes,
+    Sk: CanonicalBytes,
+    Addr: CanonicalBytes,
+    Pr: CanonicalBytes,
+    Px: CanonicalBytes,
+    Si: CanonicalBytes,
+    EncSig: CanonicalBytes,
+    N: CanonicalBytes,
+    Amt: CanonicalBytes,
+    Bmt: CanonicalBytes,
+{
+    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        let payload = match self {
+            Self::CommitAliceParameters(m) => consensus::serialize(m),
+            Self::CommitBobParameters(m) => consensus::serialize(m),
+            Self::RevealProof(m) => consensus::serialize(m),
+            Self::RevealAliceParameters(m) => consensus::serialize(m),
+            Self::RevealBobParameters(m) => consensus::serialize(m),
+            Self::CoreArbitratingSetup(m) => consensus::serialize(m),
+            Self::RefundProcedureSignatures(m) => consensus::serialize(m),
+            Self::BuyProcedureSignature(m) => consensus::serialize(m),
+            Self::Abort(m) => consensus::serialize(m),
+            Self::RevealNonces(m) => consensus::serialize(m),
+            Self::DealFill(m) => consensus::serialize(m),
+        };
+        let payload_len = u32::try_from(payload.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "Message is too long"))?;
+        let mut len = PROTOCOL_VERSION.consensus_encode(s)?;
+        len += self.message_type().consensus_encode(s)?;
+        len += payload_len.consensus_encode(s)?;
+        s.write_all(&payload)?;
+        Ok(len + payload.len())
+    }
+}
+
+impl<C, Pk, Qk, Rk, Sk, Addr, Pr, Px, Si, EncSig, N, Amt, Bmt> Decodable
+    for ProtocolMessage<C, Pk, Qk, Rk, Sk, Addr, Pr, Px, Si, EncSig, N, Amt, Bmt>
+where
+    C: CanonicalBytes,
+    Pk: CanonicalBytes,
+    Qk: CanonicalBytes,
+    Rk: CanonicalBytes,
+    Sk: CanonicalBytes,
+    Addr: CanonicalBytes,
+    Pr: CanonicalBytes,
+    Px: CanonicalBytes,
+    Si: CanonicalBytes,
+    EncSig: CanonicalBytes,
+    N: CanonicalBytes,
+    Amt: CanonicalBytes,
+    Bmt: CanonicalBytes,
+{
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        if u8::consensus_decode(d)? != PROTOCOL_VERSION {
+            return Err(consensus::Error::ParseFailed(
+                "Unsupported protocol message version",
+            ));
+        }
+        let message_type = MessageType::consensus_decode(d)?;
+        let payload_len = u32::consensus_decode(d)? as u64;
+        let mut payload = vec![];
+        d.take(payload_len).read_to_end(&mut payload)?;
+        if payload.len() as u64 != payload_len {
+            return Err(consensus::Error::ParseFailed(
+                "Protocol message shorter than its length",
+            ));
+        }
+        Ok(match message_type {
+            MessageType::CommitAliceParameters => {
+                Self::CommitAliceParameters(consensus::deserialize(&payload)?)
+            }
+            MessageType::CommitBobParameters => {
+                Self::CommitBobParameters(consensus::deserialize(&payload)?)
+            }
+            MessageType::RevealProof => Self::RevealProof(consensus::deserialize(&payload)?),
+            MessageType::RevealAliceParameters => {
+                Self::RevealAliceParameters(consensus::deserialize(&payload)?)
+            }
+            MessageType::RevealBobParameters => {
+                Self::RevealBobParameters(consensus::deserialize(&payload)?)
+            }
+            MessageType::CoreArbitratingSetup => {
+                Self::CoreArbitratingSetup(consensus::deserialize(&payload)?)
+            }
+            MessageType::RefundProcedureSignatures => {
+                Self::RefundProcedureSignatures(consensus::deserialize(&payload)?)
+            }
+            MessageType::BuyProcedureSignature => {
+                Self::BuyProcedureSignature(consensus::deserialize(&payload)?)
+            }
+            MessageType::Abort => Self::Abort(consensus::deserialize(&payload)?),
+            MessageType::RevealNonces => Self::RevealNonces(consensus::deserialize(&payload)?),
+            MessageType::DealFill => Self::DealFill(consensus::deserialize(&payload)?),
+        })
+    }
+}
+
+impl_strict_encoding!(ProtocolMessage<C, Pk, Qk, Rk, Sk, Addr, Pr, Px, Si, EncSig, N, Amt, Bmt>, C: CanonicalBytes, Pk: CanonicalBytes, Qk: CanonicalBytes, Rk: CanonicalBytes, Sk: CanonicalBytes, Addr: CanonicalBytes, Pr: CanonicalBytes, Px: CanonicalBytes, Si: CanonicalBytes, EncSig: CanonicalBytes, N: CanonicalBytes, Amt: CanonicalBytes, Bmt: CanonicalBytes);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::swap::btcxmr::message::ProtocolMessage;
+
+    #[test]
+    fn frame_protocol_message() {
+        let message = ProtocolMessage::Abort(Abort {
+            swap_id: SwapId::repeat_byte(0x42),
+            reason: AbortReason::UserCancelled,
+            error_body: Some("aborted".into()),
+            transcript: None,
+        });
+        let bytes = consensus::serialize(&message);
+        assert_eq!(bytes[0], PROTOCOL_VERSION);
+        assert_eq!(bytes[1..3], [0x09, 0x00]);
+        assert_eq!(
+            bytes.len(),
+            7 + u32::from_le_bytes(bytes[3..7].try_into().unwrap()) as usize
+        );
+        let decoded: ProtocolMessage = consensus::deserialize(&bytes).unwrap();
+        assert_eq!(decoded.message_type(), MessageType::Abort);
+        assert_eq!(decoded.swap_id(), message.swap_id());
+
+        let mut bad_version = bytes.clone();
+        bad_version[0] = 0xff;
+        assert!(consensus::deserialize::<ProtocolMessage>(&bad_version).is_err());
+        let mut bad_type = bytes.clone();
+        bad_type[1] = 0xff;
+        assert!(matches!(
+            consensus::deserialize::<ProtocolMessage>(&bad_type),
+            Err(consensus::Error::UnknownType)
+        ));
+        assert!(consensus::deserialize::<ProtocolMessage>(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn frame_reveal_nonces() {
+        use crate::crypto::musig2::SecretNonce;
+        use secp256kfun::nonce::{GlobalRng, Synthetic};
+        use secp256kfun::Scalar;
+
+        let nonce_gen = Synthetic::<sha2::Sha256, GlobalRng<rand::rngs::ThreadRng>>::default();
+        let secret_key = Scalar::random(&mut rand::thread_rng());
+        let nonce = || SecretNonce::generate(&nonce_gen, &secret_key).public_nonce();
+        let reveal = RevealNonces {
+            swap_id: SwapId::repeat_byte(0x42),
+            buy: nonce(),
+            cancel: nonce(),
+            refund: nonce(),
+        };
+        let message = ProtocolMessage::RevealNonces(reveal);
+        assert_eq!(message.message_type(), MessageType::RevealNonces);
+        assert_eq!(message.name(), "RevealNonces");
+
+        let bytes = consensus::serialize(&message);
+        assert_eq!(bytes[1..3], [0x0a, 0x00]);
+        match consensus::deserialize(&bytes).unwrap() {
+            ProtocolMessage::RevealNonces(decoded) => assert_eq!(decoded, reveal),
+            decoded => panic!("Expected RevealNonces, found {}", decoded),
+        }
+    }
+
+    #[test]
+    fn frame_deal_fill() {
+        let fill = DealFill {
+            swap_id: SwapId::repeat_byte(0x42),
+            fill: trade::DealFill {
+                fingerprint: trade::DealFingerprint::repeat_byte(0x01),
+                arbitrating_amount: bitcoin::Amount::from_sat(675),
+                accordant_amount: monero::Amount::from_pico(1350),
+            },
+        };
+        let message = ProtocolMessage::DealFill(fill);
+        assert_eq!(message.message_type(), MessageType::DealFill);
+        assert_eq!(message.name(), "DealFill");
+
+        let bytes = consensus::serialize(&message);
+        assert_eq!(bytes[1..3], [0x0b, 0x00]);
+        match consensus::deserialize(&bytes).unwrap() {
+            ProtocolMessage::DealFill(decoded) => assert_eq!(decoded, fill),
+            decoded => panic!("Expected DealFill, found {}", decoded),
+        }
+    }
+
+    #[test]
+    fn decode_commitments_without_scheme() {
+        use crate::crypto::KeccakCommitment;
+
+        // Commitments of Bob's parameters encoded before the scheme was added
+        let legacy = hex::decode(concat!(
+            "4242424242424242424242424242424242424242424242424242424242424242",
+            "20000101010101010101010101010101010101010101010101010101010101010101",
+            "20000202020202020202020202020202020202020202020202020202020202020202",
+            "20000303030303030303030303030303030303030303030303030303030303030303",
+            "20000404040404040404040404040404040404040404040404040404040404040404",
+            "00000000",
+            "20000505050505050505050505050505050505050505050505050505050505050505",
+            "00000000",
+        ))
+        .unwrap();
+        let commit: CommitBobParameters<KeccakCommitment> =
+            consensus::deserialize(&legacy).unwrap();
+        assert_eq!(commit.swap_id, SwapId::repeat_byte(0x42));
+        assert_eq!(commit.scheme, CommitmentScheme::Bare);
+        assert_eq!(commit.buy, KeccakCommitment::repeat_byte(0x01));
+        assert_eq!(commit.adaptor, KeccakCommitment::repeat_byte(0x04));
+        assert_eq!(commit.spend, KeccakCommitment::repeat_byte(0x05));
+
+        // Re-encoded with the scheme after the swap id
+        let bytes = consensus::serialize(&commit);
+        assert_eq!(bytes[32], 0x01);
+        assert_eq!(legacy, [&bytes[..32], &bytes[33..]].concat());
+        assert_eq!(
+            consensus::deserialize::<CommitBobParameters<KeccakCommitment>>(&bytes).unwrap(),
+            commit
+        );
+    }
+
+    #[test]
+    fn map_errors_to_abort_reasons() {
+        let error = Error::Crypto(crypto::Error::InvalidSignature);
+        let abort = Abort::from_error(SwapId::repeat_byte(0x42), &error);
+        assert_eq!(abort.reason, AbortReason::InvalidSignature);
+        assert_eq!(abort.error_body, Some(error.to_string()));
+        assert_eq!(
+            AbortReason::from(&Error::FeeStrategy(
+                crate::blockchain::FeeStrategyError::AmountOfFeeTooHigh
+            )),
+            AbortReason::FeeOutOfRange
+        );
+        assert_eq!(
+            AbortReason::from(&Error::Consensus(consensus::Error::UnknownType)),
+            AbortReason::InvalidMessage
+        );
+
+        let decoded: Abort = consensus::deserialize(&consensus::serialize(&abort)).unwrap();
+        assert_eq!(decoded.reason, AbortReason::InvalidSignature);
+        assert_eq!(AbortReason::from(0x0005), AbortReason::InvalidSignature);
+        assert_eq!(AbortReason::from(0x1234), AbortReason::Unknown(0x1234));
+        assert_eq!(AbortReason::Unknown(0x1234).to_u16(), 0x1234);
+        assert_eq!(AbortReason::UserCancelled.to_string(), "UserCancelled");
+        // Known codes are normalized when decoding, unknown codes are kept
+        let decoded: AbortReason =
+            consensus::deserialize(&consensus::serialize(&AbortReason::Unknown(0x0001))).unwrap();
+        assert_eq!(decoded, AbortReason::UserCancelled);
+        let decoded: AbortReason = serde_yaml::from_str("1").unwrap();
+        assert_eq!(decoded, AbortReason::UserCancelled);
+        let decoded: AbortReason = serde_yaml::from_str("4660").unwrap();
+        assert_eq!(decoded, AbortReason::Unknown(0x1234));
+        assert_eq!(
+            serde_yaml::to_string(&AbortReason::Timeout).unwrap(),
+            "---\n2\n"
+        );
+    }
+}
@@ -0,0 +1,104 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Running hash binding the protocol messages of a swap together. The transcript starts from the
+//! swap identifier and the deal fingerprint, then absorbs the taker's [`DealFill`] of a deal
+//! accepting partial fills, both commitments, both reveals and proofs, and the
+//! [`CoreArbitratingSetup`] in the protocol order, whatever the order of reception. Both participants of the same protocol run end up with the same transcript hash.
+//!
+//! The state machines in [`machine`](crate::protocol::machine) send their transcript hash in the
+//! [`RefundProcedureSignatures`], [`BuyProcedureSignature`] and [`Abort`] messages and reject
+//! signature messages with a different transcript.
+//!
+//! [`DealFill`]: crate::protocol::message::DealFill
+//! [`CoreArbitratingSetup`]: crate::protocol::message::CoreArbitratingSetup
+//! [`RefundProcedureSignatures`]: crate::protocol::message::RefundProcedureSignatures
+//! [`BuyProcedureSignature`]: crate::protocol::message::BuyProcedureSignature
+//! [`Abort`]: crate::protocol::message::Abort
+
+use std::io;
+use std::str::FromStr;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use tiny_keccak::{Hasher, Keccak};
+
+use crate::consensus::{self, serialize, Decodable, Encodable};
+use crate::hash::HashString;
+use crate::protocol::message::MessageType;
+use crate::swap::SwapId;
+use crate::trade::DealFingerprint;
+
+fixed_hash::construct_fixed_hash!(
+    /// Keccak-256 running hash over the protocol messages of a swap.
+    pub struct TranscriptHash(32);
+);
+
+impl TranscriptHash {
+    /// Start the transcript of a swap from its identifier and the fingerprint of its deal.
+    pub fn start(swap_id: SwapId, deal: DealFingerprint) -> Self {
+        let mut keccak = Keccak::v256();
+        let mut out = [0u8; 32];
+        keccak.update(swap_id.as_bytes());
+        keccak.update(deal.as_bytes());
+        keccak.finalize(&mut out);
+        Self(out)
+    }
+
+    /// Return the transcript after absorbing the consensus encoded message of the given type.
+    pub fn absorb<T: Encodable>(self, message_type: MessageType, message: &T) -> Self {
+        let mut keccak = Keccak::v256();
+        let mut out = [0u8; 32];
+        keccak.update(self.as_bytes());
+        keccak.update(&serialize(&message_type));
+        keccak.update(&serialize(message));
+        keccak.finalize(&mut out);
+        Self(out)
+    }
+}
+
+impl Serialize for TranscriptHash {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(format!("{:#x}", self).as_ref())
+    }
+}
+
+impl<'de> Deserialize<'de> for TranscriptHash {
+    fn deserialize<D>(deserializer: D) -> Result<TranscriptHash, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        TranscriptHash::from_str(&deserializer.deserialize_string(HashString)?)
+            .map_err(de::Error::custom)
+    }
+}
+
+impl Encodable for TranscriptHash {
+    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        self.0.consensus_encode(s)
+    }
+}
+
+impl Decodable for TranscriptHash {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        let bytes: [u8; 32] = Decodable::consensus_decode(d)?;
+        Ok(Self::from_slice(&bytes))
+    }
+}
+
+impl_strict_encoding!(TranscriptHash);
@@ -0,0 +1,577 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Consistency of incoming protocol messages with the agreed [`Deal`] and the counter-party
+//! revealed [`Parameters`]. Signatures are verified by the `validate_*` methods of [`Alice`] and
+//! [`Bob`], the checks here ensure that what is signed is what was agreed on: swap identifier,
+//! arbitrating amount, timelocks and keys in the scripts, fee strategy and destination addresses.
+//! Deals accepting partial fills are checked at the arbitrating amount of the taker's [`DealFill`].
+//! Commitments and revealed parameters are checked for the keys expected by the blockchains of the
+//! deal before being used to build the transactions.
+//!
+//! A message failing a check is rejected with [`Error::Diverged`] naming the message and the
+//! [`Field`] that diverged. The state machines in [`machine`](crate::protocol::machine) run these
+//! checks before processing a message.
+
+// For this file we allow having complex types
+#![allow(clippy::type_complexity)]
+
+use std::iter;
+
+use thiserror::Error;
+
+use crate::blockchain::{Fee, FeeStrategy, Transactions};
+use crate::consensus::Encodable;
+use crate::crypto::{DeriveKeys, SharedKeyId, TaggedElement};
+use crate::protocol::message::{
+    BuyProcedureSignature, CommitAliceParameters, CommitBobParameters, CoreArbitratingSetup,
+    MessageType, RefundProcedureSignatures, RevealAliceParameters, RevealBobParameters,
+    RevealProof,
+};
+use crate::protocol::ArbitratingParameters;
+use crate::protocol::{Alice, Bob, CoreArbitratingTransactions, Parameters};
+use crate::script::{DataLock, DataPunishableLock, DoubleKeys};
+use crate::swap::SwapId;
+use crate::trade::{AtomicAmount, Deal, DealFill};
+use crate::transaction::{
+    Buyable, Cancelable, Chainable, Lockable, Refundable, Transaction, TxLabel,
+};
+use crate::Res;
+
+/// A field of a protocol message checked against the deal or the counter-party parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+pub enum Field {
+    /// The swap identifier of the message.
+    #[display("swap id")]
+    SwapId,
+    /// The amount locked by the lock transaction, set by the deal or the taker's fill.
+    #[display("arbitrating amount")]
+    ArbitratingAmount,
+    /// The lock output script, built from Alice and Bob buy and cancel keys and the cancel
+    /// timelock of the deal.
+    #[display("lock script")]
+    LockScript,
+    /// The cancel transaction template: its timelock and output script, built from the cancel and
+    /// punish timelocks of the deal, Alice and Bob refund keys and Alice punish key.
+    #[display("cancel template")]
+    CancelTemplate,
+    /// The transaction does not spend the output of the previous transaction in the swap.
+    #[display("{0} previous output")]
+    PreviousOutput(TxLabel),
+    /// The address receiving the output of the transaction, Alice destination address for the buy
+    /// and Bob refund address for the refund.
+    #[display("{0} destination address")]
+    DestinationAddress(TxLabel),
+    /// The fee rate of the transaction is not in the fee strategy of the deal.
+    #[display("{0} fee rate")]
+    FeeStrategy(TxLabel),
+    /// The number of fee bump signatures does not match the fee ladder.
+    #[display("fee bumps")]
+    FeeBumps,
+    /// The tags of the extra and shared keys do not match the keys required by the arbitrating
+    /// and accordant blockchains of the deal.
+    #[display("extra keys")]
+    ExtraKeys,
+    /// The counter-party reveals a key of the local participant as its own.
+    #[display("keys")]
+    Keys,
+}
+
+/// A protocol message inconsistent with the deal or the counter-party parameters.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// A field of the message diverges from the deal or the counter-party parameters.
+    #[error("The {field} of {message} diverges from the deal")]
+    Diverged {
+        /// The type of the rejected message.
+        message: MessageType,
+        /// The field that diverged.
+        field: Field,
+    },
+}
+
+// Reject the message on the field if the check failed
+fn check<T, E>(res: Result<T, E>, message: MessageType, field: Field) -> Result<T, Error> {
+    res.map_err(|_| Error::Diverged { message, field })
+}
+
+// Reject the message on the fee of the transaction if it does not follow the strategy
+fn check_fee<Px, F>(
+    partial: &Px,
+    fee_strategy: &FeeStrategy<F>,
+    message: MessageType,
+    label: TxLabel,
+) -> Result<(), Error>
+where
+    Px: Fee<FeeUnit = F>,
+{
+    match partial.validate_fee(fee_strategy) {
+        Ok(true) => Ok(()),
+        _ => Err(Error::Diverged {
+            message,
+            field: Field::FeeStrategy(label),
+        }),
+    }
+}
+
+fn check_swap_id(expected: SwapId, found: SwapId, message: MessageType) -> Result<(), Error> {
+    check(
+        (expected == found).then(|| ()).ok_or(()),
+        message,
+        Field::SwapId,
+    )
+}
+
+// Reject the message if the tagged keys are not the ones listed by the blockchains, in order
+fn check_key_tags<Ar, Ac, A, B, C, D>(
+    extra_arbitrating_keys: &[TaggedElement<u16, A>],
+    arbitrating_shared_keys: &[TaggedElement<SharedKeyId, B>],
+    extra_accordant_keys: &[TaggedElement<u16, C>],
+    accordant_shared_keys: &[TaggedElement<SharedKeyId, D>],
+    message: MessageType,
+) -> Result<(), Error>
+where
+    Ar: DeriveKeys,
+    Ac: DeriveKeys,
+{
+    fn tags<T: Copy, E>(keys: &[TaggedElement<T, E>]) -> Vec<T> {
+        keys.iter().map(|key| *key.tag()).collect()
+    }
+    let valid = tags(extra_arbitrating_keys) == Ar::extra_public_keys()
+        && tags(arbitrating_shared_keys) == Ar::extra_shared_private_keys()
+        && tags(extra_accordant_keys) == Ac::extra_public_keys()
+        && tags(accordant_shared_keys) == Ac::extra_shared_private_keys();
+    check(valid.then(|| ()).ok_or(()), message, Field::ExtraKeys)
+}
+
+// Reject the message if one of the revealed keys is a key of the local participant
+fn check_keys<Pk, Qk>(
+    local: [&Pk; 3],
+    local_spend: &Qk,
+    revealed: [&Pk; 3],
+    revealed_spend: &Qk,
+    message: MessageType,
+) -> Result<(), Error>
+where
+    Pk: PartialEq,
+    Qk: PartialEq,
+{
+    let reused = local_spend == revealed_spend
+        || revealed
+            .iter()
+            .any(|key| local.iter().any(|local| local == key));
+    check((!reused).then(|| ()).ok_or(()), message, Field::Keys)
+}
+
+// The arbitrating parameters of the deal at the amount filled by the taker, a deal with a fill
+// range is only swapped at the amounts of a fill
+fn arbitrating_params<Amt, Bmt, Ti, F>(
+    deal: &Deal<Amt, Bmt, Ti, F>,
+    fill: Option<&DealFill<Amt, Bmt>>,
+    message: MessageType,
+) -> Result<ArbitratingParameters<Amt, Ti, F>, Error>
+where
+    Deal<Amt, Bmt, Ti, F>: Encodable,
+    Amt: AtomicAmount + Copy + PartialOrd,
+    Bmt: AtomicAmount + Copy + PartialEq,
+    Ti: Copy,
+    F: Copy,
+{
+    match fill {
+        Some(fill) => check(
+            deal.to_filled_arbitrating_params(fill),
+            message,
+            Field::ArbitratingAmount,
+        ),
+        None => check(
+            deal.fill_range
+                .is_none()
+                .then(|| deal.to_arbitrating_params())
+                .ok_or(()),
+            message,
+            Field::ArbitratingAmount,
+        ),
+    }
+}
+
+// Check the lock, cancel and refund transactions of every rung of the fee ladder against the deal
+// filled by the taker and the parameters of both participants
+fn check_core_transactions<'a, Ar, Amt, Bmt, Px, Pk, Qk, Rk, Sk, Addr, Ti, F, Pr, Ms, Si>(
+    deal: &Deal<Amt, Bmt, Ti, F>,
+    fill: Option<&DealFill<Amt, Bmt>>,
+    alice_parameters: &Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>,
+    bob_parameters: &Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>,
+    lock: &Px,
+    rungs: impl Iterator<Item = (&'a Px, &'a Px)>,
+    message: MessageType,
+) -> Result<(), Error>
+where
+    Ar: Transactions<Addr = Addr, Amt = Amt, Ti = Ti, Ms = Ms, Pk = Pk, Si = Si, Px = Px>,
+    Px: 'a + Clone + Fee<FeeUnit = F>,
+    Addr: Clone,
+    Deal<Amt, Bmt, Ti, F>: Encodable,
+    Amt: AtomicAmount + Copy + PartialOrd,
+    Bmt: AtomicAmount + Copy + PartialEq,
+    Pk: Copy,
+    Ti: Copy,
+    F: Copy,
+{
+    let arb_params = arbitrating_params(deal, fill, message)?;
+    let fee_strategy = &arb_params.fee_strategy;
+
+    let data_lock = DataLock {
+        timelock: arb_params.cancel_timelock,
+        success: DoubleKeys::new(alice_parameters.buy, bob_parameters.buy),
+        failure: DoubleKeys::new(alice_parameters.cancel, bob_parameters.cancel),
+    };
+    let lock = <Ar::Lock>::from_partial(lock.clone());
+    check(
+        lock.verify_target_amount(arb_params.arbitrating_amount),
+        message,
+        Field::ArbitratingAmount,
+    )?;
+    // The lock fee is paid by Bob's funding, only the transactions signed by Alice must follow
+    // the fee strategy
+    check(lock.verify_template(data_lock), message, Field::LockScript)?;
+
+    let punish_lock = DataPunishableLock {
+        timelock: arb_params.punish_timelock,
+        success: DoubleKeys::new(alice_parameters.refund, bob_parameters.refund),
+        failure: alice_parameters
+            .punish
+            .expect("Alice has a punish transaction"),
+    };
+    for (cancel, refund) in rungs {
+        let cancel = <Ar::Cancel>::from_partial(cancel.clone());
+        check(
+            cancel.is_build_on_top_of(&lock),
+            message,
+            Field::PreviousOutput(TxLabel::Cancel),
+        )?;
+        check(
+            cancel.verify_template(data_lock, punish_lock),
+            message,
+            Field::CancelTemplate,
+        )?;
+        check_fee(cancel.as_partial(), fee_strategy, message, TxLabel::Cancel)?;
+
+        let refund = <Ar::Refund>::from_partial(refund.clone());
+        check(
+            refund.is_build_on_top_of(&cancel),
+            message,
+            Field::PreviousOutput(TxLabel::Refund),
+        )?;
+        check(
+            refund.verify_template(bob_parameters.destination_address.clone()),
+            message,
+            Field::DestinationAddress(TxLabel::Refund),
+        )?;
+        check_fee(refund.as_partial(), fee_strategy, message, TxLabel::Refund)?;
+    }
+
+    Ok(())
+}
+
+impl<Addr, Ar, Ac> Alice<Addr, Ar, Ac>
+where
+    Addr: Clone,
+{
+    /// Validates Bob's [`CommitBobParameters`]: the commitment is for this swap and commits to the
+    /// extra and shared keys required by the blockchains of the deal.
+    pub fn validate_commit_bob_parameters<C>(
+        &self,
+        swap_id: SwapId,
+        commit: &CommitBobParameters<C>,
+    ) -> Res<()>
+    where
+        Ar: DeriveKeys,
+        Ac: DeriveKeys,
+    {
+        let message = MessageType::CommitBobParameters;
+        check_swap_id(swap_id, commit.swap_id, message)?;
+        check_key_tags::<Ar, Ac, _, _, _, _>(
+            &commit.extra_arbitrating_keys,
+            &commit.arbitrating_shared_keys,
+            &commit.extra_accordant_keys,
+            &commit.accordant_shared_keys,
+            message,
+        )?;
+        Ok(())
+    }
+
+    /// Validates Bob's [`RevealBobParameters`] against Alice's parameters: the parameters are for
+    /// this swap, carry the extra and shared keys required by the blockchains of the deal and do
+    /// not reuse Alice's keys.
+    pub fn validate_reveal_bob_parameters<Pk, Qk, Rk, Sk, Ti, F, Pr>(
+        &self,
+        swap_id: SwapId,
+        alice_parameters: &Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>,
+        reveal: &RevealBobParameters<Pk, Qk, Rk, Sk, Addr>,
+    ) -> Res<()>
+    where
+        Ar: DeriveKeys,
+        Ac: DeriveKeys,
+        Pk: PartialEq,
+        Qk: PartialEq,
+    {
+        let message = MessageType::RevealBobParameters;
+        check_swap_id(swap_id, reveal.swap_id, message)?;
+        check_key_tags::<Ar, Ac, _, _, _, _>(
+            &reveal.extra_arbitrating_keys,
+            &reveal.arbitrating_shared_keys,
+            &reveal.extra_accordant_keys,
+            &reveal.accordant_shared_keys,
+            message,
+        )?;
+        check_keys(
+            [
+                &alice_parameters.buy,
+                &alice_parameters.cancel,
+                &alice_parameters.refund,
+            ],
+            &alice_parameters.spend,
+            [&reveal.buy, &reveal.cancel, &reveal.refund],
+            &reveal.spend,
+            message,
+        )?;
+        Ok(())
+    }
+
+    /// Validates Bob's [`RevealProof`]: the proof is for this swap.
+    pub fn validate_reveal_proof<Pr>(&self, swap_id: SwapId, reveal: &RevealProof<Pr>) -> Res<()> {
+        check_swap_id(swap_id, reveal.swap_id, MessageType::RevealProof)?;
+        Ok(())
+    }
+
+    /// Validates Bob's [`CoreArbitratingSetup`] against the deal, the taker's fill if the deal
+    /// accepts partial fills, and the parameters: the lock amount and script, the cancel
+    /// template, the refund address and the fee rate of the cancel and refund transactions, for
+    /// the main transactions and every rung of the fee ladder.
+    pub fn validate_core_arbitrating_setup<Amt, Bmt, Px, Pk, Qk, Rk, Sk, Ti, F, Pr, Ms, Si>(
+        &self,
+        swap_id: SwapId,
+        deal: &Deal<Amt, Bmt, Ti, F>,
+        fill: Option<&DealFill<Amt, Bmt>>,
+        alice_parameters: &Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>,
+        bob_parameters: &Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>,
+        setup: &CoreArbitratingSetup<Px, Si>,
+    ) -> Res<()>
+    where
+        Ar: Transactions<Addr = Addr, Amt = Amt, Ti = Ti, Ms = Ms, Pk = Pk, Si = Si, Px = Px>,
+        Px: Clone + Fee<FeeUnit = F>,
+        Deal<Amt, Bmt, Ti, F>: Encodable,
+        Amt: AtomicAmount + Copy + PartialOrd,
+        Bmt: AtomicAmount + Copy + PartialEq,
+        Pk: Copy,
+        Ti: Copy,
+        F: Copy,
+    {
+        let message = MessageType::CoreArbitratingSetup;
+        check_swap_id(swap_id, setup.swap_id, message)?;
+        if setup.bump_cancel_sigs.len() != setup.bumps.len() {
+            return Err(Error::Diverged {
+                message,
+                field: Field::FeeBumps,
+            }
+            .into());
+        }
+        let rungs = iter::once((&setup.cancel, &setup.refund))
+            .chain(setup.bumps.iter().map(|bump| (&bump.cancel, &bump.refund)));
+        check_core_transactions::<Ar, _, _, _, _, _, _, _, _, _, _, _, _, _>(
+            deal,
+            fill,
+            alice_parameters,
+            bob_parameters,
+            &setup.lock,
+            rungs,
+            message,
+        )?;
+        Ok(())
+    }
+
+    /// Validates Bob's [`BuyProcedureSignature`] against the deal and the validated core
+    /// arbitrating transactions: the buy spends the lock, pays Alice destination address and
+    /// follows the fee strategy.
+    pub fn validate_buy_procedure_signature<Amt, Bmt, Px, Pk, Ti, F, Ms, Si, EncSig>(
+        &self,
+        swap_id: SwapId,
+        deal: &Deal<Amt, Bmt, Ti, F>,
+        core: &CoreArbitratingTransactions<Px>,
+        adaptor_buy: &BuyProcedureSignature<Px, EncSig>,
+    ) -> Res<()>
+    where
+        Ar: Transactions<Addr = Addr, Amt = Amt, Ti = Ti, Ms = Ms, Pk = Pk, Si = Si, Px = Px>,
+        Px: Clone + Fee<FeeUnit = F>,
+        Amt: Copy,
+        Ti: Copy,
+        F: Copy,
+    {
+        let message = MessageType::BuyProcedureSignature;
+        check_swap_id(swap_id, adaptor_buy.swap_id, message)?;
+        let fee_strategy = &deal.to_arbitrating_params().fee_strategy;
+
+        let lock = <Ar::Lock>::from_partial(core.lock.clone());
+        let buy = <Ar::Buy>::from_partial(adaptor_buy.buy.clone());
+        check(
+            buy.is_build_on_top_of(&lock),
+            message,
+            Field::PreviousOutput(TxLabel::Buy),
+        )?;
+        check(
+            buy.verify_template(self.destination_address.clone()),
+            message,
+            Field::DestinationAddress(TxLabel::Buy),
+        )?;
+        check_fee(buy.as_partial(), fee_strategy, message, TxLabel::Buy)?;
+
+        Ok(())
+    }
+}
+
+impl<Addr, Ar, Ac> Bob<Addr, Ar, Ac>
+where
+    Addr: Clone,
+{
+    /// Validates Alice's [`CommitAliceParameters`]: the commitment is for this swap and commits to
+    /// the extra and shared keys required by the blockchains of the deal.
+    pub fn validate_commit_alice_parameters<C>(
+        &self,
+        swap_id: SwapId,
+        commit: &CommitAliceParameters<C>,
+    ) -> Res<()>
+    where
+        Ar: DeriveKeys,
+        Ac: DeriveKeys,
+    {
+        let message = MessageType::CommitAliceParameters;
+        check_swap_id(swap_id, commit.swap_id, message)?;
+        check_key_tags::<Ar, Ac, _, _, _, _>(
+            &commit.extra_arbitrating_keys,
+            &commit.arbitrating_shared_keys,
+            &commit.extra_accordant_keys,
+            &commit.accordant_shared_keys,
+            message,
+        )?;
+        Ok(())
+    }
+
+    /// Validates Alice's [`RevealAliceParameters`] against Bob's parameters: the parameters are
+    /// for this swap, carry the extra and shared keys required by the blockchains of the deal and
+    /// do not reuse Bob's keys.
+    pub fn validate_reveal_alice_parameters<Pk, Qk, Rk, Sk, Ti, F, Pr>(
+        &self,
+        swap_id: SwapId,
+        bob_parameters: &Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>,
+        reveal: &RevealAliceParameters<Pk, Qk, Rk, Sk, Addr>,
+    ) -> Res<()>
+    where
+        Ar: DeriveKeys,
+        Ac: DeriveKeys,
+        Pk: PartialEq,
+        Qk: PartialEq,
+    {
+        let message = MessageType::RevealAliceParameters;
+        check_swap_id(swap_id, reveal.swap_id, message)?;
+        check_key_tags::<Ar, Ac, _, _, _, _>(
+            &reveal.extra_arbitrating_keys,
+            &reveal.arbitrating_shared_keys,
+            &reveal.extra_accordant_keys,
+            &reveal.accordant_shared_keys,
+            message,
+        )?;
+        check_keys(
+            [
+                &bob_parameters.buy,
+                &bob_parameters.cancel,
+                &bob_parameters.refund,
+            ],
+            &bob_parameters.spend,
+            [&reveal.buy, &reveal.cancel, &reveal.refund],
+            &reveal.spend,
+            message,
+        )?;
+        Ok(())
+    }
+
+    /// Validates Alice's [`RevealProof`]: the proof is for this swap.
+    pub fn validate_reveal_proof<Pr>(&self, swap_id: SwapId, reveal: &RevealProof<Pr>) -> Res<()> {
+        check_swap_id(swap_id, reveal.swap_id, MessageType::RevealProof)?;
+        Ok(())
+    }
+
+    /// Validates Alice's [`RefundProcedureSignatures`] against the deal, the taker's fill if the
+    /// deal accepts partial fills, and the parameters: Bob's core arbitrating transactions the
+    /// signatures commit to still follow the deal, the lock amount and script, the cancel
+    /// template, the refund address and the fee rates, and Alice signs one pair of fee bump
+    /// signatures per rung of the fee ladder.
+    #[allow(clippy::too_many_arguments)]
+    pub fn validate_refund_procedure_signatures<
+        Amt,
+        Bmt,
+        Px,
+        Pk,
+        Qk,
+        Rk,
+        Sk,
+        Ti,
+        F,
+        Pr,
+        Ms,
+        Si,
+        EncSig,
+    >(
+        &self,
+        swap_id: SwapId,
+        deal: &Deal<Amt, Bmt, Ti, F>,
+        fill: Option<&DealFill<Amt, Bmt>>,
+        alice_parameters: &Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>,
+        bob_parameters: &Parameters<Pk, Qk, Rk, Sk, Addr, Ti, F, Pr>,
+        core: &CoreArbitratingTransactions<Px>,
+        refund_procedure: &RefundProcedureSignatures<Si, EncSig>,
+    ) -> Res<()>
+    where
+        Ar: Transactions<Addr = Addr, Amt = Amt, Ti = Ti, Ms = Ms, Pk = Pk, Si = Si, Px = Px>,
+        Px: Clone + Fee<FeeUnit = F>,
+        Deal<Amt, Bmt, Ti, F>: Encodable,
+        Amt: AtomicAmount + Copy + PartialOrd,
+        Bmt: AtomicAmount + Copy + PartialEq,
+        Pk: Copy,
+        Ti: Copy,
+        F: Copy,
+    {
+        let message = MessageType::RefundProcedureSignatures;
+        check_swap_id(swap_id, refund_procedure.swap_id, message)?;
+        if refund_procedure.fee_bumps.len() != core.bumps.len() {
+            return Err(Error::Diverged {
+                message,
+                field: Field::FeeBumps,
+            }
+            .into());
+        }
+        let rungs = iter::once((&core.cancel, &core.refund))
+            .chain(core.bumps.iter().map(|bump| (&bump.cancel, &bump.refund)));
+        check_core_transactions::<Ar, _, _, _, _, _, _, _, _, _, _, _, _, _>(
+            deal,
+            fill,
+            alice_parameters,
+            bob_parameters,
+            &core.lock,
+            rungs,
+            message,
+        )?;
+        Ok(())
+    }
+}
@@ -17,20 +17,34 @@
 //! Concrete implementation of a swap between Bitcoin as the arbitrating blockchain and Monero as the
 //! accordant blockchain.
 
+#[cfg(all(feature = "experimental", feature = "taproot"))]
+use crate::bitcoin::taproot;
 use crate::bitcoin::{fee::SatPerVByte, timelock::CSVTimelock, BitcoinSegwitV0};
 use crate::consensus::{self, Decodable, Encodable};
+#[cfg(all(feature = "experimental", feature = "taproot"))]
+use crate::crypto::schnorr::{
+    Adaptor as SchnorrAdaptor, EncryptedSignature as SchnorrEncryptedSignature,
+};
 use crate::crypto::{
     self,
     slip10::{ChildNumber, DerivationPath, Ed25519ExtSecretKey, Secp256k1ExtSecretKey},
-    AccordantKeyId, ArbitratingKeyId, GenerateKey, GenerateSharedKey, ProveCrossGroupDleq,
-    SharedKeyId,
+    AccordantKeyId, ArbitratingKeyId, GenerateKey, GenerateSharedKey, KeccakCommitment,
+    ProveCrossGroupDleq, SharedKeyId,
+};
+#[cfg(all(feature = "experimental", feature = "taproot"))]
+use crate::crypto::{
+    musig2::{PartialSignature, PublicNonce, SecretNonce, Session},
+    MultiSign,
 };
 #[cfg(feature = "experimental")]
 use crate::crypto::{EncSign, RecoverSecret, Sign};
 use crate::monero::Monero;
 use crate::protocol;
 use crate::trade;
-use crate::{blockchain::Blockchain, crypto::dleq::DLEQProof};
+use crate::{
+    blockchain::{Blockchain, Network},
+    crypto::dleq::DLEQProof,
+};
 
 use monero::cryptonote::hash::Hash;
 
@@ -47,6 +61,8 @@ use rand::rngs::ThreadRng;
 use rand_chacha::ChaCha20Rng;
 #[cfg(feature = "experimental")]
 use secp256kfun::marker::*;
+#[cfg(all(feature = "experimental", feature = "taproot"))]
+use secp256kfun::XOnly;
 #[cfg(feature = "experimental")]
 use sha2::Sha256;
 
@@ -54,10 +70,18 @@ use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
 use bitcoin::util::psbt::PartiallySignedTransaction;
 #[cfg(feature = "experimental")]
 use bitcoin::{hashes::sha256d::Hash as Sha256dHash, secp256k1::Message};
+#[cfg(all(feature = "experimental", feature = "taproot"))]
+use bitcoin::{
+    secp256k1::{schnorr::Signature as SchnorrSignature, KeyPair, XOnlyPublicKey},
+    util::taproot::TapSighashHash,
+};
 
 use std::collections::HashMap;
 use std::str::FromStr;
 
+#[cfg(feature = "experimental")]
+#[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
+pub mod cost;
 pub mod message;
 
 #[cfg(feature = "experimental")]
@@ -84,6 +108,70 @@ pub type Alice = protocol::Alice<bitcoin::Address, BitcoinSegwitV0, Monero>;
 /// Fully defined type for Bitcoin-Monero atomic swap Bob protocol role.
 pub type Bob = protocol::Bob<bitcoin::Address, BitcoinSegwitV0, Monero>;
 
+/// Fully defined type for Bitcoin-Monero atomic swap Alice state machine.
+pub type AliceSwap = protocol::machine::AliceSwap<
+    bitcoin::Address,
+    BitcoinSegwitV0,
+    Monero,
+    bitcoin::Amount,
+    monero::Amount,
+    CSVTimelock,
+    SatPerVByte,
+    PublicKey,
+    monero::PublicKey,
+    SecretKey,
+    monero::PrivateKey,
+    DLEQProof,
+    KeccakCommitment,
+    PartiallySignedTransaction,
+    Signature,
+    EncryptedSignature,
+>;
+
+/// Fully defined type for Bitcoin-Monero atomic swap Bob state machine.
+pub type BobSwap = protocol::machine::BobSwap<
+    bitcoin::Address,
+    BitcoinSegwitV0,
+    Monero,
+    bitcoin::Amount,
+    monero::Amount,
+    CSVTimelock,
+    SatPerVByte,
+    PublicKey,
+    monero::PublicKey,
+    SecretKey,
+    monero::PrivateKey,
+    DLEQProof,
+    KeccakCommitment,
+    PartiallySignedTransaction,
+    Signature,
+    EncryptedSignature,
+>;
+
+/// Fully defined type for Bitcoin-Monero atomic swap Alice checkpoint.
+pub type AliceCheckpoint = protocol::checkpoint::Checkpoint<AliceSwap, KeyManager>;
+
+/// Fully defined type for Bitcoin-Monero atomic swap Bob checkpoint.
+pub type BobCheckpoint = protocol::checkpoint::Checkpoint<BobSwap, KeyManager>;
+
+/// Fully defined type for Bitcoin-Monero atomic swap state machine outputs.
+pub type SwapOutput = protocol::machine::Output<
+    KeccakCommitment,
+    PublicKey,
+    monero::PublicKey,
+    SecretKey,
+    monero::PrivateKey,
+    bitcoin::Address,
+    DLEQProof,
+    PartiallySignedTransaction,
+    Signature,
+    EncryptedSignature,
+    crypto::musig2::PublicNonce,
+    bitcoin::Amount,
+    monero::Amount,
+    bitcoin::Transaction,
+>;
+
 /// Fully defined type for Bitcoin-Monero atomic swap trade.
 pub type DealParameters =
     trade::DealParameters<bitcoin::Amount, monero::Amount, CSVTimelock, SatPerVByte>;
@@ -91,6 +179,40 @@ pub type DealParameters =
 /// Fully defined type for Bitcoin-Monero atomic swap public trade.
 pub type Deal = trade::Deal<bitcoin::Amount, monero::Amount, CSVTimelock, SatPerVByte>;
 
+/// Fully defined type for Bitcoin-Monero atomic swap signed public trade.
+pub type SignedDeal = trade::SignedDeal<bitcoin::Amount, monero::Amount, CSVTimelock, SatPerVByte>;
+
+/// Fully defined type for Bitcoin-Monero atomic swap taker fill of a partial deal.
+pub type DealFill = trade::DealFill<bitcoin::Amount, monero::Amount>;
+
+/// Fully defined type for Bitcoin-Monero atomic swap deal book.
+pub type DealBook = trade::DealBook<bitcoin::Amount, monero::Amount, CSVTimelock, SatPerVByte>;
+
+/// Fully defined type for Bitcoin-Monero atomic swap deal policy.
+pub type DealPolicy = trade::DealPolicy<bitcoin::Amount, monero::Amount, CSVTimelock, SatPerVByte>;
+
+/// Fully defined type for Bitcoin-Monero atomic swap deal validator.
+pub type DealValidator =
+    trade::DealValidator<bitcoin::Amount, monero::Amount, CSVTimelock, SatPerVByte>;
+
+impl Default for DealPolicy {
+    /// Accept Bitcoin-Monero deals on any network with a Bitcoin amount above the dust limit of
+    /// the lock output, a fee rate of at least 1 sat/vB, and at least 6 blocks timelocks on
+    /// mainnet.
+    fn default() -> Self {
+        let dust = bitcoin::Script::new_v0_p2wsh(&Default::default()).dust_value();
+        let min_timelock = [(Network::Mainnet, CSVTimelock::from_blocks(6))];
+        Self {
+            min_arbitrating_amount: Some(dust),
+            min_accordant_amount: Some(monero::Amount::from_pico(1)),
+            min_cancel_timelock: min_timelock.into_iter().collect(),
+            min_punish_timelock: min_timelock.into_iter().collect(),
+            min_fee_rate: Some(SatPerVByte::from_sat(1)),
+            ..Self::new(Blockchain::Bitcoin, Blockchain::Monero)
+        }
+    }
+}
+
 /// Fully defined type for Bitcoin-Monero atomic swap arbitrating parameters.
 pub type ArbitratingParameters =
     protocol::ArbitratingParameters<bitcoin::Amount, CSVTimelock, SatPerVByte>;
@@ -186,7 +308,7 @@ impl Derivation for SharedKeyId {
 
 /// Manager responsible for handling key operations (secret and public). Implements traits for
 /// handling [`GenerateKey`], [`GenerateSharedKey`] and [`Sign`].
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub struct KeyManager {
     /// The master 32-bytes seed used to derive all the keys for all the swaps.
     master_seed: [u8; 32],
@@ -200,6 +322,26 @@ pub struct KeyManager {
     bitcoin_derivations: HashMap<DerivationPath, SecretKey>,
     /// A list of already derived monero keys for ed25519 by derivation path.
     monero_derivations: HashMap<DerivationPath, monero::PrivateKey>,
+    /// A list of generated and not yet used MuSig2 secret nonces by public nonce.
+    #[cfg(all(feature = "experimental", feature = "taproot"))]
+    musig2_nonces: HashMap<PublicNonce, SecretNonce>,
+}
+
+// Secret nonces are never duplicated, a cloned key manager has no pending MuSig2 nonces like a
+// decoded one.
+impl Clone for KeyManager {
+    fn clone(&self) -> Self {
+        Self {
+            master_seed: self.master_seed,
+            swap_index: self.swap_index,
+            bitcoin_master_key: self.bitcoin_master_key,
+            monero_master_key: self.monero_master_key,
+            bitcoin_derivations: self.bitcoin_derivations.clone(),
+            monero_derivations: self.monero_derivations.clone(),
+            #[cfg(all(feature = "experimental", feature = "taproot"))]
+            musig2_nonces: HashMap::new(),
+        }
+    }
 }
 
 impl Encodable for KeyManager {
@@ -222,6 +364,8 @@ impl Decodable for KeyManager {
             monero_master_key: Ed25519ExtSecretKey::new_master(master_seed.as_ref()),
             bitcoin_derivations: HashMap::new(),
             monero_derivations: HashMap::new(),
+            #[cfg(all(feature = "experimental", feature = "taproot"))]
+            musig2_nonces: HashMap::new(),
         })
     }
 }
@@ -311,6 +455,8 @@ impl KeyManager {
             monero_master_key: Ed25519ExtSecretKey::new_master(seed.as_ref()),
             bitcoin_derivations: HashMap::new(),
             monero_derivations: HashMap::new(),
+            #[cfg(all(feature = "experimental", feature = "taproot"))]
+            musig2_nonces: HashMap::new(),
         })
     }
 }
@@ -492,7 +638,7 @@ impl RecoverSecret<PublicKey, SecretKey, Signature, EncryptedSignature> for KeyM
         encrypted_sig: EncryptedSignature,
         encryption_key: &PublicKey,
         sig: Signature,
-    ) -> SecretKey {
+    ) -> Result<SecretKey, crypto::Error> {
         let adaptor = Adaptor::<Transcript, NonceGen>::default();
         // FIXME
         // let encryption_key = Point::from(*encryption_key);
@@ -503,14 +649,261 @@ impl RecoverSecret<PublicKey, SecretKey, Signature, EncryptedSignature> for KeyM
         match adaptor.recover_decryption_key(&encryption_key, &signature, &encrypted_sig) {
             // FIXME
             // Some(decryption_key) => decryption_key.into(),
-            Some(decryption_key) => {
-                SecretKey::from_slice(decryption_key.to_bytes().as_ref()).unwrap()
-            }
-            None => panic!("signature is not the decryption of our original encrypted signature"),
+            Some(decryption_key) => SecretKey::from_slice(decryption_key.to_bytes().as_ref())
+                .map_err(crypto::Error::new),
+            None => Err(crypto::Error::InvalidSignature),
         }
     }
 }
 
+#[cfg(all(feature = "experimental", feature = "taproot"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "experimental", feature = "taproot"))))]
+impl GenerateKey<XOnlyPublicKey, ArbitratingKeyId> for KeyManager {
+    fn get_pubkey(&mut self, key_id: ArbitratingKeyId) -> Result<XOnlyPublicKey, crypto::Error> {
+        let secp = Secp256k1::new();
+        let secret_key = self.get_or_derive_bitcoin_key(key_id)?;
+
+        Ok(XOnlyPublicKey::from_keypair(&KeyPair::from_secret_key(
+            &secp, secret_key,
+        )))
+    }
+}
+
+#[cfg(all(feature = "experimental", feature = "taproot"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "experimental", feature = "taproot"))))]
+impl Sign<XOnlyPublicKey, TapSighashHash, SchnorrSignature> for KeyManager {
+    fn sign(
+        &mut self,
+        key: ArbitratingKeyId,
+        msg: TapSighashHash,
+    ) -> Result<SchnorrSignature, crypto::Error> {
+        let secp = Secp256k1::new();
+        let secret_key = self.get_or_derive_bitcoin_key(key)?;
+        let keypair = KeyPair::from_secret_key(&secp, secret_key);
+
+        taproot::sign_hash(msg, &keypair).map_err(crypto::Error::new)
+    }
+
+    fn verify_signature(
+        &self,
+        key: &XOnlyPublicKey,
+        msg: TapSighashHash,
+        sig: &SchnorrSignature,
+    ) -> Result<(), crypto::Error> {
+        let secp = Secp256k1::verification_only();
+        let message = Message::from_slice(&msg).expect("Hash is always ok");
+        secp.verify_schnorr(sig, &message, key)
+            .map_err(crypto::Error::new)
+    }
+}
+
+#[cfg(all(feature = "experimental", feature = "taproot"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "experimental", feature = "taproot"))))]
+impl EncSign<XOnlyPublicKey, TapSighashHash, SchnorrSignature, SchnorrEncryptedSignature>
+    for KeyManager
+{
+    fn encrypt_sign(
+        &mut self,
+        signing_key: ArbitratingKeyId,
+        encryption_key: &XOnlyPublicKey,
+        msg: TapSighashHash,
+    ) -> Result<SchnorrEncryptedSignature, crypto::Error> {
+        let secret_key = self.get_or_derive_bitcoin_key(signing_key)?;
+
+        let engine = SchnorrAdaptor::new(NonceGen::default());
+        let secret_signing_key = Scalar::from_slice(&secret_key[..])
+            .unwrap()
+            .mark::<NonZero>()
+            .expect("SecretKey is never zero");
+        let encryption_key = XOnly::from_bytes(encryption_key.serialize())
+            .ok_or(crypto::Error::InvalidAdaptorKey)?;
+        let message_hash: &[u8; 32] = {
+            use bitcoin::hashes::Hash;
+            msg.as_inner()
+        };
+
+        Ok(engine.encrypted_sign(&secret_signing_key, &encryption_key, message_hash))
+    }
+
+    fn verify_encrypted_signature(
+        &self,
+        signing_key: &XOnlyPublicKey,
+        encryption_key: &XOnlyPublicKey,
+        msg: TapSighashHash,
+        sig: &SchnorrEncryptedSignature,
+    ) -> Result<(), crypto::Error> {
+        let engine = SchnorrAdaptor::new(NonceGen::default());
+        let verification_key =
+            XOnly::from_bytes(signing_key.serialize()).ok_or(crypto::Error::InvalidAdaptorKey)?;
+        let encryption_key = XOnly::from_bytes(encryption_key.serialize())
+            .ok_or(crypto::Error::InvalidAdaptorKey)?;
+        let message_hash: &[u8; 32] = {
+            use bitcoin::hashes::Hash;
+            msg.as_inner()
+        };
+
+        match engine.verify_encrypted_signature(
+            &verification_key,
+            &encryption_key,
+            message_hash,
+            sig,
+        ) {
+            true => Ok(()),
+            false => Err(crypto::Error::InvalidEncryptedSignature),
+        }
+    }
+
+    fn decrypt_signature(
+        &mut self,
+        decryption_key: AccordantKeyId,
+        sig: SchnorrEncryptedSignature,
+    ) -> Result<SchnorrSignature, crypto::Error> {
+        let secret_key = match decryption_key {
+            AccordantKeyId::Spend => self.get_or_derive_monero_spend_key()?,
+            _ => return Err(crypto::Error::UnsupportedKey),
+        };
+        let mut little_endian_secret_bytes = secret_key.to_bytes();
+        little_endian_secret_bytes.reverse();
+        let secret_key = SecretKey::from_slice(little_endian_secret_bytes.as_ref())
+            .map_err(crypto::Error::new)?;
+
+        let adaptor = SchnorrAdaptor::new(NonceGen::default());
+        let decryption_key = Scalar::from_slice(&secret_key[..])
+            .unwrap()
+            .mark::<NonZero>()
+            .expect("SecretKey is never zero");
+
+        Ok(adaptor.decrypt_signature(&decryption_key, sig))
+    }
+}
+
+/// The decryption key recovered from the x-only encryption key is the one of its even `y` lift,
+/// i.e. the negation of the secret for about half of the encryption keys. The encryption secret is
+/// the Monero spend secret, a scalar lower than the ed25519 group order, while its negation on
+/// secp256k1 is always above it: the recovered key is normalized to the one that is a valid Monero
+/// scalar.
+#[cfg(all(feature = "experimental", feature = "taproot"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "experimental", feature = "taproot"))))]
+impl RecoverSecret<XOnlyPublicKey, SecretKey, SchnorrSignature, SchnorrEncryptedSignature>
+    for KeyManager
+{
+    fn recover_secret_key(
+        &self,
+        encrypted_sig: SchnorrEncryptedSignature,
+        encryption_key: &XOnlyPublicKey,
+        sig: SchnorrSignature,
+    ) -> Result<SecretKey, crypto::Error> {
+        let adaptor = SchnorrAdaptor::new(NonceGen::default());
+        let encryption_key = XOnly::from_bytes(encryption_key.serialize())
+            .ok_or(crypto::Error::InvalidAdaptorKey)?;
+
+        let mut secret_key = adaptor
+            .recover_decryption_key(&encryption_key, &sig, &encrypted_sig)
+            .map(|decryption_key| SecretKey::from_slice(decryption_key.to_bytes().as_ref()))
+            .ok_or(crypto::Error::InvalidSignature)?
+            .map_err(crypto::Error::new)?;
+
+        if !is_monero_scalar(&secret_key) {
+            secret_key.negate_assign();
+        }
+        match is_monero_scalar(&secret_key) {
+            true => Ok(secret_key),
+            false => Err(crypto::Error::InvalidAdaptorKey),
+        }
+    }
+}
+
+// Returns true if the big-endian secp256k1 secret key is a canonical ed25519 scalar.
+#[cfg(all(feature = "experimental", feature = "taproot"))]
+fn is_monero_scalar(secret_key: &SecretKey) -> bool {
+    let mut little_endian_secret_bytes = [0u8; 32];
+    little_endian_secret_bytes.copy_from_slice(&secret_key[..]);
+    little_endian_secret_bytes.reverse();
+    curve25519_dalek::scalar::Scalar::from_canonical_bytes(little_endian_secret_bytes).is_some()
+}
+
+#[cfg(all(feature = "experimental", feature = "taproot"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "experimental", feature = "taproot"))))]
+impl
+    MultiSign<
+        XOnlyPublicKey,
+        PublicNonce,
+        Session,
+        PartialSignature,
+        SchnorrSignature,
+        SchnorrEncryptedSignature,
+    > for KeyManager
+{
+    fn generate_nonce(&mut self, key: ArbitratingKeyId) -> Result<PublicNonce, crypto::Error> {
+        let secret_key = self.get_or_derive_bitcoin_key(key)?;
+        let secret_key = Scalar::from_slice(&secret_key[..])
+            .unwrap()
+            .mark::<NonZero>()
+            .expect("SecretKey is never zero");
+
+        let secret_nonce = SecretNonce::generate(&NonceGen::default(), &secret_key);
+        let public_nonce = secret_nonce.public_nonce();
+        self.musig2_nonces.insert(public_nonce, secret_nonce);
+        Ok(public_nonce)
+    }
+
+    fn partial_sign(
+        &mut self,
+        key: ArbitratingKeyId,
+        session: &Session,
+    ) -> Result<PartialSignature, crypto::Error> {
+        let secret_key = self.get_or_derive_bitcoin_key(key)?;
+        let secret_key = Scalar::from_slice(&secret_key[..])
+            .unwrap()
+            .mark::<NonZero>()
+            .expect("SecretKey is never zero");
+        // Secret nonces are removed before signing so they can never be reused
+        let secret_nonce = session
+            .nonces()
+            .iter()
+            .find_map(|nonce| self.musig2_nonces.remove(nonce))
+            .ok_or(crypto::Error::MissingNonce)?;
+
+        session
+            .partial_sign(&secret_key, secret_nonce)
+            .ok_or(crypto::Error::UnsupportedKey)
+    }
+
+    fn verify_partial_signature(
+        &self,
+        key: &XOnlyPublicKey,
+        nonce: &PublicNonce,
+        session: &Session,
+        sig: &PartialSignature,
+    ) -> Result<(), crypto::Error> {
+        let key = XOnly::from_bytes(key.serialize()).ok_or(crypto::Error::MissingKey)?;
+        match session.verify_partial_signature(&key, nonce, sig) {
+            true => Ok(()),
+            false => Err(crypto::Error::InvalidPartialSignature),
+        }
+    }
+
+    fn aggregate_signature(
+        &self,
+        session: &Session,
+        sigs: &[PartialSignature],
+    ) -> Result<SchnorrSignature, crypto::Error> {
+        session
+            .aggregate(sigs)
+            .ok_or(crypto::Error::InvalidSignature)
+    }
+
+    fn aggregate_encrypted_signature(
+        &self,
+        session: &Session,
+        sigs: &[PartialSignature],
+    ) -> Result<SchnorrEncryptedSignature, crypto::Error> {
+        session
+            .aggregate_encrypted(sigs)
+            .ok_or(crypto::Error::InvalidEncryptedSignature)
+    }
+}
+
 #[cfg(feature = "experimental")]
 #[cfg_attr(docsrs, doc(cfg(feature = "experimental")))]
 impl ProveCrossGroupDleq<PublicKey, monero::PublicKey, DLEQProof> for KeyManager {
@@ -561,3 +954,96 @@ fn test_keymanager_consensus_encoding() {
     key_manager.consensus_encode(&mut encoder).unwrap();
     KeyManager::consensus_decode(&mut std::io::Cursor::new(encoder)).unwrap();
 }
+
+#[cfg(all(feature = "experimental", feature = "taproot"))]
+#[test]
+fn test_keymanager_schnorr_adaptor_signatures() {
+    let mut alice = KeyManager::new([1; 32], 1).unwrap();
+    let mut bob = KeyManager::new([2; 32], 1).unwrap();
+    let msg = {
+        use bitcoin::hashes::Hash;
+        TapSighashHash::hash(b"farcaster")
+    };
+
+    let signing_key: XOnlyPublicKey = alice.get_pubkey(ArbitratingKeyId::Buy).unwrap();
+    let sig = alice.sign(ArbitratingKeyId::Buy, msg).unwrap();
+    assert!(alice.verify_signature(&signing_key, msg, &sig).is_ok());
+
+    let encryption_key =
+        XOnlyPublicKey::from_slice(&bob.get_encryption_key().unwrap().serialize()[1..]).unwrap();
+    let enc_sig = alice
+        .encrypt_sign(ArbitratingKeyId::Buy, &encryption_key, msg)
+        .unwrap();
+    assert!(bob
+        .verify_encrypted_signature(&signing_key, &encryption_key, msg, &enc_sig)
+        .is_ok());
+    assert!(bob
+        .verify_encrypted_signature(&encryption_key, &encryption_key, msg, &enc_sig)
+        .is_err());
+
+    let sig = bob
+        .decrypt_signature(AccordantKeyId::Spend, enc_sig.clone())
+        .unwrap();
+    assert!(alice.verify_signature(&signing_key, msg, &sig).is_ok());
+
+    let secp = Secp256k1::new();
+    let secret = alice
+        .recover_secret_key(enc_sig.clone(), &encryption_key, sig)
+        .unwrap();
+    assert_eq!(
+        PublicKey::from_secret_key(&secp, &secret),
+        bob.get_encryption_key().unwrap()
+    );
+
+    // A signature that is not the decryption of the encrypted signature is rejected
+    let other_sig = alice.sign(ArbitratingKeyId::Buy, msg).unwrap();
+    assert!(alice
+        .recover_secret_key(enc_sig, &encryption_key, other_sig)
+        .is_err());
+}
+
+#[cfg(all(feature = "experimental", feature = "taproot"))]
+#[test]
+fn test_keymanager_schnorr_recover_secret_for_both_parities() {
+    let secp = Secp256k1::new();
+    let mut alice = KeyManager::new([1; 32], 1).unwrap();
+    let msg = {
+        use bitcoin::hashes::Hash;
+        TapSighashHash::hash(b"farcaster")
+    };
+
+    let mut parities = std::collections::HashSet::new();
+    for seed in 2..=u8::MAX {
+        let mut bob = KeyManager::new([seed; 32], 1).unwrap();
+        let full_encryption_key = bob.get_encryption_key().unwrap();
+        // The first byte of the compressed key is the parity of `y`
+        let serialized_key = full_encryption_key.serialize();
+        let (parity, x) = serialized_key.split_at(1);
+        let encryption_key = XOnlyPublicKey::from_slice(x).unwrap();
+
+        let enc_sig = alice
+            .encrypt_sign(ArbitratingKeyId::Buy, &encryption_key, msg)
+            .unwrap();
+        let sig = bob
+            .decrypt_signature(AccordantKeyId::Spend, enc_sig.clone())
+            .unwrap();
+        let secret = alice
+            .recover_secret_key(enc_sig, &encryption_key, sig)
+            .unwrap();
+
+        // The recovered secret is the original one, whatever the parity of the encryption key
+        let mut spend_bytes = bob.get_or_derive_monero_spend_key().unwrap().to_bytes();
+        spend_bytes.reverse();
+        assert_eq!(secret[..], spend_bytes[..]);
+        assert_eq!(
+            PublicKey::from_secret_key(&secp, &secret),
+            full_encryption_key
+        );
+
+        parities.insert(parity[0]);
+        if parities.len() == 2 {
+            return;
+        }
+    }
+    panic!("encryption keys of both parities must be tested");
+}
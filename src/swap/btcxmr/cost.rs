@@ -0,0 +1,352 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Estimation of the on-chain costs of a Bitcoin-Monero swap before accepting a deal.
+//!
+//! The Bitcoin fees are computed on the SegWit v0 transaction templates built for the deal, with
+//! the fee strategy of the deal and a [`FeePriority`], so they match the fees set on the
+//! transactions signed during the swap. The Monero fees are estimated from the typical weight of
+//! the lock and sweep transactions and a fee per byte given by the caller, e.g. from the daemon
+//! fee estimate.
+//!
+//! Each outcome of the swap is a [`SwapPath`], the costs of each path are attributed to the
+//! participant paying them:
+//!
+//! - [`SwapPath::Buy`]: Bob pays the lock, Alice pays the buy and the Monero lock, Bob pays the
+//!   Monero sweep
+//! - [`SwapPath::Refund`]: Bob pays the lock, the cancel and the refund, Alice pays the Monero
+//!   lock and sweep
+//! - [`SwapPath::Punish`]: Bob pays the lock, Alice pays the cancel, the punish and the Monero
+//!   lock
+
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use bitcoin::util::psbt::PartiallySignedTransaction;
+use bitcoin::Amount;
+
+use crate::bitcoin::fee::{estimate_vsize, SatPerVByte};
+use crate::bitcoin::segwitv0::{BuyTx, CancelTx, FundingTx, LockTx, PunishTx, RefundTx};
+use crate::blockchain::{Fee, FeePriority, FeeStrategy, FeeStrategyError};
+use crate::role::SwapRole;
+use crate::script::{DataLock, DataPunishableLock, DoubleKeys};
+use crate::swap::btcxmr::Deal;
+use crate::transaction::{
+    Buyable, Cancelable, Fundable, Lockable, Punishable, Refundable, Transaction, TxLabel,
+};
+use crate::Res;
+
+/// Estimated weight of the Monero lock transaction, with two inputs and two outputs.
+pub const MONERO_LOCK_TX_WEIGHT: u64 = 2_000;
+
+/// Estimated weight of the Monero sweep transaction, with one input and one output padded to two.
+pub const MONERO_SWEEP_TX_WEIGHT: u64 = 1_500;
+
+/// An outcome of the swap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+#[display(Debug)]
+pub enum SwapPath {
+    /// The swap succeeds with the lock and the buy.
+    Buy,
+    /// The swap is cancelled and Bob is refunded.
+    Refund,
+    /// The swap is cancelled and Alice punishes Bob.
+    Punish,
+}
+
+/// Fees paid by one participant on a path of the swap.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FeeBreakdown {
+    /// The Bitcoin transactions paid by the participant and their fee.
+    pub bitcoin: Vec<(TxLabel, Amount)>,
+    /// The Monero lock transaction fee, if paid by the participant.
+    pub monero_lock: Option<monero::Amount>,
+    /// The Monero sweep transaction fee, if paid by the participant.
+    pub monero_sweep: Option<monero::Amount>,
+}
+
+impl FeeBreakdown {
+    /// Return the sum of the Bitcoin fees.
+    pub fn bitcoin_total(&self) -> Amount {
+        self.bitcoin.iter().map(|(_, fee)| *fee).sum()
+    }
+
+    /// Return the sum of the Monero fees.
+    pub fn monero_total(&self) -> monero::Amount {
+        monero::Amount::from_pico(
+            self.monero_lock.map_or(0, |fee| fee.as_pico())
+                + self.monero_sweep.map_or(0, |fee| fee.as_pico()),
+        )
+    }
+}
+
+/// Fees paid by Alice and Bob on a path of the swap.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PathCosts {
+    /// The fees paid by Alice.
+    pub alice: FeeBreakdown,
+    /// The fees paid by Bob.
+    pub bob: FeeBreakdown,
+}
+
+impl PathCosts {
+    /// Return the fees paid by the swap role.
+    pub fn for_role(&self, swap_role: SwapRole) -> &FeeBreakdown {
+        match swap_role {
+            SwapRole::Alice => &self.alice,
+            SwapRole::Bob => &self.bob,
+        }
+    }
+}
+
+/// Fees paid by Alice and Bob on each path of the swap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwapCosts {
+    /// The fees of the buy path.
+    pub buy: PathCosts,
+    /// The fees of the refund path.
+    pub refund: PathCosts,
+    /// The fees of the punish path.
+    pub punish: PathCosts,
+}
+
+impl SwapCosts {
+    /// Return the fees of the path.
+    pub fn path(&self, path: SwapPath) -> &PathCosts {
+        match path {
+            SwapPath::Buy => &self.buy,
+            SwapPath::Refund => &self.refund,
+            SwapPath::Punish => &self.punish,
+        }
+    }
+}
+
+/// Estimate the on-chain costs of a swap for a deal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapCostEstimator {
+    monero_fee_per_byte: monero::Amount,
+}
+
+impl SwapCostEstimator {
+    /// Create an estimator with the Monero fee per byte of weight.
+    pub fn new(monero_fee_per_byte: monero::Amount) -> Self {
+        Self {
+            monero_fee_per_byte,
+        }
+    }
+
+    /// Return the fees of each path of the swap on the deal, the fee rate is selected in the deal
+    /// fee strategy with the priority.
+    pub fn estimate(&self, deal: &Deal, priority: FeePriority) -> Res<SwapCosts> {
+        let params = &deal.parameters;
+        let strategy = &params.fee_strategy;
+        let bitcoin_network = params.network.into();
+
+        // The fees only depend on the size of the templates, any key gives the same scripts size
+        let secp = Secp256k1::new();
+        let key =
+            |byte| PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[byte; 32]).unwrap());
+        let (funding_key, alice, bob) = (key(1), key(2), key(3));
+        let destination =
+            bitcoin::Address::p2wpkh(&bitcoin::PublicKey::new(funding_key), bitcoin_network)
+                .expect("Compressed public key");
+        let lock = DataLock {
+            timelock: params.cancel_timelock,
+            success: DoubleKeys::new(alice, bob),
+            failure: DoubleKeys::new(alice, bob),
+        };
+        let punish_lock = DataPunishableLock {
+            timelock: params.punish_timelock,
+            success: DoubleKeys::new(alice, bob),
+            failure: alice,
+        };
+
+        let mut funding = FundingTx::initialize(funding_key, params.network)?;
+        let address = funding.get_address()?;
+        funding.update(bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![bitcoin::TxOut {
+                value: params.arbitrating_amount.as_sat(),
+                script_pubkey: address.script_pubkey(),
+            }],
+        })?;
+
+        // Bob funds the lock with the arbitrating amount and the lock fee
+        let lock_tx = LockTx::initialize(&funding, lock, params.arbitrating_amount)?;
+        let lock_fee = fee_rate(strategy, priority)
+            .as_native_unit()
+            .checked_mul(estimate_vsize(lock_tx.as_partial())?)
+            .ok_or(FeeStrategyError::AmountOfFeeTooHigh)?;
+
+        let mut buy = BuyTx::initialize(&lock_tx, lock, destination.clone())?;
+        let buy_fee = set_fee(buy.as_partial_mut(), strategy, priority)?;
+        let mut cancel = CancelTx::initialize(&lock_tx, lock, punish_lock)?;
+        let cancel_fee = set_fee(cancel.as_partial_mut(), strategy, priority)?;
+        let mut refund = RefundTx::initialize(&cancel, destination.clone())?;
+        let refund_fee = set_fee(refund.as_partial_mut(), strategy, priority)?;
+        let mut punish = PunishTx::initialize(&cancel, punish_lock, destination)?;
+        let punish_fee = set_fee(punish.as_partial_mut(), strategy, priority)?;
+
+        let monero_lock = Some(self.monero_fee(MONERO_LOCK_TX_WEIGHT));
+        let monero_sweep = Some(self.monero_fee(MONERO_SWEEP_TX_WEIGHT));
+        let bob_lock = FeeBreakdown {
+            bitcoin: vec![(TxLabel::Lock, lock_fee)],
+            ..Default::default()
+        };
+
+        Ok(SwapCosts {
+            buy: PathCosts {
+                alice: FeeBreakdown {
+                    bitcoin: vec![(TxLabel::Buy, buy_fee)],
+                    monero_lock,
+                    monero_sweep: None,
+                },
+                bob: FeeBreakdown {
+                    monero_sweep,
+                    ..bob_lock.clone()
+                },
+            },
+            refund: PathCosts {
+                alice: FeeBreakdown {
+                    bitcoin: vec![],
+                    monero_lock,
+                    monero_sweep,
+                },
+                bob: FeeBreakdown {
+                    bitcoin: vec![
+                        (TxLabel::Lock, lock_fee),
+                        (TxLabel::Cancel, cancel_fee),
+                        (TxLabel::Refund, refund_fee),
+                    ],
+                    ..Default::default()
+                },
+            },
+            punish: PathCosts {
+                alice: FeeBreakdown {
+                    bitcoin: vec![(TxLabel::Cancel, cancel_fee), (TxLabel::Punish, punish_fee)],
+                    monero_lock,
+                    monero_sweep: None,
+                },
+                bob: bob_lock,
+            },
+        })
+    }
+
+    fn monero_fee(&self, weight: u64) -> monero::Amount {
+        monero::Amount::from_pico(self.monero_fee_per_byte.as_pico().saturating_mul(weight))
+    }
+}
+
+// Return the fee rate applied by the strategy with the priority
+fn fee_rate(strategy: &FeeStrategy<SatPerVByte>, priority: FeePriority) -> SatPerVByte {
+    match (strategy, priority) {
+        (FeeStrategy::Fixed(rate), _) => *rate,
+        (FeeStrategy::Range { min_inc, .. }, FeePriority::Low) => *min_inc,
+        (FeeStrategy::Range { max_inc, .. }, FeePriority::High) => *max_inc,
+    }
+}
+
+fn set_fee(
+    psbt: &mut PartiallySignedTransaction,
+    strategy: &FeeStrategy<SatPerVByte>,
+    priority: FeePriority,
+) -> Res<Amount> {
+    Ok(psbt.set_fee(strategy, priority)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitcoin::timelock::CSVTimelock;
+    use crate::blockchain::{Blockchain, Network};
+    use crate::swap::btcxmr::DealParameters;
+
+    use inet2_addr::InetSocketAddr;
+    use std::str::FromStr;
+    use uuid::uuid;
+
+    fn deal(fee_strategy: FeeStrategy<SatPerVByte>) -> Deal {
+        DealParameters {
+            uuid: uuid!("67e55044-10b1-426f-9247-bb680e5fe0c8"),
+            network: Network::Testnet,
+            arbitrating_blockchain: Blockchain::Bitcoin,
+            accordant_blockchain: Blockchain::Monero,
+            arbitrating_amount: Amount::from_sat(1_000_000),
+            accordant_amount: monero::Amount::from_pico(1_000_000_000_000),
+            cancel_timelock: CSVTimelock::new(10),
+            punish_timelock: CSVTimelock::new(20),
+            fee_strategy,
+            maker_role: SwapRole::Bob,
+        }
+        .to_v1(
+            PublicKey::from_secret_key(
+                &Secp256k1::new(),
+                &SecretKey::from_slice(&[1; 32]).unwrap(),
+            ),
+            InetSocketAddr::socket(
+                FromStr::from_str("1.2.3.4").unwrap(),
+                FromStr::from_str("9735").unwrap(),
+            ),
+        )
+    }
+
+    #[test]
+    fn estimate_costs_per_path_and_role() {
+        let estimator = SwapCostEstimator::new(monero::Amount::from_pico(20_000));
+        let costs = estimator
+            .estimate(
+                &deal(FeeStrategy::Fixed(SatPerVByte::from_sat(10))),
+                FeePriority::Low,
+            )
+            .unwrap();
+
+        let lock_fee = costs.buy.bob.bitcoin[0].1;
+        assert_eq!(costs.buy.bob.bitcoin, vec![(TxLabel::Lock, lock_fee)]);
+        assert_eq!(
+            costs.path(SwapPath::Punish).bob,
+            FeeBreakdown {
+                bitcoin: vec![(TxLabel::Lock, lock_fee)],
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            costs.buy.alice.monero_total(),
+            monero::Amount::from_pico(20_000 * MONERO_LOCK_TX_WEIGHT)
+        );
+        assert_eq!(
+            costs.refund.for_role(SwapRole::Alice).monero_total(),
+            monero::Amount::from_pico(20_000 * (MONERO_LOCK_TX_WEIGHT + MONERO_SWEEP_TX_WEIGHT))
+        );
+        assert!(costs.refund.bob.bitcoin_total() > lock_fee + costs.buy.alice.bitcoin_total());
+        // The single signature punish is cheaper than the refund
+        assert!(costs.punish.alice.bitcoin[1].1 < costs.refund.bob.bitcoin[2].1);
+    }
+
+    #[test]
+    fn estimate_costs_with_priority() {
+        let estimator = SwapCostEstimator::new(monero::Amount::from_pico(20_000));
+        let deal = deal(FeeStrategy::Range {
+            min_inc: SatPerVByte::from_sat(5),
+            max_inc: SatPerVByte::from_sat(15),
+        });
+        let low = estimator.estimate(&deal, FeePriority::Low).unwrap();
+        let high = estimator.estimate(&deal, FeePriority::High).unwrap();
+        assert_eq!(
+            high.refund.bob.bitcoin_total(),
+            low.refund.bob.bitcoin_total() * 3
+        );
+    }
+}
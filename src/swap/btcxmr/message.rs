@@ -17,6 +17,7 @@
 //! A set of re-export messages with concrete types for Bitcoin and Monero swaps.
 
 use crate::crypto::dleq::DLEQProof;
+use crate::crypto::musig2::PublicNonce;
 use crate::crypto::KeccakCommitment;
 use crate::protocol::message;
 
@@ -54,3 +55,23 @@ pub type RevealBobParameters = message::RevealBobParameters<
 >;
 
 pub type RevealProof = message::RevealProof<DLEQProof>;
+
+pub type RevealNonces = message::RevealNonces<PublicNonce>;
+
+pub type DealFill = message::DealFill<bitcoin::Amount, monero::Amount>;
+
+pub type ProtocolMessage = message::ProtocolMessage<
+    KeccakCommitment,
+    PublicKey,
+    monero::PublicKey,
+    SecretKey,
+    monero::PrivateKey,
+    Address,
+    DLEQProof,
+    PartiallySignedTransaction,
+    Signature,
+    EncryptedSignature,
+    PublicNonce,
+    bitcoin::Amount,
+    monero::Amount,
+>;
@@ -33,8 +33,50 @@
 //! - The deal parameters, containing the asset types, amounts, timings, etc.
 //! - A node identifier, used to secure the communication with the other peer
 //! - A peer address, used to connect to the other peer
+//! - From [`Version`] 2, an optional expiry and a nonce chosen by the maker to reject replays of
+//!   old deals
+//! - From [`Version`] 3, a [`FillRange`] of arbitrating amounts the taker can choose from
+//!
+//! ## Partial fills
+//!
+//! The amounts of a deal with a [`FillRange`] only define the rate of the trade. The taker
+//! chooses an arbitrating amount inside the range and sends a [`DealFill`] to the maker, binding
+//! the chosen amounts to the deal fingerprint. Both participants then use
+//! [`Deal::to_filled_arbitrating_params`] instead of [`Deal::to_arbitrating_params`].
+//!
+//! Deals and deal parameters can also be encoded in bech32m with a human-readable prefix per
+//! network, e.g. `fcdeal1...` on mainnet. The uppercase form is compact in QR codes. Parsing a
+//! deal accepts the bech32m form, in lower or upper case, and the legacy `Deal:` and hex forms.
+//!
+//! Encoded deals are longer than the 90 characters limit of BIP-350, the error detection
+//! guarantees of the bech32m checksum only hold up to that length. Beyond it typos are detected
+//! on a best-effort basis, the encoding is not meant to protect against transmission errors and a
+//! [`SignedDeal`] must be used to authenticate a deal.
+//!
+//! A deal can be signed by the maker with its node identifier key into a [`SignedDeal`], takers
+//! verify the signature before connecting to the maker. A signed deal is formatted as:
+//!
+//! ```text
+//! "SignedDeal:" | base58(serialize(deal) | serialize(signature))
+//! ```
+//!
+//! ## Validation
+//!
+//! Deals received from the network are checked against a local [`DealPolicy`] with a
+//! [`DealValidator`] before starting a swap.
+//!
+//! ## Price
+//!
+//! The amounts of a deal define its exact [`Price`], see [`DealParameters::price`]. Deal
+//! parameters can be created from a price and one of the amounts with
+//! [`DealParameters::at_price`].
+//!
+//! ## Deal book
+//!
+//! Deals received from the network are stored, filtered and sorted in a [`DealBook`].
 
-use bitcoin::secp256k1::PublicKey;
+use bitcoin::bech32::{self, FromBase32, ToBase32, Variant};
+use bitcoin::secp256k1::{ecdsa::Signature, Message, PublicKey, Secp256k1, SecretKey};
 use inet2_addr::InetSocketAddr;
 use serde::ser::{Serialize, Serializer};
 use serde::{de, Deserialize, Deserializer};
@@ -44,6 +86,7 @@ use thiserror::Error;
 use tiny_keccak::{Hasher, Keccak};
 use uuid::Uuid;
 
+use std::collections::HashSet;
 use std::fmt;
 use std::io;
 
@@ -53,12 +96,41 @@ use crate::hash::HashString;
 use crate::protocol::ArbitratingParameters;
 use crate::role::{SwapRole, TradeRole};
 
+pub mod book;
+pub mod price;
+pub mod validator;
+
+pub use book::{DealBook, DealFilter};
+pub use price::{FixedAmount, Price, Rounding};
+pub use validator::{DealPolicy, DealValidator, Violation};
+
 /// First six magic bytes of a deal. Bytes are included inside the base58 encoded part.
 pub const DEAL_MAGIC_BYTES: &[u8; 6] = b"FCSWAP";
 
 /// Prefix for serialized deal.
 pub const DEAL_PREFIX: &str = "Deal:";
 
+/// Human-readable part of bech32m encoded deals on mainnet.
+pub const DEAL_HRP_MAINNET: &str = "fcdeal";
+
+/// Human-readable part of bech32m encoded deals on testnet.
+pub const DEAL_HRP_TESTNET: &str = "fcdealtest";
+
+/// Human-readable part of bech32m encoded deals on local networks.
+pub const DEAL_HRP_LOCAL: &str = "fcdeallocal";
+
+/// Human-readable part of bech32m encoded deal parameters on mainnet.
+pub const DEAL_PARAMETERS_HRP_MAINNET: &str = "fcparams";
+
+/// Human-readable part of bech32m encoded deal parameters on testnet.
+pub const DEAL_PARAMETERS_HRP_TESTNET: &str = "fcparamstest";
+
+/// Human-readable part of bech32m encoded deal parameters on local networks.
+pub const DEAL_PARAMETERS_HRP_LOCAL: &str = "fcparamslocal";
+
+/// Prefix for serialized signed deal.
+pub const SIGNED_DEAL_PREFIX: &str = "SignedDeal:";
+
 /// A deal version containing the version and the activated features if any.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
 #[display("v{0}")]
@@ -70,6 +142,26 @@ impl Version {
         Self::new(1)
     }
 
+    /// Create a new version 2 deal, with an optional expiry and a maker nonce.
+    pub fn new_v2() -> Self {
+        Self::new(2)
+    }
+
+    /// Create a new version 3 deal, with a fill range on top of version 2 features.
+    pub fn new_v3() -> Self {
+        Self::new(3)
+    }
+
+    /// Return `true` if deals of this version carry an expiry and a nonce.
+    pub fn has_replay_protection(&self) -> bool {
+        self.0 == 2 || self.0 == 3
+    }
+
+    /// Return `true` if deals of this version carry a fill range.
+    pub fn has_partial_fill(&self) -> bool {
+        self.0 == 3
+    }
+
     /// Create a deal from a raw version and feature `u16`.
     pub fn new(version: u16) -> Self {
         Version(version)
@@ -93,6 +185,131 @@ impl Decodable for Version {
     }
 }
 
+/// Expiry of a deal, a taker must not start a swap on an expired deal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+pub enum DealExpiry {
+    /// UNIX timestamp in seconds.
+    #[display("timestamp {0}")]
+    Timestamp(u64),
+    /// Block height of the arbitrating blockchain.
+    #[display("height {0}")]
+    BlockHeight(u32),
+}
+
+impl DealExpiry {
+    /// Return `true` if the expiry is reached at the given time or block height.
+    pub fn is_reached(&self, timestamp: u64, block_height: u32) -> bool {
+        match self {
+            Self::Timestamp(expiry) => timestamp >= *expiry,
+            Self::BlockHeight(expiry) => block_height >= *expiry,
+        }
+    }
+}
+
+impl Encodable for DealExpiry {
+    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        match self {
+            Self::Timestamp(timestamp) => {
+                Ok(0x01u8.consensus_encode(s)? + timestamp.consensus_encode(s)?)
+            }
+            Self::BlockHeight(height) => {
+                Ok(0x02u8.consensus_encode(s)? + height.consensus_encode(s)?)
+            }
+        }
+    }
+}
+
+impl Decodable for DealExpiry {
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        match Decodable::consensus_decode(d)? {
+            0x01u8 => Ok(Self::Timestamp(Decodable::consensus_decode(d)?)),
+            0x02u8 => Ok(Self::BlockHeight(Decodable::consensus_decode(d)?)),
+            _ => Err(consensus::Error::UnknownType),
+        }
+    }
+}
+
+impl_strict_encoding!(DealExpiry);
+
+/// An amount convertible from and to the atomic unit of its asset, e.g. satoshi or piconero, used
+/// to compute amounts at the rate of a deal.
+pub trait AtomicAmount {
+    /// The number of decimals of one whole unit of the asset in atomic units.
+    const DECIMALS: u32;
+
+    /// Return the amount in atomic units.
+    fn as_atomic_units(&self) -> u64;
+
+    /// Create an amount from atomic units.
+    fn from_atomic_units(units: u64) -> Self;
+}
+
+/// Range of arbitrating amounts, inclusive, a taker can choose from when filling a deal of
+/// [`Version`] 3.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FillRange<Amt> {
+    /// The minimum arbitrating amount, inclusive.
+    #[serde(with = "string")]
+    #[serde(bound(serialize = "Amt: Display"))]
+    #[serde(bound(deserialize = "Amt: FromStr, Amt::Err: Display"))]
+    pub min_arbitrating_amount: Amt,
+    /// The maximum arbitrating amount, inclusive.
+    #[serde(with = "string")]
+    #[serde(bound(serialize = "Amt: Display"))]
+    #[serde(bound(deserialize = "Amt: FromStr, Amt::Err: Display"))]
+    pub max_arbitrating_amount: Amt,
+}
+
+impl<Amt> FillRange<Amt>
+where
+    Amt: PartialOrd,
+{
+    /// Create a new fill range.
+    pub fn new(min_arbitrating_amount: Amt, max_arbitrating_amount: Amt) -> Self {
+        Self {
+            min_arbitrating_amount,
+            max_arbitrating_amount,
+        }
+    }
+
+    /// Return `true` if the arbitrating amount is inside the range.
+    pub fn contains(&self, arbitrating_amount: &Amt) -> bool {
+        *arbitrating_amount >= self.min_arbitrating_amount
+            && *arbitrating_amount <= self.max_arbitrating_amount
+    }
+}
+
+impl<Amt> Encodable for FillRange<Amt>
+where
+    Amt: CanonicalBytes,
+{
+    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        let len = self
+            .min_arbitrating_amount
+            .as_canonical_bytes()
+            .consensus_encode(s)?;
+        Ok(len
+            + self
+                .max_arbitrating_amount
+                .as_canonical_bytes()
+                .consensus_encode(s)?)
+    }
+}
+
+impl<Amt> Decodable for FillRange<Amt>
+where
+    Amt: CanonicalBytes,
+{
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(FillRange {
+            min_arbitrating_amount: Amt::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            max_arbitrating_amount: Amt::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+        })
+    }
+}
+
+impl_strict_encoding!(FillRange<Amt>, Amt: CanonicalBytes,);
+
 /// Errors used when manipulating deals, deal parameters, and versions.
 #[derive(Error, Debug)]
 pub enum Error {
@@ -102,6 +319,30 @@ pub enum Error {
     /// The deal signature does not pass the validation tests.
     #[error("Invalid signature")]
     InvalidSignature,
+    /// The secret key used to sign the deal does not match the deal node identifier.
+    #[error("Signing key does not match the deal node id")]
+    InvalidSigningKey,
+    /// The deal expiry is reached.
+    #[error("Deal expired at {0}")]
+    ExpiredDeal(DealExpiry),
+    /// The deal does not carry a nonce, it is not of [`Version`] 2.
+    #[error("Missing deal nonce")]
+    MissingNonce,
+    /// The deal nonce was already used by another deal.
+    #[error("Deal nonce {0} already used")]
+    ReplayedDeal(u64),
+    /// The deal does not carry a fill range, it is not of [`Version`] 3.
+    #[error("Deal does not accept partial fills")]
+    NotFillable,
+    /// The amount chosen by the taker is outside of the deal fill range.
+    #[error("Fill amount is outside of the deal range")]
+    FillOutOfRange,
+    /// The fill does not match the deal fingerprint or the deal rate.
+    #[error("Fill does not match the deal")]
+    InvalidFill,
+    /// The deal violates the local policy.
+    #[error("Invalid deal, {} policy violation(s)", .0.len())]
+    InvalidDeal(Vec<Violation>),
 }
 
 fixed_hash::construct_fixed_hash!(
@@ -227,6 +468,50 @@ impl<Amt, Bmt, Ti, F> DealParameters<Amt, Bmt, Ti, F> {
             parameters: self,
             node_id,
             peer_address,
+            expiry: None,
+            nonce: None,
+            fill_range: None,
+        }
+    }
+
+    /// Transform the deal parameters in a deal of [`Version`] 2 with an optional expiry and the
+    /// maker nonce protecting against replays of the deal.
+    pub fn to_v2(
+        self,
+        node_id: PublicKey,
+        peer_address: InetSocketAddr,
+        expiry: Option<DealExpiry>,
+        nonce: u64,
+    ) -> Deal<Amt, Bmt, Ti, F> {
+        Deal {
+            version: Version::new_v2(),
+            parameters: self,
+            node_id,
+            peer_address,
+            expiry,
+            nonce: Some(nonce),
+            fill_range: None,
+        }
+    }
+
+    /// Transform the deal parameters in a deal of [`Version`] 3 accepting partial fills inside
+    /// the fill range. The amounts of the parameters define the rate of the deal.
+    pub fn to_v3(
+        self,
+        node_id: PublicKey,
+        peer_address: InetSocketAddr,
+        expiry: Option<DealExpiry>,
+        nonce: u64,
+        fill_range: FillRange<Amt>,
+    ) -> Deal<Amt, Bmt, Ti, F> {
+        Deal {
+            version: Version::new_v3(),
+            parameters: self,
+            node_id,
+            peer_address,
+            expiry,
+            nonce: Some(nonce),
+            fill_range: Some(fill_range),
         }
     }
 
@@ -239,6 +524,33 @@ impl<Amt, Bmt, Ti, F> DealParameters<Amt, Bmt, Ti, F> {
     }
 }
 
+impl<Amt, Bmt, Ti, F> DealParameters<Amt, Bmt, Ti, F>
+where
+    Amt: AtomicAmount + Copy,
+    Bmt: AtomicAmount + Copy,
+{
+    /// Return the exact price of the deal parameters, `None` if one of the amounts is zero.
+    pub fn price(&self) -> Option<Price<Amt, Bmt>> {
+        Price::new(self.arbitrating_amount, self.accordant_amount)
+    }
+
+    /// Return the deal parameters with the fixed amount and the other amount computed at the
+    /// price with the rounding.
+    pub fn at_price(
+        self,
+        price: Price<Amt, Bmt>,
+        fixed: FixedAmount<Amt, Bmt>,
+        rounding: Rounding,
+    ) -> Self {
+        let (arbitrating_amount, accordant_amount) = price.amounts(fixed, rounding);
+        Self {
+            arbitrating_amount,
+            accordant_amount,
+            ..self
+        }
+    }
+}
+
 impl<Amt, Bmt, Ti, F> DealParameters<Amt, Bmt, Ti, F> {
     /// Return the unique deal identifier. Same as [`Self::uuid()`].
     pub fn id(&self) -> Uuid {
@@ -335,7 +647,8 @@ impl_strict_encoding!(DealParameters<Amt, Bmt, Ti, F>, Amt: CanonicalBytes, Bmt:
 /// maker peer connection information are contained in the deal.
 #[derive(Debug, Clone, Eq, Hash, PartialEq, Serialize, Deserialize)]
 pub struct Deal<Amt, Bmt, Ti, F> {
-    /// The deal version.
+    /// The deal version. A deal is encoded with the first version carrying its expiry, nonce and
+    /// fill range when its version does not.
     pub version: Version,
     /// The content of the deal.
     #[serde(bound(serialize = "Amt: Display, Bmt: Display, Ti: Serialize, F: Serialize"))]
@@ -348,6 +661,18 @@ pub struct Deal<Amt, Bmt, Ti, F> {
     /// Address of the listening daemon's peer. An internet socket address, which consists of an IP
     /// or Tor address and a port number.
     pub peer_address: InetSocketAddr,
+    /// Expiry of the deal, only encoded in deals of [`Version`] 2.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiry: Option<DealExpiry>,
+    /// Nonce chosen by the maker to reject replays of the deal, only encoded in deals of
+    /// [`Version`] 2.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<u64>,
+    /// Range of arbitrating amounts the taker can fill, only encoded in deals of [`Version`] 3.
+    #[serde(bound(serialize = "Amt: Display"))]
+    #[serde(bound(deserialize = "Amt: FromStr, Amt::Err: Display"))]
+    #[serde(default = "Option::default", skip_serializing_if = "Option::is_none")]
+    pub fill_range: Option<FillRange<Amt>>,
 }
 
 impl<Amt, Bmt, Ti, F> Deal<Amt, Bmt, Ti, F>
@@ -366,6 +691,66 @@ where
     }
 }
 
+impl<Amt, Bmt, Ti, F> Deal<Amt, Bmt, Ti, F>
+where
+    Self: Encodable,
+    Amt: AtomicAmount + Copy + PartialOrd,
+    Bmt: AtomicAmount + Copy + PartialEq,
+    Ti: Copy,
+    F: Copy,
+{
+    /// Return the accordant amount matching the arbitrating amount at the price of the deal,
+    /// rounded down to the accordant atomic unit, or zero if the deal has no price.
+    pub fn accordant_amount_at_rate(&self, arbitrating_amount: Amt) -> Bmt {
+        self.price().map_or(Bmt::from_atomic_units(0), |price| {
+            price.accordant_amount(arbitrating_amount, Rounding::Down)
+        })
+    }
+
+    /// Create the taker [`DealFill`] for the arbitrating amount, fails with [`Error::NotFillable`]
+    /// if the deal has no fill range or [`Error::FillOutOfRange`] if the amount is not inside.
+    pub fn fill(&self, arbitrating_amount: Amt) -> Result<DealFill<Amt, Bmt>, Error> {
+        let range = self.fill_range.as_ref().ok_or(Error::NotFillable)?;
+        if !range.contains(&arbitrating_amount) {
+            return Err(Error::FillOutOfRange);
+        }
+        Ok(DealFill {
+            fingerprint: self.fingerprint(),
+            arbitrating_amount,
+            accordant_amount: self.accordant_amount_at_rate(arbitrating_amount),
+        })
+    }
+
+    /// Validate the fill against the deal: the fingerprint must match, the arbitrating amount
+    /// must be inside the fill range and the accordant amount must follow the deal rate.
+    pub fn validate_fill(&self, fill: &DealFill<Amt, Bmt>) -> Result<(), Error> {
+        let range = self.fill_range.as_ref().ok_or(Error::NotFillable)?;
+        if fill.fingerprint != self.fingerprint() {
+            return Err(Error::InvalidFill);
+        }
+        if !range.contains(&fill.arbitrating_amount) {
+            return Err(Error::FillOutOfRange);
+        }
+        if fill.accordant_amount != self.accordant_amount_at_rate(fill.arbitrating_amount) {
+            return Err(Error::InvalidFill);
+        }
+        Ok(())
+    }
+
+    /// Return the arbitrating parameters of the deal with the arbitrating amount chosen by the
+    /// taker, after validating the fill with [`Deal::validate_fill`].
+    pub fn to_filled_arbitrating_params(
+        &self,
+        fill: &DealFill<Amt, Bmt>,
+    ) -> Result<ArbitratingParameters<Amt, Ti, F>, Error> {
+        self.validate_fill(fill)?;
+        Ok(ArbitratingParameters {
+            arbitrating_amount: fill.arbitrating_amount,
+            ..self.to_arbitrating_params()
+        })
+    }
+}
+
 impl<Amt, Bmt, Ti, F> Deal<Amt, Bmt, Ti, F>
 where
     Self: Encodable,
@@ -408,6 +793,43 @@ impl<Amt, Bmt, Ti, F> Deal<Amt, Bmt, Ti, F> {
     pub fn swap_role(&self, trade_role: &TradeRole) -> SwapRole {
         self.parameters.swap_role(trade_role)
     }
+
+    /// Return the exact price of the deal, see [`DealParameters::price`].
+    pub fn price(&self) -> Option<Price<Amt, Bmt>>
+    where
+        Amt: AtomicAmount + Copy,
+        Bmt: AtomicAmount + Copy,
+    {
+        self.parameters.price()
+    }
+
+    /// Return `true` if the deal has an expiry and it is reached at the given time or block
+    /// height.
+    pub fn is_expired(&self, timestamp: u64, block_height: u32) -> bool {
+        self.expiry
+            .map_or(false, |expiry| expiry.is_reached(timestamp, block_height))
+    }
+
+    /// Validate the deal is not expired at the given time or block height, fails with
+    /// [`Error::ExpiredDeal`] otherwise.
+    pub fn validate_expiry(&self, timestamp: u64, block_height: u32) -> Result<(), Error> {
+        match self.expiry {
+            Some(expiry) if expiry.is_reached(timestamp, block_height) => {
+                Err(Error::ExpiredDeal(expiry))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Validate the deal nonce is not in the set of nonces already used by the maker, fails with
+    /// [`Error::ReplayedDeal`] if it is or with [`Error::MissingNonce`] if the deal has no nonce.
+    pub fn validate_nonce(&self, used_nonces: &HashSet<u64>) -> Result<(), Error> {
+        match self.nonce {
+            Some(nonce) if used_nonces.contains(&nonce) => Err(Error::ReplayedDeal(nonce)),
+            Some(_) => Ok(()),
+            None => Err(Error::MissingNonce),
+        }
+    }
 }
 
 impl<Amt, Bmt, Ti, F> Display for Deal<Amt, Bmt, Ti, F>
@@ -431,12 +853,37 @@ where
     type Err = consensus::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if &s[..5] != DEAL_PREFIX {
-            return Err(consensus::Error::IncorrectMagicBytes);
+        if let Some(encoded) = s.strip_prefix(DEAL_PREFIX) {
+            let decoded = base58_monero::decode_check(encoded).map_err(consensus::Error::new)?;
+            let mut res = std::io::Cursor::new(decoded);
+            return Decodable::consensus_decode(&mut res);
+        }
+        let hrps = [DEAL_HRP_MAINNET, DEAL_HRP_TESTNET, DEAL_HRP_LOCAL];
+        if has_bech32_hrp(s, &hrps) {
+            Self::from_bech32m(s)
+        } else if is_hex(s) {
+            Self::from_hex(s)
+        } else {
+            Err(consensus::Error::IncorrectMagicBytes)
+        }
+    }
+}
+
+impl<Amt, Bmt, Ti, F> Deal<Amt, Bmt, Ti, F> {
+    // Return the version used to encode the deal, raised to the first version carrying the fields
+    // set on the deal so they are never dropped from the encoding
+    fn encoded_version(&self) -> Version {
+        let required = if self.fill_range.is_some() {
+            Version::new_v3()
+        } else if self.expiry.is_some() || self.nonce.is_some() {
+            Version::new_v2()
+        } else {
+            Version::new_v1()
+        };
+        match self.version.to_u16() < required.to_u16() {
+            true => required,
+            false => self.version.clone(),
         }
-        let decoded = base58_monero::decode_check(&s[5..]).map_err(consensus::Error::new)?;
-        let mut res = std::io::Cursor::new(decoded);
-        Decodable::consensus_decode(&mut res)
     }
 }
 
@@ -448,17 +895,26 @@ where
     F: CanonicalBytes,
 {
     fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        let version = self.encoded_version();
         let mut len = DEAL_MAGIC_BYTES.consensus_encode(s)?;
-        len += self.version.consensus_encode(s)?;
+        len += version.consensus_encode(s)?;
         len += self.parameters.consensus_encode(s)?;
         len += self.node_id.as_canonical_bytes().consensus_encode(s)?;
-        len +=
-            strict_encoding::StrictEncode::strict_encode(&self.peer_address, s).map_err(|_| {
+        len += strict_encoding::StrictEncode::strict_encode(&self.peer_address, &mut *s).map_err(
+            |_| {
                 io::Error::new(
                     io::ErrorKind::InvalidData,
                     "Failed to encode InetSocketAddr",
                 )
-            })?;
+            },
+        )?;
+        if version.has_replay_protection() {
+            len += self.expiry.consensus_encode(s)?;
+            len += self.nonce.consensus_encode(s)?;
+        }
+        if version.has_partial_fill() {
+            len += self.fill_range.consensus_encode(s)?;
+        }
         Ok(len)
     }
 }
@@ -475,18 +931,381 @@ where
         if magic_bytes != *DEAL_MAGIC_BYTES {
             return Err(consensus::Error::IncorrectMagicBytes);
         }
+        let version: Version = Decodable::consensus_decode(d)?;
+        let parameters = Decodable::consensus_decode(d)?;
+        let node_id = PublicKey::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?;
+        let peer_address =
+            strict_encoding::StrictDecode::strict_decode(&mut *d).map_err(consensus::Error::new)?;
+        let (expiry, nonce) = match version.has_replay_protection() {
+            true => (
+                Decodable::consensus_decode(d)?,
+                Decodable::consensus_decode(d)?,
+            ),
+            false => (None, None),
+        };
+        let fill_range = match version.has_partial_fill() {
+            true => Decodable::consensus_decode(d)?,
+            false => None,
+        };
         Ok(Deal {
-            version: Decodable::consensus_decode(d)?,
-            parameters: Decodable::consensus_decode(d)?,
-            node_id: PublicKey::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
-            peer_address: strict_encoding::StrictDecode::strict_decode(d)
-                .map_err(consensus::Error::new)?,
+            version,
+            parameters,
+            node_id,
+            peer_address,
+            expiry,
+            nonce,
+            fill_range,
         })
     }
 }
 
 impl_strict_encoding!(Deal<Amt, Bmt, Ti, F>, Amt: CanonicalBytes, Bmt: CanonicalBytes, Ti: CanonicalBytes, F: CanonicalBytes,);
 
+// Return the human-readable part of bech32m encoded deals and deal parameters for the network
+fn deal_hrp(network: Network) -> &'static str {
+    match network {
+        Network::Mainnet => DEAL_HRP_MAINNET,
+        Network::Testnet => DEAL_HRP_TESTNET,
+        Network::Local => DEAL_HRP_LOCAL,
+    }
+}
+
+fn deal_parameters_hrp(network: Network) -> &'static str {
+    match network {
+        Network::Mainnet => DEAL_PARAMETERS_HRP_MAINNET,
+        Network::Testnet => DEAL_PARAMETERS_HRP_TESTNET,
+        Network::Local => DEAL_PARAMETERS_HRP_LOCAL,
+    }
+}
+
+// Return true if the string starts with one of the human-readable parts followed by the bech32
+// separator, in lower or upper case
+fn has_bech32_hrp(s: &str, hrps: &[&str]) -> bool {
+    hrps.iter().any(|hrp| {
+        s.get(..hrp.len() + 1).map_or(false, |prefix| {
+            prefix.eq_ignore_ascii_case(&format!("{}1", hrp))
+        })
+    })
+}
+
+fn is_hex(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+// Encode the value in bech32m, the result is not limited to 90 characters and the checksum only
+// detects errors on a best-effort basis past that length
+fn encode_bech32m<T: Encodable>(hrp: &str, value: &T) -> String {
+    bech32::encode(hrp, serialize(value).to_base32(), Variant::Bech32m)
+        .expect("Human-readable parts are valid")
+}
+
+// Decode a bech32m string, in lower or upper case, and return its human-readable part and the
+// decoded value
+fn decode_bech32m<T: Decodable>(s: &str) -> Result<(String, T), consensus::Error> {
+    let (hrp, data, variant) = bech32::decode(s).map_err(consensus::Error::new)?;
+    if variant != Variant::Bech32m {
+        return Err(consensus::Error::ParseFailed("Checksum is not bech32m"));
+    }
+    let bytes = Vec::<u8>::from_base32(&data).map_err(consensus::Error::new)?;
+    Ok((hrp, consensus::deserialize(&bytes)?))
+}
+
+impl<Amt, Bmt, Ti, F> DealParameters<Amt, Bmt, Ti, F>
+where
+    Amt: CanonicalBytes,
+    Bmt: CanonicalBytes,
+    Ti: CanonicalBytes,
+    F: CanonicalBytes,
+{
+    /// Returns the bech32m encoding of the consensus encoded deal parameters with the
+    /// human-readable part of the parameters' network, in lower case. The encoding is longer
+    /// than 90 characters, see the [module documentation](self) about the checksum.
+    pub fn to_bech32m(&self) -> String {
+        encode_bech32m(deal_parameters_hrp(self.network), self)
+    }
+
+    /// Returns the bech32m encoding of the deal parameters in upper case, the compact form for
+    /// QR codes alphanumeric mode.
+    pub fn to_bech32m_uppercase(&self) -> String {
+        self.to_bech32m().to_uppercase()
+    }
+
+    /// Parse bech32m encoded deal parameters, fails if the human-readable part does not match the
+    /// network of the parameters.
+    pub fn from_bech32m(s: &str) -> Result<Self, consensus::Error> {
+        let (hrp, params): (_, Self) = decode_bech32m(s)?;
+        match hrp == deal_parameters_hrp(params.network) {
+            true => Ok(params),
+            false => Err(consensus::Error::ParseFailed(
+                "Human-readable part does not match the network",
+            )),
+        }
+    }
+
+    /// Returns the hex string representation of the consensus encoded deal parameters.
+    pub fn to_hex(&self) -> String {
+        serialize_hex(self)
+    }
+
+    /// Parse the hex string representation of consensus encoded deal parameters.
+    pub fn from_hex(s: &str) -> Result<Self, consensus::Error> {
+        consensus::deserialize(&hex::decode(s).map_err(consensus::Error::new)?)
+    }
+}
+
+impl<Amt, Bmt, Ti, F> FromStr for DealParameters<Amt, Bmt, Ti, F>
+where
+    Amt: CanonicalBytes,
+    Bmt: CanonicalBytes,
+    Ti: CanonicalBytes,
+    F: CanonicalBytes,
+{
+    type Err = consensus::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hrps = [
+            DEAL_PARAMETERS_HRP_MAINNET,
+            DEAL_PARAMETERS_HRP_TESTNET,
+            DEAL_PARAMETERS_HRP_LOCAL,
+        ];
+        if has_bech32_hrp(s, &hrps) {
+            Self::from_bech32m(s)
+        } else if is_hex(s) {
+            Self::from_hex(s)
+        } else {
+            Err(consensus::Error::IncorrectMagicBytes)
+        }
+    }
+}
+
+impl<Amt, Bmt, Ti, F> Deal<Amt, Bmt, Ti, F>
+where
+    Amt: CanonicalBytes,
+    Bmt: CanonicalBytes,
+    Ti: CanonicalBytes,
+    F: CanonicalBytes,
+{
+    /// Returns the bech32m encoding of the consensus encoded deal with the human-readable part of
+    /// the deal's network, in lower case. The encoding is longer than 90 characters, see the
+    /// [module documentation](self) about the checksum.
+    pub fn to_bech32m(&self) -> String {
+        encode_bech32m(deal_hrp(self.parameters.network), self)
+    }
+
+    /// Returns the bech32m encoding of the deal in upper case, the compact form for QR codes
+    /// alphanumeric mode.
+    pub fn to_bech32m_uppercase(&self) -> String {
+        self.to_bech32m().to_uppercase()
+    }
+
+    /// Parse a bech32m encoded deal, fails if the human-readable part does not match the network
+    /// of the deal.
+    pub fn from_bech32m(s: &str) -> Result<Self, consensus::Error> {
+        let (hrp, deal): (_, Self) = decode_bech32m(s)?;
+        match hrp == deal_hrp(deal.parameters.network) {
+            true => Ok(deal),
+            false => Err(consensus::Error::ParseFailed(
+                "Human-readable part does not match the network",
+            )),
+        }
+    }
+
+    /// Parse the hex string representation of a consensus encoded deal, see [`Deal::to_hex`].
+    pub fn from_hex(s: &str) -> Result<Self, consensus::Error> {
+        consensus::deserialize(&hex::decode(s).map_err(consensus::Error::new)?)
+    }
+}
+
+impl<Amt, Bmt, Ti, F> Deal<Amt, Bmt, Ti, F>
+where
+    Self: Encodable,
+{
+    /// Sign the deal [`DealFingerprint`] with the secret key of the deal's node identifier, fails
+    /// with [`Error::InvalidSigningKey`] if the key does not match the node identifier.
+    pub fn sign(self, secret_key: &SecretKey) -> Result<SignedDeal<Amt, Bmt, Ti, F>, Error> {
+        let secp = Secp256k1::new();
+        if PublicKey::from_secret_key(&secp, secret_key) != self.node_id {
+            return Err(Error::InvalidSigningKey);
+        }
+        let signature = secp.sign_ecdsa(&self.fingerprint().into(), secret_key);
+        Ok(SignedDeal {
+            deal: self,
+            signature,
+        })
+    }
+}
+
+impl From<DealFingerprint> for Message {
+    fn from(fingerprint: DealFingerprint) -> Self {
+        Message::from_slice(fingerprint.as_bytes()).expect("A fingerprint is 32 bytes")
+    }
+}
+
+/// A deal signed by the maker with the key of its node identifier, proving the deal is published
+/// by the node the takers connect to.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SignedDeal<Amt, Bmt, Ti, F> {
+    /// The signed deal.
+    #[serde(bound(serialize = "Amt: Display, Bmt: Display, Ti: Serialize, F: Serialize"))]
+    #[serde(bound(
+        deserialize = "Amt: FromStr, Amt::Err: Display, Bmt: FromStr, Bmt::Err: Display, Ti: Deserialize<'de>, F: Deserialize<'de>"
+    ))]
+    pub deal: Deal<Amt, Bmt, Ti, F>,
+    /// The ECDSA signature of the deal fingerprint by the deal node identifier.
+    pub signature: Signature,
+}
+
+impl<Amt, Bmt, Ti, F> SignedDeal<Amt, Bmt, Ti, F>
+where
+    Amt: CanonicalBytes,
+    Bmt: CanonicalBytes,
+    Ti: CanonicalBytes,
+    F: CanonicalBytes,
+{
+    /// Verify the signature of the deal fingerprint against the deal node identifier, fails with
+    /// [`Error::InvalidSignature`] if the signature is not valid.
+    pub fn verify(&self) -> Result<(), Error> {
+        Secp256k1::verification_only()
+            .verify_ecdsa(
+                &self.deal.fingerprint().into(),
+                &self.signature,
+                &self.deal.node_id,
+            )
+            .map_err(|_| Error::InvalidSignature)
+    }
+
+    /// Verify the signature and return the deal if valid.
+    pub fn into_verified_deal(self) -> Result<Deal<Amt, Bmt, Ti, F>, Error> {
+        self.verify()?;
+        Ok(self.deal)
+    }
+
+    /// Returns the hex string representation of the consensus encoded signed deal.
+    pub fn to_hex(&self) -> String {
+        serialize_hex(self)
+    }
+}
+
+impl<Amt, Bmt, Ti, F> Display for SignedDeal<Amt, Bmt, Ti, F>
+where
+    Self: Encodable,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let encoded = base58_monero::encode_check(consensus::serialize(self).as_ref())
+            .expect("Encoding in base58 check works");
+        write!(f, "{}{}", SIGNED_DEAL_PREFIX, encoded)
+    }
+}
+
+impl<Amt, Bmt, Ti, F> FromStr for SignedDeal<Amt, Bmt, Ti, F>
+where
+    Amt: CanonicalBytes,
+    Bmt: CanonicalBytes,
+    Ti: CanonicalBytes,
+    F: CanonicalBytes,
+{
+    type Err = consensus::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let encoded = s
+            .strip_prefix(SIGNED_DEAL_PREFIX)
+            .ok_or(consensus::Error::IncorrectMagicBytes)?;
+        let decoded = base58_monero::decode_check(encoded).map_err(consensus::Error::new)?;
+        let mut res = std::io::Cursor::new(decoded);
+        Decodable::consensus_decode(&mut res)
+    }
+}
+
+impl<Amt, Bmt, Ti, F> Encodable for SignedDeal<Amt, Bmt, Ti, F>
+where
+    Amt: CanonicalBytes,
+    Bmt: CanonicalBytes,
+    Ti: CanonicalBytes,
+    F: CanonicalBytes,
+{
+    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        let mut len = self.deal.consensus_encode(s)?;
+        len += self
+            .signature
+            .serialize_compact()
+            .to_vec()
+            .consensus_encode(s)?;
+        Ok(len)
+    }
+}
+
+impl<Amt, Bmt, Ti, F> Decodable for SignedDeal<Amt, Bmt, Ti, F>
+where
+    Amt: CanonicalBytes,
+    Bmt: CanonicalBytes,
+    Ti: CanonicalBytes,
+    F: CanonicalBytes,
+{
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        Ok(SignedDeal {
+            deal: Decodable::consensus_decode(d)?,
+            signature: Signature::from_compact(unwrap_vec_ref!(d).as_ref())
+                .map_err(consensus::Error::new)?,
+        })
+    }
+}
+
+impl_strict_encoding!(SignedDeal<Amt, Bmt, Ti, F>, Amt: CanonicalBytes, Bmt: CanonicalBytes, Ti: CanonicalBytes, F: CanonicalBytes,);
+
+/// Amounts chosen by the taker when filling a deal of [`Version`] 3, sent to the maker during the
+/// trade setup. The fill is bound to the deal by its fingerprint.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct DealFill<Amt, Bmt> {
+    /// The fingerprint of the filled deal.
+    pub fingerprint: DealFingerprint,
+    /// The arbitrating amount chosen by the taker inside the deal fill range.
+    #[serde(with = "string")]
+    #[serde(bound(serialize = "Amt: Display"))]
+    #[serde(bound(deserialize = "Amt: FromStr, Amt::Err: Display"))]
+    pub arbitrating_amount: Amt,
+    /// The accordant amount at the rate of the deal.
+    #[serde(with = "string")]
+    #[serde(bound(serialize = "Bmt: Display"))]
+    #[serde(bound(deserialize = "Bmt: FromStr, Bmt::Err: Display"))]
+    pub accordant_amount: Bmt,
+}
+
+impl<Amt, Bmt> Encodable for DealFill<Amt, Bmt>
+where
+    Amt: CanonicalBytes,
+    Bmt: CanonicalBytes,
+{
+    fn consensus_encode<W: io::Write>(&self, s: &mut W) -> Result<usize, io::Error> {
+        let mut len = self.fingerprint.to_fixed_bytes().consensus_encode(s)?;
+        len += self
+            .arbitrating_amount
+            .as_canonical_bytes()
+            .consensus_encode(s)?;
+        Ok(len
+            + self
+                .accordant_amount
+                .as_canonical_bytes()
+                .consensus_encode(s)?)
+    }
+}
+
+impl<Amt, Bmt> Decodable for DealFill<Amt, Bmt>
+where
+    Amt: CanonicalBytes,
+    Bmt: CanonicalBytes,
+{
+    fn consensus_decode<D: io::Read>(d: &mut D) -> Result<Self, consensus::Error> {
+        let fingerprint: [u8; 32] = Decodable::consensus_decode(d)?;
+        Ok(DealFill {
+            fingerprint: DealFingerprint(fingerprint),
+            arbitrating_amount: Amt::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+            accordant_amount: Bmt::from_canonical_bytes(unwrap_vec_ref!(d).as_ref())?,
+        })
+    }
+}
+
+impl_strict_encoding!(DealFill<Amt, Bmt>, Amt: CanonicalBytes, Bmt: CanonicalBytes,);
+
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
@@ -612,6 +1431,250 @@ mod tests {
         );
     }
 
+    #[test]
+    fn reject_deal_params_with_invalid_timelock() {
+        // Bit 23 is outside the BIP-68 timelock value and type flag
+        let s = "---\nuuid: 67e55044-10b1-426f-9247-bb680e5fe0c8\nnetwork: Testnet\narbitrating_blockchain: Bitcoin\naccordant_blockchain: Monero\narbitrating_amount: 0.00000005 BTC\naccordant_amount: 0.000000000006 XMR\ncancel_timelock: 8388608\npunish_timelock: 8\nfee_strategy:\n  Fixed: 9 satoshi/vByte\nmaker_role: Bob\n";
+        let res: Result<
+            DealParameters<bitcoin::Amount, monero::Amount, CSVTimelock, SatPerVByte>,
+            _,
+        > = serde_yaml::from_str(s);
+        assert!(res.is_err());
+
+        let mut deal_params = DEAL_PARAMS.clone();
+        deal_params.cancel_timelock = CSVTimelock::new(1 << 23);
+        let bytes = consensus::serialize(&deal_params);
+        let res: Result<
+            DealParameters<bitcoin::Amount, monero::Amount, CSVTimelock, SatPerVByte>,
+            _,
+        > = consensus::deserialize(&bytes);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn encode_deal_in_bech32m() {
+        let deal =
+            Deal::<bitcoin::Amount, monero::Amount, CSVTimelock, SatPerVByte>::from_str(S).unwrap();
+        let encoded = deal.to_bech32m();
+        assert!(encoded.starts_with(&format!("{}1", deal_hrp(deal.parameters.network))));
+        assert_eq!(Deal::from_str(&encoded).unwrap(), deal);
+        assert_eq!(Deal::from_str(&deal.to_bech32m_uppercase()).unwrap(), deal);
+        // Legacy forms are still accepted
+        assert_eq!(Deal::from_str(&deal.to_hex()).unwrap(), deal);
+        assert_eq!(Deal::from_str(&deal.to_string()).unwrap(), deal);
+
+        // This typo is detected by the checksum, detection is only best-effort as the encoded
+        // deal is longer than 90 characters
+        assert!(encoded.len() > 90);
+        let mut typo = encoded.into_bytes();
+        let i = typo.len() / 2;
+        typo[i] = if typo[i] == b'q' { b'p' } else { b'q' };
+        let typo = String::from_utf8(typo).unwrap();
+        assert!(
+            Deal::<bitcoin::Amount, monero::Amount, CSVTimelock, SatPerVByte>::from_str(&typo)
+                .is_err()
+        );
+
+        // The human-readable part must match the deal network
+        let data = bech32::decode(&deal.to_bech32m()).unwrap().1;
+        let wrong_network = bech32::encode(DEAL_HRP_LOCAL, data, Variant::Bech32m).unwrap();
+        assert!(
+            Deal::<bitcoin::Amount, monero::Amount, CSVTimelock, SatPerVByte>::from_str(
+                &wrong_network
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn encode_deal_params_in_bech32m() {
+        let encoded = DEAL_PARAMS.to_bech32m();
+        assert!(encoded.starts_with("fcparamstest1"));
+        assert!(encoded.len() > 90);
+        assert_eq!(DealParameters::from_str(&encoded).unwrap(), *DEAL_PARAMS);
+        assert_eq!(
+            DealParameters::from_str(&DEAL_PARAMS.to_bech32m_uppercase()).unwrap(),
+            *DEAL_PARAMS
+        );
+        assert_eq!(
+            DealParameters::from_str(&DEAL_PARAMS.to_hex()).unwrap(),
+            *DEAL_PARAMS
+        );
+    }
+
+    #[test]
+    fn sign_and_verify_deal() {
+        let sk = bitcoin::util::key::PrivateKey::from_wif(
+            "L1HKVVLHXiUhecWnwFYF6L3shkf1E12HUmuZTESvBXUdx3yqVP1D",
+        )
+        .unwrap()
+        .inner;
+        let deal = DEAL_PARAMS.clone().to_v1(*NODE_ID, *PEER_ADDRESS);
+        let signed = deal.clone().sign(&sk).expect("Node id key signs the deal");
+        assert!(signed.verify().is_ok());
+
+        let encoded = signed.to_string();
+        assert!(encoded.starts_with(SIGNED_DEAL_PREFIX));
+        let parsed = SignedDeal::from_str(&encoded).expect("Parsable signed deal");
+        assert_eq!(parsed, signed);
+        assert_eq!(parsed.into_verified_deal().unwrap(), deal);
+        assert!(matches!(
+            SignedDeal::<bitcoin::Amount, monero::Amount, CSVTimelock, SatPerVByte>::from_str(
+                &deal.to_string()
+            ),
+            Err(consensus::Error::IncorrectMagicBytes)
+        ));
+
+        // Tampering with the deal invalidates the signature
+        let mut tampered = signed.clone();
+        tampered.deal.parameters.accordant_amount = monero::Amount::from_pico(1);
+        assert!(matches!(tampered.verify(), Err(Error::InvalidSignature)));
+
+        // Only the node id key can sign
+        let other = SecretKey::from_slice(&[1; 32]).unwrap();
+        assert!(matches!(deal.sign(&other), Err(Error::InvalidSigningKey)));
+    }
+
+    #[test]
+    fn encode_and_validate_deal_v2() {
+        let expiry = DealExpiry::BlockHeight(800_000);
+        let deal = DEAL_PARAMS
+            .clone()
+            .to_v2(*NODE_ID, *PEER_ADDRESS, Some(expiry), 42);
+        assert!(deal.version.has_replay_protection());
+
+        let parsed = Deal::from_str(&deal.to_string()).expect("Parsable v2 deal");
+        assert_eq!(parsed, deal);
+        assert_ne!(
+            deal.fingerprint(),
+            DEAL_PARAMS
+                .clone()
+                .to_v2(*NODE_ID, *PEER_ADDRESS, Some(expiry), 43)
+                .fingerprint()
+        );
+
+        assert!(deal.validate_expiry(0, 799_999).is_ok());
+        assert!(matches!(
+            deal.validate_expiry(0, 800_000),
+            Err(Error::ExpiredDeal(DealExpiry::BlockHeight(800_000)))
+        ));
+
+        let mut used_nonces = HashSet::new();
+        assert!(deal.validate_nonce(&used_nonces).is_ok());
+        used_nonces.insert(42);
+        assert!(matches!(
+            deal.validate_nonce(&used_nonces),
+            Err(Error::ReplayedDeal(42))
+        ));
+
+        // Version 1 deals never expire and carry no nonce
+        let v1 = DEAL_PARAMS.clone().to_v1(*NODE_ID, *PEER_ADDRESS);
+        assert!(!v1.is_expired(u64::MAX, u32::MAX));
+        assert!(matches!(
+            v1.validate_nonce(&used_nonces),
+            Err(Error::MissingNonce)
+        ));
+    }
+
+    #[test]
+    fn encode_deal_with_version_of_its_fields() {
+        // A version 1 deal with a nonce is encoded as version 2
+        let mut deal = DEAL_PARAMS.clone().to_v1(*NODE_ID, *PEER_ADDRESS);
+        deal.nonce = Some(42);
+        let decoded: Deal<bitcoin::Amount, monero::Amount, CSVTimelock, SatPerVByte> =
+            consensus::deserialize(&consensus::serialize(&deal)).unwrap();
+        assert_eq!(decoded.version, Version::new_v2());
+        assert_eq!(decoded.nonce, Some(42));
+        assert_eq!(decoded.fingerprint(), deal.fingerprint());
+
+        // A version 2 deal with a fill range is encoded as version 3
+        let range = FillRange::new(
+            bitcoin::Amount::from_sat(500),
+            bitcoin::Amount::from_sat(2000),
+        );
+        let mut deal = DEAL_PARAMS.clone().to_v2(*NODE_ID, *PEER_ADDRESS, None, 7);
+        deal.fill_range = Some(range);
+        let decoded: Deal<bitcoin::Amount, monero::Amount, CSVTimelock, SatPerVByte> =
+            consensus::deserialize(&consensus::serialize(&deal)).unwrap();
+        assert_eq!(decoded.version, Version::new_v3());
+        assert_eq!(decoded.fill_range, Some(range));
+
+        // Deals without the fields keep their version
+        let deal = DEAL_PARAMS.clone().to_v2(*NODE_ID, *PEER_ADDRESS, None, 7);
+        let decoded: Deal<bitcoin::Amount, monero::Amount, CSVTimelock, SatPerVByte> =
+            consensus::deserialize(&consensus::serialize(&deal)).unwrap();
+        assert_eq!(decoded, deal);
+    }
+
+    #[test]
+    fn fill_deal_v3() {
+        let range = FillRange::new(
+            bitcoin::Amount::from_sat(500),
+            bitcoin::Amount::from_sat(2000),
+        );
+        let deal = DEAL_PARAMS
+            .clone()
+            .to_v3(*NODE_ID, *PEER_ADDRESS, None, 7, range);
+        assert!(deal.version.has_replay_protection());
+        let parsed = Deal::from_str(&deal.to_string()).expect("Parsable v3 deal");
+        assert_eq!(parsed, deal);
+
+        // 1350 sat for 10000 piconero, 675 sat fill for 5000 piconero
+        let fill = deal.fill(bitcoin::Amount::from_sat(675)).unwrap();
+        assert_eq!(fill.fingerprint, deal.fingerprint());
+        assert_eq!(fill.accordant_amount, monero::Amount::from_pico(5000));
+        assert_eq!(
+            consensus::deserialize::<DealFill<bitcoin::Amount, monero::Amount>>(
+                &consensus::serialize(&fill)
+            )
+            .unwrap(),
+            fill
+        );
+        let params = deal.to_filled_arbitrating_params(&fill).unwrap();
+        assert_eq!(params.arbitrating_amount, bitcoin::Amount::from_sat(675));
+        assert_eq!(params.cancel_timelock, DEAL_PARAMS.cancel_timelock);
+
+        assert!(matches!(
+            deal.fill(bitcoin::Amount::from_sat(2001)),
+            Err(Error::FillOutOfRange)
+        ));
+        let mut off_rate = fill;
+        off_rate.accordant_amount = monero::Amount::from_pico(6000);
+        assert!(matches!(
+            deal.validate_fill(&off_rate),
+            Err(Error::InvalidFill)
+        ));
+        let v1 = DEAL_PARAMS.clone().to_v1(*NODE_ID, *PEER_ADDRESS);
+        assert!(matches!(v1.validate_fill(&fill), Err(Error::NotFillable)));
+    }
+
+    #[test]
+    fn deal_params_at_price() {
+        let price = DEAL_PARAMS.price().expect("Deal amounts are not zero");
+        assert_eq!(
+            price.to_string(),
+            format!(
+                "{} per {}",
+                monero::Amount::from_pico(740_740_740),
+                bitcoin::Amount::ONE_BTC
+            )
+        );
+        let params = DEAL_PARAMS.clone().at_price(
+            price,
+            FixedAmount::Arbitrating(bitcoin::Amount::from_sat(2700)),
+            Rounding::Down,
+        );
+        assert_eq!(params.accordant_amount, monero::Amount::from_pico(20000));
+        assert_eq!(params.price(), Some(price));
+        let params = DEAL_PARAMS.clone().at_price(
+            price,
+            FixedAmount::Accordant(monero::Amount::from_pico(1)),
+            Rounding::Up,
+        );
+        assert_eq!(params.arbitrating_amount, bitcoin::Amount::from_sat(1));
+        assert!(params.price().unwrap() < price);
+    }
+
     #[test]
     fn serialize_deal_in_yaml() {
         let deal =
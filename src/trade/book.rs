@@ -0,0 +1,380 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! In-memory book of the deals received from the network.
+//!
+//! A [`DealBook`] stores deals by [`DealFingerprint`] and by uuid. Deals re-broadcast by makers
+//! with the same content are deduplicated, a deal published again under the same uuid with a new
+//! content replaces the previous one. Deals can be filtered with a [`DealFilter`], sorted by price
+//! and removed once expired.
+//!
+//! The book serializes with serde as the list of its deals.
+
+use serde::ser::{Serialize, SerializeSeq, Serializer};
+use serde::{Deserialize, Deserializer};
+use uuid::Uuid;
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::blockchain::{Blockchain, Network};
+use crate::consensus::Encodable;
+use crate::role::{SwapRole, TradeRole};
+use crate::trade::{AtomicAmount, Deal, DealFingerprint, Price};
+
+/// Criteria selecting deals in a [`DealBook`]. Criteria set to `None` are not checked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DealFilter<Amt, Bmt> {
+    /// The network of the deal.
+    pub network: Option<Network>,
+    /// The arbitrating blockchain of the deal.
+    pub arbitrating_blockchain: Option<Blockchain>,
+    /// The accordant blockchain of the deal.
+    pub accordant_blockchain: Option<Blockchain>,
+    /// The swap role of the taker of the deal.
+    pub taker_role: Option<SwapRole>,
+    /// The minimum arbitrating amount, inclusive. A deal with a fill range matches if its maximum
+    /// is above.
+    pub min_arbitrating_amount: Option<Amt>,
+    /// The maximum arbitrating amount, inclusive. A deal with a fill range matches if its minimum
+    /// is below.
+    pub max_arbitrating_amount: Option<Amt>,
+    /// The minimum price, inclusive.
+    pub min_price: Option<Price<Amt, Bmt>>,
+    /// The maximum price, inclusive.
+    pub max_price: Option<Price<Amt, Bmt>>,
+}
+
+impl<Amt, Bmt> Default for DealFilter<Amt, Bmt> {
+    fn default() -> Self {
+        Self {
+            network: None,
+            arbitrating_blockchain: None,
+            accordant_blockchain: None,
+            taker_role: None,
+            min_arbitrating_amount: None,
+            max_arbitrating_amount: None,
+            min_price: None,
+            max_price: None,
+        }
+    }
+}
+
+impl<Amt, Bmt> DealFilter<Amt, Bmt>
+where
+    Amt: AtomicAmount + Copy + Eq + PartialOrd,
+    Bmt: AtomicAmount + Copy + Eq,
+{
+    /// Return `true` if the deal matches all the criteria of the filter. Deals without a price
+    /// do not match price bounds.
+    pub fn matches<Ti, F>(&self, deal: &Deal<Amt, Bmt, Ti, F>) -> bool {
+        let params = &deal.parameters;
+        let (min_amount, max_amount) = match &deal.fill_range {
+            Some(range) => (&range.min_arbitrating_amount, &range.max_arbitrating_amount),
+            None => (&params.arbitrating_amount, &params.arbitrating_amount),
+        };
+        let price = deal.price();
+        self.network.map_or(true, |n| n == params.network)
+            && self
+                .arbitrating_blockchain
+                .map_or(true, |b| b == params.arbitrating_blockchain)
+            && self
+                .accordant_blockchain
+                .map_or(true, |b| b == params.accordant_blockchain)
+            && self
+                .taker_role
+                .map_or(true, |r| r == deal.swap_role(&TradeRole::Taker))
+            && self
+                .min_arbitrating_amount
+                .as_ref()
+                .map_or(true, |min| max_amount >= min)
+            && self
+                .max_arbitrating_amount
+                .as_ref()
+                .map_or(true, |max| min_amount <= max)
+            && self
+                .min_price
+                .as_ref()
+                .map_or(true, |min| price.map_or(false, |price| price >= *min))
+            && self
+                .max_price
+                .as_ref()
+                .map_or(true, |max| price.map_or(false, |price| price <= *max))
+    }
+}
+
+/// An in-memory book of deals keyed by [`DealFingerprint`] and uuid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DealBook<Amt, Bmt, Ti, F> {
+    deals: BTreeMap<DealFingerprint, Deal<Amt, Bmt, Ti, F>>,
+    uuids: HashMap<Uuid, DealFingerprint>,
+}
+
+impl<Amt, Bmt, Ti, F> Default for DealBook<Amt, Bmt, Ti, F> {
+    fn default() -> Self {
+        Self {
+            deals: BTreeMap::new(),
+            uuids: HashMap::new(),
+        }
+    }
+}
+
+impl<Amt, Bmt, Ti, F> DealBook<Amt, Bmt, Ti, F> {
+    /// Create an empty deal book.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the number of deals in the book.
+    pub fn len(&self) -> usize {
+        self.deals.len()
+    }
+
+    /// Return `true` if the book contains no deal.
+    pub fn is_empty(&self) -> bool {
+        self.deals.is_empty()
+    }
+
+    /// Return the deal with the fingerprint if any.
+    pub fn get(&self, fingerprint: &DealFingerprint) -> Option<&Deal<Amt, Bmt, Ti, F>> {
+        self.deals.get(fingerprint)
+    }
+
+    /// Return the deal with the uuid if any.
+    pub fn get_by_uuid(&self, uuid: &Uuid) -> Option<&Deal<Amt, Bmt, Ti, F>> {
+        self.uuids
+            .get(uuid)
+            .and_then(|fingerprint| self.get(fingerprint))
+    }
+
+    /// Remove the deal with the fingerprint and return it if any.
+    pub fn remove(&mut self, fingerprint: &DealFingerprint) -> Option<Deal<Amt, Bmt, Ti, F>> {
+        let deal = self.deals.remove(fingerprint)?;
+        self.uuids.remove(&deal.uuid());
+        Some(deal)
+    }
+
+    /// Remove the deal with the uuid and return it if any.
+    pub fn remove_by_uuid(&mut self, uuid: &Uuid) -> Option<Deal<Amt, Bmt, Ti, F>> {
+        let fingerprint = self.uuids.remove(uuid)?;
+        self.deals.remove(&fingerprint)
+    }
+
+    /// Return an iterator over the deals of the book, ordered by fingerprint.
+    pub fn iter(&self) -> impl Iterator<Item = &Deal<Amt, Bmt, Ti, F>> {
+        self.deals.values()
+    }
+
+    /// Remove the deals expired at the given time or block height and return them.
+    pub fn remove_expired(
+        &mut self,
+        timestamp: u64,
+        block_height: u32,
+    ) -> Vec<Deal<Amt, Bmt, Ti, F>> {
+        let expired: Vec<DealFingerprint> = self
+            .deals
+            .iter()
+            .filter(|(_, deal)| deal.is_expired(timestamp, block_height))
+            .map(|(fingerprint, _)| *fingerprint)
+            .collect();
+        expired
+            .iter()
+            .filter_map(|fingerprint| self.remove(fingerprint))
+            .collect()
+    }
+}
+
+impl<Amt, Bmt, Ti, F> DealBook<Amt, Bmt, Ti, F>
+where
+    Deal<Amt, Bmt, Ti, F>: Encodable,
+{
+    /// Insert the deal in the book, return `false` if a deal with the same fingerprint is already
+    /// in the book. A deal already in the book with the same uuid is replaced.
+    pub fn insert(&mut self, deal: Deal<Amt, Bmt, Ti, F>) -> bool {
+        let fingerprint = deal.fingerprint();
+        if self.deals.contains_key(&fingerprint) {
+            return false;
+        }
+        self.remove_by_uuid(&deal.uuid());
+        self.uuids.insert(deal.uuid(), fingerprint);
+        self.deals.insert(fingerprint, deal);
+        true
+    }
+}
+
+impl<Amt, Bmt, Ti, F> DealBook<Amt, Bmt, Ti, F>
+where
+    Amt: AtomicAmount + Copy + Eq + PartialOrd,
+    Bmt: AtomicAmount + Copy + Eq,
+{
+    /// Return the deals matching the filter, ordered by fingerprint.
+    pub fn filter<'a>(
+        &'a self,
+        filter: &'a DealFilter<Amt, Bmt>,
+    ) -> impl Iterator<Item = &'a Deal<Amt, Bmt, Ti, F>> {
+        self.iter().filter(move |deal| filter.matches(deal))
+    }
+
+    /// Return the deals matching the filter sorted by price, from the lowest to the highest
+    /// accordant amount per arbitrating amount, deals without a price last.
+    pub fn sorted_by_price(&self, filter: &DealFilter<Amt, Bmt>) -> Vec<&Deal<Amt, Bmt, Ti, F>> {
+        let mut deals: Vec<_> = self.iter().filter(|deal| filter.matches(deal)).collect();
+        deals.sort_by_key(|deal| (deal.price().is_none(), deal.price()));
+        deals
+    }
+}
+
+impl<Amt, Bmt, Ti, F> Serialize for DealBook<Amt, Bmt, Ti, F>
+where
+    Deal<Amt, Bmt, Ti, F>: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(self.len()))?;
+        for deal in self.iter() {
+            seq.serialize_element(deal)?;
+        }
+        seq.end()
+    }
+}
+
+impl<'de, Amt, Bmt, Ti, F> Deserialize<'de> for DealBook<Amt, Bmt, Ti, F>
+where
+    Deal<Amt, Bmt, Ti, F>: Deserialize<'de> + Encodable,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let mut book = Self::new();
+        for deal in Vec::<Deal<Amt, Bmt, Ti, F>>::deserialize(deserializer)? {
+            book.insert(deal);
+        }
+        Ok(book)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitcoin::fee::SatPerVByte;
+    use crate::bitcoin::timelock::CSVTimelock;
+    use crate::blockchain::FeeStrategy;
+    use crate::swap::btcxmr::{Deal, DealParameters};
+    use crate::trade::DealExpiry;
+
+    use inet2_addr::InetSocketAddr;
+    use secp256k1::PublicKey;
+    use std::str::FromStr;
+    use uuid::uuid;
+
+    fn deal(uuid: Uuid, sat: u64, pico: u64) -> Deal {
+        let node_id = PublicKey::from_str(
+            "02e77b779cdc2c713823f7a19147a67e4209c74d77e2cb5045bce0584a6be064d4",
+        )
+        .unwrap();
+        let peer_address = InetSocketAddr::socket(
+            FromStr::from_str("1.2.3.4").unwrap(),
+            FromStr::from_str("9735").unwrap(),
+        );
+        DealParameters {
+            uuid,
+            network: Network::Testnet,
+            arbitrating_blockchain: Blockchain::Bitcoin,
+            accordant_blockchain: Blockchain::Monero,
+            arbitrating_amount: bitcoin::Amount::from_sat(sat),
+            accordant_amount: monero::Amount::from_pico(pico),
+            cancel_timelock: CSVTimelock::from_blocks(10),
+            punish_timelock: CSVTimelock::from_blocks(20),
+            fee_strategy: FeeStrategy::Fixed(SatPerVByte::from_sat(10)),
+            maker_role: SwapRole::Bob,
+        }
+        .to_v1(node_id, peer_address)
+    }
+
+    #[test]
+    fn deduplicate_and_replace_deals() {
+        let mut book = DealBook::new();
+        let first = deal(uuid!("67e55044-10b1-426f-9247-bb680e5fe0c8"), 1000, 1000);
+        assert!(book.insert(first.clone()));
+
+        // Re-broadcast with a new uuid has the same fingerprint
+        let mut rebroadcast = first.clone();
+        rebroadcast.randomize_uuid();
+        assert!(!book.insert(rebroadcast));
+        assert_eq!(book.len(), 1);
+
+        // Same uuid with a new content replaces the deal
+        let updated = deal(first.uuid(), 1000, 2000);
+        assert!(book.insert(updated.clone()));
+        assert_eq!(book.len(), 1);
+        assert_eq!(book.get_by_uuid(&first.uuid()), Some(&updated));
+        assert_eq!(book.get(&first.fingerprint()), None);
+    }
+
+    #[test]
+    fn filter_and_sort_deals_by_price() {
+        let mut book = DealBook::new();
+        let cheap = deal(uuid!("67e55044-10b1-426f-9247-bb680e5fe0c1"), 1000, 3000);
+        let mid = deal(uuid!("67e55044-10b1-426f-9247-bb680e5fe0c2"), 2000, 4000);
+        let mut expensive = deal(uuid!("67e55044-10b1-426f-9247-bb680e5fe0c3"), 1000, 1000);
+        expensive.parameters.maker_role = SwapRole::Alice;
+        for deal in [&cheap, &mid, &expensive] {
+            book.insert(deal.clone());
+        }
+
+        let all = DealFilter::default();
+        assert_eq!(book.sorted_by_price(&all), vec![&expensive, &mid, &cheap]);
+
+        let filter = DealFilter {
+            taker_role: Some(SwapRole::Alice),
+            min_price: Price::new(bitcoin::Amount::from_sat(1), monero::Amount::from_pico(2)),
+            ..Default::default()
+        };
+        assert_eq!(book.sorted_by_price(&filter), vec![&mid, &cheap]);
+
+        let filter = DealFilter {
+            min_arbitrating_amount: Some(bitcoin::Amount::from_sat(1500)),
+            ..Default::default()
+        };
+        assert_eq!(book.filter(&filter).collect::<Vec<_>>(), vec![&mid]);
+    }
+
+    #[test]
+    fn remove_expired_deals_and_serialize() {
+        let mut book = DealBook::new();
+        let expiring = deal(uuid!("67e55044-10b1-426f-9247-bb680e5fe0c1"), 1000, 1000);
+        let expiring = expiring.parameters.to_v2(
+            expiring.node_id,
+            expiring.peer_address,
+            Some(DealExpiry::Timestamp(100)),
+            1,
+        );
+        let other = deal(uuid!("67e55044-10b1-426f-9247-bb680e5fe0c2"), 1000, 1000);
+        book.insert(expiring.clone());
+        book.insert(other.clone());
+
+        let s = serde_yaml::to_string(&book).expect("Encode deal book in yaml");
+        let decoded: DealBook<_, _, _, _> =
+            serde_yaml::from_str(&s).expect("Decode deal book from yaml");
+        assert_eq!(decoded, book);
+
+        assert!(book.remove_expired(99, 0).is_empty());
+        assert_eq!(book.remove_expired(100, 0), vec![expiring]);
+        assert_eq!(book.iter().collect::<Vec<_>>(), vec![&other]);
+    }
+}
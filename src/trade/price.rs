@@ -0,0 +1,272 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Exact price of a deal, the rate between its arbitrating and accordant amounts.
+//!
+//! A [`Price`] is a fraction of atomic units, e.g. piconero per satoshi, compared and converted
+//! without floating point arithmetic. Quotes in whole units of each asset are displayed with the
+//! decimals of the asset.
+//!
+//! ```
+//! use farcaster_core::trade::{Price, Rounding};
+//!
+//! // 150 XMR for 1 BTC
+//! let price = Price::new(
+//!     bitcoin::Amount::from_btc(1.0).unwrap(),
+//!     monero::Amount::from_xmr(150.0).unwrap(),
+//! )
+//! .unwrap();
+//! assert_eq!(
+//!     price.accordant_per_arbitrating(Rounding::Down),
+//!     monero::Amount::from_xmr(150.0).unwrap()
+//! );
+//! assert_eq!(
+//!     price.accordant_amount(bitcoin::Amount::from_sat(1), Rounding::Down),
+//!     monero::Amount::from_pico(1_500_000)
+//! );
+//! ```
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::marker::PhantomData;
+
+use crate::trade::AtomicAmount;
+
+/// Rounding of an amount computed at a [`Price`] to the atomic unit of its asset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display)]
+#[display(Debug)]
+pub enum Rounding {
+    /// Round toward zero.
+    Down,
+    /// Round away from zero.
+    Up,
+    /// Round to the nearest atomic unit, half away from zero.
+    Nearest,
+}
+
+impl Rounding {
+    // Divide with the rounding mode, the denominator must not be zero
+    fn div(&self, numerator: u128, denominator: u128) -> u128 {
+        let quotient = numerator / denominator;
+        let remainder = numerator % denominator;
+        match self {
+            Self::Down => quotient,
+            Self::Up if remainder > 0 => quotient + 1,
+            Self::Up => quotient,
+            Self::Nearest if remainder * 2 >= denominator => quotient + 1,
+            Self::Nearest => quotient,
+        }
+    }
+}
+
+/// The amount fixed when computing the amounts of a deal at a [`Price`], the other amount is
+/// derived from the price.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FixedAmount<Amt, Bmt> {
+    /// The arbitrating amount is fixed.
+    Arbitrating(Amt),
+    /// The accordant amount is fixed.
+    Accordant(Bmt),
+}
+
+/// Exact price of a deal, the accordant amount exchanged for an arbitrating amount. The fraction
+/// of atomic units is stored irreducible, equal prices are equal whatever the amounts they are
+/// created from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Price<Amt, Bmt> {
+    arbitrating_units: u64,
+    accordant_units: u64,
+    _amounts: PhantomData<(Amt, Bmt)>,
+}
+
+impl<Amt, Bmt> Price<Amt, Bmt>
+where
+    Amt: AtomicAmount + Copy,
+    Bmt: AtomicAmount + Copy,
+{
+    /// Create the price of the accordant amount exchanged for the arbitrating amount, `None` if
+    /// one of the amounts is zero.
+    pub fn new(arbitrating_amount: Amt, accordant_amount: Bmt) -> Option<Self> {
+        let arbitrating_units = arbitrating_amount.as_atomic_units();
+        let accordant_units = accordant_amount.as_atomic_units();
+        if arbitrating_units == 0 || accordant_units == 0 {
+            return None;
+        }
+        let gcd = gcd(arbitrating_units, accordant_units);
+        Some(Self {
+            arbitrating_units: arbitrating_units / gcd,
+            accordant_units: accordant_units / gcd,
+            _amounts: PhantomData,
+        })
+    }
+
+    /// Return the accordant amount exchanged for the arbitrating amount at this price.
+    pub fn accordant_amount(&self, arbitrating_amount: Amt, rounding: Rounding) -> Bmt {
+        Bmt::from_atomic_units(saturate(rounding.div(
+            arbitrating_amount.as_atomic_units() as u128 * self.accordant_units as u128,
+            self.arbitrating_units as u128,
+        )))
+    }
+
+    /// Return the arbitrating amount exchanged for the accordant amount at this price.
+    pub fn arbitrating_amount(&self, accordant_amount: Bmt, rounding: Rounding) -> Amt {
+        Amt::from_atomic_units(saturate(rounding.div(
+            accordant_amount.as_atomic_units() as u128 * self.arbitrating_units as u128,
+            self.accordant_units as u128,
+        )))
+    }
+
+    /// Return both amounts at this price, the amount not fixed is computed with the rounding.
+    pub fn amounts(&self, fixed: FixedAmount<Amt, Bmt>, rounding: Rounding) -> (Amt, Bmt) {
+        match fixed {
+            FixedAmount::Arbitrating(amount) => {
+                let accordant = self.accordant_amount(amount, rounding);
+                (amount, accordant)
+            }
+            FixedAmount::Accordant(amount) => (self.arbitrating_amount(amount, rounding), amount),
+        }
+    }
+
+    /// Return the accordant amount exchanged for one whole arbitrating unit, e.g. XMR per BTC.
+    pub fn accordant_per_arbitrating(&self, rounding: Rounding) -> Bmt {
+        self.accordant_amount(one(), rounding)
+    }
+
+    /// Return the arbitrating amount exchanged for one whole accordant unit, e.g. BTC per XMR.
+    pub fn arbitrating_per_accordant(&self, rounding: Rounding) -> Amt {
+        self.arbitrating_amount(one(), rounding)
+    }
+}
+
+impl<Amt, Bmt> PartialOrd for Price<Amt, Bmt>
+where
+    Amt: Eq,
+    Bmt: Eq,
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// A price is higher than another when more accordant assets are exchanged for the same
+/// arbitrating amount.
+impl<Amt, Bmt> Ord for Price<Amt, Bmt>
+where
+    Amt: Eq,
+    Bmt: Eq,
+{
+    fn cmp(&self, other: &Self) -> Ordering {
+        let lhs = self.accordant_units as u128 * other.arbitrating_units as u128;
+        let rhs = other.accordant_units as u128 * self.arbitrating_units as u128;
+        lhs.cmp(&rhs)
+    }
+}
+
+/// Display the accordant amount exchanged for one whole arbitrating unit, rounded down, e.g.
+/// `150.000000000000 XMR per 1.00000000 BTC`.
+impl<Amt, Bmt> fmt::Display for Price<Amt, Bmt>
+where
+    Amt: AtomicAmount + Copy + fmt::Display,
+    Bmt: AtomicAmount + Copy + fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} per {}",
+            self.accordant_per_arbitrating(Rounding::Down),
+            one::<Amt>()
+        )
+    }
+}
+
+// Return one whole unit of the asset
+fn one<T: AtomicAmount>() -> T {
+    T::from_atomic_units(10u64.pow(T::DECIMALS))
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        let r = a % b;
+        a = b;
+        b = r;
+    }
+    a
+}
+
+fn saturate(units: u128) -> u64 {
+    units.min(u64::MAX as u128) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type XmrPrice = Price<bitcoin::Amount, monero::Amount>;
+
+    fn sat(sat: u64) -> bitcoin::Amount {
+        bitcoin::Amount::from_sat(sat)
+    }
+
+    fn pico(pico: u64) -> monero::Amount {
+        monero::Amount::from_pico(pico)
+    }
+
+    #[test]
+    fn compute_exact_prices() {
+        // 3 XMR for 0.02 BTC
+        let price = XmrPrice::new(sat(2_000_000), pico(3_000_000_000_000)).unwrap();
+        assert_eq!(price, XmrPrice::new(sat(2), pico(3_000_000)).unwrap());
+        assert_eq!(
+            price.accordant_per_arbitrating(Rounding::Down),
+            pico(150_000_000_000_000)
+        );
+        assert_eq!(
+            price.arbitrating_per_accordant(Rounding::Down),
+            sat(666_666)
+        );
+        assert_eq!(price.arbitrating_per_accordant(Rounding::Up), sat(666_667));
+        assert_eq!(
+            price.arbitrating_per_accordant(Rounding::Nearest),
+            sat(666_667)
+        );
+        assert!(XmrPrice::new(sat(0), pico(1)).is_none());
+    }
+
+    #[test]
+    fn compare_prices() {
+        let low = XmrPrice::new(sat(3), pico(1)).unwrap();
+        let high = XmrPrice::new(sat(2), pico(1)).unwrap();
+        assert!(low < high);
+        assert_eq!(low.max(high), high);
+    }
+
+    #[test]
+    fn compute_amounts_with_rounding() {
+        let price = XmrPrice::new(sat(3), pico(2)).unwrap();
+        assert_eq!(
+            price.amounts(FixedAmount::Arbitrating(sat(4)), Rounding::Down),
+            (sat(4), pico(2))
+        );
+        assert_eq!(
+            price.amounts(FixedAmount::Arbitrating(sat(4)), Rounding::Nearest),
+            (sat(4), pico(3))
+        );
+        assert_eq!(
+            price.amounts(FixedAmount::Accordant(pico(3)), Rounding::Up),
+            (sat(5), pico(3))
+        );
+    }
+}
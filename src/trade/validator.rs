@@ -0,0 +1,384 @@
+// Copyright 2021-2022 Farcaster Devs
+//
+// This library is free software; you can redistribute it and/or
+// modify it under the terms of the GNU Lesser General Public
+// License as published by the Free Software Foundation; either
+// version 3 of the License, or (at your option) any later version.
+//
+// This library is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public
+// License along with this library; if not, write to the Free Software
+// Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA 02110-1301, USA
+
+//! Validation of deals against a local policy before starting a swap.
+//!
+//! A [`DealPolicy`] lists what a participant is willing to trade: the networks, the blockchains,
+//! the amounts, the minimum timelocks and the fee rates. A [`DealValidator`] checks the deal
+//! parameters against the policy and returns every [`Violation`] found, not only the first one.
+//!
+//! ```
+//! use farcaster_core::blockchain::Network;
+//! use farcaster_core::swap::btcxmr::{Deal, DealPolicy, DealValidator};
+//!
+//! let deal = "Deal:Cke4ftrP5A7CRkYdGNd87TRU6sUP1kBKM1LQM2fvVdFMNR4gmBqNCsR11111uMM4pF11111112Lvo11111TBALTh113GTvtvqfD1111114A4TUWxWeBc1WxwGBKaUssrb6pnijjhnb6RAs1HBr1CaX7o1a1111111111111111111111111111111111111111115T1WG8uDoZeAW1q";
+//! let deal: Deal = deal.parse().unwrap();
+//!
+//! let validator = DealValidator::new(DealPolicy::default());
+//! assert!(validator.validate(&deal).is_ok());
+//!
+//! let policy = DealPolicy {
+//!     networks: vec![Network::Mainnet],
+//!     ..Default::default()
+//! };
+//! let violations = DealValidator::new(policy).check(&deal.parameters);
+//! assert_eq!(violations.len(), 1);
+//! ```
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::blockchain::{Blockchain, FeeStrategy, Network};
+use crate::consensus::CanonicalBytes;
+use crate::trade::{self, Deal, DealParameters};
+
+/// A rule of a [`DealPolicy`] not respected by a deal.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Violation {
+    /// The deal is on a network not accepted by the policy.
+    #[error("Network {0} is not accepted")]
+    UnacceptedNetwork(Network),
+    /// The arbitrating blockchain of the deal is not the one of the policy.
+    #[error("Arbitrating blockchain {found} does not match {expected}")]
+    ArbitratingBlockchainMismatch {
+        /// The arbitrating blockchain of the policy.
+        expected: Blockchain,
+        /// The arbitrating blockchain of the deal.
+        found: Blockchain,
+    },
+    /// The accordant blockchain of the deal is not the one of the policy.
+    #[error("Accordant blockchain {found} does not match {expected}")]
+    AccordantBlockchainMismatch {
+        /// The accordant blockchain of the policy.
+        expected: Blockchain,
+        /// The accordant blockchain of the deal.
+        found: Blockchain,
+    },
+    /// The arbitrating amount is below the policy minimum, e.g. the dust limit.
+    #[error("Arbitrating amount is too low")]
+    ArbitratingAmountTooLow,
+    /// The arbitrating amount is above the policy maximum.
+    #[error("Arbitrating amount is too high")]
+    ArbitratingAmountTooHigh,
+    /// The accordant amount is below the policy minimum.
+    #[error("Accordant amount is too low")]
+    AccordantAmountTooLow,
+    /// The accordant amount is above the policy maximum.
+    #[error("Accordant amount is too high")]
+    AccordantAmountTooHigh,
+    /// The cancel timelock is not a valid timelock value, e.g. a raw CSV timelock outside of
+    /// BIP-68.
+    #[error("Cancel timelock is not valid")]
+    InvalidCancelTimelock,
+    /// The punish timelock is not a valid timelock value, e.g. a raw CSV timelock outside of
+    /// BIP-68.
+    #[error("Punish timelock is not valid")]
+    InvalidPunishTimelock,
+    /// The cancel timelock is shorter than the policy minimum for the network of the deal.
+    #[error("Cancel timelock is too short")]
+    CancelTimelockTooShort,
+    /// The punish timelock is shorter than the policy minimum for the network of the deal.
+    #[error("Punish timelock is too short")]
+    PunishTimelockTooShort,
+    /// The cancel timelock is not strictly shorter than the punish timelock, or they cannot be
+    /// compared.
+    #[error("Cancel timelock is not shorter than punish timelock")]
+    TimelockOrdering,
+    /// The fee strategy is outside of the fee rates accepted by the policy or is an empty range.
+    #[error("Fee strategy is out of bounds")]
+    FeeStrategyOutOfBounds,
+}
+
+/// The deals a participant is willing to trade. Optional bounds set to `None` are not checked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DealPolicy<Amt, Bmt, Ti, F> {
+    /// The accepted networks.
+    pub networks: Vec<Network>,
+    /// The expected arbitrating blockchain.
+    pub arbitrating_blockchain: Blockchain,
+    /// The expected accordant blockchain.
+    pub accordant_blockchain: Blockchain,
+    /// The minimum arbitrating amount, inclusive.
+    pub min_arbitrating_amount: Option<Amt>,
+    /// The maximum arbitrating amount, inclusive.
+    pub max_arbitrating_amount: Option<Amt>,
+    /// The minimum accordant amount, inclusive.
+    pub min_accordant_amount: Option<Bmt>,
+    /// The maximum accordant amount, inclusive.
+    pub max_accordant_amount: Option<Bmt>,
+    /// The minimum cancel timelock per network, inclusive.
+    pub min_cancel_timelock: HashMap<Network, Ti>,
+    /// The minimum punish timelock per network, inclusive.
+    pub min_punish_timelock: HashMap<Network, Ti>,
+    /// The minimum fee rate of the fee strategy, inclusive.
+    pub min_fee_rate: Option<F>,
+    /// The maximum fee rate of the fee strategy, inclusive.
+    pub max_fee_rate: Option<F>,
+}
+
+impl<Amt, Bmt, Ti, F> DealPolicy<Amt, Bmt, Ti, F> {
+    /// Create a policy accepting any deal on any network between the two blockchains.
+    pub fn new(arbitrating_blockchain: Blockchain, accordant_blockchain: Blockchain) -> Self {
+        Self {
+            networks: vec![Network::Mainnet, Network::Testnet, Network::Local],
+            arbitrating_blockchain,
+            accordant_blockchain,
+            min_arbitrating_amount: None,
+            max_arbitrating_amount: None,
+            min_accordant_amount: None,
+            max_accordant_amount: None,
+            min_cancel_timelock: HashMap::new(),
+            min_punish_timelock: HashMap::new(),
+            min_fee_rate: None,
+            max_fee_rate: None,
+        }
+    }
+}
+
+/// Validate deals against a [`DealPolicy`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DealValidator<Amt, Bmt, Ti, F> {
+    policy: DealPolicy<Amt, Bmt, Ti, F>,
+}
+
+impl<Amt, Bmt, Ti, F> DealValidator<Amt, Bmt, Ti, F>
+where
+    Amt: PartialOrd,
+    Bmt: PartialOrd,
+    Ti: PartialOrd + CanonicalBytes,
+    F: PartialOrd,
+{
+    /// Create a new validator enforcing the policy.
+    pub fn new(policy: DealPolicy<Amt, Bmt, Ti, F>) -> Self {
+        Self { policy }
+    }
+
+    /// Return the policy enforced by the validator.
+    pub fn policy(&self) -> &DealPolicy<Amt, Bmt, Ti, F> {
+        &self.policy
+    }
+
+    /// Return all the violations of the policy by the deal parameters, an empty list if the
+    /// parameters are valid.
+    pub fn check(&self, params: &DealParameters<Amt, Bmt, Ti, F>) -> Vec<Violation> {
+        let policy = &self.policy;
+        let mut violations = vec![];
+
+        if !policy.networks.contains(&params.network) {
+            violations.push(Violation::UnacceptedNetwork(params.network));
+        }
+        if params.arbitrating_blockchain != policy.arbitrating_blockchain {
+            violations.push(Violation::ArbitratingBlockchainMismatch {
+                expected: policy.arbitrating_blockchain,
+                found: params.arbitrating_blockchain,
+            });
+        }
+        if params.accordant_blockchain != policy.accordant_blockchain {
+            violations.push(Violation::AccordantBlockchainMismatch {
+                expected: policy.accordant_blockchain,
+                found: params.accordant_blockchain,
+            });
+        }
+
+        if below(&params.arbitrating_amount, &policy.min_arbitrating_amount) {
+            violations.push(Violation::ArbitratingAmountTooLow);
+        }
+        if above(&params.arbitrating_amount, &policy.max_arbitrating_amount) {
+            violations.push(Violation::ArbitratingAmountTooHigh);
+        }
+        if below(&params.accordant_amount, &policy.min_accordant_amount) {
+            violations.push(Violation::AccordantAmountTooLow);
+        }
+        if above(&params.accordant_amount, &policy.max_accordant_amount) {
+            violations.push(Violation::AccordantAmountTooHigh);
+        }
+
+        // Timelocks built from raw values are only valid if they can be decoded back, as done
+        // when receiving a deal
+        if !is_valid(&params.cancel_timelock) {
+            violations.push(Violation::InvalidCancelTimelock);
+        }
+        if !is_valid(&params.punish_timelock) {
+            violations.push(Violation::InvalidPunishTimelock);
+        }
+
+        // Timelocks of different units, e.g. blocks and time, cannot be compared and are
+        // rejected
+        if below(
+            &params.cancel_timelock,
+            &policy.min_cancel_timelock.get(&params.network),
+        ) {
+            violations.push(Violation::CancelTimelockTooShort);
+        }
+        if below(
+            &params.punish_timelock,
+            &policy.min_punish_timelock.get(&params.network),
+        ) {
+            violations.push(Violation::PunishTimelockTooShort);
+        }
+        if params.cancel_timelock.partial_cmp(&params.punish_timelock) != Some(Ordering::Less) {
+            violations.push(Violation::TimelockOrdering);
+        }
+
+        let (min, max) = match &params.fee_strategy {
+            FeeStrategy::Fixed(rate) => (rate, rate),
+            FeeStrategy::Range { min_inc, max_inc } => (min_inc, max_inc),
+        };
+        if matches!(min.partial_cmp(max), None | Some(Ordering::Greater))
+            || below(min, &policy.min_fee_rate)
+            || above(max, &policy.max_fee_rate)
+        {
+            violations.push(Violation::FeeStrategyOutOfBounds);
+        }
+
+        violations
+    }
+
+    /// Validate the deal against the policy, returns [`trade::Error::InvalidDeal`] with all the
+    /// violations if the deal is not valid.
+    pub fn validate(&self, deal: &Deal<Amt, Bmt, Ti, F>) -> Result<(), trade::Error> {
+        let violations = self.check(&deal.parameters);
+        match violations.is_empty() {
+            true => Ok(()),
+            false => Err(trade::Error::InvalidDeal(violations)),
+        }
+    }
+}
+
+// Return true if the value decodes from its canonical bytes
+fn is_valid<T: CanonicalBytes>(value: &T) -> bool {
+    T::from_canonical_bytes(&value.as_canonical_bytes()).is_ok()
+}
+
+// Return true if the value is not above or equal to the bound, including when they cannot be
+// compared
+fn below<T: PartialOrd, B: std::borrow::Borrow<T>>(value: &T, bound: &Option<B>) -> bool {
+    matches!(bound, Some(bound) if matches!(value.partial_cmp(bound.borrow()), None | Some(Ordering::Less)))
+}
+
+// Return true if the value is not below or equal to the bound, including when they cannot be
+// compared
+fn above<T: PartialOrd>(value: &T, bound: &Option<T>) -> bool {
+    matches!(bound, Some(bound) if matches!(value.partial_cmp(bound), None | Some(Ordering::Greater)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitcoin::fee::SatPerVByte;
+    use crate::bitcoin::timelock::CSVTimelock;
+    use crate::role::SwapRole;
+    use crate::swap::btcxmr::{DealParameters, DealPolicy, DealValidator};
+
+    use uuid::uuid;
+
+    fn params() -> DealParameters {
+        DealParameters {
+            uuid: uuid!("67e55044-10b1-426f-9247-bb680e5fe0c8"),
+            network: Network::Mainnet,
+            arbitrating_blockchain: Blockchain::Bitcoin,
+            accordant_blockchain: Blockchain::Monero,
+            arbitrating_amount: bitcoin::Amount::from_sat(100_000),
+            accordant_amount: monero::Amount::from_pico(1_000_000),
+            cancel_timelock: CSVTimelock::from_blocks(10),
+            punish_timelock: CSVTimelock::from_blocks(20),
+            fee_strategy: FeeStrategy::Fixed(SatPerVByte::from_sat(10)),
+            maker_role: SwapRole::Bob,
+        }
+    }
+
+    #[test]
+    fn accept_deal_within_policy() {
+        let validator = DealValidator::new(DealPolicy::default());
+        assert!(validator.check(&params()).is_empty());
+    }
+
+    #[test]
+    fn list_all_violations() {
+        let validator = DealValidator::new(DealPolicy {
+            networks: vec![Network::Testnet],
+            max_accordant_amount: Some(monero::Amount::from_pico(1_000)),
+            max_fee_rate: Some(SatPerVByte::from_sat(50)),
+            ..Default::default()
+        });
+
+        let mut params = params();
+        params.accordant_blockchain = Blockchain::Bitcoin;
+        params.arbitrating_amount = bitcoin::Amount::from_sat(300);
+        params.cancel_timelock = CSVTimelock::from_blocks(5);
+        params.punish_timelock = CSVTimelock::from_blocks(5);
+        params.fee_strategy = FeeStrategy::Range {
+            min_inc: SatPerVByte::from_sat(10),
+            max_inc: SatPerVByte::from_sat(60),
+        };
+        assert_eq!(
+            validator.check(&params),
+            vec![
+                Violation::UnacceptedNetwork(Network::Mainnet),
+                Violation::AccordantBlockchainMismatch {
+                    expected: Blockchain::Monero,
+                    found: Blockchain::Bitcoin,
+                },
+                Violation::ArbitratingAmountTooLow,
+                Violation::AccordantAmountTooHigh,
+                Violation::CancelTimelockTooShort,
+                Violation::PunishTimelockTooShort,
+                Violation::TimelockOrdering,
+                Violation::FeeStrategyOutOfBounds,
+            ]
+        );
+    }
+
+    #[test]
+    fn reject_incomparable_timelocks() {
+        let validator = DealValidator::new(DealPolicy::default());
+        let mut params = params();
+        // One day relative timelock cannot be compared with the minimum in blocks
+        params.punish_timelock = CSVTimelock::from_seconds(86_400).unwrap();
+        assert_eq!(
+            validator.check(&params),
+            vec![
+                Violation::PunishTimelockTooShort,
+                Violation::TimelockOrdering
+            ]
+        );
+
+        // Empty fee range
+        let mut params = self::params();
+        params.fee_strategy = FeeStrategy::Range {
+            min_inc: SatPerVByte::from_sat(10),
+            max_inc: SatPerVByte::from_sat(5),
+        };
+        assert_eq!(
+            validator.check(&params),
+            vec![Violation::FeeStrategyOutOfBounds]
+        );
+    }
+
+    #[test]
+    fn reject_invalid_timelocks() {
+        let validator = DealValidator::new(DealPolicy::default());
+        let mut params = params();
+        // Raw value outside of BIP-68, the value bits are compared as 20 blocks
+        params.punish_timelock = CSVTimelock::new(1 << 23 | 20);
+        assert_eq!(
+            validator.check(&params),
+            vec![Violation::InvalidPunishTimelock]
+        );
+    }
+}
@@ -210,6 +210,28 @@ pub trait Witnessable<Ms, Pk, Si> {
 
     /// Add a cooperation to the transaction and store it internally for later usage.
     fn add_witness(&mut self, pubkey: Pk, sig: Si) -> Result<(), Error>;
+
+    /// Return the number of inputs to sign, each input requires its own witness. Transactions
+    /// have a single input by default.
+    fn inputs_count(&self) -> usize {
+        1
+    }
+
+    /// Generate the witness message of the input at the given index.
+    fn generate_input_witness_message(&self, index: usize, path: ScriptPath) -> Result<Ms, Error> {
+        match index {
+            0 => self.generate_witness_message(path),
+            _ => Err(Error::MissingUTXO),
+        }
+    }
+
+    /// Add a cooperation for the input at the given index.
+    fn add_input_witness(&mut self, index: usize, pubkey: Pk, sig: Si) -> Result<(), Error> {
+        match index {
+            0 => self.add_witness(pubkey, sig),
+            _ => Err(Error::MissingUTXO),
+        }
+    }
 }
 
 /// Define a transaction that must have a finalization step.
@@ -298,6 +320,12 @@ pub trait Fundable<Tx, Out, Addr, Pk>: Linkable<Out> {
     /// Boolean indicating whether the transaction was seen
     fn was_seen(&self) -> bool;
 
+    /// Return all the outputs available for funding the swap, e.g. when the funding address
+    /// received several payments. Returns the consumable output by default.
+    fn get_consumable_outputs(&self) -> Result<Vec<Out>, Error> {
+        Ok(vec![self.get_consumable_output()?])
+    }
+
     /// Create a raw funding structure based only on the transaction seen on-chain.
     fn raw(tx: Tx) -> Result<Self, Error>
     where
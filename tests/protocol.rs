@@ -19,15 +19,29 @@ use farcaster_core::bitcoin::BitcoinSegwitV0 as Btc;
 use farcaster_core::monero::Monero as Xmr;
 use farcaster_core::swap::btcxmr::KeyManager;
 
-use farcaster_core::blockchain::{FeePriority, Network};
-use farcaster_core::consensus::deserialize;
+use farcaster_core::bitcoin::fee::SatPerVByte;
+use farcaster_core::bitcoin::timelock::CSVTimelock;
+use farcaster_core::blockchain::{FeePriority, FeeStrategy, Network};
+use farcaster_core::consensus::{deserialize, serialize};
+use farcaster_core::crypto::musig2::PublicNonce;
 use farcaster_core::crypto::KeccakCommitment;
 use farcaster_core::crypto::{
-    ArbitratingKeyId, CommitmentEngine, GenerateKey, ProveCrossGroupDleq,
+    ArbitratingKeyId, CommitmentEngine, CommitmentNonce, CommitmentScheme, GenerateKey,
+    ProveCrossGroupDleq,
 };
+use farcaster_core::protocol::checkpoint::Checkpoint;
+use farcaster_core::protocol::machine::{self, Event, Output, Step};
 use farcaster_core::protocol::message::*;
-use farcaster_core::swap::btcxmr::{Alice, Bob, Deal, Parameters};
+use farcaster_core::protocol::transcript::TranscriptHash;
+use farcaster_core::protocol::validation::{self, Field};
+use farcaster_core::role::SwapRole;
+use farcaster_core::swap::btcxmr::message::ProtocolMessage;
+use farcaster_core::swap::btcxmr::{
+    Alice, AliceCheckpoint, AliceSwap, Bob, BobCheckpoint, BobSwap, Deal, DealPolicy,
+    DealValidator, Parameters, SwapOutput,
+};
 use farcaster_core::swap::SwapId;
+use farcaster_core::trade::{self, FillRange, Violation};
 use farcaster_core::transaction::*;
 
 use bitcoin::blockdata::transaction::{OutPoint, TxIn, TxOut};
@@ -40,6 +54,7 @@ use ecdsa_fun::adaptor::EncryptedSignature;
 
 use monero::{PrivateKey as MPriv, PublicKey as MPub};
 
+use std::collections::HashSet;
 use std::str::FromStr;
 
 macro_rules! test_strict_ser {
@@ -121,6 +136,50 @@ fn execute_offline_protocol() {
         .verify_with_reveal(&commitment_engine, reveal_bob_params.clone())
         .is_ok());
 
+    // Parameters are committed with salted commitments, the nonces must be revealed
+    assert_eq!(commit_alice_params.scheme, CommitmentScheme::Salted);
+    let mut missing_nonces = reveal_alice_params.clone();
+    missing_nonces.nonces = None;
+    assert!(commit_alice_params
+        .verify_with_reveal(&commitment_engine, missing_nonces)
+        .is_err());
+    let mut other_nonce = reveal_bob_params.clone();
+    other_nonce.nonces.as_mut().unwrap().spend = CommitmentNonce::new_random();
+    assert!(commit_bob_params
+        .verify_with_reveal(&commitment_engine, other_nonce)
+        .is_err());
+
+    // Bare commitments without nonces are still verified
+    let bare_params = Parameters {
+        commitment_nonces: None,
+        ..alice_params.clone()
+    };
+    let bare_commit = bare_params.commit_alice(swap_id, &commitment_engine);
+    assert_eq!(bare_commit.scheme, CommitmentScheme::Bare);
+    test_strict_ser!(bare_commit, CommitAliceParameters<KeccakCommitment>);
+    assert!(bare_commit
+        .verify_with_reveal(
+            &commitment_engine,
+            bare_params.clone().reveal_alice(swap_id)
+        )
+        .is_ok());
+
+    // Encodings from before the commitment scheme and nonces were added are bare and decode
+    // without nonces
+    let mut legacy_commit = serialize(&bare_commit);
+    legacy_commit.remove(32);
+    let decoded: CommitAliceParameters<KeccakCommitment> = deserialize(&legacy_commit).unwrap();
+    assert_eq!(decoded, bare_commit);
+    let mut legacy_reveal = serialize(&bare_params.clone().reveal_alice(swap_id));
+    assert_eq!(legacy_reveal.pop(), Some(0x00));
+    let decoded: RevealAliceParameters<BPub, MPub, BPriv, MPriv, Address> =
+        deserialize(&legacy_reveal).unwrap();
+    assert_eq!(decoded, bare_params.clone().reveal_alice(swap_id));
+    let mut legacy_params = serialize(&bare_params);
+    assert_eq!(legacy_params.pop(), Some(0x00));
+    let decoded: Parameters = deserialize(&legacy_params).unwrap();
+    assert_eq!(serialize(&decoded), serialize(&bare_params));
+
     //
     // Get Funding Address and Transaction
     //
@@ -159,10 +218,15 @@ fn execute_offline_protocol() {
     let bob_cosign_cancel = bob
         .cosign_arbitrating_cancel(&mut bob_key_manager, &core)
         .unwrap();
+    // The deal has a fixed fee strategy, no fee bump is created
+    let bob_cosign_bumps = bob
+        .cosign_arbitrating_cancel_bumps(&mut bob_key_manager, &core)
+        .unwrap();
+    assert!(bob_cosign_bumps.is_empty());
 
-    let core_arb_setup = core
-        .clone()
-        .into_arbitrating_setup(swap_id, bob_cosign_cancel);
+    let core_arb_setup =
+        core.clone()
+            .into_arbitrating_setup(swap_id, bob_cosign_cancel, bob_cosign_bumps);
     test_strict_ser!(core_arb_setup, CoreArbitratingSetup<PartiallySignedTransaction, Signature>);
 
     //
@@ -187,10 +251,22 @@ fn execute_offline_protocol() {
         )
         .unwrap();
 
+    let fee_bumps = alice
+        .sign_fee_bumps(
+            &mut alice_key_manager,
+            &alice_params,
+            &bob_params,
+            &core,
+            deal.to_arbitrating_params(),
+        )
+        .unwrap();
+
     let refund_proc_sig = RefundProcedureSignatures {
         swap_id,
         cancel_sig,
         refund_adaptor_sig: refund_adaptor_sig.clone(),
+        fee_bumps,
+        transcript: None,
     };
     test_strict_ser!(refund_proc_sig, RefundProcedureSignatures<Signature, EncryptedSignature>);
 
@@ -219,9 +295,10 @@ fn execute_offline_protocol() {
     let signed_lock = bob
         .sign_arbitrating_lock(&mut bob_key_manager, &core)
         .unwrap();
+    assert_eq!(signed_lock.len(), 1);
 
     let mut lock = LockTx::from_partial(core.lock.clone());
-    lock.add_witness(funding_key, signed_lock).unwrap();
+    lock.add_witness(funding_key, signed_lock[0]).unwrap();
     assert!(Broadcastable::<bitcoin::Transaction>::finalize_and_extract(&mut lock).is_ok());
 
     // ...seen arbitrating lock...
@@ -271,12 +348,14 @@ fn execute_offline_protocol() {
         .expect("Considered valid in tests");
 
     let secp = Secp256k1::new();
-    let btc_adaptor_priv = bob.recover_accordant_key(
-        &mut bob_key_manager,
-        &alice_params,
-        adaptor_buy.buy_adaptor_sig,
-        buy_tx,
-    );
+    let btc_adaptor_priv = bob
+        .recover_accordant_key(
+            &mut bob_key_manager,
+            &alice_params,
+            adaptor_buy.buy_adaptor_sig,
+            buy_tx,
+        )
+        .unwrap();
     let mut secret_bits: Vec<u8> = (*btc_adaptor_priv.as_ref()).into();
     secret_bits.reverse();
     let xmr_spend_priv = MPriv::from_slice(secret_bits.as_ref()).expect("Valid Monero Private Key");
@@ -327,12 +406,14 @@ fn execute_offline_protocol() {
         .generate_proof()
         .expect("Considered valid in tests");
 
-    let btc_adaptor_priv = alice.recover_accordant_key(
-        &mut alice_key_manager,
-        &bob_params,
-        refund_adaptor_sig,
-        refund_tx,
-    );
+    let btc_adaptor_priv = alice
+        .recover_accordant_key(
+            &mut alice_key_manager,
+            &bob_params,
+            refund_adaptor_sig,
+            refund_tx,
+        )
+        .unwrap();
     let mut secret_bits: Vec<u8> = (*btc_adaptor_priv.as_ref()).into();
     secret_bits.reverse();
     let xmr_spend_priv = MPriv::from_slice(secret_bits.as_ref()).expect("Valid Monero Private Key");
@@ -369,3 +450,703 @@ fn execute_offline_protocol() {
         .unwrap();
     let _ = Broadcastable::<bitcoin::Transaction>::finalize_and_extract(&mut refund).unwrap();
 }
+
+fn unwrap_message(output: SwapOutput) -> ProtocolMessage {
+    match output {
+        Output::Message(msg) => {
+            // Every message goes through its wire frame before reaching the counterparty
+            let bytes = serialize(&msg);
+            let framed: ProtocolMessage = deserialize(&bytes).expect("Parsable message");
+            assert_eq!(framed.message_type(), msg.message_type());
+            assert_eq!(serialize(&framed), bytes);
+            framed
+        }
+        output => panic!("Expected a message, found {:?}", output),
+    }
+}
+
+fn unwrap_broadcast(output: SwapOutput, label: TxLabel) -> bitcoin::Transaction {
+    match output {
+        Output::Broadcast(l, tx) if l == label => tx,
+        output => panic!("Expected a {} broadcast, found {:?}", label, output),
+    }
+}
+
+fn assert_recovered_key(output: SwapOutput, key_manager: &mut KeyManager) {
+    let (_, encryption_key, _) = key_manager.generate_proof().unwrap();
+    match output {
+        Output::RecoveredAccordantKey(key) => assert_eq!(
+            BPub::from_secret_key(&Secp256k1::new(), &key),
+            encryption_key
+        ),
+        output => panic!("Expected a recovered key, found {:?}", output),
+    }
+}
+
+fn assert_out_of_order(res: Result<Vec<SwapOutput>, farcaster_core::Error>) {
+    assert!(matches!(
+        res,
+        Err(farcaster_core::Error::StateMachine(
+            machine::Error::OutOfOrder { .. }
+        ))
+    ));
+}
+
+/// Save and restore the state machines and their key managers through a checkpoint after each
+/// step when enabled, and record the steps at which checkpoints are taken.
+struct Checkpoints {
+    enabled: bool,
+    steps: HashSet<Step>,
+}
+
+impl Checkpoints {
+    fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            steps: HashSet::new(),
+        }
+    }
+
+    fn alice(&mut self, swap: &mut AliceSwap, key_manager: &mut KeyManager) {
+        if self.enabled {
+            self.steps.insert(swap.step());
+            let checkpoint = AliceCheckpoint::new(swap.clone(), key_manager.clone());
+            (*swap, *key_manager) = round_trip(checkpoint).into_inner();
+        }
+    }
+
+    fn bob(&mut self, swap: &mut BobSwap, key_manager: &mut KeyManager) {
+        if self.enabled {
+            self.steps.insert(swap.step());
+            let checkpoint = BobCheckpoint::new(swap.clone(), key_manager.clone());
+            (*swap, *key_manager) = round_trip(checkpoint).into_inner();
+        }
+    }
+}
+
+fn round_trip<T, W>(checkpoint: Checkpoint<T, W>) -> Checkpoint<T, W>
+where
+    Checkpoint<T, W>: strict_encoding::StrictEncode + strict_encoding::StrictDecode,
+    T: farcaster_core::consensus::Encodable + farcaster_core::consensus::Decodable,
+    W: farcaster_core::consensus::Encodable + farcaster_core::consensus::Decodable,
+{
+    let bytes = serialize(&checkpoint);
+    let restored: Checkpoint<T, W> = deserialize(&bytes).expect("Parsable checkpoint");
+    assert_eq!(serialize(&restored), bytes);
+    let strict_ser = strict_encoding::strict_serialize(&restored).unwrap();
+    assert_eq!(strict_ser, bytes);
+    strict_encoding::strict_deserialize(&strict_ser).expect("Parsable checkpoint")
+}
+
+#[test]
+fn execute_swap_state_machines() {
+    run_swap_state_machines(Checkpoints::new(false));
+}
+
+#[test]
+fn restore_swap_state_machines_at_every_step() {
+    let steps = run_swap_state_machines(Checkpoints::new(true));
+    assert_eq!(steps.len(), 18);
+}
+
+// The deal of the state machine tests, Bob pre-signs two fee bumps of the cancel and refund
+// transactions in the range of the fee strategy
+fn init_swaps() -> (Alice, Bob, Deal) {
+    let (alice, bob, mut deal) = init();
+    deal.parameters.fee_strategy = FeeStrategy::Range {
+        min_inc: SatPerVByte::from_sat(20),
+        max_inc: SatPerVByte::from_sat(40),
+    };
+    // The punish timelock must be longer than the cancel timelock to pass the validator
+    deal.parameters.punish_timelock = CSVTimelock::from_blocks(20);
+    (alice, bob.with_fee_bumps(2), deal)
+}
+
+fn funding_tx(address: &Address, vout: u32, value: u64) -> bitcoin::Transaction {
+    bitcoin::Transaction {
+        version: 2,
+        lock_time: 0,
+        input: vec![TxIn {
+            previous_output: OutPoint {
+                vout,
+                ..OutPoint::null()
+            },
+            script_sig: bitcoin::blockdata::script::Script::default(),
+            sequence: (1 << 31) as u32,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value,
+            script_pubkey: address.script_pubkey(),
+        }],
+    }
+}
+
+/// Alice and Bob state machines of a swap with their key managers, checkpointed after each input.
+/// Each step of the protocol runs the previous ones.
+struct SwapRun {
+    alice: AliceSwap,
+    bob: BobSwap,
+    alice_key_manager: KeyManager,
+    bob_key_manager: KeyManager,
+    checkpoints: Checkpoints,
+}
+
+impl SwapRun {
+    fn new(checkpoints: Checkpoints) -> Self {
+        let (alice, bob, deal) = init_swaps();
+        let swap_id = SwapId::random();
+        let validator = DealValidator::new(DealPolicy::default());
+        Self::with_swaps(
+            AliceSwap::new_validated(swap_id, alice, deal.clone(), &validator).unwrap(),
+            BobSwap::new_validated(swap_id, bob, deal, &validator).unwrap(),
+            checkpoints,
+        )
+    }
+
+    // A swap of the deal turned into a version 3 deal accepting partial fills, the taker fills
+    // the arbitrating amount
+    fn filled(checkpoints: Checkpoints, arbitrating_amount: bitcoin::Amount) -> Self {
+        let (alice, bob, deal) = init_swaps();
+        let range = FillRange::new(
+            bitcoin::Amount::from_sat(10000),
+            deal.parameters.arbitrating_amount,
+        );
+        let deal = deal
+            .parameters
+            .to_v3(deal.node_id, deal.peer_address, None, 7, range);
+        let fill = deal.fill(arbitrating_amount).unwrap();
+        let swap_id = SwapId::random();
+        let validator = DealValidator::new(DealPolicy::default());
+        let mut alice = AliceSwap::new_validated(swap_id, alice, deal.clone(), &validator).unwrap();
+        let mut bob = BobSwap::new_validated(swap_id, bob, deal.clone(), &validator).unwrap();
+        match deal.parameters.maker_role {
+            SwapRole::Alice => bob = bob.with_fill(fill).unwrap(),
+            SwapRole::Bob => alice = alice.with_fill(fill).unwrap(),
+        }
+        Self::with_swaps(alice, bob, checkpoints)
+    }
+
+    fn with_swaps(alice: AliceSwap, bob: BobSwap, checkpoints: Checkpoints) -> Self {
+        let mut run = Self {
+            alice,
+            bob,
+            alice_key_manager: KeyManager::new([1; 32], 1).unwrap(),
+            bob_key_manager: KeyManager::new([2; 32], 1).unwrap(),
+            checkpoints,
+        };
+        run.checkpoint();
+        run
+    }
+
+    fn checkpoint(&mut self) {
+        self.checkpoints
+            .alice(&mut self.alice, &mut self.alice_key_manager);
+        self.checkpoints
+            .bob(&mut self.bob, &mut self.bob_key_manager);
+    }
+
+    fn alice_message(
+        &mut self,
+        msg: ProtocolMessage,
+    ) -> Result<Vec<SwapOutput>, farcaster_core::Error> {
+        let res = self
+            .alice
+            .handle_message(&mut self.alice_key_manager, &CommitmentEngine, msg);
+        self.checkpoint();
+        res
+    }
+
+    fn bob_message(
+        &mut self,
+        msg: ProtocolMessage,
+    ) -> Result<Vec<SwapOutput>, farcaster_core::Error> {
+        let res = self
+            .bob
+            .handle_message(&mut self.bob_key_manager, &CommitmentEngine, msg);
+        self.checkpoint();
+        res
+    }
+
+    fn alice_event(
+        &mut self,
+        event: Event<bitcoin::Transaction>,
+    ) -> Result<Vec<SwapOutput>, farcaster_core::Error> {
+        let res = self.alice.handle_event(&mut self.alice_key_manager, event);
+        self.checkpoint();
+        res
+    }
+
+    fn bob_event(
+        &mut self,
+        event: Event<bitcoin::Transaction>,
+    ) -> Result<Vec<SwapOutput>, farcaster_core::Error> {
+        let res = self.bob.handle_event(&mut self.bob_key_manager, event);
+        self.checkpoint();
+        res
+    }
+
+    // Start both swaps, deliver the taker's fill if any and return Alice's and Bob's commitments
+    fn start(&mut self) -> (ProtocolMessage, ProtocolMessage) {
+        let alice_outputs = self
+            .alice
+            .start(&mut self.alice_key_manager, &CommitmentEngine)
+            .unwrap();
+        let bob_outputs = self
+            .bob
+            .start(&mut self.bob_key_manager, &CommitmentEngine)
+            .unwrap();
+        self.checkpoint();
+        // The taker sends its fill before its commitment
+        let mut alice_messages: Vec<_> = alice_outputs.into_iter().map(unwrap_message).collect();
+        let mut bob_messages: Vec<_> = bob_outputs.into_iter().map(unwrap_message).collect();
+        let commit_alice = alice_messages.pop().unwrap();
+        let commit_bob = bob_messages.pop().unwrap();
+        for fill in alice_messages {
+            assert!(self.bob_message(fill).unwrap().is_empty());
+        }
+        for fill in bob_messages {
+            assert!(self.alice_message(fill).unwrap().is_empty());
+        }
+        (commit_alice, commit_bob)
+    }
+
+    // Exchange the commitments and return Alice's and Bob's reveals, the parameters then the proof
+    fn commit(&mut self) -> (Vec<ProtocolMessage>, Vec<ProtocolMessage>) {
+        let (commit_alice, commit_bob) = self.start();
+        let reveal_bob = self.bob_message(commit_alice).unwrap();
+        let reveal_alice = self.alice_message(commit_bob).unwrap();
+        (
+            reveal_alice.into_iter().map(unwrap_message).collect(),
+            reveal_bob.into_iter().map(unwrap_message).collect(),
+        )
+    }
+
+    // Exchange the reveals and return the funding address requested by Bob
+    fn reveal(
+        &mut self,
+        reveal_alice: Vec<ProtocolMessage>,
+        reveal_bob: Vec<ProtocolMessage>,
+    ) -> Address {
+        let mut outputs = vec![];
+        for msg in reveal_alice {
+            outputs = self.bob_message(msg).unwrap();
+        }
+        for msg in reveal_bob {
+            assert!(self.alice_message(msg).unwrap().is_empty());
+        }
+        assert_eq!(self.alice.step(), Step::CoreArbitratingSetup);
+        assert_eq!(self.bob.step(), Step::Funding);
+        match outputs.remove(0) {
+            Output::FundArbitrating(address) => address,
+            output => panic!("Expected a funding request, found {:?}", output),
+        }
+    }
+
+    // Fund the swap and return Bob's core arbitrating setup
+    fn fund(&mut self) -> ProtocolMessage {
+        let (reveal_alice, reveal_bob) = self.commit();
+        let address = self.reveal(reveal_alice, reveal_bob);
+        let funding = funding_tx(&address, 0, 123456789);
+        let mut outputs = self.bob_event(Event::FundingSeen(vec![funding])).unwrap();
+        unwrap_message(outputs.remove(0))
+    }
+
+    // Exchange the core arbitrating setup and the refund procedure signatures, return the
+    // arbitrating lock broadcasted by Bob
+    fn setup(&mut self) -> bitcoin::Transaction {
+        let core_arb_setup = self.fund();
+        let mut outputs = self.alice_message(core_arb_setup).unwrap();
+        let refund_proc_sig = unwrap_message(outputs.remove(0));
+        let mut outputs = self.bob_message(refund_proc_sig).unwrap();
+        assert_eq!(self.bob.step(), Step::AccordantLock);
+        unwrap_broadcast(outputs.remove(0), TxLabel::Lock)
+    }
+
+    // Lock the assets of both participants and return the arbitrating lock
+    fn lock(&mut self) -> bitcoin::Transaction {
+        let lock_tx = self.setup();
+        let outputs = self.alice_event(Event::ArbitratingLockFinal).unwrap();
+        assert!(matches!(outputs[..], [Output::LockAccordant]));
+        lock_tx
+    }
+}
+
+// Execute a swap until the buy, then cancel it from the accordant lock and refund or punish, and
+// abort another swap before any asset is locked. Return the steps at which checkpoints are taken.
+fn run_swap_state_machines(checkpoints: Checkpoints) -> HashSet<Step> {
+    let mut run = SwapRun::new(checkpoints);
+    let lock_tx = run.lock();
+    let alice_before_buy = run.alice.clone();
+    let bob_before_buy = run.bob.clone();
+
+    //
+    // Buy
+    //
+    let mut outputs = run.bob_event(Event::AccordantLockFinal).unwrap();
+    let adaptor_buy = unwrap_message(outputs.remove(0));
+    let mut outputs = run.alice_message(adaptor_buy).unwrap();
+    let buy_tx = unwrap_broadcast(outputs.remove(0), TxLabel::Buy);
+
+    let mut outputs = run.bob_event(Event::BuySeen(buy_tx.clone())).unwrap();
+    assert_recovered_key(outputs.remove(0), &mut run.alice_key_manager);
+    assert_eq!(run.bob.step(), Step::SwapSuccess);
+    run.alice_event(Event::BuySeen(buy_tx)).unwrap();
+    assert_eq!(run.alice.step(), Step::SwapSuccess);
+    assert!(run.alice.step().is_final());
+    assert_out_of_order(run.alice_event(Event::CancelSeen(lock_tx)));
+
+    //
+    // Cancel and refund
+    //
+    run.alice = alice_before_buy;
+    run.bob = bob_before_buy;
+
+    // Bob cancels when the cancel timelock expires
+    let mut outputs = run.bob_event(Event::CancelTimelockExpired).unwrap();
+    let cancel_tx = unwrap_broadcast(outputs.remove(0), TxLabel::Cancel);
+    run.alice_event(Event::CancelSeen(cancel_tx.clone()))
+        .unwrap();
+    assert_eq!(run.alice.step(), Step::Cancel);
+    let alice_after_cancel = run.alice.clone();
+
+    let mut outputs = run.bob_event(Event::CancelSeen(cancel_tx.clone())).unwrap();
+    let refund_tx = unwrap_broadcast(outputs.remove(0), TxLabel::Refund);
+    assert_eq!(refund_tx.input[0].previous_output.txid, cancel_tx.txid());
+    let mut outputs = run
+        .alice_event(Event::RefundSeen(refund_tx.clone()))
+        .unwrap();
+    assert_recovered_key(outputs.remove(0), &mut run.bob_key_manager);
+    assert_eq!(run.alice.step(), Step::Refunded);
+    run.bob_event(Event::RefundSeen(refund_tx)).unwrap();
+    assert_eq!(run.bob.step(), Step::Refunded);
+
+    //
+    // Cancel and punish
+    //
+    run.alice = alice_after_cancel;
+    let mut outputs = run.alice_event(Event::PunishTimelockExpired).unwrap();
+    let punish_tx = unwrap_broadcast(outputs.remove(0), TxLabel::Punish);
+    assert_eq!(punish_tx.input[0].previous_output.txid, cancel_tx.txid());
+    run.alice_event(Event::PunishSeen).unwrap();
+    assert_eq!(run.alice.step(), Step::Punished);
+
+    //
+    // Abort before any asset is locked
+    //
+    let mut run = SwapRun::new(run.checkpoints);
+    run.start();
+    run.bob
+        .abort::<bitcoin::Transaction, PublicNonce>(AbortReason::UserCancelled, None)
+        .unwrap();
+    assert_eq!(run.bob.step(), Step::Aborted);
+    run.checkpoint();
+
+    run.checkpoints.steps
+}
+
+#[test]
+fn reject_inputs_out_of_order() {
+    let mut run = SwapRun::new(Checkpoints::new(false));
+    let (commit_alice, commit_bob) = run.start();
+    assert_out_of_order(
+        run.alice
+            .start(&mut run.alice_key_manager, &CommitmentEngine),
+    );
+
+    // Alice cannot receive her own commitment
+    assert_out_of_order(run.alice_message(commit_alice.clone()));
+    assert_eq!(run.alice.step(), Step::Commit);
+
+    // The proof is expected after the parameters
+    run.bob_message(commit_alice).unwrap();
+    let mut reveal_alice = run.alice_message(commit_bob).unwrap();
+    let alice_proof = unwrap_message(reveal_alice.remove(1));
+    assert_out_of_order(run.bob_message(alice_proof));
+    assert_eq!(run.bob.step(), Step::RevealParameters);
+}
+
+#[test]
+fn reject_messages_for_another_swap() {
+    let mut run = SwapRun::new(Checkpoints::new(false));
+    run.start();
+    let (_, other_commit) = SwapRun::new(Checkpoints::new(false)).start();
+    assert!(matches!(
+        run.alice_message(other_commit),
+        Err(farcaster_core::Error::StateMachine(
+            machine::Error::SwapIdMismatch { .. }
+        ))
+    ));
+    assert_eq!(run.alice.step(), Step::Commit);
+}
+
+#[test]
+fn fund_swap_with_several_payments() {
+    let mut run = SwapRun::new(Checkpoints::new(false));
+    let (reveal_alice, reveal_bob) = run.commit();
+    let address = run.reveal(reveal_alice, reveal_bob);
+
+    // The first payment does not cover the arbitrating amount
+    let arbitrating_amount = run.bob.deal().parameters.arbitrating_amount;
+    let first_payment = funding_tx(&address, 0, arbitrating_amount.as_sat() / 2);
+    let second_payment = funding_tx(&address, 1, 123456789);
+    assert!(run
+        .bob_event(Event::FundingSeen(vec![first_payment.clone()]))
+        .is_err());
+    assert_eq!(run.bob.step(), Step::Funding);
+
+    let mut outputs = run
+        .bob_event(Event::FundingSeen(vec![first_payment, second_payment]))
+        .unwrap();
+    let core_arb_setup = unwrap_message(outputs.remove(0));
+    let mut outputs = run.alice_message(core_arb_setup).unwrap();
+    let refund_proc_sig = unwrap_message(outputs.remove(0));
+    let mut outputs = run.bob_message(refund_proc_sig).unwrap();
+    let lock_tx = unwrap_broadcast(outputs.remove(0), TxLabel::Lock);
+    // Both payments are consolidated in the lock
+    assert_eq!(lock_tx.input.len(), 2);
+}
+
+#[test]
+fn start_swaps_on_accepted_deals() {
+    let (_, bob, deal) = init_swaps();
+    let policy = DealPolicy {
+        max_fee_rate: Some(SatPerVByte::from_sat(30)),
+        ..Default::default()
+    };
+    let res = BobSwap::new_validated(SwapId::random(), bob, deal, &DealValidator::new(policy));
+    assert!(matches!(
+        res,
+        Err(farcaster_core::Error::Trade(trade::Error::InvalidDeal(violations)))
+            if violations == vec![Violation::FeeStrategyOutOfBounds]
+    ));
+}
+
+#[test]
+fn reject_parameters_diverging_from_deal() {
+    let mut run = SwapRun::new(Checkpoints::new(false));
+    let (commit_alice, commit_bob) = run.start();
+
+    // A commitment missing a key required by the blockchains of the deal is rejected
+    if let ProtocolMessage::CommitAliceParameters(commit) = &commit_alice {
+        let mut missing_key = commit.clone();
+        missing_key.accordant_shared_keys.pop();
+        assert!(matches!(
+            run.bob_message(ProtocolMessage::CommitAliceParameters(missing_key)),
+            Err(farcaster_core::Error::Validation(
+                validation::Error::Diverged {
+                    message: MessageType::CommitAliceParameters,
+                    field: Field::ExtraKeys,
+                }
+            ))
+        ));
+        assert_eq!(run.bob.step(), Step::Commit);
+    }
+
+    let reveal_bob = run.bob_message(commit_alice).unwrap();
+    let reveal_alice = run.alice_message(commit_bob).unwrap();
+    let alice_buy = match unwrap_message(reveal_alice[0].clone()) {
+        ProtocolMessage::RevealAliceParameters(reveal) => reveal.buy,
+        msg => panic!("Expected Alice parameters, found {}", msg),
+    };
+
+    // Bob cannot reveal Alice's keys as his own
+    if let ProtocolMessage::RevealBobParameters(reveal) = unwrap_message(reveal_bob[0].clone()) {
+        let mut reused_key = reveal;
+        reused_key.buy = alice_buy;
+        assert!(matches!(
+            run.alice_message(ProtocolMessage::RevealBobParameters(reused_key)),
+            Err(farcaster_core::Error::Validation(
+                validation::Error::Diverged {
+                    message: MessageType::RevealBobParameters,
+                    field: Field::Keys,
+                }
+            ))
+        ));
+        assert_eq!(run.alice.step(), Step::RevealParameters);
+    }
+}
+
+#[test]
+fn reject_setup_diverging_from_deal() {
+    let mut run = SwapRun::new(Checkpoints::new(false));
+    let core_arb_setup = run.fund();
+
+    // A setup diverging from the deal is rejected with the diverging field
+    if let ProtocolMessage::CoreArbitratingSetup(setup) = &core_arb_setup {
+        let mut other_refund = setup.clone();
+        other_refund.refund = setup.bumps[0].refund.clone();
+        let mut missing_bump = setup.clone();
+        missing_bump.bumps.pop();
+        for (setup, diverged) in [
+            (other_refund, Field::PreviousOutput(TxLabel::Refund)),
+            (missing_bump, Field::FeeBumps),
+        ] {
+            assert!(matches!(
+                run.alice_message(ProtocolMessage::CoreArbitratingSetup(setup)),
+                Err(farcaster_core::Error::Validation(validation::Error::Diverged {
+                    message: MessageType::CoreArbitratingSetup,
+                    field,
+                })) if field == diverged
+            ));
+        }
+        assert_eq!(run.alice.step(), Step::CoreArbitratingSetup);
+    }
+
+    run.alice_message(core_arb_setup).unwrap();
+    assert_eq!(run.alice.step(), Step::ArbitratingLock);
+}
+
+#[test]
+fn reject_signatures_from_another_transcript() {
+    let mut run = SwapRun::new(Checkpoints::new(false));
+    let core_arb_setup = run.fund();
+    let mut outputs = run.alice_message(core_arb_setup).unwrap();
+    let refund_proc_sig = unwrap_message(outputs.remove(0));
+
+    // Both participants saw the same protocol run, a signature message from another run is
+    // rejected
+    assert!(run.alice.transcript().is_some());
+    assert_eq!(run.alice.transcript(), run.bob.transcript());
+    if let ProtocolMessage::RefundProcedureSignatures(refund_procedure) = &refund_proc_sig {
+        assert_eq!(refund_procedure.transcript, run.alice.transcript());
+        let mut other_run = refund_procedure.clone();
+        other_run.transcript = Some(TranscriptHash::repeat_byte(0x42));
+        // A peer cannot strip the transcript to skip the binding
+        let mut stripped = refund_procedure.clone();
+        stripped.transcript = None;
+        for (refund_procedure, transcript) in [
+            (other_run, Some(TranscriptHash::repeat_byte(0x42))),
+            (stripped, None),
+        ] {
+            assert!(matches!(
+                run.bob_message(ProtocolMessage::RefundProcedureSignatures(refund_procedure)),
+                Err(farcaster_core::Error::StateMachine(
+                    machine::Error::TranscriptMismatch { found, .. }
+                )) if found == transcript
+            ));
+        }
+        assert_eq!(run.bob.step(), Step::RefundProcedureSignatures);
+    }
+
+    run.bob_message(refund_proc_sig).unwrap();
+    assert_eq!(run.bob.step(), Step::AccordantLock);
+}
+
+#[test]
+fn abort_swap_before_lock() {
+    let mut run = SwapRun::new(Checkpoints::new(false));
+    run.start();
+    let outputs = run
+        .bob
+        .abort::<bitcoin::Transaction, PublicNonce>(AbortReason::Timeout, Some("No answer".into()))
+        .unwrap();
+    match unwrap_message(outputs.into_iter().next().unwrap()) {
+        ProtocolMessage::Abort(abort) => assert_eq!(abort.reason, AbortReason::Timeout),
+        msg => panic!("Expected an abort, found {}", msg),
+    }
+    assert_eq!(run.bob.step(), Step::Aborted);
+
+    // The swap cannot be aborted once the lock is broadcasted
+    let mut run = SwapRun::new(Checkpoints::new(false));
+    run.setup();
+    assert_out_of_order(
+        run.bob
+            .abort::<bitcoin::Transaction, PublicNonce>(AbortReason::UserCancelled, None),
+    );
+}
+
+#[test]
+fn bump_cancel_along_fee_ladder() {
+    let mut run = SwapRun::new(Checkpoints::new(false));
+    let lock_tx = run.lock();
+    let mut outputs = run.bob_event(Event::CancelTimelockExpired).unwrap();
+    let cancel_tx = unwrap_broadcast(outputs.remove(0), TxLabel::Cancel);
+    assert_eq!(run.bob.rung(), 0);
+
+    // The network fee rate increases, Bob replaces the cancel with the last rung of the ladder
+    let mut outputs = run.bob.bump_cancel(&SatPerVByte::from_sat(35)).unwrap();
+    let bumped_cancel_tx = unwrap_broadcast(outputs.remove(0), TxLabel::Cancel);
+    assert_eq!(
+        bumped_cancel_tx.input[0].previous_output,
+        cancel_tx.input[0].previous_output
+    );
+    assert_ne!(bumped_cancel_tx.txid(), cancel_tx.txid());
+    assert_eq!(run.bob.rung(), 2);
+    assert!(run
+        .bob
+        .bump_cancel::<PublicNonce>(&SatPerVByte::from_sat(20))
+        .unwrap()
+        .is_empty());
+
+    // Alice sees the bumped cancel on-chain, unknown transactions are rejected
+    assert!(matches!(
+        run.alice_event(Event::CancelSeen(lock_tx)),
+        Err(farcaster_core::Error::StateMachine(
+            machine::Error::UnknownTransaction("cancel")
+        ))
+    ));
+    run.alice_event(Event::CancelSeen(bumped_cancel_tx.clone()))
+        .unwrap();
+    assert_eq!(run.alice.step(), Step::Cancel);
+    assert_eq!(run.alice.rung(), 2);
+
+    // The refund and the punish are built on top of the bumped cancel
+    let mut outputs = run
+        .bob_event(Event::CancelSeen(bumped_cancel_tx.clone()))
+        .unwrap();
+    let refund_tx = unwrap_broadcast(outputs.remove(0), TxLabel::Refund);
+    assert_eq!(
+        refund_tx.input[0].previous_output.txid,
+        bumped_cancel_tx.txid()
+    );
+    let mut outputs = run.alice_event(Event::PunishTimelockExpired).unwrap();
+    let punish_tx = unwrap_broadcast(outputs.remove(0), TxLabel::Punish);
+    assert_eq!(
+        punish_tx.input[0].previous_output.txid,
+        bumped_cancel_tx.txid()
+    );
+}
+
+#[test]
+fn execute_swap_at_partial_fill() {
+    let arbitrating_amount = bitcoin::Amount::from_sat(60000);
+    let mut run = SwapRun::filled(Checkpoints::new(true), arbitrating_amount);
+    let fill = *run.alice.fill().or(run.bob.fill()).unwrap();
+    assert!(arbitrating_amount < run.alice.deal().parameters.arbitrating_amount);
+    assert_eq!(fill.arbitrating_amount, arbitrating_amount);
+
+    // The maker only accepts the taker's commitment after its fill
+    let mut other = SwapRun::filled(Checkpoints::new(false), arbitrating_amount);
+    let mut outputs = other
+        .alice
+        .start(&mut other.alice_key_manager, &CommitmentEngine)
+        .unwrap();
+    let commit_alice = unwrap_message(outputs.pop().unwrap());
+    let mut outputs = other
+        .bob
+        .start(&mut other.bob_key_manager, &CommitmentEngine)
+        .unwrap();
+    let commit_bob = unwrap_message(outputs.pop().unwrap());
+    match other.alice.deal().parameters.maker_role {
+        SwapRole::Alice => assert_out_of_order(other.alice_message(commit_bob)),
+        SwapRole::Bob => assert_out_of_order(other.bob_message(commit_alice)),
+    }
+
+    // Both participants lock and swap the filled amounts
+    let lock_tx = run.lock();
+    assert_eq!(run.alice.fill(), Some(&fill));
+    assert_eq!(run.bob.fill(), Some(&fill));
+    assert_eq!(lock_tx.output[0].value, arbitrating_amount.as_sat());
+    assert_eq!(run.alice.arbitrating_amount(), arbitrating_amount);
+    assert_eq!(run.alice.accordant_amount(), fill.accordant_amount);
+    assert!(fill.accordant_amount < run.alice.deal().parameters.accordant_amount);
+
+    let mut outputs = run.bob_event(Event::AccordantLockFinal).unwrap();
+    let adaptor_buy = unwrap_message(outputs.remove(0));
+    let mut outputs = run.alice_message(adaptor_buy).unwrap();
+    let buy_tx = unwrap_broadcast(outputs.remove(0), TxLabel::Buy);
+    let mut outputs = run.bob_event(Event::BuySeen(buy_tx)).unwrap();
+    assert_recovered_key(outputs.remove(0), &mut run.alice_key_manager);
+    assert_eq!(run.bob.step(), Step::SwapSuccess);
+}
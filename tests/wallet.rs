@@ -191,8 +191,9 @@ fn key_manager_can_recover_secret() {
         .verify_signature(&pubkey, bytes, &decrypt_sig)
         .is_ok());
 
-    let recovered_secret: bitcoin::secp256k1::SecretKey =
-        key_manager.recover_secret_key(enc_sig, &encryption_key, decrypt_sig);
+    let recovered_secret: bitcoin::secp256k1::SecretKey = key_manager
+        .recover_secret_key(enc_sig, &encryption_key, decrypt_sig)
+        .unwrap();
 
     // check equality on canonical bytes
     let mut secret = secret.as_canonical_bytes();